@@ -2,12 +2,12 @@ use bytes::Bytes;
 use criterion::{criterion_group, Benchmark, Criterion, Throughput};
 use futures::sync::mpsc;
 use futures::{Future, Sink, Stream};
-use vector::sinks::util::{Batch, BatchSink, Buffer, Partition, PartitionedBatchSink};
+use vector::sinks::util::{Batch, BatchSink, Buffer, Compression, Partition, PartitionedBatchSink};
 use vector::test_util::random_lines;
 
 fn batching(
     bench_name: &'static str,
-    gzip: bool,
+    compression: Compression,
     max_size: usize,
     num_events: usize,
     event_len: usize,
@@ -24,7 +24,7 @@ fn batching(
             |input| {
                 let (tx, _rx) = mpsc::unbounded();
                 let batch_sink =
-                    BatchSink::new(tx.sink_map_err(|_| ()), Buffer::new(gzip), max_size);
+                    BatchSink::new(tx.sink_map_err(|_| ()), Buffer::new(compression), max_size);
 
                 input.forward(batch_sink).wait().unwrap()
             },
@@ -37,7 +37,7 @@ fn batching(
 
 fn partitioned_batching(
     bench_name: &'static str,
-    gzip: bool,
+    compression: Compression,
     max_size: usize,
     num_events: usize,
     event_len: usize,
@@ -60,7 +60,7 @@ fn partitioned_batching(
                 let (tx, _rx) = mpsc::unbounded();
                 let batch_sink = PartitionedBatchSink::new(
                     tx.sink_map_err(|_| ()),
-                    PartitionedBuffer::new(gzip),
+                    PartitionedBuffer::new(compression),
                     max_size,
                 );
 
@@ -78,7 +78,7 @@ fn benchmark_batching(c: &mut Criterion) {
         "batch",
         batching(
             "no compression 10mb with 2mb batches",
-            false,
+            Compression::None,
             2_000_000,
             100_000,
             100,
@@ -86,18 +86,40 @@ fn benchmark_batching(c: &mut Criterion) {
     );
     c.bench(
         "batch",
-        batching("gzip 10mb with 2mb batches", true, 2_000_000, 100_000, 100),
+        batching(
+            "gzip 10mb with 2mb batches",
+            Compression::Gzip(None),
+            2_000_000,
+            100_000,
+            100,
+        ),
+    );
+    c.bench(
+        "batch",
+        batching(
+            "gzip 10mb with 500kb batches",
+            Compression::Gzip(None),
+            500_000,
+            100_000,
+            100,
+        ),
     );
     c.bench(
         "batch",
-        batching("gzip 10mb with 500kb batches", true, 500_000, 100_000, 100),
+        batching(
+            "zstd 10mb with 2mb batches",
+            Compression::Zstd(None),
+            2_000_000,
+            100_000,
+            100,
+        ),
     );
 
     c.bench(
         "partitioned_batch",
         partitioned_batching(
             "no compression 10mb with 2mb batches",
-            false,
+            Compression::None,
             2_000_000,
             100_000,
             100,
@@ -105,7 +127,13 @@ fn benchmark_batching(c: &mut Criterion) {
     );
     c.bench(
         "partitioned_batch",
-        partitioned_batching("gzip 10mb with 2mb batches", true, 2_000_000, 100_000, 100),
+        partitioned_batching(
+            "gzip 10mb with 2mb batches",
+            Compression::Gzip(None),
+            2_000_000,
+            100_000,
+            100,
+        ),
     );
 }
 
@@ -129,9 +157,9 @@ impl Partition<Bytes> for InnerBuffer {
 }
 
 impl PartitionedBuffer {
-    pub fn new(gzip: bool) -> Self {
+    pub fn new(compression: Compression) -> Self {
         Self {
-            inner: Buffer::new(gzip),
+            inner: Buffer::new(compression),
             key: None,
         }
     }
@@ -27,7 +27,7 @@ fn benchmark_http_no_compression(c: &mut Criterion) {
                     "out",
                     &["in"],
                     sinks::http::HttpSinkConfig {
-                        uri: out_addr.to_string(),
+                        uri: out_addr.to_string().into(),
                         compression: Some(sinks::util::Compression::None),
                         ..Default::default()
                     },
@@ -75,7 +75,7 @@ fn benchmark_http_gzip(c: &mut Criterion) {
                     "out",
                     &["in"],
                     sinks::http::HttpSinkConfig {
-                        uri: out_addr.to_string(),
+                        uri: out_addr.to_string().into(),
                         ..Default::default()
                     },
                 );
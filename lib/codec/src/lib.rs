@@ -5,6 +5,10 @@ use bytes::{BufMut, Bytes, BytesMut};
 use std::{cmp, io, usize};
 use tokio_codec::{Decoder, Encoder};
 
+mod octet_counting;
+
+pub use octet_counting::OctetCountingDecoder;
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct BytesDelimitedCodec {
     delim: u8,
@@ -0,0 +1,151 @@
+use crate::BytesDelimitedCodec;
+use bytes::{Bytes, BytesMut};
+use std::io;
+use tokio_codec::Decoder;
+
+/// The longest length prefix read, enough for any frame that fits in memory.
+const MAX_LENGTH_DIGITS: usize = 10;
+
+/// Decodes syslog messages framed as in RFC 6587, either with octet counting,
+/// `MSG-LEN SP SYSLOG-MSG`, or non-transparently by newlines. The framing is
+/// detected once per connection, from the first byte received: messages start
+/// with a `<` while lengths start with a digit.
+#[derive(Clone, Debug)]
+pub struct OctetCountingDecoder {
+    max_length: usize,
+    framing: Option<Framing>,
+    delimited: BytesDelimitedCodec,
+    /// The bytes left to discard of a frame longer than `max_length`.
+    discarding: usize,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Framing {
+    OctetCounting,
+    NonTransparent,
+}
+
+impl OctetCountingDecoder {
+    pub fn new() -> Self {
+        Self::new_with_max_length(usize::max_value())
+    }
+
+    /// Returns an `OctetCountingDecoder` discarding frames longer than
+    /// `max_length`.
+    pub fn new_with_max_length(max_length: usize) -> Self {
+        OctetCountingDecoder {
+            max_length,
+            framing: None,
+            delimited: BytesDelimitedCodec::new_with_max_length(b'\n', max_length),
+            discarding: 0,
+        }
+    }
+
+    fn decode_octet_counted(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, io::Error> {
+        loop {
+            if self.discarding > 0 {
+                let discarded = self.discarding.min(buf.len());
+                buf.advance(discarded);
+                self.discarding -= discarded;
+                if self.discarding > 0 {
+                    return Ok(None);
+                }
+            }
+
+            // Some senders end frames with a newline as well
+            let skipped = buf
+                .iter()
+                .take_while(|b| **b == b'\n' || **b == b'\r')
+                .count();
+            buf.advance(skipped);
+
+            let digits = buf.iter().take_while(|b| b.is_ascii_digit()).count();
+            if digits == buf.len() && digits <= MAX_LENGTH_DIGITS {
+                // The length isn't complete yet
+                return Ok(None);
+            }
+            if digits == 0 || digits > MAX_LENGTH_DIGITS || buf[digits] != b' ' {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid octet counting frame length",
+                ));
+            }
+
+            let length = std::str::from_utf8(&buf[..digits])
+                .ok()
+                .and_then(|digits| digits.parse::<usize>().ok())
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "invalid octet counting frame length",
+                    )
+                })?;
+
+            if length > self.max_length {
+                warn!(
+                    message = "discarding frame larger than max_length",
+                    length = length,
+                    max_len = self.max_length,
+                    rate_limit_secs = 30
+                );
+                buf.advance(digits + 1);
+                self.discarding = length;
+                continue;
+            }
+
+            if buf.len() < digits + 1 + length {
+                buf.reserve(digits + 1 + length - buf.len());
+                return Ok(None);
+            }
+
+            buf.advance(digits + 1);
+            let frame = buf.split_to(length);
+            trace!(
+                message = "decoding the frame.",
+                bytes_proccesed = frame.len()
+            );
+            return Ok(Some(frame.freeze()));
+        }
+    }
+}
+
+impl Default for OctetCountingDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for OctetCountingDecoder {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, io::Error> {
+        if self.framing.is_none() {
+            let first = buf.iter().find(|b| !b.is_ascii_whitespace());
+            self.framing = match first {
+                Some(b) if b.is_ascii_digit() => Some(Framing::OctetCounting),
+                Some(_) => Some(Framing::NonTransparent),
+                None => return Ok(None),
+            };
+        }
+
+        match self.framing {
+            Some(Framing::OctetCounting) => self.decode_octet_counted(buf),
+            _ => self.delimited.decode(buf),
+        }
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, io::Error> {
+        match self.framing {
+            Some(Framing::NonTransparent) => self.delimited.decode_eof(buf),
+            _ => match self.decode(buf)? {
+                Some(frame) => Ok(Some(frame)),
+                // A truncated last frame is dropped
+                None => {
+                    buf.clear();
+                    Ok(None)
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use bytes::{BufMut, BytesMut};
+use codec::OctetCountingDecoder;
+use tokio_codec::Decoder;
+
+#[test]
+fn octet_counting_decode() {
+    let mut codec = OctetCountingDecoder::new();
+    let buf = &mut BytesMut::new();
+    buf.put_slice(b"15 <13>1 line one\n14 <13>1 line tw");
+
+    assert_eq!(Some("<13>1 line one\n".into()), codec.decode(buf).unwrap());
+    assert_eq!(None, codec.decode(buf).unwrap());
+
+    buf.put_slice(b"o11 <13>1 thre");
+    assert_eq!(Some("<13>1 line two".into()), codec.decode(buf).unwrap());
+    assert_eq!(None, codec.decode(buf).unwrap());
+    assert_eq!(None, codec.decode_eof(buf).unwrap());
+}
+
+#[test]
+fn octet_counting_decode_trailing_newlines() {
+    let mut codec = OctetCountingDecoder::new();
+    let buf = &mut BytesMut::new();
+    buf.put_slice(b"5 first\n6 second\r\n");
+
+    assert_eq!(Some("first".into()), codec.decode(buf).unwrap());
+    assert_eq!(Some("second".into()), codec.decode(buf).unwrap());
+    assert_eq!(None, codec.decode_eof(buf).unwrap());
+}
+
+#[test]
+fn octet_counting_decode_max_length() {
+    let mut codec = OctetCountingDecoder::new_with_max_length(6);
+    let buf = &mut BytesMut::new();
+    buf.put_slice(b"7 1234567");
+
+    assert_eq!(None, codec.decode(buf).unwrap());
+    assert!(buf.is_empty());
+
+    buf.put_slice(b"6 123456");
+    assert_eq!(Some("123456".into()), codec.decode(buf).unwrap());
+}
+
+#[test]
+fn octet_counting_decode_invalid_length() {
+    let mut codec = OctetCountingDecoder::new();
+    let buf = &mut BytesMut::new();
+    buf.put_slice(b"12x <13>1 message");
+
+    assert!(codec.decode(buf).is_err());
+}
+
+#[test]
+fn non_transparent_decode() {
+    let mut codec = OctetCountingDecoder::new();
+    let buf = &mut BytesMut::new();
+    buf.put_slice(b"<13>1 line one\n<13>1 line two\n<13>1 3");
+
+    assert_eq!(Some("<13>1 line one".into()), codec.decode(buf).unwrap());
+    assert_eq!(Some("<13>1 line two".into()), codec.decode(buf).unwrap());
+    assert_eq!(None, codec.decode(buf).unwrap());
+    assert_eq!(Some("<13>1 3".into()), codec.decode_eof(buf).unwrap());
+}
@@ -4,9 +4,16 @@
 //! with this basic subscriber. It will enable all spans and events that match the
 //! metric capturing criteria. This means every span is enabled regardless of its level
 //! and any event with a field name ending with `_counter` or `_gauge`.
+//!
+//! Events may also carry a `scope` field, whose value is prepended to the
+//! metric keys recorded from that event. This allows the same callsite to
+//! report separate metrics per component, e.g. `my_sink.buffer_events_gauge`.
+//! Events without a `scope` of their own use the one of the innermost entered
+//! span that has a `scope` field.
 
 use hotmic::Sink;
 use std::{
+    cell::RefCell,
     collections::{HashMap, HashSet},
     fmt,
     sync::{Mutex, RwLock},
@@ -19,26 +26,39 @@ use tracing_core::{
 
 /// Metrics collector
 // TODO(lucio): move this to a trait
-pub type Collector = Sink<&'static str>;
+pub type Collector = Sink<String>;
 
 /// The subscriber that wraps another subscriber and produces metrics
 pub struct MetricsSubscriber<S> {
     inner: S,
     spans: Mutex<HashMap<Id, Span>>,
     interest: RwLock<HashSet<&'static str>>,
-    collector: Collector,
+    collector: Box<dyn Fn() -> Option<Collector> + Send + Sync>,
 }
 
 /// A `tracing_core::field::Visit` implementation that captures fields
 /// that contain `counter` or `gague` in their name and dispatches the `i64`
-/// or `u64` value to the underlying metrics sink.
+/// or `u64` value to the underlying metrics sink once `finish` is called.
 pub struct MetricVisitor {
     collector: Collector,
+    scope: Option<String>,
+    measurements: Vec<(&'static str, Measurement)>,
+}
+
+enum Measurement {
+    Count(i64),
+    Gauge(u64),
+}
+
+thread_local! {
+    /// The spans entered on this thread, innermost last.
+    static ENTERED: RefCell<Vec<Id>> = RefCell::new(Vec::new());
 }
 
 #[derive(Debug, Default)]
 struct Span {
     key: &'static str,
+    scope: Option<String>,
     start_duration: Option<u64>,
     start_execution: Option<u64>,
     end_duration: Option<u64>,
@@ -48,13 +68,37 @@ struct Span {
 impl<S> MetricsSubscriber<S> {
     /// Create a new `MetricsSubscriber` with the underlying subscriber and collector.
     pub fn new(inner: S, collector: Collector) -> Self {
+        Self::lazy(inner, move || Some(collector.clone()))
+    }
+
+    /// Create a new `MetricsSubscriber` that only records metrics once
+    /// `collector` returns one. Until then every callsite is left to the
+    /// underlying subscriber, so `tracing_core::callsite::rebuild_interest_cache`
+    /// has to be called once it does.
+    pub fn lazy<F>(inner: S, collector: F) -> Self
+    where
+        F: Fn() -> Option<Collector> + Send + Sync + 'static,
+    {
         MetricsSubscriber {
             inner,
-            collector,
+            collector: Box::new(collector),
             interest: RwLock::new(HashSet::new()),
             spans: Mutex::new(HashMap::new()),
         }
     }
+
+    /// The scope of the innermost span entered on this thread that has one.
+    fn current_scope(&self) -> Option<String> {
+        let spans = self.spans.lock().unwrap();
+        ENTERED.with(|entered| {
+            entered
+                .borrow()
+                .iter()
+                .rev()
+                .filter_map(|id| spans.get(id).and_then(|span| span.scope.clone()))
+                .next()
+        })
+    }
 }
 
 impl<S: Subscriber> Subscriber for MetricsSubscriber<S> {
@@ -68,13 +112,21 @@ impl<S: Subscriber> Subscriber for MetricsSubscriber<S> {
         let id = self.inner.new_span(span);
         let key = metadata.name();
 
-        let span = Span {
-            key,
-            ref_count: 1,
-            ..Default::default()
-        };
+        let mut scope = ScopeVisitor(None);
+        span.record(&mut scope);
 
-        self.spans.lock().unwrap().insert(id.clone(), span);
+        // Scoped spans are tracked regardless, so that the scope applies to
+        // metrics recorded in spans created before recording started.
+        if scope.0.is_some() || (self.collector)().is_some() {
+            let span = Span {
+                key,
+                scope: scope.0,
+                ref_count: 1,
+                ..Default::default()
+            };
+
+            self.spans.lock().unwrap().insert(id.clone(), span);
+        }
         id
     }
 
@@ -87,8 +139,14 @@ impl<S: Subscriber> Subscriber for MetricsSubscriber<S> {
     }
 
     fn event(&self, event: &Event<'_>) {
-        let mut recorder = MetricVisitor::new(self.collector.clone());
-        event.record(&mut recorder);
+        if let Some(collector) = (self.collector)() {
+            let mut recorder = MetricVisitor::new(collector);
+            event.record(&mut recorder);
+            if recorder.scope.is_none() {
+                recorder.scope = self.current_scope();
+            }
+            recorder.finish();
+        }
 
         let selective_interest = {
             self.interest
@@ -105,10 +163,16 @@ impl<S: Subscriber> Subscriber for MetricsSubscriber<S> {
 
     fn enter(&self, span: &Id) {
         self.inner.enter(span);
+        ENTERED.with(|entered| entered.borrow_mut().push(span.clone()));
+
+        let collector = match (self.collector)() {
+            Some(collector) => collector,
+            None => return,
+        };
 
         let mut spans = self.spans.lock().unwrap();
         if let Some(span) = &mut spans.get_mut(span) {
-            let start = self.collector.clock().start();
+            let start = collector.clock().start();
             span.start_execution = Some(start);
 
             if let None = span.start_duration {
@@ -119,10 +183,21 @@ impl<S: Subscriber> Subscriber for MetricsSubscriber<S> {
 
     fn exit(&self, span: &Id) {
         self.inner.exit(span);
+        ENTERED.with(|entered| {
+            let mut entered = entered.borrow_mut();
+            if let Some(position) = entered.iter().rposition(|id| id == span) {
+                entered.remove(position);
+            }
+        });
+
+        let collector = match (self.collector)() {
+            Some(collector) => collector,
+            None => return,
+        };
 
         let mut spans = self.spans.lock().unwrap();
         if let Some(span) = &mut spans.get_mut(span) {
-            let end = self.collector.clock().end();
+            let end = collector.clock().end();
 
             // TODO: bring this back when we can do it without an allocation
             // if let Some(start) = span.start_execution {
@@ -138,7 +213,9 @@ impl<S: Subscriber> Subscriber for MetricsSubscriber<S> {
 
     // extra non required fn
     fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
-        if metadata.name().contains("event")
+        if (self.collector)().is_none() {
+            self.inner.register_callsite(metadata)
+        } else if metadata.name().contains("event")
             && metadata
                 .fields()
                 .iter()
@@ -179,8 +256,8 @@ impl<S: Subscriber> Subscriber for MetricsSubscriber<S> {
 
             if span.ref_count == 0 {
                 if let Some(start) = span.start_duration {
-                    if let Some(end) = span.end_duration {
-                        self.collector.update_timing(span.key, start, end);
+                    if let (Some(end), Some(collector)) = (span.end_duration, (self.collector)()) {
+                        collector.update_timing(span.key.to_string(), start, end);
                     }
                 }
             }
@@ -195,28 +272,81 @@ impl<S: Subscriber> Subscriber for MetricsSubscriber<S> {
 impl MetricVisitor {
     /// Create a new visitor with the underlying collector.
     pub fn new(collector: Collector) -> Self {
-        MetricVisitor { collector }
+        MetricVisitor {
+            collector,
+            scope: None,
+            measurements: Vec::new(),
+        }
+    }
+
+    /// Dispatch all recorded measurements to the collector, prefixing
+    /// their keys with the event's `scope` field if one was present.
+    pub fn finish(self) {
+        let MetricVisitor {
+            collector,
+            scope,
+            measurements,
+        } = self;
+
+        for (name, measurement) in measurements {
+            let key = match &scope {
+                Some(scope) => format!("{}.{}", scope, name),
+                None => name.to_string(),
+            };
+
+            match measurement {
+                Measurement::Count(value) => collector.update_count(key, value),
+                Measurement::Gauge(value) => collector.update_gauge(key, value),
+            }
+        }
+    }
+
+    fn record(&mut self, field: &Field, value: i64) {
+        if field.name().ends_with("_counter") {
+            self.measurements
+                .push((field.name(), Measurement::Count(value)));
+        } else if field.name().ends_with("_gauge") {
+            self.measurements
+                .push((field.name(), Measurement::Gauge(value as u64)));
+        }
     }
 }
 
 impl Visit for MetricVisitor {
-    fn record_str(&mut self, _field: &Field, _value: &str) {}
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "scope" {
+            self.scope = Some(value.to_string());
+        }
+    }
 
-    fn record_debug(&mut self, _field: &Field, _value: &dyn fmt::Debug) {}
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "scope" {
+            self.scope = Some(format!("{:?}", value));
+        }
+    }
 
     fn record_u64(&mut self, field: &Field, value: u64) {
-        if field.name().ends_with("_counter") {
-            self.collector.update_count(field.name(), value as i64);
-        } else if field.name().ends_with("_gauge") {
-            self.collector.update_gauge(field.name(), value);
-        }
+        self.record(field, value as i64);
     }
 
     fn record_i64(&mut self, field: &Field, value: i64) {
-        if field.name().ends_with("_counter") {
-            self.collector.update_count(field.name(), value);
-        } else if field.name().ends_with("_gauge") {
-            self.collector.update_gauge(field.name(), value as u64);
+        self.record(field, value);
+    }
+}
+
+/// Captures the `scope` field of a span.
+struct ScopeVisitor(Option<String>);
+
+impl Visit for ScopeVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "scope" {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "scope" {
+            self.0 = Some(format!("{:?}", value));
         }
     }
 }
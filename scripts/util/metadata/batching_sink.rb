@@ -37,7 +37,7 @@ class BatchingSink < Sink
         "null" => false,
         "simple" => batch_is_simple,
         "type" => "int",
-        "unit" => "bytes"
+        "unit" => hash.fetch("batch_size_unit", "bytes")
       })
 
     @options.batch_timeout =
@@ -59,7 +59,7 @@ class BatchingSink < Sink
         "name" => "request_in_flight_limit",
         "category" => "Requests",
         "default" => @request_in_flight_limit,
-        "description" => "The maximum number of in-flight requests allowed at any given time.",
+        "description" => "The maximum number of in-flight requests allowed at any given time, or `\"adaptive\"` to adjust it to the latency and back pressure of the downstream service.",
         "null" => false,
         "type" => "int"
       })
@@ -120,5 +120,27 @@ class BatchingSink < Sink
         "type" => "int",
         "unit" => "seconds"
       })
+
+    @options.request_retry_max_backoff_secs =
+      Option.new({
+        "name" => "request_retry_max_backoff_secs",
+        "category" => "Requests",
+        "default" => 10,
+        "description" => "The maximum amount of time to wait between two attempts of a failed request. The wait doubles with every retry up to this value. A longer wait the service asks for with a `Retry-After` header is honoured, up to 30 minutes.",
+        "null" => false,
+        "type" => "int",
+        "unit" => "seconds"
+      })
+
+    @options.request_retry_max_duration_secs =
+      Option.new({
+        "name" => "request_retry_max_duration_secs",
+        "category" => "Requests",
+        "examples" => [3600],
+        "description" => "The maximum amount of time to spend retrying a request, counted from its first failed attempt. Retries are not limited in time if unset.",
+        "null" => true,
+        "type" => "int",
+        "unit" => "seconds"
+      })
   end
 end
\ No newline at end of file
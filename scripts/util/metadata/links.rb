@@ -200,7 +200,7 @@ class Links
 
         source_file_url =
           case "#{name}_#{type}"
-          when "statsd_source"
+          when "prometheus_source", "statsd_source", "syslog_source"
             "#{VECTOR_ROOT}/tree/master/src/#{type.pluralize}/#{name}/mod.rs"
           else
             "#{VECTOR_ROOT}/tree/master/src/#{type.pluralize}/#{name}.rs"
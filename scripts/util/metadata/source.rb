@@ -15,6 +15,7 @@ class Source < Component
     @delivery_guarantee = hash.fetch("delivery_guarantee")
     @output_types = hash.fetch("output_types")
     @through_description = hash.fetch("through_description")
+    tls_options = hash["tls_options"]
 
     # delivery_guarantee
 
@@ -30,5 +31,77 @@ class Source < Component
     if @through_description.strip[-1] == "."
       raise("#{self.class.name}#through_description cannot not end with a period")
     end
+
+    # An empty array means TLS options are supported
+    if !tls_options.nil?
+      options = {}
+
+      options["enabled"] =
+        {
+          "type" => "bool",
+          "null" => true,
+          "default" => false,
+          "description" => "Require TLS for incoming connections."
+        }
+
+      options["crt_path"] =
+        {
+          "type" => "string",
+          "null" => true,
+          "examples" => ["/path/to/host_certificate.crt"],
+          "description" => "Absolute path to the certificate file presented to clients, in DER or PEM format (X.509) or PKCS#12. If this is set and is not a PKCS#12 archive, `key_path` must also be set. Changes to the file are picked up for new connections."
+        }
+
+      options["key_path"] =
+        {
+          "type" => "string",
+          "null" => true,
+          "examples" => ["/path/to/host_certificate.key"],
+          "description" => "Absolute path to the certificate key file, in DER or PEM format (PKCS#8). If this is set, `crt_path` must also be set."
+        }
+
+      options["key_pass"] =
+        {
+          "type" => "string",
+          "null" => true,
+          "examples" => ["PassWord1"],
+          "description" => "Pass phrase used to unlock the encrypted key file."
+        }
+
+      options["ca_path"] =
+        {
+          "type" => "string",
+          "null" => true,
+          "examples" => ["/path/to/certificate_authority.crt"],
+          "description" => "Absolute path to a CA certificate file, in DER or PEM format (X.509). Clients presenting a certificate not signed by it are rejected."
+        }
+
+      options["verify_certificate"] =
+        {
+          "type" => "bool",
+          "null" => true,
+          "default" => false,
+          "description" => "If `true`, clients not presenting a certificate are rejected."
+        }
+
+      if tls_options.include?("+peer_key")
+        options["peer_key"] =
+          {
+            "type" => "string",
+            "null" => true,
+            "examples" => ["peer"],
+            "description" => "The key name added to each event representing the subject of the client certificate."
+          }
+      end
+
+      @options.tls =
+        Option.new({
+          "name" => "tls",
+          "description" => "Configures the TLS options for connections to this source.",
+          "options" => options,
+          "null" => true,
+          "type" => "table"
+        })
+    end
   end
 end
\ No newline at end of file
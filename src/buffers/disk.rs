@@ -166,11 +166,13 @@ pub struct Reader {
     db: Arc<Database<Key>>,
     read_offset: usize,
     delete_offset: usize,
+    write_offset: Arc<AtomicUsize>,
     write_notifier: Arc<AtomicTask>,
     blocked_write_tasks: Arc<Mutex<Vec<Task>>>,
     current_size: Arc<AtomicUsize>,
     ack_counter: Arc<AtomicUsize>,
     unacked_sizes: VecDeque<usize>,
+    sink_name: String,
 }
 
 // Writebatch isn't Send, but the leveldb docs explicitly say that it's okay to share across threads
@@ -182,6 +184,7 @@ impl Stream for Reader {
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
         self.delete_acked();
+        self.report_usage();
 
         // If there's no value at read_offset, we return NotReady and rely on Writer
         // using write_notifier to wake this task up after the next write.
@@ -227,6 +230,21 @@ impl Drop for Reader {
 }
 
 impl Reader {
+    // Events and bytes that are written to disk but not yet acked by the sink.
+    fn report_usage(&self) {
+        let events = self
+            .write_offset
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.delete_offset);
+        let bytes = self.current_size.load(Ordering::Relaxed);
+
+        trace!(
+            buffer_events_gauge = events as u64,
+            buffer_bytes_gauge = bytes as u64,
+            scope = %self.sink_name
+        );
+    }
+
     fn delete_acked(&mut self) {
         let num_to_delete = self.ack_counter.swap(0, Ordering::Relaxed);
 
@@ -263,6 +281,7 @@ pub fn open(
     data_dir: &Path,
     buffer_dir: &Path,
     max_size: usize,
+    sink_name: &str,
 ) -> Result<(Writer, Reader, super::Acker), Error> {
     let path = data_dir.join(buffer_dir);
 
@@ -317,11 +336,13 @@ pub fn open(
     let ack_counter = Arc::new(AtomicUsize::new(0));
     let acker = super::Acker::Disk(Arc::clone(&ack_counter), Arc::clone(&write_notifier));
 
+    let write_offset = Arc::new(AtomicUsize::new(tail));
+
     let writer = Writer {
         db: Arc::clone(&db),
         write_notifier: Arc::clone(&write_notifier),
         blocked_write_tasks: Arc::clone(&blocked_write_tasks),
-        offset: Arc::new(AtomicUsize::new(tail)),
+        offset: Arc::clone(&write_offset),
         writebatch: Writebatch::new(),
         batch_size: 0,
         max_size,
@@ -333,9 +354,11 @@ pub fn open(
         blocked_write_tasks,
         read_offset: head,
         delete_offset: head,
+        write_offset,
         current_size,
         ack_counter,
         unacked_sizes: VecDeque::new(),
+        sink_name: sink_name.to_string(),
     };
 
     Ok((writer, reader, acker))
@@ -1,11 +1,14 @@
 use crate::Event;
-use futures::{sync::mpsc, task::AtomicTask, AsyncSink, Poll, Sink, StartSend, Stream};
+use futures::{
+    sync::mpsc, task::AtomicTask, try_ready, Async, AsyncSink, Poll, Sink, StartSend, Stream,
+};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc,
 };
+use std::time::Instant;
 
 #[cfg(feature = "leveldb")]
 mod disk;
@@ -48,31 +51,40 @@ impl Default for WhenFull {
 }
 
 pub enum BufferInputCloner {
-    Memory(mpsc::Sender<Event>, WhenFull),
+    Memory(mpsc::Sender<Event>, WhenFull, Option<BufferMetrics>),
     #[cfg(feature = "leveldb")]
-    Disk(disk::Writer, WhenFull),
+    Disk(disk::Writer, WhenFull, BufferMetrics),
 }
 
 impl BufferInputCloner {
     pub fn get(&self) -> Box<dyn Sink<SinkItem = Event, SinkError = ()> + Send> {
         match self {
-            BufferInputCloner::Memory(tx, when_full) => {
+            BufferInputCloner::Memory(tx, when_full, None) => {
+                let inner = tx.clone().sink_map_err(|e| error!("sender error: {:?}", e));
+                if when_full == &WhenFull::DropNewest {
+                    Box::new(DropWhenFull::new(inner, None))
+                } else {
+                    Box::new(inner)
+                }
+            }
+
+            BufferInputCloner::Memory(tx, when_full, Some(metrics)) => {
                 let inner = tx.clone().sink_map_err(|e| error!("sender error: {:?}", e));
+                let inner = MeteredSender::new(inner, *when_full, metrics.clone());
                 if when_full == &WhenFull::DropNewest {
-                    Box::new(DropWhenFull { inner })
+                    Box::new(DropWhenFull::new(inner, Some(metrics.clone())))
                 } else {
                     Box::new(inner)
                 }
             }
 
             #[cfg(feature = "leveldb")]
-            BufferInputCloner::Disk(writer, when_full) => {
+            BufferInputCloner::Disk(writer, when_full, metrics) => {
+                let inner = MeteredSender::new(writer.clone(), *when_full, metrics.clone());
                 if when_full == &WhenFull::DropNewest {
-                    Box::new(DropWhenFull {
-                        inner: writer.clone(),
-                    })
+                    Box::new(DropWhenFull::new(inner, Some(metrics.clone())))
                 } else {
-                    Box::new(writer.clone())
+                    Box::new(inner)
                 }
             }
         }
@@ -98,9 +110,13 @@ impl BufferConfig {
                 num_items,
                 when_full,
             } => {
+                let metrics = BufferMetrics::new(sink_name, true);
                 let (tx, rx) = mpsc::channel(*num_items);
-                let tx = BufferInputCloner::Memory(tx, *when_full);
-                let rx = Box::new(rx);
+                let rx = Box::new(MeteredReceiver {
+                    inner: rx,
+                    metrics: metrics.clone(),
+                });
+                let tx = BufferInputCloner::Memory(tx, *when_full, Some(metrics));
                 Ok((tx, rx, Acker::Null))
             }
 
@@ -114,9 +130,11 @@ impl BufferConfig {
                     .ok_or_else(|| "Must set data_dir to use on-disk buffering.".to_string())?;
                 let buffer_dir = format!("{}_buffer", sink_name);
 
-                let (tx, rx, acker) = disk::open(&data_dir, buffer_dir.as_ref(), *max_size)
-                    .map_err(|err| err.to_string())?;
-                let tx = BufferInputCloner::Disk(tx, *when_full);
+                let (tx, rx, acker) =
+                    disk::open(&data_dir, buffer_dir.as_ref(), *max_size, sink_name)
+                        .map_err(|err| err.to_string())?;
+                let metrics = BufferMetrics::new(sink_name, false);
+                let tx = BufferInputCloner::Disk(tx, *when_full, metrics);
                 let rx = Box::new(rx);
                 Ok((tx, rx, acker))
             }
@@ -155,8 +173,148 @@ impl Acker {
     }
 }
 
+/// Per sink buffer usage, reported through the internal metrics subscriber
+/// with the sink name as the metric scope.
+#[derive(Clone)]
+pub struct BufferMetrics {
+    sink_name: Arc<String>,
+    // Only tracked for memory buffers, the disk buffer reports its own usage.
+    buffered_events: Option<Arc<AtomicUsize>>,
+}
+
+impl BufferMetrics {
+    fn new(sink_name: &str, track_events: bool) -> Self {
+        Self {
+            sink_name: Arc::new(sink_name.to_string()),
+            buffered_events: if track_events {
+                Some(Arc::new(AtomicUsize::new(0)))
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Counts an event about to be written, so the count can't go below
+    /// zero when the receiver reads it right away.
+    fn event_adding(&self) {
+        if let Some(buffered_events) = &self.buffered_events {
+            buffered_events.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn event_added(&self) {
+        if let Some(buffered_events) = &self.buffered_events {
+            let count = buffered_events.load(Ordering::Relaxed);
+            trace!(buffer_events_gauge = count as u64, scope = %self.sink_name);
+        }
+    }
+
+    fn event_not_added(&self) {
+        if let Some(buffered_events) = &self.buffered_events {
+            buffered_events.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    fn event_removed(&self) {
+        if let Some(buffered_events) = &self.buffered_events {
+            let count = buffered_events.fetch_sub(1, Ordering::Relaxed) - 1;
+            trace!(buffer_events_gauge = count as u64, scope = %self.sink_name);
+        }
+    }
+
+    fn event_dropped(&self) {
+        trace!(buffer_dropped_events_counter = 1u64, scope = %self.sink_name);
+    }
+
+    fn unblocked(&self, since: Instant) {
+        let elapsed = since.elapsed();
+        let nanos = elapsed.as_secs() * 1_000_000_000 + u64::from(elapsed.subsec_nanos());
+        trace!(buffer_blocked_nanoseconds_counter = nanos, scope = %self.sink_name);
+    }
+}
+
+/// Wraps the input side of a buffer to count the events written to it and,
+/// for `WhenFull::Block`, the time spent waiting for room in the buffer.
+pub struct MeteredSender<S> {
+    inner: S,
+    when_full: WhenFull,
+    metrics: BufferMetrics,
+    blocked_since: Option<Instant>,
+}
+
+impl<S> MeteredSender<S> {
+    fn new(inner: S, when_full: WhenFull, metrics: BufferMetrics) -> Self {
+        Self {
+            inner,
+            when_full,
+            metrics,
+            blocked_since: None,
+        }
+    }
+}
+
+impl<S: Sink> Sink for MeteredSender<S> {
+    type SinkItem = S::SinkItem;
+    type SinkError = S::SinkError;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        // Counted first, the receiver may read the event before it returns
+        self.metrics.event_adding();
+        match self.inner.start_send(item) {
+            Ok(AsyncSink::Ready) => {
+                if let Some(since) = self.blocked_since.take() {
+                    self.metrics.unblocked(since);
+                }
+                self.metrics.event_added();
+                Ok(AsyncSink::Ready)
+            }
+            Ok(AsyncSink::NotReady(item)) => {
+                self.metrics.event_not_added();
+                if self.when_full == WhenFull::Block && self.blocked_since.is_none() {
+                    self.blocked_since = Some(Instant::now());
+                }
+                Ok(AsyncSink::NotReady(item))
+            }
+            Err(error) => {
+                self.metrics.event_not_added();
+                Err(error)
+            }
+        }
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        self.inner.poll_complete()
+    }
+}
+
+/// Wraps the output side of a memory buffer to count the events read from it.
+pub struct MeteredReceiver<S> {
+    inner: S,
+    metrics: BufferMetrics,
+}
+
+impl<S: Stream> Stream for MeteredReceiver<S> {
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let item = try_ready!(self.inner.poll());
+        if item.is_some() {
+            self.metrics.event_removed();
+        }
+        Ok(Async::Ready(item))
+    }
+}
+
 pub struct DropWhenFull<S> {
     inner: S,
+    metrics: Option<BufferMetrics>,
+}
+
+impl<S> DropWhenFull<S> {
+    fn new(inner: S, metrics: Option<BufferMetrics>) -> Self {
+        Self { inner, metrics }
+    }
 }
 
 impl<S: Sink> Sink for DropWhenFull<S> {
@@ -170,6 +328,9 @@ impl<S: Sink> Sink for DropWhenFull<S> {
                     message = "Shedding load; dropping event.",
                     rate_limit_secs = 10
                 );
+                if let Some(metrics) = &self.metrics {
+                    metrics.event_dropped();
+                }
                 Ok(AsyncSink::Ready)
             }
             other => other,
@@ -183,16 +344,17 @@ impl<S: Sink> Sink for DropWhenFull<S> {
 
 #[cfg(test)]
 mod test {
-    use super::DropWhenFull;
+    use super::{BufferMetrics, DropWhenFull, MeteredReceiver, MeteredSender, WhenFull};
     use crate::test_util::block_on;
     use futures::{future, sync::mpsc, Async, AsyncSink, Sink, Stream};
+    use std::sync::atomic::Ordering;
 
     #[test]
     fn drop_when_full() {
         block_on::<_, _, ()>(future::lazy(|| {
             let (tx, mut rx) = mpsc::channel(2);
 
-            let mut tx = DropWhenFull { inner: tx };
+            let mut tx = DropWhenFull::new(tx, None);
 
             assert_eq!(tx.start_send(1), Ok(AsyncSink::Ready));
             assert_eq!(tx.start_send(2), Ok(AsyncSink::Ready));
@@ -208,4 +370,46 @@ mod test {
         }))
         .unwrap();
     }
+
+    #[test]
+    fn metered_memory_buffer_counts_events() {
+        block_on::<_, _, ()>(future::lazy(|| {
+            let metrics = BufferMetrics::new("test", true);
+            let (tx, rx) = mpsc::channel(1);
+
+            let mut tx = MeteredSender::new(tx, WhenFull::Block, metrics.clone());
+            let mut rx = MeteredReceiver {
+                inner: rx,
+                metrics: metrics.clone(),
+            };
+            let buffered = metrics.buffered_events.clone().unwrap();
+
+            assert_eq!(tx.start_send(1), Ok(AsyncSink::Ready));
+            assert_eq!(tx.start_send(2), Ok(AsyncSink::Ready));
+            assert_eq!(tx.start_send(3), Ok(AsyncSink::NotReady(3)));
+            assert_eq!(buffered.load(Ordering::Relaxed), 2);
+            assert!(tx.blocked_since.is_some());
+
+            assert_eq!(rx.poll(), Ok(Async::Ready(Some(1))));
+            assert_eq!(buffered.load(Ordering::Relaxed), 1);
+
+            assert_eq!(tx.start_send(3), Ok(AsyncSink::Ready));
+            assert_eq!(buffered.load(Ordering::Relaxed), 2);
+            assert!(tx.blocked_since.is_none());
+
+            future::ok(())
+        }))
+        .unwrap();
+    }
+
+    #[test]
+    fn metered_memory_buffer_counts_events_read_right_away() {
+        let metrics = BufferMetrics::new("test", true);
+        let buffered = metrics.buffered_events.clone().unwrap();
+
+        metrics.event_adding();
+        metrics.event_removed();
+        metrics.event_added();
+        assert_eq!(buffered.load(Ordering::Relaxed), 0);
+    }
 }
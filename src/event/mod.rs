@@ -90,6 +90,10 @@ impl LogEvent {
         self.fields.get(key).map(|v| &v.value)
     }
 
+    pub fn get_mut(&mut self, key: &Atom) -> Option<&mut ValueKind> {
+        self.fields.get_mut(key).map(|v| &mut v.value)
+    }
+
     pub fn into_value(mut self, key: &Atom) -> Option<ValueKind> {
         self.fields.remove(key).map(|v| v.value)
     }
@@ -160,13 +160,7 @@ fn main() {
         Color::Never => false,
     };
 
-    let (metrics_controller, metrics_sink) = metrics::build();
-
-    trace::init(
-        color,
-        levels.as_str(),
-        opts.metrics_addr.map(|_| metrics_sink),
-    );
+    trace::init(color, levels.as_str());
 
     sub_command.map(|s| {
         std::process::exit(match s {
@@ -220,7 +214,7 @@ fn main() {
         debug!("Starting metrics server");
 
         rt.spawn(
-            metrics::serve(&metrics_addr, metrics_controller)
+            metrics::serve(&metrics_addr, metrics::controller())
                 .instrument(info_span!("metrics", addr = ?metrics_addr))
                 .select(metrics_tripwire)
                 .map(|_| ())
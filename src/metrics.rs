@@ -8,10 +8,15 @@ use hyper::{
     service::{make_service_fn, service_fn_ok},
     Body, Request, Response, Server,
 };
-use std::net::SocketAddr;
-use tracing::field;
+use lazy_static::lazy_static;
+use std::{net::SocketAddr, sync::RwLock};
+use tracing::{callsite, field};
 
-pub type Sink = hotmic::Sink<&'static str>;
+pub type Sink = hotmic::Sink<String>;
+
+lazy_static! {
+    static ref RECORDING: RwLock<Option<(Controller, Sink)>> = RwLock::new(None);
+}
 
 /// Build the metrics receiver, controller and sink
 pub fn build() -> (Controller, Sink) {
@@ -26,6 +31,33 @@ pub fn build() -> (Controller, Sink) {
     (controller, sink)
 }
 
+/// The controller of Vector's own metrics, for the metrics server and the
+/// `internal_metrics` source. Recording metrics enables every span, so they
+/// are only recorded from the first call on.
+pub fn controller() -> Controller {
+    if let Some((controller, _)) = &*RECORDING.read().unwrap() {
+        return controller.clone();
+    }
+
+    let controller = {
+        let mut recording = RECORDING.write().unwrap();
+        recording.get_or_insert_with(build).0.clone()
+    };
+
+    // Callsites registered so far were left to the logging subscriber.
+    callsite::rebuild_interest_cache();
+    controller
+}
+
+/// The sink metrics are recorded to, once `controller` has been called
+pub fn sink() -> Option<Sink> {
+    RECORDING
+        .read()
+        .unwrap()
+        .as_ref()
+        .map(|(_, sink)| sink.clone())
+}
+
 /// Serve the metrics server via the address from the metrics controller
 pub fn serve(addr: &SocketAddr, controller: Controller) -> impl Future<Item = (), Error = ()> {
     let make_svc = make_service_fn(move |addr_stream: &AddrStream| {
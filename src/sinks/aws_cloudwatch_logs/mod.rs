@@ -5,18 +5,21 @@ use crate::{
     event::{self, Event, LogEvent, ValueKind},
     region::RegionOrEndpoint,
     sinks::util::{
-        retries::{FixedRetryPolicy, RetryLogic},
-        BatchServiceSink, PartitionBuffer, PartitionInnerBuffer, SinkExt,
+        adaptive_concurrency::{
+            AdaptiveConcurrencyLimit, AdaptiveConcurrencyLimitLayer, InFlightLimit,
+        },
+        encoding::EncodingConfig,
+        proxy::{ProxyConfig, ProxySettings},
+        retries::{RetryLogic, RetryPolicy},
+        rusoto, BatchServiceSink, ByteSize, PartitionBuffer, PartitionInnerBuffer, SinkExt,
+        VecBuffer,
     },
     template::Template,
     topology::config::{DataType, SinkConfig},
 };
 use bytes::Bytes;
 use futures::{future, stream::iter_ok, sync::oneshot, Async, Future, Poll, Sink};
-use rusoto_core::{
-    request::{BufferedHttpResponse, HttpClient},
-    Region, RusotoError,
-};
+use rusoto_core::{request::BufferedHttpResponse, Region, RusotoError};
 use rusoto_credential::DefaultCredentialsProvider;
 use rusoto_logs::{
     CloudWatchLogs, CloudWatchLogsClient, CreateLogGroupError, CreateLogStreamError,
@@ -27,21 +30,18 @@ use snafu::{ResultExt, Snafu};
 use std::{collections::HashMap, convert::TryInto, fmt, time::Duration};
 use tower::{
     buffer::Buffer,
+    layer::Layer,
     limit::{
         concurrency::ConcurrencyLimit,
         rate::{Rate, RateLimit},
     },
     retry::Retry,
     timeout::Timeout,
-    Service, ServiceBuilder, ServiceExt,
+    Service, ServiceExt,
 };
 
 #[derive(Debug, Snafu)]
 enum BuildError {
-    #[snafu(display("{}", source))]
-    HttpClientError {
-        source: rusoto_core::request::TlsError,
-    },
     #[snafu(display("{}", source))]
     InvalidCloudwatchCredentials {
         source: rusoto_core::CredentialsError,
@@ -55,24 +55,34 @@ pub struct CloudwatchLogsSinkConfig {
     pub stream_name: Template,
     #[serde(flatten)]
     pub region: RegionOrEndpoint,
-    pub encoding: Encoding,
+    pub encoding: EncodingConfig<Encoding>,
     pub create_missing_group: Option<bool>,
     pub create_missing_stream: Option<bool>,
     pub batch_timeout: Option<u64>,
+    /// The maximum number of events in a batch.
     pub batch_size: Option<usize>,
+    pub batch_max_bytes: Option<usize>,
 
     // Tower Request based configuration
-    pub request_in_flight_limit: Option<usize>,
+    pub request_in_flight_limit: Option<InFlightLimit>,
     pub request_timeout_secs: Option<u64>,
     pub request_rate_limit_duration_secs: Option<u64>,
     pub request_rate_limit_num: Option<u64>,
     pub request_retry_attempts: Option<usize>,
     pub request_retry_backoff_secs: Option<u64>,
+    pub request_retry_max_backoff_secs: Option<u64>,
+    pub request_retry_max_duration_secs: Option<u64>,
+
+    #[serde(default)]
+    pub proxy: ProxyConfig,
 }
 
+// https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_PutLogEvents.html
+const MAX_BATCH_SIZE: usize = 1_048_576;
+const MAX_BATCH_EVENTS: usize = 10_000;
+
 pub struct CloudwatchLogsSvc {
     client: CloudWatchLogsClient,
-    encoding: Encoding,
     stream_name: String,
     group_name: String,
     create_missing_group: bool,
@@ -85,18 +95,23 @@ type Svc = Buffer<
     ConcurrencyLimit<
         RateLimit<
             Retry<
-                FixedRetryPolicy<CloudwatchRetryLogic>,
-                Buffer<Timeout<CloudwatchLogsSvc>, Vec<Event>>,
+                RetryPolicy<CloudwatchRetryLogic>,
+                AdaptiveConcurrencyLimit<
+                    Buffer<Timeout<CloudwatchLogsSvc>, Vec<InputLogEvent>>,
+                    CloudwatchRetryLogic,
+                >,
             >,
         >,
     >,
-    Vec<Event>,
+    Vec<InputLogEvent>,
 >;
 
 pub struct CloudwatchLogsPartitionSvc {
     config: CloudwatchLogsSinkConfig,
     clients: HashMap<CloudwatchKey, Svc>,
     request_config: RequestConfig,
+    // Shared by the clients of all partitions
+    in_flight_limit: AdaptiveConcurrencyLimitLayer<CloudwatchRetryLogic>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone, Derivative)]
@@ -115,6 +130,8 @@ pub struct RequestConfig {
     rate_limit_num: u64,
     retry_attempts: usize,
     retry_backoff_secs: u64,
+    retry_max_backoff_secs: u64,
+    retry_max_duration: Option<Duration>,
 }
 
 #[derive(Debug)]
@@ -132,25 +149,33 @@ pub enum CloudwatchError {
 impl SinkConfig for CloudwatchLogsSinkConfig {
     fn build(&self, acker: Acker) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
         let batch_timeout = self.batch_timeout.unwrap_or(1);
-        let batch_size = self.batch_size.unwrap_or(1000);
+        let batch_size = self.batch_size.unwrap_or(1000).min(MAX_BATCH_EVENTS);
+        let batch_max_bytes = self
+            .batch_max_bytes
+            .unwrap_or(MAX_BATCH_SIZE)
+            .min(MAX_BATCH_SIZE);
 
         let log_group = self.group_name.clone();
         let log_stream = self.stream_name.clone();
+        let encoding = self.encoding.clone();
 
-        let in_flight_limit = self.request_in_flight_limit.unwrap_or(5);
-
-        let svc = ServiceBuilder::new()
-            .concurrency_limit(in_flight_limit)
-            .service(CloudwatchLogsPartitionSvc::new(self.clone())?);
+        let svc = CloudwatchLogsPartitionSvc::new(self.clone())?;
 
         let sink = {
             let svc_sink = BatchServiceSink::new(svc, acker)
                 .partitioned_batched_with_min(
-                    PartitionBuffer::new(Vec::new()),
-                    batch_size,
+                    PartitionBuffer::new(VecBuffer::new()),
+                    batch_max_bytes,
                     Duration::from_secs(batch_timeout),
                 )
-                .with_flat_map(move |event| iter_ok(partition(event, &log_group, &log_stream)));
+                .max_events(batch_size)
+                .with_flat_map(move |event| {
+                    let encoded = partition(event, &log_group, &log_stream).map(|req| {
+                        let (event, key) = req.into_parts();
+                        PartitionInnerBuffer::new(encode_log(event.into_log(), &encoding), key)
+                    });
+                    iter_ok(encoded)
+                });
             Box::new(svc_sink)
         };
 
@@ -171,6 +196,10 @@ impl CloudwatchLogsPartitionSvc {
         let rate_limit_num = config.request_rate_limit_num.unwrap_or(5);
         let retry_attempts = config.request_retry_attempts.unwrap_or(usize::max_value());
         let retry_backoff_secs = config.request_retry_backoff_secs.unwrap_or(1);
+        let retry_max_backoff_secs = config.request_retry_max_backoff_secs.unwrap_or(10);
+        let retry_max_duration = config
+            .request_retry_max_duration_secs
+            .map(Duration::from_secs);
 
         let request_config = RequestConfig {
             timeout_secs,
@@ -178,17 +207,29 @@ impl CloudwatchLogsPartitionSvc {
             rate_limit_num,
             retry_attempts,
             retry_backoff_secs,
+            retry_max_backoff_secs,
+            retry_max_duration,
         };
 
+        let in_flight_limit = AdaptiveConcurrencyLimitLayer::new(
+            config
+                .request_in_flight_limit
+                .unwrap_or(InFlightLimit::Fixed(5)),
+            CloudwatchRetryLogic,
+        );
+
         Ok(Self {
             config,
             clients: HashMap::new(),
             request_config,
+            in_flight_limit,
         })
     }
 }
 
-impl Service<PartitionInnerBuffer<Vec<Event>, CloudwatchKey>> for CloudwatchLogsPartitionSvc {
+impl Service<PartitionInnerBuffer<Vec<InputLogEvent>, CloudwatchKey>>
+    for CloudwatchLogsPartitionSvc
+{
     type Response = ();
     type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
     type Future = Box<dyn Future<Item = Self::Response, Error = Self::Error> + Send + 'static>;
@@ -197,7 +238,10 @@ impl Service<PartitionInnerBuffer<Vec<Event>, CloudwatchKey>> for CloudwatchLogs
         Ok(().into())
     }
 
-    fn call(&mut self, req: PartitionInnerBuffer<Vec<Event>, CloudwatchKey>) -> Self::Future {
+    fn call(
+        &mut self,
+        req: PartitionInnerBuffer<Vec<InputLogEvent>, CloudwatchKey>,
+    ) -> Self::Future {
         let (events, key) = req.into_parts();
 
         let RequestConfig {
@@ -206,23 +250,28 @@ impl Service<PartitionInnerBuffer<Vec<Event>, CloudwatchKey>> for CloudwatchLogs
             rate_limit_num,
             retry_attempts,
             retry_backoff_secs,
+            retry_max_backoff_secs,
+            retry_max_duration,
         } = self.request_config;
 
         let svc = if let Some(svc) = &mut self.clients.get_mut(&key) {
             svc.clone()
         } else {
             let svc = {
-                let policy = FixedRetryPolicy::new(
+                let policy = RetryPolicy::new(
                     retry_attempts,
                     Duration::from_secs(retry_backoff_secs),
                     CloudwatchRetryLogic,
-                );
+                )
+                .max_backoff(Duration::from_secs(retry_max_backoff_secs))
+                .max_duration(retry_max_duration);
 
                 let cloudwatch = CloudwatchLogsSvc::new(&self.config, &key).unwrap();
                 let timeout = Timeout::new(cloudwatch, Duration::from_secs(timeout_secs));
 
                 let buffer = Buffer::new(timeout, 1);
-                let retry = Retry::new(policy, buffer);
+                let limit = self.in_flight_limit.layer(buffer);
+                let retry = Retry::new(policy, limit);
 
                 let rate = RateLimit::new(
                     retry,
@@ -253,7 +302,7 @@ impl Service<PartitionInnerBuffer<Vec<Event>, CloudwatchKey>> for CloudwatchLogs
 impl CloudwatchLogsSvc {
     pub fn new(config: &CloudwatchLogsSinkConfig, key: &CloudwatchKey) -> crate::Result<Self> {
         let region = config.region.clone().try_into()?;
-        let client = create_client(region)?;
+        let client = create_client(region, &config.proxy)?;
 
         let group_name = String::from_utf8_lossy(&key.group[..]).into_owned();
         let stream_name = String::from_utf8_lossy(&key.stream[..]).into_owned();
@@ -263,7 +312,6 @@ impl CloudwatchLogsSvc {
 
         Ok(CloudwatchLogsSvc {
             client,
-            encoding: config.encoding.clone(),
             stream_name,
             group_name,
             create_missing_group,
@@ -272,31 +320,9 @@ impl CloudwatchLogsSvc {
             token_rx: None,
         })
     }
-
-    pub fn encode_log(&self, mut log: LogEvent) -> InputLogEvent {
-        let timestamp = if let Some(ValueKind::Timestamp(ts)) = log.remove(&event::TIMESTAMP) {
-            ts.timestamp_millis()
-        } else {
-            chrono::Utc::now().timestamp_millis()
-        };
-
-        match self.encoding {
-            Encoding::Json => {
-                let message = serde_json::to_string(&log.unflatten()).unwrap();
-                InputLogEvent { message, timestamp }
-            }
-            Encoding::Text => {
-                let message = log
-                    .get(&event::MESSAGE)
-                    .map(|v| v.to_string_lossy())
-                    .unwrap_or_else(|| "".into());
-                InputLogEvent { message, timestamp }
-            }
-        }
-    }
 }
 
-impl Service<Vec<Event>> for CloudwatchLogsSvc {
+impl Service<Vec<InputLogEvent>> for CloudwatchLogsSvc {
     type Response = ();
     type Error = CloudwatchError;
     type Future = request::CloudwatchFuture;
@@ -323,14 +349,8 @@ impl Service<Vec<Event>> for CloudwatchLogsSvc {
         }
     }
 
-    fn call(&mut self, req: Vec<Event>) -> Self::Future {
+    fn call(&mut self, events: Vec<InputLogEvent>) -> Self::Future {
         if self.token_rx.is_none() {
-            let events = req
-                .into_iter()
-                .map(|e| e.into_log())
-                .map(|e| self.encode_log(e))
-                .collect::<Vec<_>>();
-
             let (tx, rx) = oneshot::channel();
             self.token_rx = Some(rx);
 
@@ -351,6 +371,39 @@ impl Service<Vec<Event>> for CloudwatchLogsSvc {
     }
 }
 
+fn encode_log(mut log: LogEvent, encoding: &EncodingConfig<Encoding>) -> InputLogEvent {
+    let timestamp = if let Some(ValueKind::Timestamp(ts)) = log.remove(&event::TIMESTAMP) {
+        ts.timestamp_millis()
+    } else {
+        chrono::Utc::now().timestamp_millis()
+    };
+
+    let mut event = Event::from(log);
+    encoding.apply_rules(&mut event);
+    let log = event.into_log();
+
+    match encoding.codec {
+        Encoding::Json => {
+            let message = serde_json::to_string(&log.unflatten()).unwrap();
+            InputLogEvent { message, timestamp }
+        }
+        Encoding::Text => {
+            let message = log
+                .get(&event::MESSAGE)
+                .map(|v| v.to_string_lossy())
+                .unwrap_or_else(|| "".into());
+            InputLogEvent { message, timestamp }
+        }
+    }
+}
+
+impl ByteSize for InputLogEvent {
+    // The size of a log event is counted as its message plus 26 bytes.
+    fn byte_size(&self) -> usize {
+        self.message.len() + 26
+    }
+}
+
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct CloudwatchKey {
     group: Bytes,
@@ -416,7 +469,7 @@ fn healthcheck(config: CloudwatchLogsSinkConfig) -> crate::Result<super::Healthc
 
     let group_name = String::from_utf8_lossy(&config.group_name.get_ref()[..]).into_owned();
 
-    let client = create_client(config.region.clone().try_into()?)?;
+    let client = create_client(config.region.clone().try_into()?, &config.proxy)?;
 
     let request = DescribeLogGroupsRequest {
         limit: Some(1),
@@ -459,8 +512,8 @@ fn healthcheck(config: CloudwatchLogsSinkConfig) -> crate::Result<super::Healthc
     Ok(Box::new(fut))
 }
 
-fn create_client(region: Region) -> crate::Result<CloudWatchLogsClient> {
-    let http = HttpClient::new().context(HttpClientError)?;
+fn create_client(region: Region, proxy: &ProxyConfig) -> crate::Result<CloudWatchLogsClient> {
+    let http = rusoto::client(ProxySettings::from_config(proxy)?)?;
     let creds = DefaultCredentialsProvider::new().context(InvalidCloudwatchCredentials)?;
 
     Ok(CloudWatchLogsClient::new_with(http, creds, region))
@@ -594,10 +647,7 @@ impl From<RusotoError<DescribeLogStreamsError>> for CloudwatchError {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{
-        event::{self, Event, ValueKind},
-        region::RegionOrEndpoint,
-    };
+    use crate::event::{self, Event, ValueKind};
     use std::collections::HashMap;
     use string_cache::DefaultAtom as Atom;
 
@@ -692,23 +742,11 @@ mod tests {
         assert!(stream_val.is_none());
     }
 
-    fn svc(config: CloudwatchLogsSinkConfig) -> CloudwatchLogsSvc {
-        let config = CloudwatchLogsSinkConfig {
-            region: RegionOrEndpoint::with_endpoint("http://localhost:6000".into()),
-            ..config
-        };
-        let key = CloudwatchKey {
-            stream: "stream".into(),
-            group: "group".into(),
-        };
-        CloudwatchLogsSvc::new(&config, &key).unwrap()
-    }
-
     #[test]
     fn cloudwatch_encoded_event_retains_timestamp() {
         let mut event = Event::from("hello world").into_log();
         event.insert_explicit("key".into(), "value".into());
-        let encoded = svc(Default::default()).encode_log(event.clone());
+        let encoded = encode_log(event.clone(), &Default::default());
 
         let ts = if let ValueKind::Timestamp(ts) = event[&event::TIMESTAMP] {
             ts.timestamp_millis()
@@ -722,12 +760,12 @@ mod tests {
     #[test]
     fn cloudwatch_encode_log_as_json() {
         let config = CloudwatchLogsSinkConfig {
-            encoding: Encoding::Json,
+            encoding: Encoding::Json.into(),
             ..Default::default()
         };
         let mut event = Event::from("hello world").into_log();
         event.insert_implicit("key".into(), "value".into());
-        let encoded = svc(config).encode_log(event.clone());
+        let encoded = encode_log(event.clone(), &config.encoding);
         let map: HashMap<Atom, String> = serde_json::from_str(&encoded.message[..]).unwrap();
         assert!(map.get(&event::TIMESTAMP).is_none());
     }
@@ -735,12 +773,12 @@ mod tests {
     #[test]
     fn cloudwatch_encode_log_as_text() {
         let config = CloudwatchLogsSinkConfig {
-            encoding: Encoding::Text,
+            encoding: Encoding::Text.into(),
             ..Default::default()
         };
         let mut event = Event::from("hello world").into_log();
         event.insert_explicit("key".into(), "value".into());
-        let encoded = svc(config).encode_log(event.clone());
+        let encoded = encode_log(event.clone(), &config.encoding);
         assert_eq!(encoded.message, "hello world");
     }
 }
@@ -798,7 +836,7 @@ mod integration_tests {
         request.log_group_name = GROUP_NAME.into();
         request.start_time = Some(timestamp.timestamp_millis());
 
-        let client = create_client(region).unwrap();
+        let client = create_client(region, &Default::default()).unwrap();
 
         let response = rt.block_on(client.get_log_events(request)).unwrap();
 
@@ -847,7 +885,7 @@ mod integration_tests {
         request.log_group_name = group_name;
         request.start_time = Some(timestamp.timestamp_millis());
 
-        let client = create_client(region).unwrap();
+        let client = create_client(region, &Default::default()).unwrap();
 
         let response = rt.block_on(client.get_log_events(request)).unwrap();
 
@@ -898,7 +936,7 @@ mod integration_tests {
         request.log_group_name = group_name.into();
         request.start_time = Some(timestamp.timestamp_millis());
 
-        let client = create_client(region).unwrap();
+        let client = create_client(region, &Default::default()).unwrap();
 
         let response = rt.block_on(client.get_log_events(request)).unwrap();
 
@@ -923,7 +961,7 @@ mod integration_tests {
             endpoint: "http://localhost:6000".into(),
         };
 
-        let client = create_client(region.clone()).unwrap();
+        let client = create_client(region.clone(), &Default::default()).unwrap();
         ensure_group(region);
 
         let config = CloudwatchLogsSinkConfig {
@@ -1020,7 +1058,7 @@ mod integration_tests {
     }
 
     fn ensure_group(region: Region) {
-        let client = create_client(region).unwrap();
+        let client = create_client(region, &Default::default()).unwrap();
 
         let req = CreateLogGroupRequest {
             log_group_name: GROUP_NAME.into(),
@@ -3,8 +3,10 @@ use crate::{
     event::Metric,
     region::RegionOrEndpoint,
     sinks::util::{
-        retries::{FixedRetryPolicy, RetryLogic},
-        BatchServiceSink, MetricBuffer, SinkExt,
+        adaptive_concurrency::{AdaptiveConcurrencyLimitLayer, InFlightLimit},
+        proxy::{ProxyConfig, ProxySettings},
+        retries::{RetryLogic, RetryPolicy},
+        rusoto, BatchServiceSink, MetricBuffer, SinkExt,
     },
     topology::config::{DataType, SinkConfig},
 };
@@ -14,6 +16,7 @@ use rusoto_cloudwatch::{
     CloudWatch, CloudWatchClient, Dimension, MetricDatum, PutMetricDataError, PutMetricDataInput,
 };
 use rusoto_core::{Region, RusotoError, RusotoFuture};
+use rusoto_credential::DefaultCredentialsProvider;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::{convert::TryInto, time::Duration};
@@ -35,12 +38,17 @@ pub struct CloudWatchMetricsSinkConfig {
     pub batch_timeout: Option<u64>,
 
     // Tower Request based configuration
-    pub request_in_flight_limit: Option<usize>,
+    pub request_in_flight_limit: Option<InFlightLimit>,
     pub request_timeout_secs: Option<u64>,
     pub request_rate_limit_duration_secs: Option<u64>,
     pub request_rate_limit_num: Option<u64>,
     pub request_retry_attempts: Option<usize>,
     pub request_retry_backoff_secs: Option<u64>,
+    pub request_retry_max_backoff_secs: Option<u64>,
+    pub request_retry_max_duration_secs: Option<u64>,
+
+    #[serde(default)]
+    pub proxy: ProxyConfig,
 }
 
 #[typetag::serde(name = "aws_cloudwatch_metrics")]
@@ -61,30 +69,41 @@ impl CloudWatchMetricsSvc {
         config: CloudWatchMetricsSinkConfig,
         acker: Acker,
     ) -> crate::Result<super::RouterSink> {
-        let client = Self::create_client(config.region.clone().try_into()?)?;
+        let client = Self::create_client(config.region.clone().try_into()?, &config.proxy)?;
 
         let batch_size = config.batch_size.unwrap_or(20);
         let batch_timeout = config.batch_timeout.unwrap_or(1);
 
         let timeout = config.request_timeout_secs.unwrap_or(30);
-        let in_flight_limit = config.request_in_flight_limit.unwrap_or(5);
+        let in_flight_limit = config
+            .request_in_flight_limit
+            .unwrap_or(InFlightLimit::Fixed(5));
         let rate_limit_duration = config.request_rate_limit_duration_secs.unwrap_or(1);
         let rate_limit_num = config.request_rate_limit_num.unwrap_or(150);
         let retry_attempts = config.request_retry_attempts.unwrap_or(usize::max_value());
         let retry_backoff_secs = config.request_retry_backoff_secs.unwrap_or(1);
+        let retry_max_backoff_secs = config.request_retry_max_backoff_secs.unwrap_or(10);
+        let retry_max_duration = config
+            .request_retry_max_duration_secs
+            .map(Duration::from_secs);
 
-        let policy = FixedRetryPolicy::new(
+        let policy = RetryPolicy::new(
             retry_attempts,
             Duration::from_secs(retry_backoff_secs),
             CloudWatchMetricsRetryLogic,
-        );
+        )
+        .max_backoff(Duration::from_secs(retry_max_backoff_secs))
+        .max_duration(retry_max_duration);
 
         let cloudwatch_metrics = CloudWatchMetricsSvc { client, config };
 
         let svc = ServiceBuilder::new()
-            .concurrency_limit(in_flight_limit)
             .rate_limit(rate_limit_num, Duration::from_secs(rate_limit_duration))
             .retry(policy)
+            .layer(AdaptiveConcurrencyLimitLayer::new(
+                in_flight_limit,
+                CloudWatchMetricsRetryLogic,
+            ))
             .timeout(Duration::from_secs(timeout))
             .service(cloudwatch_metrics);
 
@@ -98,7 +117,7 @@ impl CloudWatchMetricsSvc {
     }
 
     fn healthcheck(config: &CloudWatchMetricsSinkConfig) -> crate::Result<super::Healthcheck> {
-        let client = Self::create_client(config.region.clone().try_into()?)?;
+        let client = Self::create_client(config.region.clone().try_into()?, &config.proxy)?;
 
         let datum = MetricDatum {
             metric_name: "healthcheck".into(),
@@ -116,24 +135,21 @@ impl CloudWatchMetricsSvc {
         Ok(Box::new(healthcheck))
     }
 
-    fn create_client(region: Region) -> crate::Result<CloudWatchClient> {
+    fn create_client(region: Region, proxy: &ProxyConfig) -> crate::Result<CloudWatchClient> {
+        // Moto (used for mocking AWS) doesn't recognize 'custom' as valid region name
         #[cfg(test)]
-        {
-            // Moto (used for mocking AWS) doesn't recognize 'custom' as valid region name
-            let region = match region {
-                Region::Custom { endpoint, .. } => Region::Custom {
-                    name: "us-east-1".into(),
-                    endpoint,
-                },
-                _ => panic!("Only Custom regions are supported for CloudWatchClient testing"),
-            };
-            Ok(CloudWatchClient::new(region))
-        }
+        let region = match region {
+            Region::Custom { endpoint, .. } => Region::Custom {
+                name: "us-east-1".into(),
+                endpoint,
+            },
+            _ => panic!("Only Custom regions are supported for CloudWatchClient testing"),
+        };
 
-        #[cfg(not(test))]
-        {
-            Ok(CloudWatchClient::new(region))
-        }
+        let http = rusoto::client(ProxySettings::from_config(proxy)?)?;
+        let creds = DefaultCredentialsProvider::new()?;
+
+        Ok(CloudWatchClient::new_with(http, creds, region))
     }
 
     fn encode_events(&mut self, events: Vec<Metric>) -> PutMetricDataInput {
@@ -269,7 +285,7 @@ mod tests {
     fn svc() -> CloudWatchMetricsSvc {
         let config = config();
         let region = config.region.clone().try_into().unwrap();
-        let client = CloudWatchMetricsSvc::create_client(region).unwrap();
+        let client = CloudWatchMetricsSvc::create_client(region, &config.proxy).unwrap();
 
         CloudWatchMetricsSvc { client, config }
     }
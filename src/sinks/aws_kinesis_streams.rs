@@ -3,15 +3,19 @@ use crate::{
     event::{self, Event},
     region::RegionOrEndpoint,
     sinks::util::{
-        retries::{FixedRetryPolicy, RetryLogic},
-        BatchServiceSink, SinkExt,
+        adaptive_concurrency::{AdaptiveConcurrencyLimitLayer, InFlightLimit},
+        encoding::EncodingConfig,
+        proxy::{ProxyConfig, ProxySettings},
+        retries::{RetryLogic, RetryPolicy},
+        rusoto, BatchServiceSink, ByteSize, SinkExt, VecBuffer,
     },
     topology::config::{DataType, SinkConfig},
 };
 use bytes::Bytes;
 use futures::{stream::iter_ok, Future, Poll, Sink};
 use rand::random;
-use rusoto_core::{RusotoError, RusotoFuture};
+use rusoto_core::{Region, RusotoError, RusotoFuture};
+use rusoto_credential::DefaultCredentialsProvider;
 use rusoto_kinesis::{
     Kinesis, KinesisClient, ListStreamsInput, PutRecordsError, PutRecordsInput, PutRecordsOutput,
     PutRecordsRequestEntry,
@@ -23,6 +27,10 @@ use string_cache::DefaultAtom as Atom;
 use tower::{Service, ServiceBuilder};
 use tracing_futures::{Instrument, Instrumented};
 
+// https://docs.aws.amazon.com/kinesis/latest/APIReference/API_PutRecords.html
+const MAX_BATCH_SIZE: usize = 5 * 1024 * 1024;
+const MAX_BATCH_EVENTS: usize = 500;
+
 #[derive(Clone)]
 pub struct KinesisService {
     client: Arc<KinesisClient>,
@@ -36,17 +44,24 @@ pub struct KinesisSinkConfig {
     pub partition_key_field: Option<Atom>,
     #[serde(flatten)]
     pub region: RegionOrEndpoint,
-    pub encoding: Encoding,
+    pub encoding: EncodingConfig<Encoding>,
+    /// The maximum number of events in a batch.
     pub batch_size: Option<usize>,
+    pub batch_max_bytes: Option<usize>,
     pub batch_timeout: Option<u64>,
 
     // Tower Request based configuration
-    pub request_in_flight_limit: Option<usize>,
+    pub request_in_flight_limit: Option<InFlightLimit>,
     pub request_timeout_secs: Option<u64>,
     pub request_rate_limit_duration_secs: Option<u64>,
     pub request_rate_limit_num: Option<u64>,
     pub request_retry_attempts: Option<usize>,
     pub request_retry_backoff_secs: Option<u64>,
+    pub request_retry_max_backoff_secs: Option<u64>,
+    pub request_retry_max_duration_secs: Option<u64>,
+
+    #[serde(default)]
+    pub proxy: ProxyConfig,
 }
 
 #[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone, Derivative)]
@@ -77,37 +92,63 @@ impl KinesisService {
         config: KinesisSinkConfig,
         acker: Acker,
     ) -> crate::Result<impl Sink<SinkItem = Event, SinkError = ()>> {
-        let client = Arc::new(KinesisClient::new(config.region.clone().try_into()?));
-
-        let batch_size = config.batch_size.unwrap_or(bytesize::mib(1u64) as usize);
+        let client = Arc::new(create_client(
+            config.region.clone().try_into()?,
+            &config.proxy,
+        )?);
+
+        let batch_size = config
+            .batch_size
+            .unwrap_or(MAX_BATCH_EVENTS)
+            .min(MAX_BATCH_EVENTS);
+        let batch_max_bytes = config
+            .batch_max_bytes
+            .unwrap_or(MAX_BATCH_SIZE)
+            .min(MAX_BATCH_SIZE);
         let batch_timeout = config.batch_timeout.unwrap_or(1);
 
         let timeout = config.request_timeout_secs.unwrap_or(30);
-        let in_flight_limit = config.request_in_flight_limit.unwrap_or(5);
+        let in_flight_limit = config
+            .request_in_flight_limit
+            .unwrap_or(InFlightLimit::Fixed(5));
         let rate_limit_duration = config.request_rate_limit_duration_secs.unwrap_or(1);
         let rate_limit_num = config.request_rate_limit_num.unwrap_or(5);
         let retry_attempts = config.request_retry_attempts.unwrap_or(usize::max_value());
         let retry_backoff_secs = config.request_retry_backoff_secs.unwrap_or(1);
+        let retry_max_backoff_secs = config.request_retry_max_backoff_secs.unwrap_or(10);
+        let retry_max_duration = config
+            .request_retry_max_duration_secs
+            .map(Duration::from_secs);
         let encoding = config.encoding.clone();
         let partition_key_field = config.partition_key_field.clone();
 
-        let policy = FixedRetryPolicy::new(
+        let policy = RetryPolicy::new(
             retry_attempts,
             Duration::from_secs(retry_backoff_secs),
             KinesisRetryLogic,
-        );
+        )
+        .max_backoff(Duration::from_secs(retry_max_backoff_secs))
+        .max_duration(retry_max_duration);
 
         let kinesis = KinesisService { client, config };
 
         let svc = ServiceBuilder::new()
-            .concurrency_limit(in_flight_limit)
             .rate_limit(rate_limit_num, Duration::from_secs(rate_limit_duration))
             .retry(policy)
+            .layer(AdaptiveConcurrencyLimitLayer::new(
+                in_flight_limit,
+                KinesisRetryLogic,
+            ))
             .timeout(Duration::from_secs(timeout))
             .service(kinesis);
 
         let sink = BatchServiceSink::new(svc, acker)
-            .batched_with_min(Vec::new(), batch_size, Duration::from_secs(batch_timeout))
+            .batched_with_min(
+                VecBuffer::new(),
+                batch_max_bytes,
+                Duration::from_secs(batch_timeout),
+            )
+            .max_events(batch_size)
             .with_flat_map(move |e| iter_ok(encode_event(e, &partition_key_field, &encoding)));
 
         Ok(sink)
@@ -181,7 +222,7 @@ enum HealthcheckError {
 }
 
 fn healthcheck(config: KinesisSinkConfig) -> crate::Result<super::Healthcheck> {
-    let client = KinesisClient::new(config.region.try_into()?);
+    let client = create_client(config.region.try_into()?, &config.proxy)?;
     let stream_name = config.stream_name;
 
     let fut = client
@@ -206,10 +247,17 @@ fn healthcheck(config: KinesisSinkConfig) -> crate::Result<super::Healthcheck> {
     Ok(Box::new(fut))
 }
 
+fn create_client(region: Region, proxy: &ProxyConfig) -> crate::Result<KinesisClient> {
+    let http = rusoto::client(ProxySettings::from_config(proxy)?)?;
+    let creds = DefaultCredentialsProvider::new()?;
+
+    Ok(KinesisClient::new_with(http, creds, region))
+}
+
 fn encode_event(
-    event: Event,
+    mut event: Event,
     partition_key_field: &Option<Atom>,
-    encoding: &Encoding,
+    encoding: &EncodingConfig<Encoding>,
 ) -> Option<PutRecordsRequestEntry> {
     let partition_key = if let Some(partition_key_field) = partition_key_field {
         if let Some(v) = event.as_log().get(&partition_key_field) {
@@ -232,8 +280,9 @@ fn encode_event(
         partition_key
     };
 
+    encoding.apply_rules(&mut event);
     let log = event.into_log();
-    let data = match encoding {
+    let data = match encoding.codec {
         Encoding::Json => {
             serde_json::to_vec(&log.unflatten()).expect("Error encoding event as json.")
         }
@@ -253,6 +302,13 @@ fn encode_event(
     })
 }
 
+impl ByteSize for PutRecordsRequestEntry {
+    // Both the data and the partition key count towards the request size.
+    fn byte_size(&self) -> usize {
+        self.data.len() + self.partition_key.len()
+    }
+}
+
 fn gen_partition_key() -> String {
     random::<[char; 16]>()
         .iter()
@@ -274,7 +330,7 @@ mod tests {
     #[test]
     fn kinesis_encode_event_text() {
         let message = "hello world".to_string();
-        let event = encode_event(message.clone().into(), &None, &Encoding::Text).unwrap();
+        let event = encode_event(message.clone().into(), &None, &Encoding::Text.into()).unwrap();
 
         assert_eq!(&event.data[..], message.as_bytes());
     }
@@ -286,7 +342,7 @@ mod tests {
         event
             .as_mut_log()
             .insert_explicit("key".into(), "value".into());
-        let event = encode_event(event, &None, &Encoding::Json).unwrap();
+        let event = encode_event(event, &None, &Encoding::Json.into()).unwrap();
 
         let map: HashMap<String, String> = serde_json::from_slice(&event.data[..]).unwrap();
 
@@ -300,7 +356,7 @@ mod tests {
         event
             .as_mut_log()
             .insert_implicit("key".into(), "some_key".into());
-        let event = encode_event(event, &Some("key".into()), &Encoding::Text).unwrap();
+        let event = encode_event(event, &Some("key".into()), &Encoding::Text.into()).unwrap();
 
         assert_eq!(&event.data[..], "hello world".as_bytes());
         assert_eq!(&event.partition_key, &"some_key".to_string());
@@ -312,7 +368,7 @@ mod tests {
         event
             .as_mut_log()
             .insert_implicit("key".into(), random_string(300).into());
-        let event = encode_event(event, &Some("key".into()), &Encoding::Text).unwrap();
+        let event = encode_event(event, &Some("key".into()), &Encoding::Text.into()).unwrap();
 
         assert_eq!(&event.data[..], "hello world".as_bytes());
         assert_eq!(event.partition_key.len(), 256);
@@ -3,8 +3,12 @@ use crate::{
     event::{self, Event},
     region::RegionOrEndpoint,
     sinks::util::{
-        retries::{FixedRetryPolicy, RetryLogic},
-        BatchServiceSink, Buffer, PartitionBuffer, PartitionInnerBuffer, SinkExt,
+        adaptive_concurrency::{AdaptiveConcurrencyLimitLayer, InFlightLimit},
+        encoding::EncodingConfig,
+        proxy::{ProxyConfig, ProxySettings},
+        retries::{RetryLogic, RetryPolicy},
+        rusoto, BatchServiceSink, Buffer, Compression, PartitionBuffer, PartitionInnerBuffer,
+        SinkExt,
     },
     template::Template,
     topology::config::{DataType, SinkConfig},
@@ -29,7 +33,7 @@ use uuid::Uuid;
 pub struct S3Sink {
     client: S3Client,
     bucket: String,
-    gzip: bool,
+    compression: Compression,
     filename_time_format: String,
     filename_append_uuid: bool,
     filename_extension: Option<String>,
@@ -45,18 +49,23 @@ pub struct S3SinkConfig {
     pub filename_extension: Option<String>,
     #[serde(flatten)]
     pub region: RegionOrEndpoint,
-    pub encoding: Encoding,
+    pub encoding: EncodingConfig<Encoding>,
     pub batch_size: Option<usize>,
-    pub compression: Compression,
+    pub compression: Option<Compression>,
     pub batch_timeout: Option<u64>,
 
     // Tower Request based configuration
-    pub request_in_flight_limit: Option<usize>,
+    pub request_in_flight_limit: Option<InFlightLimit>,
     pub request_timeout_secs: Option<u64>,
     pub request_rate_limit_duration_secs: Option<u64>,
     pub request_rate_limit_num: Option<u64>,
     pub request_retry_attempts: Option<usize>,
     pub request_retry_backoff_secs: Option<u64>,
+    pub request_retry_max_backoff_secs: Option<u64>,
+    pub request_retry_max_duration_secs: Option<u64>,
+
+    #[serde(default)]
+    pub proxy: ProxyConfig,
 }
 
 #[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone, Derivative)]
@@ -68,15 +77,6 @@ pub enum Encoding {
     Ndjson,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, Derivative)]
-#[serde(rename_all = "snake_case")]
-#[derivative(Default)]
-pub enum Compression {
-    #[derivative(Default)]
-    Gzip,
-    None,
-}
-
 #[typetag::serde(name = "aws_s3")]
 impl SinkConfig for S3SinkConfig {
     fn build(&self, acker: Acker) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
@@ -104,24 +104,29 @@ enum HealthcheckError {
 impl S3Sink {
     pub fn new(config: &S3SinkConfig, acker: Acker) -> crate::Result<super::RouterSink> {
         let timeout = config.request_timeout_secs.unwrap_or(60);
-        let in_flight_limit = config.request_in_flight_limit.unwrap_or(25);
+        let in_flight_limit = config
+            .request_in_flight_limit
+            .unwrap_or(InFlightLimit::Fixed(25));
         let rate_limit_duration = config.request_rate_limit_duration_secs.unwrap_or(1);
         let rate_limit_num = config.request_rate_limit_num.unwrap_or(25);
         let retry_attempts = config.request_retry_attempts.unwrap_or(usize::max_value());
         let retry_backoff_secs = config.request_retry_backoff_secs.unwrap_or(1);
+        let retry_max_backoff_secs = config.request_retry_max_backoff_secs.unwrap_or(10);
+        let retry_max_duration = config
+            .request_retry_max_duration_secs
+            .map(Duration::from_secs);
         let encoding = config.encoding.clone();
 
-        let policy = FixedRetryPolicy::new(
+        let policy = RetryPolicy::new(
             retry_attempts,
             Duration::from_secs(retry_backoff_secs),
             S3RetryLogic,
-        );
+        )
+        .max_backoff(Duration::from_secs(retry_max_backoff_secs))
+        .max_duration(retry_max_duration);
 
         let batch_timeout = config.batch_timeout.unwrap_or(300);
-        let compression = match config.compression {
-            Compression::Gzip => true,
-            Compression::None => false,
-        };
+        let compression = config.compression.unwrap_or(Compression::Gzip(None));
         let batch_size = config.batch_size.unwrap_or(bytesize::mib(10u64) as usize);
         let filename_time_format = config.filename_time_format.clone().unwrap_or("%s".into());
         let filename_append_uuid = config.filename_append_uuid.unwrap_or(true);
@@ -133,18 +138,21 @@ impl S3Sink {
         };
 
         let s3 = S3Sink {
-            client: Self::create_client(config.region.clone().try_into()?),
+            client: Self::create_client(config.region.clone().try_into()?, &config.proxy)?,
             bucket: config.bucket.clone(),
-            gzip: compression,
+            compression,
             filename_time_format,
             filename_append_uuid,
             filename_extension: config.filename_extension.clone(),
         };
 
         let svc = ServiceBuilder::new()
-            .concurrency_limit(in_flight_limit)
             .rate_limit(rate_limit_num, Duration::from_secs(rate_limit_duration))
             .retry(policy)
+            .layer(AdaptiveConcurrencyLimitLayer::new(
+                in_flight_limit,
+                S3RetryLogic,
+            ))
             .timeout(Duration::from_secs(timeout))
             .service(s3);
 
@@ -160,7 +168,7 @@ impl S3Sink {
     }
 
     pub fn healthcheck(config: &S3SinkConfig) -> crate::Result<super::Healthcheck> {
-        let client = Self::create_client(config.region.clone().try_into()?);
+        let client = Self::create_client(config.region.clone().try_into()?, &config.proxy)?;
 
         let request = HeadBucketRequest {
             bucket: config.bucket.clone(),
@@ -183,24 +191,28 @@ impl S3Sink {
         Ok(Box::new(healthcheck))
     }
 
-    pub fn create_client(region: Region) -> S3Client {
+    pub fn create_client(region: Region, proxy: &ProxyConfig) -> crate::Result<S3Client> {
+        let d = rusoto::client(ProxySettings::from_config(proxy)?)?;
+
         // Hack around the fact that rusoto will not pick up runtime
         // env vars. This is designed to only for test purposes use
         // static credentials.
         #[cfg(not(test))]
         {
-            S3Client::new(region)
+            use rusoto_credential::DefaultCredentialsProvider;
+
+            let p = DefaultCredentialsProvider::new()?;
+
+            Ok(S3Client::new_with(d, p, region))
         }
 
         #[cfg(test)]
         {
-            use rusoto_core::HttpClient;
             use rusoto_credential::StaticProvider;
 
             let p = StaticProvider::new_minimal("test-access-key".into(), "test-secret-key".into());
-            let d = HttpClient::new().unwrap();
 
-            S3Client::new_with(d, p, region)
+            Ok(S3Client::new_with(d, p, region))
         }
     }
 }
@@ -222,7 +234,7 @@ impl Service<PartitionInnerBuffer<Vec<u8>, Bytes>> for S3Sink {
             &self.filename_time_format,
             self.filename_extension.clone(),
             self.filename_append_uuid,
-            self.gzip,
+            self.compression,
         );
 
         debug!(
@@ -236,11 +248,7 @@ impl Service<PartitionInnerBuffer<Vec<u8>, Bytes>> for S3Sink {
             body: Some(inner.into()),
             bucket: self.bucket.clone(),
             key,
-            content_encoding: if self.gzip {
-                Some("gzip".to_string())
-            } else {
-                None
-            },
+            content_encoding: self.compression.content_encoding().map(ToString::to_string),
             ..Default::default()
         };
 
@@ -271,7 +279,7 @@ fn generate_key(
     time_format: &str,
     extension: Option<String>,
     uuid: bool,
-    gzip: bool,
+    compression: Compression,
 ) -> String {
     // TODO: pull the seconds from the last event
     let filename = {
@@ -285,7 +293,10 @@ fn generate_key(
         }
     };
 
-    let extension = extension.unwrap_or_else(|| if gzip { "log.gz".into() } else { "log".into() });
+    let extension = extension.unwrap_or_else(|| match compression.extension() {
+        Some(extension) => format!("log.{}", extension),
+        None => "log".into(),
+    });
 
     let key = String::from_utf8_lossy(&key[..]).into_owned();
 
@@ -293,9 +304,9 @@ fn generate_key(
 }
 
 fn encode_event(
-    event: Event,
+    mut event: Event,
     key_prefix: &Template,
-    encoding: &Encoding,
+    encoding: &EncodingConfig<Encoding>,
 ) -> Option<PartitionInnerBuffer<Vec<u8>, Bytes>> {
     let key = key_prefix
         .render_string(&event)
@@ -308,15 +319,16 @@ fn encode_event(
         })
         .ok()?;
 
+    encoding.apply_rules(&mut event);
     let log = event.into_log();
-    let bytes = match encoding {
+    let bytes = match encoding.codec {
         Encoding::Ndjson => serde_json::to_vec(&log.unflatten())
             .map(|mut b| {
                 b.push(b'\n');
                 b
             })
             .expect("Failed to encode event as json, this is a bug!"),
-        &Encoding::Text => {
+        Encoding::Text => {
             let mut bytes = log
                 .get(&event::MESSAGE)
                 .map(|v| v.as_bytes().to_vec())
@@ -340,8 +352,12 @@ mod tests {
     fn s3_encode_event_text() {
         let message = "hello world".to_string();
         let batch_time_format = Template::from("date=%F");
-        let bytes =
-            encode_event(message.clone().into(), &batch_time_format, &Encoding::Text).unwrap();
+        let bytes = encode_event(
+            message.clone().into(),
+            &batch_time_format,
+            &Encoding::Text.into(),
+        )
+        .unwrap();
 
         let encoded_message = message + "\n";
         let (bytes, _) = bytes.into_parts();
@@ -357,7 +373,7 @@ mod tests {
             .insert_explicit("key".into(), "value".into());
 
         let batch_time_format = Template::from("date=%F");
-        let bytes = encode_event(event, &batch_time_format, &Encoding::Ndjson).unwrap();
+        let bytes = encode_event(event, &batch_time_format, &Encoding::Ndjson.into()).unwrap();
 
         let (bytes, _) = bytes.into_parts();
         let map: HashMap<String, String> = serde_json::from_slice(&bytes[..]).unwrap();
@@ -368,22 +384,38 @@ mod tests {
 
     #[test]
     fn s3_generate_key() {
+        let none = Compression::None;
+        let gzip = Compression::Gzip(None);
         assert_eq!(
-            generate_key("key/".as_bytes(), &"date", Some("ext".into()), false, false),
+            generate_key("key/".as_bytes(), &"date", Some("ext".into()), false, gzip),
             "key/date.ext"
         );
         assert_eq!(
-            generate_key("key/".as_bytes(), &"date", None, false, false),
+            generate_key("key/".as_bytes(), &"date", None, false, none),
             "key/date.log"
         );
         assert_eq!(
-            generate_key("key/".as_bytes(), &"date", None, false, true),
+            generate_key("key/".as_bytes(), &"date", None, false, gzip),
             "key/date.log.gz"
         );
         assert_eq!(
-            generate_key("key".as_bytes(), &"date", None, false, true),
+            generate_key("key".as_bytes(), &"date", None, false, gzip),
             "keydate.log.gz"
         );
+        assert_eq!(
+            generate_key(
+                "key/".as_bytes(),
+                &"date",
+                None,
+                false,
+                Compression::Zstd(None)
+            ),
+            "key/date.log.zst"
+        );
+        assert_eq!(
+            generate_key("key/".as_bytes(), &"date", None, false, Compression::Snappy),
+            "key/date.log.sz"
+        );
     }
 }
 
@@ -550,7 +582,7 @@ mod integration_tests {
 
         let config = S3SinkConfig {
             batch_size: Some(1000),
-            compression: Compression::Gzip,
+            compression: Some(Compression::Gzip(None)),
             filename_time_format: Some("%S%f".into()),
             ..config()
         };
@@ -612,7 +644,7 @@ mod integration_tests {
             endpoint: "http://localhost:9000".to_owned(),
         };
 
-        S3Sink::create_client(region)
+        S3Sink::create_client(region, &Default::default()).unwrap()
     }
 
     fn config() -> S3SinkConfig {
@@ -621,7 +653,7 @@ mod integration_tests {
         S3SinkConfig {
             key_prefix: Some(random_string(10) + "/date=%F/"),
             bucket: BUCKET.to_string(),
-            compression: Compression::None,
+            compression: Some(Compression::None),
             batch_timeout: Some(5),
             region: RegionOrEndpoint::with_endpoint("http://localhost:9000".to_owned()),
             ..Default::default()
@@ -2,19 +2,24 @@ use crate::{
     buffers::Acker,
     event::Event,
     sinks::util::{
-        http::{HttpRetryLogic, HttpService, Response},
-        retries::{FixedRetryPolicy, RetryLogic},
+        adaptive_concurrency::{AdaptiveConcurrencyLimitLayer, InFlightLimit},
+        buffer::compression::Algorithm,
+        encoding::EncodingConfig,
+        http::{
+            authorize, https_client, Auth, Authorizer, BasicAuth, HttpRetryLogic, HttpService,
+            Response,
+        },
+        proxy::{ProxyConfig, ProxySettings},
+        retries::{RetryLogic, RetryPolicy},
         tls::{TlsOptions, TlsSettings},
         BatchServiceSink, Buffer, Compression, SinkExt,
     },
     topology::config::{DataType, SinkConfig},
 };
 use futures::{Future, Sink};
-use headers::HeaderMapExt;
 use http::StatusCode;
 use http::{Method, Uri};
-use hyper::{Body, Client, Request};
-use hyper_tls::HttpsConnector;
+use hyper::{Body, Request};
 use serde::{Deserialize, Serialize};
 use snafu::ResultExt;
 use std::borrow::Cow;
@@ -30,24 +35,57 @@ pub struct ClickhouseConfig {
     pub batch_size: Option<usize>,
     pub batch_timeout: Option<u64>,
     pub compression: Option<Compression>,
-    pub basic_auth: Option<ClickHouseBasicAuthConfig>,
+    #[serde(default)]
+    pub encoding: EncodingConfig<Encoding>,
+    pub auth: Option<Auth>,
+    pub basic_auth: Option<BasicAuth>,
 
     // Tower Request based configuration
-    pub request_in_flight_limit: Option<usize>,
+    pub request_in_flight_limit: Option<InFlightLimit>,
     pub request_timeout_secs: Option<u64>,
     pub request_rate_limit_duration_secs: Option<u64>,
     pub request_rate_limit_num: Option<u64>,
     pub request_retry_attempts: Option<usize>,
     pub request_retry_backoff_secs: Option<u64>,
+    pub request_retry_max_backoff_secs: Option<u64>,
+    pub request_retry_max_duration_secs: Option<u64>,
 
     pub tls: Option<TlsOptions>,
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+}
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    Ndjson,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Ndjson
+    }
 }
 
+/// The encodings ClickHouse decompresses with `enable_http_compression`.
+const SUPPORTED_COMPRESSION: &[Algorithm] = &[Algorithm::None, Algorithm::Gzip, Algorithm::Deflate];
+
 #[typetag::serde(name = "clickhouse")]
 impl SinkConfig for ClickhouseConfig {
     fn build(&self, acker: Acker) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
-        let sink = clickhouse(self.clone(), acker)?;
-        let healtcheck = healthcheck(self.host.clone(), self.basic_auth.clone());
+        let tls_settings = TlsSettings::from_options(&self.tls)?;
+        let proxy_settings = ProxySettings::from_config(&self.proxy)?;
+        let authorizer = Auth::choose(&self.auth, &self.basic_auth)?
+            .map(|auth| auth.build(tls_settings.clone(), proxy_settings.clone()))
+            .transpose()?;
+        let sink = clickhouse(
+            self.clone(),
+            authorizer.clone(),
+            tls_settings.clone(),
+            proxy_settings.clone(),
+            acker,
+        )?;
+        let healtcheck = healthcheck(self.host.clone(), authorizer, tls_settings, proxy_settings)?;
 
         Ok((sink, healtcheck))
     }
@@ -57,119 +95,119 @@ impl SinkConfig for ClickhouseConfig {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, Default)]
-#[serde(deny_unknown_fields)]
-pub struct ClickHouseBasicAuthConfig {
-    pub password: String,
-    pub user: String,
-}
-
-impl ClickHouseBasicAuthConfig {
-    fn apply(&self, header_map: &mut http::header::HeaderMap) {
-        let auth = headers::Authorization::basic(&self.user, &self.password);
-        header_map.typed_insert(auth)
-    }
-}
-
-fn clickhouse(config: ClickhouseConfig, acker: Acker) -> crate::Result<super::RouterSink> {
+fn clickhouse(
+    config: ClickhouseConfig,
+    authorizer: Option<Authorizer>,
+    tls_settings: TlsSettings,
+    proxy_settings: ProxySettings,
+    acker: Acker,
+) -> crate::Result<super::RouterSink> {
     let host = config.host.clone();
     let database = config.database.clone().unwrap_or("default".into());
     let table = config.table.clone();
 
-    let gzip = match config.compression.unwrap_or(Compression::Gzip) {
-        Compression::None => false,
-        Compression::Gzip => true,
-    };
+    let compression = config
+        .compression
+        .unwrap_or(Compression::Gzip(None))
+        .supported_by(SUPPORTED_COMPRESSION)?;
 
     let batch_size = config.batch_size.unwrap_or(bytesize::mib(10u64) as usize);
     let batch_timeout = config.batch_timeout.unwrap_or(1);
 
     let timeout = config.request_timeout_secs.unwrap_or(60);
-    let in_flight_limit = config.request_in_flight_limit.unwrap_or(5);
+    let in_flight_limit = config
+        .request_in_flight_limit
+        .unwrap_or(InFlightLimit::Fixed(5));
     let rate_limit_duration = config.request_rate_limit_duration_secs.unwrap_or(1);
     let rate_limit_num = config.request_rate_limit_num.unwrap_or(5);
     let retry_attempts = config.request_retry_attempts.unwrap_or(usize::max_value());
     let retry_backoff_secs = config.request_retry_backoff_secs.unwrap_or(1);
+    let retry_max_backoff_secs = config.request_retry_max_backoff_secs.unwrap_or(10);
+    let retry_max_duration = config
+        .request_retry_max_duration_secs
+        .map(Duration::from_secs);
 
-    let basic_auth = config.basic_auth.clone();
-
-    let policy = FixedRetryPolicy::new(
+    let retry_logic = ClickhouseRetryLogic {
+        inner: HttpRetryLogic,
+    };
+    let policy = RetryPolicy::new(
         retry_attempts,
         Duration::from_secs(retry_backoff_secs),
-        ClickhouseRetryLogic {
-            inner: HttpRetryLogic,
-        },
-    );
+        retry_logic.clone(),
+    )
+    .max_backoff(Duration::from_secs(retry_max_backoff_secs))
+    .max_duration(retry_max_duration);
 
     let uri = encode_uri(&host, &database, &table)?;
-    let tls_settings = TlsSettings::from_options(&config.tls)?;
-
-    let http_service =
-        HttpService::builder()
-            .tls_settings(tls_settings)
-            .build(move |body: Vec<u8>| {
-                let mut builder = hyper::Request::builder();
-                builder.method(Method::POST);
-                builder.uri(uri.clone());
+    let encoding = config.encoding.clone();
 
-                builder.header("Content-Type", "application/x-ndjson");
+    let http_service = HttpService::builder()
+        .tls_settings(tls_settings)
+        .proxy_settings(proxy_settings)
+        .authorizer(authorizer)
+        .build(move |body: Vec<u8>| {
+            let mut builder = hyper::Request::builder();
+            builder.method(Method::POST);
+            builder.uri(uri.clone());
 
-                if gzip {
-                    builder.header("Content-Encoding", "gzip");
-                }
+            builder.header("Content-Type", "application/x-ndjson");
 
-                let mut request = builder.body(body).unwrap();
-
-                if let Some(auth) = &basic_auth {
-                    auth.apply(request.headers_mut());
-                }
+            if let Some(content_encoding) = compression.content_encoding() {
+                builder.header("Content-Encoding", content_encoding);
+            }
 
-                request
-            });
+            builder.body(body).unwrap()
+        });
 
     let service = ServiceBuilder::new()
-        .concurrency_limit(in_flight_limit)
         .rate_limit(rate_limit_num, Duration::from_secs(rate_limit_duration))
         .retry(policy)
+        .layer(AdaptiveConcurrencyLimitLayer::new(
+            in_flight_limit,
+            retry_logic,
+        ))
         .timeout(Duration::from_secs(timeout))
         .service(http_service);
 
     let sink = BatchServiceSink::new(service, acker)
         .batched_with_min(
-            Buffer::new(gzip),
+            Buffer::new(compression),
             batch_size,
             Duration::from_secs(batch_timeout),
         )
-        .with(move |event: Event| {
-            let mut body = serde_json::to_vec(&event.as_log().all_fields())
-                .expect("Events should be valid json!");
-            body.push(b'\n');
-            Ok(body)
-        });
+        .with(move |event: Event| Ok(encode_event(event, &encoding)));
 
     Ok(Box::new(sink))
 }
 
-fn healthcheck(host: String, basic_auth: Option<ClickHouseBasicAuthConfig>) -> super::Healthcheck {
+fn encode_event(mut event: Event, encoding: &EncodingConfig<Encoding>) -> Vec<u8> {
+    encoding.apply_rules(&mut event);
+
+    let mut body =
+        serde_json::to_vec(&event.as_log().all_fields()).expect("Events should be valid json!");
+    body.push(b'\n');
+    body
+}
+
+fn healthcheck(
+    host: String,
+    authorizer: Option<Authorizer>,
+    tls_settings: TlsSettings,
+    proxy_settings: ProxySettings,
+) -> crate::Result<super::Healthcheck> {
     // TODO: check if table exists?
     let uri = format!("{}/?query=SELECT%201", host);
-    let mut request = Request::get(uri).body(Body::empty()).unwrap();
+    let request = Request::get(uri).body(Body::empty()).unwrap();
 
-    if let Some(auth) = &basic_auth {
-        auth.apply(request.headers_mut());
-    }
-
-    let https = HttpsConnector::new(4).expect("TLS initialization failed");
-    let client = Client::builder().build(https);
-    let healthcheck = client
-        .request(request)
-        .map_err(|err| err.into())
+    let client = https_client(tls_settings, proxy_settings)?;
+    let healthcheck = authorize(authorizer.as_ref(), request)
+        .and_then(move |request| client.request(request).map_err(|err| err.into()))
         .and_then(|response| match response.status() {
             hyper::StatusCode::OK => Ok(()),
             status => Err(super::HealthcheckError::UnexpectedStatus { status }.into()),
         });
 
-    Box::new(healthcheck)
+    Ok(Box::new(healthcheck))
 }
 
 fn encode_uri(host: &str, database: &str, table: &str) -> crate::Result<Uri> {
@@ -226,11 +264,19 @@ impl RetryLogic for ClickhouseRetryLogic {
             _ => self.inner.should_retry_response(response),
         }
     }
+
+    fn retry_after(&self, response: &Self::Response) -> Option<Duration> {
+        self.inner.retry_after(response)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        event::{self, ValueKind},
+        sinks::util::encoding::TimestampFormat,
+    };
 
     #[test]
     fn encode_valid() {
@@ -245,6 +291,30 @@ mod tests {
     fn encode_invalid() {
         encode_uri("localhost:80", "my_database", "my_table").unwrap_err();
     }
+
+    #[test]
+    fn encode_event_applies_encoding_rules() {
+        let mut event = Event::from("raw log line");
+        event
+            .as_mut_log()
+            .insert_explicit("host".into(), "example.com".into());
+        let encoding = EncodingConfig {
+            only_fields: Some(vec!["message".into(), "timestamp".into()]),
+            timestamp_format: Some(TimestampFormat::Unix),
+            ..Encoding::Ndjson.into()
+        };
+        let timestamp = event.as_log()[&event::TIMESTAMP].clone();
+
+        let body = encode_event(event, &encoding);
+
+        let row: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(row["message"], "raw log line");
+        assert_eq!(row["host"], serde_json::Value::Null);
+        match timestamp {
+            ValueKind::Timestamp(ts) => assert_eq!(row["timestamp"], ts.timestamp()),
+            _ => panic!("timestamp is not a timestamp"),
+        }
+    }
 }
 
 #[cfg(test)]
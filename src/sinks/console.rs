@@ -1,4 +1,4 @@
-use super::util::SinkExt;
+use super::util::{encoding::EncodingConfig, SinkExt};
 use crate::{
     buffers::Acker,
     event::{self, Event},
@@ -29,7 +29,7 @@ impl Default for Target {
 pub struct ConsoleSinkConfig {
     #[serde(default)]
     pub target: Target,
-    pub encoding: Encoding,
+    pub encoding: EncodingConfig<Encoding>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone)]
@@ -62,9 +62,10 @@ impl SinkConfig for ConsoleSinkConfig {
     }
 }
 
-fn encode_event(event: Event, encoding: &Encoding) -> Result<String, ()> {
+fn encode_event(mut event: Event, encoding: &EncodingConfig<Encoding>) -> Result<String, ()> {
+    encoding.apply_rules(&mut event);
     match event {
-        Event::Log(log) => match encoding {
+        Event::Log(log) => match encoding.codec {
             Encoding::Json => {
                 serde_json::to_string(&log.unflatten()).map_err(|e| panic!("Error encoding: {}", e))
             }
@@ -89,7 +90,10 @@ mod test {
     #[test]
     fn encodes_raw_logs() {
         let event = Event::from("foo");
-        assert_eq!(Ok("foo".to_string()), encode_event(event, &Encoding::Text));
+        assert_eq!(
+            Ok("foo".to_string()),
+            encode_event(event, &Encoding::Text.into())
+        );
     }
 
     #[test]
@@ -106,7 +110,7 @@ mod test {
         });
         assert_eq!(
             Ok(r#"{"type":"counter","name":"foos","val":100.0,"timestamp":"2018-11-14T08:09:10.000000011Z","tags":{"key":"value"}}"#.to_string()),
-            encode_event(event, &Encoding::Text)
+            encode_event(event, &Encoding::Text.into())
         );
     }
 
@@ -121,7 +125,7 @@ mod test {
         });
         assert_eq!(
             Ok(r#"{"type":"histogram","name":"glork","val":10.0,"sample_rate":1,"timestamp":null,"tags":null}"#.to_string()),
-            encode_event(event, &Encoding::Text)
+            encode_event(event, &Encoding::Text.into())
         );
     }
 }
@@ -2,8 +2,13 @@ use crate::{
     buffers::Acker,
     event::Metric,
     sinks::util::{
-        http::{Error as HttpError, HttpRetryLogic, HttpService, Response as HttpResponse},
-        retries::FixedRetryPolicy,
+        adaptive_concurrency::{AdaptiveConcurrencyLimitLayer, InFlightLimit},
+        http::{
+            https_client, Error as HttpError, HttpRetryLogic, HttpService, Response as HttpResponse,
+        },
+        proxy::{ProxyConfig, ProxySettings},
+        retries::RetryPolicy,
+        tls::TlsSettings,
         BatchServiceSink, MetricBuffer, SinkExt,
     },
     topology::config::{DataType, SinkConfig},
@@ -12,7 +17,6 @@ use chrono::{DateTime, Utc};
 use futures::{Future, Poll};
 use http::{uri::InvalidUri, Method, StatusCode, Uri};
 use hyper;
-use hyper_tls::HttpsConnector;
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
 use std::collections::HashMap;
@@ -48,12 +52,17 @@ pub struct DatadogConfig {
     pub batch_timeout: Option<u64>,
 
     // Tower Request based configuration
-    pub request_in_flight_limit: Option<usize>,
+    pub request_in_flight_limit: Option<InFlightLimit>,
     pub request_timeout_secs: Option<u64>,
     pub request_rate_limit_duration_secs: Option<u64>,
     pub request_rate_limit_num: Option<u64>,
     pub request_retry_attempts: Option<usize>,
     pub request_retry_backoff_secs: Option<u64>,
+    pub request_retry_max_backoff_secs: Option<u64>,
+    pub request_retry_max_duration_secs: Option<u64>,
+
+    #[serde(default)]
+    pub proxy: ProxyConfig,
 }
 
 pub fn default_host() -> String {
@@ -104,30 +113,40 @@ impl DatadogSvc {
         let batch_timeout = config.batch_timeout.unwrap_or(1);
 
         let timeout = config.request_timeout_secs.unwrap_or(60);
-        let in_flight_limit = config.request_in_flight_limit.unwrap_or(5);
+        let in_flight_limit = config
+            .request_in_flight_limit
+            .unwrap_or(InFlightLimit::Fixed(5));
         let rate_limit_duration = config.request_rate_limit_duration_secs.unwrap_or(1);
         let rate_limit_num = config.request_rate_limit_num.unwrap_or(5);
         let retry_attempts = config.request_retry_attempts.unwrap_or(5);
         let retry_backoff_secs = config.request_retry_backoff_secs.unwrap_or(1);
+        let retry_max_backoff_secs = config.request_retry_max_backoff_secs.unwrap_or(10);
+        let retry_max_duration = config
+            .request_retry_max_duration_secs
+            .map(Duration::from_secs);
 
-        let policy = FixedRetryPolicy::new(
+        let policy = RetryPolicy::new(
             retry_attempts,
             Duration::from_secs(retry_backoff_secs),
             HttpRetryLogic,
-        );
+        )
+        .max_backoff(Duration::from_secs(retry_max_backoff_secs))
+        .max_duration(retry_max_duration);
 
         let uri = format!("{}/api/v1/series?api_key={}", config.host, config.api_key)
             .parse::<Uri>()
             .context(super::UriParseError)?;
 
-        let http_service = HttpService::new(move |body: Vec<u8>| {
-            let mut builder = hyper::Request::builder();
-            builder.method(Method::POST);
-            builder.uri(uri.clone());
+        let http_service = HttpService::builder()
+            .proxy_settings(ProxySettings::from_config(&config.proxy)?)
+            .build(move |body: Vec<u8>| {
+                let mut builder = hyper::Request::builder();
+                builder.method(Method::POST);
+                builder.uri(uri.clone());
 
-            builder.header("Content-Type", "application/json");
-            builder.body(body).unwrap()
-        });
+                builder.header("Content-Type", "application/json");
+                builder.body(body).unwrap()
+            });
 
         let datadog_http_service = DatadogSvc {
             config,
@@ -138,9 +157,12 @@ impl DatadogSvc {
         };
 
         let service = ServiceBuilder::new()
-            .concurrency_limit(in_flight_limit)
             .rate_limit(rate_limit_num, Duration::from_secs(rate_limit_duration))
             .retry(policy)
+            .layer(AdaptiveConcurrencyLimitLayer::new(
+                in_flight_limit,
+                HttpRetryLogic,
+            ))
             .timeout(Duration::from_secs(timeout))
             .service(datadog_http_service);
 
@@ -160,8 +182,10 @@ impl DatadogSvc {
 
         let request = hyper::Request::get(uri).body(hyper::Body::empty()).unwrap();
 
-        let https = HttpsConnector::new(4).expect("TLS initialization failed");
-        let client = hyper::Client::builder().build(https);
+        let client = https_client(
+            TlsSettings::default(),
+            ProxySettings::from_config(&config.proxy)?,
+        )?;
 
         let healthcheck = client
             .request(request)
@@ -3,15 +3,20 @@ use crate::{
     event::Event,
     region::RegionOrEndpoint,
     sinks::util::{
-        http::{https_client, HttpRetryLogic, HttpService},
-        retries::FixedRetryPolicy,
+        adaptive_concurrency::{AdaptiveConcurrencyLimitLayer, InFlightLimit},
+        buffer::compression::Algorithm,
+        circuit_breaker::CircuitBreakerLayer,
+        encoding::EncodingConfig,
+        http::{authorize, https_client, Auth, Authorizer, BasicAuth, HttpRetryLogic, HttpService},
+        proxy::{ProxyConfig, ProxySettings},
+        retries::RetryPolicy,
         tls::{TlsOptions, TlsSettings},
         BatchServiceSink, Buffer, Compression, SinkExt,
     },
     template::Template,
     topology::config::{DataType, SinkConfig},
 };
-use futures::{stream::iter_ok, Future, Sink};
+use futures::{future, stream::iter_ok, Future, Sink};
 use http::{uri::InvalidUri, Method, Uri};
 use hyper::{
     header::{HeaderName, HeaderValue},
@@ -38,31 +43,45 @@ pub struct ElasticSearchConfig {
     pub batch_size: Option<usize>,
     pub batch_timeout: Option<u64>,
     pub compression: Option<Compression>,
+    #[serde(default)]
+    pub encoding: EncodingConfig<Encoding>,
     pub provider: Option<Provider>,
     #[serde(flatten)]
     pub region: Option<RegionOrEndpoint>,
 
     // Tower Request based configuration
-    pub request_in_flight_limit: Option<usize>,
+    pub request_in_flight_limit: Option<InFlightLimit>,
     pub request_timeout_secs: Option<u64>,
     pub request_rate_limit_duration_secs: Option<u64>,
     pub request_rate_limit_num: Option<u64>,
     pub request_retry_attempts: Option<usize>,
     pub request_retry_backoff_secs: Option<u64>,
+    pub request_retry_max_backoff_secs: Option<u64>,
+    pub request_retry_max_duration_secs: Option<u64>,
+    pub request_circuit_breaker_failures: Option<usize>,
+    pub request_circuit_breaker_probe_secs: Option<u64>,
 
-    pub basic_auth: Option<ElasticSearchBasicAuthConfig>,
+    pub auth: Option<Auth>,
+    pub basic_auth: Option<BasicAuth>,
 
     pub headers: Option<HashMap<String, String>>,
     pub query: Option<HashMap<String, String>>,
 
     pub tls: Option<TlsOptions>,
+    #[serde(default)]
+    pub proxy: ProxyConfig,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, Default)]
-#[serde(deny_unknown_fields)]
-pub struct ElasticSearchBasicAuthConfig {
-    pub password: String,
-    pub user: String,
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    Ndjson,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Ndjson
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -72,12 +91,15 @@ pub enum Provider {
     Aws,
 }
 
+/// Elasticsearch decompresses request bodies with `http.compression` set.
+const SUPPORTED_COMPRESSION: &[Algorithm] = &[Algorithm::None, Algorithm::Gzip, Algorithm::Deflate];
+
 #[typetag::serde(name = "elasticsearch")]
 impl SinkConfig for ElasticSearchConfig {
     fn build(&self, acker: Acker) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
         let common = ElasticSearchCommon::parse_config(&self)?;
         let healthcheck = healthcheck(&common)?;
-        let sink = es(self, common, acker);
+        let sink = es(self, common, acker)?;
 
         Ok((sink, healthcheck))
     }
@@ -87,12 +109,14 @@ impl SinkConfig for ElasticSearchConfig {
     }
 }
 
+#[derive(Clone)]
 struct ElasticSearchCommon {
     host: String,
-    authorization: Option<String>,
+    authorizer: Option<Authorizer>,
     region: Option<Region>,
     credentials: Option<AwsCredentials>,
     tls_settings: TlsSettings,
+    proxy_settings: ProxySettings,
 }
 
 #[derive(Debug, Snafu)]
@@ -115,11 +139,6 @@ impl ElasticSearchCommon {
             host: config.host.clone(),
         })?;
 
-        let authorization = config.basic_auth.as_ref().map(|auth| {
-            let token = format!("{}:{}", auth.user, auth.password);
-            format!("Basic {}", base64::encode(token.as_bytes()))
-        });
-
         let region: Option<Region> = match config.region {
             Some(ref region) => Some(region.try_into()?),
             None => None,
@@ -146,13 +165,22 @@ impl ElasticSearchCommon {
         };
 
         let tls_settings = TlsSettings::from_options(&config.tls)?;
+        let proxy_settings = ProxySettings::from_config(&config.proxy)?;
+
+        // Requests to AWS are signed instead
+        let auth = Auth::choose(&config.auth, &config.basic_auth)?;
+        let authorizer = match (&credentials, &auth) {
+            (None, Some(auth)) => Some(auth.build(tls_settings.clone(), proxy_settings.clone())?),
+            _ => None,
+        };
 
         Ok(Self {
             host: config.host.clone(),
-            authorization,
+            authorizer,
             region,
             credentials,
             tls_settings,
+            proxy_settings,
         })
     }
 
@@ -170,22 +198,29 @@ fn es(
     config: &ElasticSearchConfig,
     common: ElasticSearchCommon,
     acker: Acker,
-) -> super::RouterSink {
+) -> crate::Result<super::RouterSink> {
     let id_key = config.id_key.clone();
-    let mut gzip = match config.compression.unwrap_or(Compression::Gzip) {
-        Compression::None => false,
-        Compression::Gzip => true,
-    };
+    let mut compression = config
+        .compression
+        .unwrap_or(Compression::Gzip(None))
+        .supported_by(SUPPORTED_COMPRESSION)?;
 
     let batch_size = config.batch_size.unwrap_or(bytesize::mib(10u64) as usize);
     let batch_timeout = config.batch_timeout.unwrap_or(1);
 
     let timeout = config.request_timeout_secs.unwrap_or(60);
-    let in_flight_limit = config.request_in_flight_limit.unwrap_or(5);
+    let in_flight_limit = config
+        .request_in_flight_limit
+        .unwrap_or(InFlightLimit::Fixed(5));
     let rate_limit_duration = config.request_rate_limit_duration_secs.unwrap_or(1);
     let rate_limit_num = config.request_rate_limit_num.unwrap_or(5);
     let retry_attempts = config.request_retry_attempts.unwrap_or(usize::max_value());
     let retry_backoff_secs = config.request_retry_backoff_secs.unwrap_or(1);
+    let retry_max_backoff_secs = config.request_retry_max_backoff_secs.unwrap_or(10);
+    let retry_max_duration = config
+        .request_retry_max_duration_secs
+        .map(Duration::from_secs);
+    let circuit_breaker_probe_secs = config.request_circuit_breaker_probe_secs.unwrap_or(30);
 
     let index = if let Some(idx) = &config.index {
         Template::from(idx.as_str())
@@ -193,12 +228,15 @@ fn es(
         Template::from("vector-%Y.%m.%d")
     };
     let doc_type = config.doc_type.clone().unwrap_or("_doc".into());
+    let encoding = config.encoding.clone();
 
-    let policy = FixedRetryPolicy::new(
+    let policy = RetryPolicy::new(
         retry_attempts,
         Duration::from_secs(retry_backoff_secs),
         HttpRetryLogic,
-    );
+    )
+    .max_backoff(Duration::from_secs(retry_max_backoff_secs))
+    .max_duration(retry_max_duration);
 
     let headers = config
         .headers
@@ -215,29 +253,35 @@ fn es(
     let path_query = path_query.finish();
 
     if common.credentials.is_some() {
-        gzip = false;
+        compression = Compression::None;
     }
 
+    let probe_common = common.clone();
+    let circuit_breaker = CircuitBreakerLayer::new(
+        config.request_circuit_breaker_failures,
+        HttpRetryLogic,
+        move || healthcheck(&probe_common).unwrap_or_else(|error| Box::new(future::err(error))),
+    )
+    .probe_interval(Duration::from_secs(circuit_breaker_probe_secs));
+
     let http_service = HttpService::builder()
         .tls_settings(common.tls_settings.clone())
+        .proxy_settings(common.proxy_settings.clone())
+        .authorizer(common.authorizer.clone())
         .build(move |body: Vec<u8>| {
             let (uri, mut builder) = common.request_builder(Method::POST, &path_query);
 
             match common.credentials {
                 None => {
                     builder.header("Content-Type", "application/x-ndjson");
-                    if gzip {
-                        builder.header("Content-Encoding", "gzip");
+                    if let Some(content_encoding) = compression.content_encoding() {
+                        builder.header("Content-Encoding", content_encoding);
                     }
 
                     for (header, value) in &headers {
                         builder.header(&header[..], &value[..]);
                     }
 
-                    if let Some(ref auth) = common.authorization {
-                        builder.header("Authorization", &auth[..]);
-                    }
-
                     builder.body(body).unwrap()
                 }
                 Some(ref credentials) => {
@@ -271,28 +315,33 @@ fn es(
         });
 
     let service = ServiceBuilder::new()
-        .concurrency_limit(in_flight_limit)
         .rate_limit(rate_limit_num, Duration::from_secs(rate_limit_duration))
         .retry(policy)
+        .layer(circuit_breaker)
+        .layer(AdaptiveConcurrencyLimitLayer::new(
+            in_flight_limit,
+            HttpRetryLogic,
+        ))
         .timeout(Duration::from_secs(timeout))
         .service(http_service);
 
     let sink = BatchServiceSink::new(service, acker)
         .batched_with_min(
-            Buffer::new(gzip),
+            Buffer::new(compression),
             batch_size,
             Duration::from_secs(batch_timeout),
         )
-        .with_flat_map(move |e| iter_ok(encode_event(e, &index, &doc_type, &id_key)));
+        .with_flat_map(move |e| iter_ok(encode_event(e, &index, &doc_type, &id_key, &encoding)));
 
-    Box::new(sink)
+    Ok(Box::new(sink))
 }
 
 fn encode_event(
-    event: Event,
+    mut event: Event,
     index: &Template,
     doc_type: &str,
     id_key: &Option<String>,
+    encoding: &EncodingConfig<Encoding>,
 ) -> Option<Vec<u8>> {
     let index = index
         .render_string(&event)
@@ -320,6 +369,7 @@ fn encode_event(
     let mut body = serde_json::to_vec(&action).unwrap();
     body.push(b'\n');
 
+    encoding.apply_rules(&mut event);
     serde_json::to_writer(&mut body, &event.into_log().unflatten()).unwrap();
     body.push(b'\n');
     Some(body)
@@ -327,25 +377,18 @@ fn encode_event(
 
 fn healthcheck(common: &ElasticSearchCommon) -> crate::Result<super::Healthcheck> {
     let (uri, mut builder) = common.request_builder(Method::GET, "/_cluster/health");
-    match &common.credentials {
-        None => {
-            if let Some(authorization) = &common.authorization {
-                builder.header("Authorization", authorization.clone());
-            }
-        }
-        Some(credentials) => {
-            let mut signer =
-                SignedRequest::new("GET", "es", common.region.as_ref().unwrap(), uri.path());
-            signer.set_hostname(uri.host().map(|s| s.into()));
-            finish_signer(&mut signer, &credentials, &mut builder);
-        }
+    if let Some(credentials) = &common.credentials {
+        let mut signer =
+            SignedRequest::new("GET", "es", common.region.as_ref().unwrap(), uri.path());
+        signer.set_hostname(uri.host().map(|s| s.into()));
+        finish_signer(&mut signer, &credentials, &mut builder);
     }
     let request = builder.body(Body::empty())?;
 
+    let client = https_client(common.tls_settings.clone(), common.proxy_settings.clone())?;
     Ok(Box::new(
-        https_client(common.tls_settings.clone())?
-            .request(request)
-            .map_err(|err| err.into())
+        authorize(common.authorizer.as_ref(), request)
+            .and_then(move |request| client.request(request).map_err(|err| err.into()))
             .and_then(|response| match response.status() {
                 hyper::StatusCode::OK => Ok(()),
                 status => Err(super::HealthcheckError::UnexpectedStatus { status }.into()),
@@ -429,6 +472,33 @@ mod tests {
 
         assert_eq!(json!({}), action);
     }
+
+    #[test]
+    fn encode_event_applies_encoding_rules() {
+        let mut event = Event::from("butts");
+        event
+            .as_mut_log()
+            .insert_explicit("foo".into(), "bar".into());
+        let encoding = EncodingConfig {
+            except_fields: Some(vec!["foo".into(), "timestamp".into()]),
+            ..Encoding::Ndjson.into()
+        };
+
+        let body = encode_event(
+            event,
+            &Template::from("vector"),
+            "_doc",
+            &Some("foo".into()),
+            &encoding,
+        )
+        .unwrap();
+
+        let mut lines = body.split(|b| *b == b'\n');
+        let action: serde_json::Value = serde_json::from_slice(lines.next().unwrap()).unwrap();
+        let doc: serde_json::Value = serde_json::from_slice(lines.next().unwrap()).unwrap();
+        assert_eq!(action["index"]["_id"], json!("bar"));
+        assert_eq!(doc, json!({"message": "butts"}));
+    }
 }
 
 #[cfg(test)]
@@ -605,7 +675,7 @@ mod integration_tests {
         let request = Request::post(uri).body(Body::empty()).unwrap();
 
         let common = ElasticSearchCommon::parse_config(config).expect("Config error");
-        https_client(common.tls_settings)
+        https_client(common.tls_settings, common.proxy_settings)
             .expect("Could not build client to flush")
             .request(request)
             .map_err(|source| dbg!(source).into())
@@ -1,5 +1,8 @@
 use super::Encoding;
-use crate::event::{self, Event};
+use crate::{
+    event::{self, Event},
+    sinks::util::encoding::EncodingConfig,
+};
 use bytes::{Bytes, BytesMut};
 use codec::BytesDelimitedCodec;
 use futures::{try_ready, Async, AsyncSink, Future, Poll, Sink, StartSend};
@@ -12,7 +15,7 @@ const BACKPRESSURE_BOUNDARY: usize = INITIAL_CAPACITY;
 #[derive(Debug)]
 pub struct File {
     state: State,
-    encoding: Encoding,
+    encoding: EncodingConfig<Encoding>,
     buffer: BytesMut,
     codec: BytesDelimitedCodec,
 }
@@ -25,7 +28,7 @@ enum State {
 }
 
 impl File {
-    pub fn new(path: Bytes, encoding: Encoding) -> Self {
+    pub fn new(path: Bytes, encoding: EncodingConfig<Encoding>) -> Self {
         let path = BytesPath(path);
 
         let fut = file::OpenOptions::new()
@@ -45,10 +48,11 @@ impl File {
         }
     }
 
-    fn encode_event(&self, event: Event) -> Bytes {
+    fn encode_event(&self, mut event: Event) -> Bytes {
+        self.encoding.apply_rules(&mut event);
         let log = event.into_log();
 
-        match self.encoding {
+        match self.encoding.codec {
             Encoding::Ndjson => serde_json::to_vec(&log.unflatten())
                 .map(Bytes::from)
                 .expect("Unable to encode event as JSON."),
@@ -233,7 +237,7 @@ mod tests {
             .join("test.out");
 
         let b = Bytes::from(path.clone().to_str().unwrap().as_bytes());
-        let sink = File::new(b, encoding);
+        let sink = File::new(b, encoding.into());
 
         let mut rt = crate::test_util::runtime();
         let pump = sink
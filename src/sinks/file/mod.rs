@@ -4,7 +4,7 @@ use self::file::File;
 use crate::{
     buffers::Acker,
     event::Event,
-    sinks::util::SinkExt,
+    sinks::util::{encoding::EncodingConfig, SinkExt},
     template::Template,
     topology::config::{DataType, SinkConfig},
 };
@@ -19,7 +19,7 @@ use tokio::timer::Delay;
 pub struct FileSinkConfig {
     pub path: Template,
     pub idle_timeout_secs: Option<u64>,
-    pub encoding: Encoding,
+    pub encoding: EncodingConfig<Encoding>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone)]
@@ -57,7 +57,7 @@ impl SinkConfig for FileSinkConfig {
 #[derive(Debug, Default)]
 pub struct PartitionedFileSink {
     path: Template,
-    encoding: Encoding,
+    encoding: EncodingConfig<Encoding>,
     idle_timeout_secs: u64,
     partitions: HashMap<Bytes, File>,
     last_accessed: HashMap<Bytes, Instant>,
@@ -197,7 +197,7 @@ mod tests {
         let config = FileSinkConfig {
             path: template.clone().into(),
             idle_timeout_secs: None,
-            encoding: Encoding::Text,
+            encoding: Encoding::Text.into(),
         };
 
         let (sink, _) = config.build(Acker::Null).unwrap();
@@ -224,7 +224,7 @@ mod tests {
         let config = FileSinkConfig {
             path: template.clone().into(),
             idle_timeout_secs: None,
-            encoding: Encoding::Text,
+            encoding: Encoding::Text.into(),
         };
 
         let (sink, _) = config.build(Acker::Null).unwrap();
@@ -2,15 +2,19 @@ use crate::{
     buffers::Acker,
     event::{self, Event},
     sinks::util::{
-        http::{https_client, HttpRetryLogic, HttpService},
-        retries::FixedRetryPolicy,
+        adaptive_concurrency::{AdaptiveConcurrencyLimitLayer, InFlightLimit},
+        encoding::EncodingConfig,
+        http::{authorize, https_client, Auth, Authorizer, BasicAuth, HttpRetryLogic, HttpService},
+        proxy::{ProxyConfig, ProxySettings},
+        retries::RetryPolicy,
         tls::{TlsOptions, TlsSettings},
-        BatchServiceSink, Buffer, Compression, SinkExt,
+        BatchServiceSink, Buffer, Compression, Framing, PartitionBuffer, PartitionInnerBuffer,
+        SinkExt,
     },
+    template::Template,
     topology::config::{DataType, SinkConfig},
 };
 use futures::{future, stream::iter_ok, Future, Sink};
-use headers::HeaderMapExt;
 use http::{
     header::{self, HeaderName, HeaderValue},
     Method, Uri,
@@ -34,31 +38,41 @@ enum BuildError {
         value: String,
         source: header::InvalidHeaderValue,
     },
+    #[snafu(display("`json_wrapper_key` can only be used with the `json` encoding"))]
+    WrapperKeyWithoutJson,
+    #[snafu(display("Only one of `json_wrapper_key` and `framing` can be set"))]
+    WrapperKeyWithFraming,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, Default)]
 #[serde(deny_unknown_fields)]
 pub struct HttpSinkConfig {
-    pub uri: String,
+    pub uri: Template,
     pub method: Option<HttpMethod>,
     pub healthcheck_uri: Option<String>,
-    #[serde(flatten)]
+    pub auth: Option<Auth>,
     pub basic_auth: Option<BasicAuth>,
-    pub headers: Option<IndexMap<String, String>>,
+    pub headers: Option<IndexMap<String, Template>>,
     pub batch_size: Option<usize>,
     pub batch_timeout: Option<u64>,
     pub compression: Option<Compression>,
-    pub encoding: Encoding,
+    pub encoding: EncodingConfig<Encoding>,
+    pub json_wrapper_key: Option<String>,
+    pub framing: Option<Framing>,
 
     // Tower Request based configuration
-    pub request_in_flight_limit: Option<usize>,
+    pub request_in_flight_limit: Option<InFlightLimit>,
     pub request_timeout_secs: Option<u64>,
     pub request_rate_limit_duration_secs: Option<u64>,
     pub request_rate_limit_num: Option<u64>,
     pub request_retry_attempts: Option<usize>,
     pub request_retry_backoff_secs: Option<u64>,
+    pub request_retry_max_backoff_secs: Option<u64>,
+    pub request_retry_max_duration_secs: Option<u64>,
 
     pub tls: Option<TlsOptions>,
+    #[serde(default)]
+    pub proxy: ProxyConfig,
 }
 
 #[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone, Derivative)]
@@ -77,25 +91,40 @@ pub enum Encoding {
     #[derivative(Default)]
     Text,
     Ndjson,
+    Json,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
-#[serde(deny_unknown_fields)]
-pub struct BasicAuth {
-    user: String,
-    password: String,
+/// Batches are partitioned by the rendered `uri` and header values, so
+/// every request goes to a single endpoint with a single set of headers.
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+struct PartitionKey {
+    uri: Uri,
+    headers: Vec<(HeaderName, HeaderValue)>,
 }
 
 #[typetag::serde(name = "http")]
 impl SinkConfig for HttpSinkConfig {
     fn build(&self, acker: Acker) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
-        validate_headers(&self.headers)?;
+        let uri = validate_uri(&self.uri)?;
+        let headers = validate_headers(&self.headers)?;
         let tls = TlsSettings::from_options(&self.tls)?;
-        let sink = http(self.clone(), acker, tls.clone())?;
+        let proxy = ProxySettings::from_config(&self.proxy)?;
+        let authorizer = Auth::choose(&self.auth, &self.basic_auth)?
+            .map(|auth| auth.build(tls.clone(), proxy.clone()))
+            .transpose()?;
+        let sink = http(
+            self.clone(),
+            uri,
+            headers,
+            authorizer.clone(),
+            acker,
+            tls.clone(),
+            proxy.clone(),
+        )?;
 
         match self.healthcheck_uri.clone() {
             Some(healthcheck_uri) => {
-                let healthcheck = healthcheck(healthcheck_uri, self.basic_auth.clone(), tls)?;
+                let healthcheck = healthcheck(healthcheck_uri, authorizer, tls, proxy)?;
                 Ok((sink, healthcheck))
             }
             None => Ok((sink, Box::new(future::ok(())))),
@@ -109,39 +138,48 @@ impl SinkConfig for HttpSinkConfig {
 
 fn http(
     config: HttpSinkConfig,
+    uri: Template,
+    headers: Vec<(HeaderName, Template)>,
+    authorizer: Option<Authorizer>,
     acker: Acker,
     tls_settings: TlsSettings,
+    proxy_settings: ProxySettings,
 ) -> crate::Result<super::RouterSink> {
-    let uri = build_uri(&config.uri)?;
-
-    let gzip = match config.compression.unwrap_or(Compression::None) {
-        Compression::None => false,
-        Compression::Gzip => true,
-    };
+    let framing = framing(&config)?;
+    let compression = config.compression.unwrap_or(Compression::None);
     let batch_timeout = config.batch_timeout.unwrap_or(1);
     let batch_size = config.batch_size.unwrap_or(bytesize::mib(10u64) as usize);
 
     let timeout = config.request_timeout_secs.unwrap_or(30);
-    let in_flight_limit = config.request_in_flight_limit.unwrap_or(10);
+    let in_flight_limit = config
+        .request_in_flight_limit
+        .unwrap_or(InFlightLimit::Fixed(10));
     let rate_limit_duration = config.request_rate_limit_duration_secs.unwrap_or(1);
     let rate_limit_num = config.request_rate_limit_num.unwrap_or(10);
     let retry_attempts = config.request_retry_attempts.unwrap_or(usize::max_value());
     let retry_backoff_secs = config.request_retry_backoff_secs.unwrap_or(1);
+    let retry_max_backoff_secs = config.request_retry_max_backoff_secs.unwrap_or(10);
+    let retry_max_duration = config
+        .request_retry_max_duration_secs
+        .map(Duration::from_secs);
     let encoding = config.encoding.clone();
-    let headers = config.headers.clone();
-    let basic_auth = config.basic_auth.clone();
     let method = config.method.clone().unwrap_or(HttpMethod::Post);
 
-    let policy = FixedRetryPolicy::new(
+    let policy = RetryPolicy::new(
         retry_attempts,
         Duration::from_secs(retry_backoff_secs),
         HttpRetryLogic,
-    );
-
-    let http_service =
-        HttpService::builder()
-            .tls_settings(tls_settings)
-            .build(move |body: Vec<u8>| {
+    )
+    .max_backoff(Duration::from_secs(retry_max_backoff_secs))
+    .max_duration(retry_max_duration);
+
+    let http_service = HttpService::builder()
+        .tls_settings(tls_settings)
+        .proxy_settings(proxy_settings)
+        .authorizer(authorizer)
+        .build(
+            move |request: PartitionInnerBuffer<Vec<u8>, PartitionKey>| {
+                let (body, key) = request.into_parts();
                 let mut builder = hyper::Request::builder();
 
                 let method = match method {
@@ -151,68 +189,73 @@ fn http(
 
                 builder.method(method);
 
-                builder.uri(uri.clone());
+                builder.uri(key.uri);
 
-                match encoding {
+                match encoding.codec {
                     Encoding::Text => builder.header("Content-Type", "text/plain"),
                     Encoding::Ndjson => builder.header("Content-Type", "application/x-ndjson"),
+                    Encoding::Json => builder.header("Content-Type", "application/json"),
                 };
 
-                if gzip {
-                    builder.header("Content-Encoding", "gzip");
-                }
-
-                if let Some(headers) = &headers {
-                    for (header, value) in headers.iter() {
-                        builder.header(header.as_str(), value.as_str());
+                if let Some(content_encoding) = compression.content_encoding() {
+                    // A `Content-Encoding` given in `headers` takes precedence
+                    let overridden = key
+                        .headers
+                        .iter()
+                        .any(|(name, _)| *name == header::CONTENT_ENCODING);
+                    if !overridden {
+                        builder.header("Content-Encoding", content_encoding);
                     }
                 }
 
-                let mut request = builder.body(body).unwrap();
-
-                if let Some(auth) = &basic_auth {
-                    auth.apply(request.headers_mut());
+                for (name, value) in key.headers {
+                    builder.header(name, value);
                 }
 
-                request
-            });
+                builder.body(body).unwrap()
+            },
+        );
 
     let service = ServiceBuilder::new()
-        .concurrency_limit(in_flight_limit)
         .rate_limit(rate_limit_num, Duration::from_secs(rate_limit_duration))
         .retry(policy)
+        .layer(AdaptiveConcurrencyLimitLayer::new(
+            in_flight_limit,
+            HttpRetryLogic,
+        ))
         .timeout(Duration::from_secs(timeout))
         .service(http_service);
 
     let encoding = config.encoding.clone();
     let sink = BatchServiceSink::new(service, acker)
-        .batched_with_min(
-            Buffer::new(gzip),
+        .partitioned_batched_with_min(
+            PartitionBuffer::new(Buffer::with_framing(compression, framing)),
             batch_size,
             Duration::from_secs(batch_timeout),
         )
-        .with_flat_map(move |event| iter_ok(encode_event(event, &encoding)));
+        .with_flat_map(move |event| {
+            let request = partition(&event, &uri, &headers).and_then(|key| {
+                encode_event(event, &encoding).map(|body| PartitionInnerBuffer::new(body, key))
+            });
+            iter_ok(request)
+        });
 
     Ok(Box::new(sink))
 }
 
 fn healthcheck(
     uri: String,
-    auth: Option<BasicAuth>,
+    authorizer: Option<Authorizer>,
     tls_settings: TlsSettings,
+    proxy_settings: ProxySettings,
 ) -> crate::Result<super::Healthcheck> {
     let uri = build_uri(&uri)?;
-    let mut request = Request::head(&uri).body(Body::empty()).unwrap();
-
-    if let Some(auth) = auth {
-        auth.apply(request.headers_mut());
-    }
+    let request = Request::head(&uri).body(Body::empty()).unwrap();
 
-    let client = https_client(tls_settings)?;
+    let client = https_client(tls_settings, proxy_settings)?;
 
-    let healthcheck = client
-        .request(request)
-        .map_err(|err| err.into())
+    let healthcheck = authorize(authorizer.as_ref(), request)
+        .and_then(move |request| client.request(request).map_err(|err| err.into()))
         .and_then(|response| {
             use hyper::StatusCode;
 
@@ -225,22 +268,90 @@ fn healthcheck(
     Ok(Box::new(healthcheck))
 }
 
-impl BasicAuth {
-    fn apply(&self, header_map: &mut http::header::HeaderMap) {
-        let auth = headers::Authorization::basic(&self.user, &self.password);
-        header_map.typed_insert(auth)
+/// The uri only renders event fields, a `%` is kept as is. A templated uri
+/// is only known once it is rendered for an event.
+fn validate_uri(uri: &Template) -> crate::Result<Template> {
+    let uri = uri.clone().fields_only();
+    if !uri.is_dynamic() {
+        build_uri(&String::from_utf8_lossy(uri.get_ref()))?;
     }
+    Ok(uri)
 }
 
-fn validate_headers(headers: &Option<IndexMap<String, String>>) -> crate::Result<()> {
+/// Parses the header names and checks the values that aren't templated,
+/// templated values are checked once they are rendered. Like the uri they
+/// only render event fields.
+fn validate_headers(
+    headers: &Option<IndexMap<String, Template>>,
+) -> crate::Result<Vec<(HeaderName, Template)>> {
+    let mut validated = Vec::new();
     if let Some(map) = headers {
         for (name, value) in map {
-            HeaderName::from_bytes(name.as_bytes()).with_context(|| InvalidHeaderName { name })?;
-            HeaderValue::from_bytes(value.as_bytes())
-                .with_context(|| InvalidHeaderValue { value })?;
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| InvalidHeaderName { name })?;
+            let value = value.clone().fields_only();
+            if !value.is_dynamic() {
+                HeaderValue::from_bytes(value.get_ref()).with_context(|| InvalidHeaderValue {
+                    value: String::from_utf8_lossy(value.get_ref()),
+                })?;
+            }
+            validated.push((name, value));
         }
     }
-    Ok(())
+    Ok(validated)
+}
+
+fn partition(
+    event: &Event,
+    uri: &Template,
+    headers: &[(HeaderName, Template)],
+) -> Option<PartitionKey> {
+    let rendered = uri.render_string(event).map_err(|missing_keys| {
+        warn!(
+            message = "Keys in uri template do not exist on the event; Dropping event.",
+            ?missing_keys,
+            rate_limit_secs = 30,
+        );
+    });
+    let uri = build_uri(&rendered.ok()?)
+        .map_err(|error| {
+            warn!(
+                message = "Rendered uri is invalid; Dropping event.",
+                %error,
+                rate_limit_secs = 30,
+            );
+        })
+        .ok()?;
+
+    let mut rendered_headers = Vec::with_capacity(headers.len());
+    for (name, value) in headers {
+        let value = value
+            .render(event)
+            .map_err(|missing_keys| {
+                warn!(
+                    message = "Keys in header template do not exist on the event; Dropping event.",
+                    header = %name,
+                    ?missing_keys,
+                    rate_limit_secs = 30,
+                );
+            })
+            .ok()?;
+        let value = HeaderValue::from_shared(value)
+            .map_err(|_| {
+                warn!(
+                    message = "Rendered header value is invalid; Dropping event.",
+                    header = %name,
+                    rate_limit_secs = 30,
+                );
+            })
+            .ok()?;
+        rendered_headers.push((name.clone(), value));
+    }
+
+    Some(PartitionKey {
+        uri,
+        headers: rendered_headers,
+    })
 }
 
 fn build_uri(raw: &str) -> crate::Result<Uri> {
@@ -257,10 +368,34 @@ fn build_uri(raw: &str) -> crate::Result<Uri> {
         .expect("bug building uri"))
 }
 
-fn encode_event(event: Event, encoding: &Encoding) -> Option<Vec<u8>> {
+/// How the encoded events are put together into a request body. Text and
+/// ndjson put every event on its own line, json sends a JSON array.
+fn framing(config: &HttpSinkConfig) -> crate::Result<Framing> {
+    let newline = Framing {
+        delimiter: "\n".into(),
+        suffix: "\n".into(),
+        ..Default::default()
+    };
+
+    match (&config.json_wrapper_key, &config.framing) {
+        (Some(_), Some(_)) => Err(Box::new(BuildError::WrapperKeyWithFraming)),
+        (Some(key), None) => match config.encoding.codec {
+            Encoding::Json => Ok(Framing::json_object(key)),
+            _ => Err(Box::new(BuildError::WrapperKeyWithoutJson)),
+        },
+        (None, Some(framing)) => Ok(framing.clone()),
+        (None, None) => match config.encoding.codec {
+            Encoding::Text | Encoding::Ndjson => Ok(newline),
+            Encoding::Json => Ok(Framing::json_array()),
+        },
+    }
+}
+
+fn encode_event(mut event: Event, encoding: &EncodingConfig<Encoding>) -> Option<Vec<u8>> {
+    encoding.apply_rules(&mut event);
     let event = event.into_log();
 
-    let mut body = match encoding {
+    let body = match encoding.codec {
         Encoding::Text => {
             if let Some(v) = event.get(&event::MESSAGE) {
                 v.to_string_lossy().into_bytes()
@@ -273,13 +408,11 @@ fn encode_event(event: Event, encoding: &Encoding) -> Option<Vec<u8>> {
             }
         }
 
-        Encoding::Ndjson => serde_json::to_vec(&event.unflatten())
+        Encoding::Ndjson | Encoding::Json => serde_json::to_vec(&event.unflatten())
             .map_err(|e| panic!("Unable to encode into JSON: {}", e))
             .ok()?,
     };
 
-    body.push(b'\n');
-
     Some(body)
 }
 
@@ -303,17 +436,17 @@ mod tests {
 
     #[test]
     fn http_encode_event_text() {
-        let encoding = Encoding::Text;
+        let encoding = Encoding::Text.into();
         let event = Event::from("hello world");
 
         let bytes = encode_event(event, &encoding).unwrap();
 
-        assert_eq!(bytes, Vec::from(&"hello world\n"[..]));
+        assert_eq!(bytes, Vec::from(&"hello world"[..]));
     }
 
     #[test]
     fn http_encode_event_json() {
-        let encoding = Encoding::Ndjson;
+        let encoding = Encoding::Ndjson.into();
         let event = Event::from("hello world");
 
         let bytes = encode_event(event, &encoding).unwrap();
@@ -330,6 +463,67 @@ mod tests {
         assert_eq!(output.message, "hello world".to_string());
     }
 
+    #[test]
+    fn http_framing() {
+        let parse = |config: &str| {
+            let config: HttpSinkConfig = toml::from_str(config).unwrap();
+            framing(&config)
+        };
+
+        let newline = parse(
+            r#"
+            uri = "http://localhost/"
+            encoding = "ndjson"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(newline.delimiter, "\n");
+        assert_eq!(newline.suffix, "\n");
+
+        let array = parse(
+            r#"
+            uri = "http://localhost/"
+            encoding = "json"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(array, Framing::json_array());
+
+        let wrapped = parse(
+            r#"
+            uri = "http://localhost/"
+            encoding = "json"
+            json_wrapper_key = "events"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(wrapped, Framing::json_object("events"));
+
+        let custom = parse(
+            r#"
+            uri = "http://localhost/"
+            encoding = "text"
+            framing = { delimiter = "\u0000" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(custom.prefix, "");
+        assert_eq!(custom.delimiter, "\u{0}");
+
+        assert_downcast_matches!(
+            parse(
+                r#"
+                uri = "http://localhost/"
+                encoding = "text"
+                json_wrapper_key = "events"
+                "#
+            )
+            .unwrap_err(),
+            BuildError,
+            BuildError::WrapperKeyWithoutJson
+        );
+    }
+
     #[test]
     fn http_validates_normal_headers() {
         let config = r#"
@@ -361,6 +555,71 @@ mod tests {
         );
     }
 
+    #[test]
+    fn http_partitions_by_rendered_uri_and_headers() {
+        let config = r#"
+        uri = "http://example.com/{{tenant}}/logs"
+        [headers]
+        X-Tenant = "{{tenant}}"
+        X-Static = "static"
+        "#;
+        let config: HttpSinkConfig = toml::from_str(&config).unwrap();
+        let headers = super::validate_headers(&config.headers).unwrap();
+
+        let mut event = Event::from("hello world");
+        event
+            .as_mut_log()
+            .insert_explicit("tenant".into(), "acme".into());
+
+        let uri = super::validate_uri(&config.uri).unwrap();
+
+        let key = partition(&event, &uri, &headers).unwrap();
+        assert_eq!(key.uri, "http://example.com/acme/logs");
+        assert_eq!(
+            key.headers,
+            vec![
+                (
+                    HeaderName::from_static("x-tenant"),
+                    HeaderValue::from_static("acme")
+                ),
+                (
+                    HeaderName::from_static("x-static"),
+                    HeaderValue::from_static("static")
+                ),
+            ]
+        );
+
+        let event = Event::from("hello world");
+        assert!(partition(&event, &uri, &headers).is_none());
+    }
+
+    #[test]
+    fn http_keeps_percent_encoded_uri() {
+        let config = r#"
+        uri = "http://example.com/logs?q=a%20b&tenant={{tenant}}"
+        [headers]
+        X-Query = "100%25"
+        "#;
+        let config: HttpSinkConfig = toml::from_str(&config).unwrap();
+        let uri = super::validate_uri(&config.uri).unwrap();
+        let headers = super::validate_headers(&config.headers).unwrap();
+
+        let mut event = Event::from("hello world");
+        event
+            .as_mut_log()
+            .insert_explicit("tenant".into(), "acme".into());
+
+        let key = partition(&event, &uri, &headers).unwrap();
+        assert_eq!(key.uri, "http://example.com/logs?q=a%20b&tenant=acme");
+        assert_eq!(
+            key.headers,
+            vec![(
+                HeaderName::from_static("x-query"),
+                HeaderValue::from_static("100%25")
+            )]
+        );
+    }
+
     #[test]
     fn http_happy_path_post() {
         let num_lines = 1000;
@@ -369,10 +628,9 @@ mod tests {
 
         let config = r#"
         uri = "http://$IN_ADDR/frames"
-        user = "waldo"
         compression = "gzip"
-        password = "hunter2"
         encoding = "ndjson"
+        auth = { strategy = "basic", user = "waldo", password = "hunter2" }
     "#
         .replace("$IN_ADDR", &format!("{}", in_addr));
         let config: HttpSinkConfig = toml::from_str(&config).unwrap();
@@ -427,10 +685,9 @@ mod tests {
         let config = r#"
         uri = "http://$IN_ADDR/frames"
         method = "put"
-        user = "waldo"
         compression = "gzip"
-        password = "hunter2"
         encoding = "ndjson"
+        auth = { strategy = "basic", user = "waldo", password = "hunter2" }
     "#
         .replace("$IN_ADDR", &format!("{}", in_addr));
         let config: HttpSinkConfig = toml::from_str(&config).unwrap();
@@ -538,6 +795,56 @@ mod tests {
         assert_eq!(input_lines, output_lines);
     }
 
+    #[test]
+    fn http_sends_wrapped_json_array() {
+        let num_lines = 1000;
+
+        let in_addr = next_addr();
+
+        let config = r#"
+        uri = "http://$IN_ADDR/frames"
+        encoding = "json"
+        json_wrapper_key = "events"
+    "#
+        .replace("$IN_ADDR", &format!("{}", in_addr));
+        let config: HttpSinkConfig = toml::from_str(&config).unwrap();
+
+        let (sink, _healthcheck) = config.build(Acker::Null).unwrap();
+        let (rx, trigger, server) = build_test_server(&in_addr);
+
+        let (input_lines, events) = random_lines_with_stream(100, num_lines);
+        let pump = sink.send_all(events);
+
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.spawn(server);
+
+        rt.block_on(pump).unwrap();
+        drop(trigger);
+
+        let output_lines = rx
+            .wait()
+            .map(Result::unwrap)
+            .flat_map(|(parts, body)| {
+                assert_eq!(
+                    Some("application/json"),
+                    parts
+                        .headers
+                        .get("Content-Type")
+                        .map(|v| v.to_str().unwrap())
+                );
+
+                let val: serde_json::Value = serde_json::from_slice(&body[..]).unwrap();
+                val["events"].as_array().unwrap().clone()
+            })
+            .map(|val| val["message"].as_str().unwrap().to_owned())
+            .collect::<Vec<_>>();
+
+        shutdown_on_idle(rt);
+
+        assert_eq!(num_lines, output_lines.len());
+        assert_eq!(input_lines, output_lines);
+    }
+
     fn build_test_server(
         addr: &std::net::SocketAddr,
     ) -> (
@@ -1,6 +1,7 @@
 use crate::{
     buffers::Acker,
     event::{self, Event},
+    sinks::util::encoding::EncodingConfig,
     sinks::util::tls::TlsOptions,
     sinks::util::MetadataFuture,
     topology::config::{DataType, SinkConfig},
@@ -8,7 +9,7 @@ use crate::{
 use futures::{
     future::{self, poll_fn, IntoFuture},
     stream::FuturesUnordered,
-    Async, AsyncSink, Future, Poll, Sink, StartSend, Stream,
+    try_ready, Async, AsyncSink, Future, Poll, Sink, StartSend, Stream,
 };
 use rdkafka::{
     consumer::{BaseConsumer, Consumer},
@@ -34,7 +35,7 @@ pub struct KafkaSinkConfig {
     bootstrap_servers: Vec<String>,
     topic: String,
     key_field: Option<Atom>,
-    encoding: Encoding,
+    encoding: EncodingConfig<Encoding>,
     tls: Option<KafkaSinkTlsConfig>,
 }
 
@@ -56,8 +57,10 @@ pub struct KafkaSink {
     producer: FutureProducer,
     topic: String,
     key_field: Option<Atom>,
-    encoding: Encoding,
+    encoding: EncodingConfig<Encoding>,
     in_flight: FuturesUnordered<MetadataFuture<DeliveryFuture, usize>>,
+    /// The key and body of an encoded event the producer queue had no room for.
+    pending: Option<(Vec<u8>, Vec<u8>)>,
 
     acker: Acker,
     seq_head: usize,
@@ -120,6 +123,7 @@ impl KafkaSink {
             key_field: config.key_field,
             encoding: config.encoding,
             in_flight: FuturesUnordered::new(),
+            pending: None,
             acker,
             seq_head: 0,
             seq_tail: 0,
@@ -133,39 +137,68 @@ impl Sink for KafkaSink {
     type SinkError = ();
 
     fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
-        let topic = self.topic.clone();
+        if let Async::NotReady = self.poll_pending()? {
+            return Ok(AsyncSink::NotReady(item));
+        }
 
-        let (key, body) = encode_event(&item, &self.key_field, &self.encoding);
+        debug!(message = "sending event.", count = 1);
+        self.pending = Some(encode_event(item, &self.key_field, &self.encoding));
+        self.poll_pending()?;
 
-        let record = FutureRecord::to(&topic).key(&key).payload(&body[..]);
+        Ok(AsyncSink::Ready)
+    }
 
-        debug!(message = "sending event.", count = 1);
-        let future = match self.producer.send_result(record) {
-            Ok(f) => f,
-            Err((e, record)) => {
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        try_ready!(self.poll_pending());
+        self.poll_deliveries()
+    }
+}
+
+impl KafkaSink {
+    /// Hands the pending record to the producer. If the producer queue is still
+    /// full after polling the deliveries in flight, the record stays pending.
+    fn poll_pending(&mut self) -> Poll<(), ()> {
+        if let Some(record) = self.pending.take() {
+            if let Err(record) = self.send(record) {
                 // Docs suggest this will only happen when the producer queue is full, so let's
                 // treat it as we do full buffers in other sinks
-                debug!("rdkafka queue full: {}", e);
-                self.poll_complete()?;
-
-                match self.producer.send_result(record) {
-                    Ok(f) => f,
-                    Err((e, _record)) => {
-                        debug!("rdkafka queue still full: {}", e);
-                        return Ok(AsyncSink::NotReady(item));
-                    }
+                self.poll_deliveries()?;
+
+                if let Err(record) = self.send(record) {
+                    debug!("rdkafka queue still full.");
+                    self.pending = Some(record);
+                    return Ok(Async::NotReady);
                 }
             }
+        }
+
+        Ok(Async::Ready(()))
+    }
+
+    fn send(&mut self, (key, body): (Vec<u8>, Vec<u8>)) -> Result<(), (Vec<u8>, Vec<u8>)> {
+        let result = {
+            let record = FutureRecord::to(&self.topic).key(&key).payload(&body[..]);
+            self.producer
+                .send_result(record)
+                .map_err(|(error, _record)| error)
         };
 
-        let seqno = self.seq_head;
-        self.seq_head += 1;
+        match result {
+            Ok(future) => {
+                let seqno = self.seq_head;
+                self.seq_head += 1;
 
-        self.in_flight.push(future.join(future::ok(seqno)));
-        Ok(AsyncSink::Ready)
+                self.in_flight.push(future.join(future::ok(seqno)));
+                Ok(())
+            }
+            Err(error) => {
+                debug!("rdkafka queue full: {}", error);
+                Err((key, body))
+            }
+        }
     }
 
-    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+    fn poll_deliveries(&mut self) -> Poll<(), ()> {
         loop {
             match self.in_flight.poll() {
                 // nothing ready yet
@@ -220,9 +253,9 @@ fn healthcheck(config: KafkaSinkConfig) -> super::Healthcheck {
 }
 
 fn encode_event(
-    event: &Event,
+    mut event: Event,
     key_field: &Option<Atom>,
-    encoding: &Encoding,
+    encoding: &EncodingConfig<Encoding>,
 ) -> (Vec<u8>, Vec<u8>) {
     let key = key_field
         .as_ref()
@@ -230,8 +263,10 @@ fn encode_event(
         .map(|v| v.as_bytes().to_vec())
         .unwrap_or_default();
 
-    let body = match encoding {
-        Encoding::Json => serde_json::to_vec(&event.as_log().clone().unflatten()).unwrap(),
+    encoding.apply_rules(&mut event);
+
+    let body = match encoding.codec {
+        Encoding::Json => serde_json::to_vec(&event.into_log().unflatten()).unwrap(),
         Encoding::Text => event
             .as_log()
             .get(&event::MESSAGE)
@@ -252,7 +287,8 @@ mod tests {
     fn kafka_encode_event_text() {
         let key = "";
         let message = "hello world".to_string();
-        let (key_bytes, bytes) = encode_event(&message.clone().into(), &None, &Encoding::Text);
+        let (key_bytes, bytes) =
+            encode_event(message.clone().into(), &None, &Encoding::Text.into());
 
         assert_eq!(&key_bytes[..], key.as_bytes());
         assert_eq!(&bytes[..], message.as_bytes());
@@ -269,7 +305,7 @@ mod tests {
             .as_mut_log()
             .insert_explicit("foo".into(), "bar".into());
 
-        let (key, bytes) = encode_event(&event, &Some("key".into()), &Encoding::Json);
+        let (key, bytes) = encode_event(event, &Some("key".into()), &Encoding::Json.into());
 
         let map: HashMap<String, String> = serde_json::from_slice(&bytes[..]).unwrap();
 
@@ -278,6 +314,26 @@ mod tests {
         assert_eq!(map["key"], "value".to_string());
         assert_eq!(map["foo"], "bar".to_string());
     }
+
+    #[test]
+    fn kafka_encode_event_except_key_field() {
+        let mut event = Event::from("hello world");
+        event
+            .as_mut_log()
+            .insert_explicit("key".into(), "value".into());
+
+        let encoding = EncodingConfig {
+            except_fields: Some(vec!["key".into(), "timestamp".into()]),
+            ..Encoding::Json.into()
+        };
+        let (key, bytes) = encode_event(event, &Some("key".into()), &encoding);
+
+        let map: HashMap<String, String> = serde_json::from_slice(&bytes[..]).unwrap();
+
+        assert_eq!(&key[..], "value".as_bytes());
+        assert_eq!(map.len(), 1);
+        assert_eq!(map[&event::MESSAGE.to_string()], "hello world");
+    }
 }
 
 #[cfg(feature = "kafka-integration-tests")]
@@ -326,7 +382,7 @@ mod integration_test {
         let config = KafkaSinkConfig {
             bootstrap_servers: bootstrap_servers.clone(),
             topic: topic.clone(),
-            encoding: Encoding::Text,
+            encoding: Encoding::Text.into(),
             key_field: None,
             tls,
         };
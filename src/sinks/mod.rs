@@ -17,6 +17,7 @@ pub mod kafka;
 pub mod prometheus;
 pub mod splunk_hec;
 pub mod statsd;
+pub mod syslog;
 pub mod tcp;
 pub mod util;
 pub mod vector;
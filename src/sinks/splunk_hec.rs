@@ -2,8 +2,12 @@ use crate::{
     buffers::Acker,
     event::{self, Event, ValueKind},
     sinks::util::{
-        http::{HttpRetryLogic, HttpService},
-        retries::FixedRetryPolicy,
+        adaptive_concurrency::{AdaptiveConcurrencyLimitLayer, InFlightLimit},
+        buffer::compression::Algorithm,
+        encoding::EncodingConfig,
+        http::{https_client, HttpRetryLogic, HttpService},
+        proxy::{ProxyConfig, ProxySettings},
+        retries::RetryPolicy,
         tls::{TlsOptions, TlsSettings},
         BatchServiceSink, Buffer, Compression, SinkExt,
     },
@@ -12,8 +16,7 @@ use crate::{
 use bytes::Bytes;
 use futures::{stream::iter_ok, Future, Sink};
 use http::{HttpTryFrom, Method, Request, StatusCode, Uri};
-use hyper::{Body, Client};
-use hyper_tls::HttpsConnector;
+use hyper::Body;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use snafu::{ResultExt, Snafu};
@@ -34,20 +37,24 @@ pub struct HecSinkConfig {
     pub host: String,
     #[serde(default = "default_host_field")]
     pub host_field: Atom,
-    pub encoding: Encoding,
+    pub encoding: EncodingConfig<Encoding>,
     pub compression: Option<Compression>,
     pub batch_size: Option<usize>,
     pub batch_timeout: Option<u64>,
 
     // Tower Request based configuration
-    pub request_in_flight_limit: Option<usize>,
+    pub request_in_flight_limit: Option<InFlightLimit>,
     pub request_timeout_secs: Option<u64>,
     pub request_rate_limit_duration_secs: Option<u64>,
     pub request_rate_limit_num: Option<u64>,
     pub request_retry_attempts: Option<usize>,
     pub request_retry_backoff_secs: Option<u64>,
+    pub request_retry_max_backoff_secs: Option<u64>,
+    pub request_retry_max_duration_secs: Option<u64>,
 
     pub tls: Option<TlsOptions>,
+    #[serde(default)]
+    pub proxy: ProxyConfig,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, Derivative)]
@@ -63,12 +70,19 @@ fn default_host_field() -> Atom {
     event::HOST.clone()
 }
 
+/// The HTTP Event Collector only decompresses gzip.
+const SUPPORTED_COMPRESSION: &[Algorithm] = &[Algorithm::None, Algorithm::Gzip];
+
 #[typetag::serde(name = "splunk_hec")]
 impl SinkConfig for HecSinkConfig {
     fn build(&self, acker: Acker) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
         validate_host(&self.host)?;
         let sink = hec(self.clone(), acker)?;
-        let healthcheck = healthcheck(self.token.clone(), self.host.clone())?;
+        let healthcheck = healthcheck(
+            self.token.clone(),
+            self.host.clone(),
+            ProxySettings::from_config(&self.proxy)?,
+        )?;
 
         Ok((sink, healthcheck))
     }
@@ -84,25 +98,33 @@ pub fn hec(config: HecSinkConfig, acker: Acker) -> crate::Result<super::RouterSi
     let host_field = config.host_field;
 
     let batch_size = config.batch_size.unwrap_or(bytesize::mib(1u64) as usize);
-    let gzip = match config.compression.unwrap_or(Compression::None) {
-        Compression::None => false,
-        Compression::Gzip => true,
-    };
+    let compression = config
+        .compression
+        .unwrap_or(Compression::None)
+        .supported_by(SUPPORTED_COMPRESSION)?;
     let batch_timeout = config.batch_timeout.unwrap_or(1);
 
     let timeout = config.request_timeout_secs.unwrap_or(60);
-    let in_flight_limit = config.request_in_flight_limit.unwrap_or(10);
+    let in_flight_limit = config
+        .request_in_flight_limit
+        .unwrap_or(InFlightLimit::Fixed(10));
     let rate_limit_duration = config.request_rate_limit_duration_secs.unwrap_or(1);
     let rate_limit_num = config.request_rate_limit_num.unwrap_or(10);
     let retry_attempts = config.request_retry_attempts.unwrap_or(usize::max_value());
     let retry_backoff_secs = config.request_retry_backoff_secs.unwrap_or(1);
+    let retry_max_backoff_secs = config.request_retry_max_backoff_secs.unwrap_or(10);
+    let retry_max_duration = config
+        .request_retry_max_duration_secs
+        .map(Duration::from_secs);
     let encoding = config.encoding.clone();
 
-    let policy = FixedRetryPolicy::new(
+    let policy = RetryPolicy::new(
         retry_attempts,
         Duration::from_secs(retry_backoff_secs),
         HttpRetryLogic,
-    );
+    )
+    .max_backoff(Duration::from_secs(retry_max_backoff_secs))
+    .max_duration(retry_max_duration);
 
     let uri = format!("{}/services/collector/event", host)
         .parse::<Uri>()
@@ -110,36 +132,40 @@ pub fn hec(config: HecSinkConfig, acker: Acker) -> crate::Result<super::RouterSi
     let token = Bytes::from(format!("Splunk {}", token));
 
     let tls_settings = TlsSettings::from_options(&config.tls)?;
+    let proxy_settings = ProxySettings::from_config(&config.proxy)?;
 
-    let http_service =
-        HttpService::builder()
-            .tls_settings(tls_settings)
-            .build(move |body: Vec<u8>| {
-                let mut builder = Request::builder();
-                builder.method(Method::POST);
-                builder.uri(uri.clone());
+    let http_service = HttpService::builder()
+        .tls_settings(tls_settings)
+        .proxy_settings(proxy_settings)
+        .build(move |body: Vec<u8>| {
+            let mut builder = Request::builder();
+            builder.method(Method::POST);
+            builder.uri(uri.clone());
 
-                builder.header("Content-Type", "application/json");
+            builder.header("Content-Type", "application/json");
 
-                if gzip {
-                    builder.header("Content-Encoding", "gzip");
-                }
+            if let Some(content_encoding) = compression.content_encoding() {
+                builder.header("Content-Encoding", content_encoding);
+            }
 
-                builder.header("Authorization", token.clone());
+            builder.header("Authorization", token.clone());
 
-                builder.body(body).unwrap()
-            });
+            builder.body(body).unwrap()
+        });
 
     let service = ServiceBuilder::new()
-        .concurrency_limit(in_flight_limit)
         .rate_limit(rate_limit_num, Duration::from_secs(rate_limit_duration))
         .retry(policy)
+        .layer(AdaptiveConcurrencyLimitLayer::new(
+            in_flight_limit,
+            HttpRetryLogic,
+        ))
         .timeout(Duration::from_secs(timeout))
         .service(http_service);
 
     let sink = BatchServiceSink::new(service, acker)
         .batched_with_min(
-            Buffer::new(gzip),
+            Buffer::new(compression),
             batch_size,
             Duration::from_secs(batch_timeout),
         )
@@ -156,7 +182,11 @@ enum HealthcheckError {
     QueuesFull,
 }
 
-pub fn healthcheck(token: String, host: String) -> crate::Result<super::Healthcheck> {
+pub fn healthcheck(
+    token: String,
+    host: String,
+    proxy_settings: ProxySettings,
+) -> crate::Result<super::Healthcheck> {
     let uri = format!("{}/services/collector/health/1.0", host)
         .parse::<Uri>()
         .context(super::UriParseError)?;
@@ -166,8 +196,7 @@ pub fn healthcheck(token: String, host: String) -> crate::Result<super::Healthch
         .body(Body::empty())
         .unwrap();
 
-    let https = HttpsConnector::new(4).expect("TLS initialization failed");
-    let client = Client::builder().build(https);
+    let client = https_client(TlsSettings::default(), proxy_settings)?;
 
     let healthcheck = client
         .request(request)
@@ -191,17 +220,23 @@ pub fn validate_host(host: &str) -> crate::Result<()> {
     }
 }
 
-fn encode_event(host_field: &Atom, event: Event, encoding: &Encoding) -> Option<Vec<u8>> {
-    let mut event = event.into_log();
+fn encode_event(
+    host_field: &Atom,
+    mut event: Event,
+    encoding: &EncodingConfig<Encoding>,
+) -> Option<Vec<u8>> {
+    let host = event.as_log().get(&host_field).cloned();
+    let timestamp =
+        if let Some(ValueKind::Timestamp(ts)) = event.as_mut_log().remove(&event::TIMESTAMP) {
+            ts.timestamp()
+        } else {
+            chrono::Utc::now().timestamp()
+        };
 
-    let host = event.get(&host_field).cloned();
-    let timestamp = if let Some(ValueKind::Timestamp(ts)) = event.remove(&event::TIMESTAMP) {
-        ts.timestamp()
-    } else {
-        chrono::Utc::now().timestamp()
-    };
+    encoding.apply_rules(&mut event);
+    let event = event.into_log();
 
-    let mut body = match encoding {
+    let mut body = match encoding.codec {
         Encoding::Json => json!({
             "fields": event.explicit_fields(),
             "event": event.unflatten(),
@@ -245,7 +280,7 @@ mod tests {
             .as_mut_log()
             .insert_explicit("key".into(), "value".into());
 
-        let bytes = encode_event(&host, event, &Encoding::Json).unwrap();
+        let bytes = encode_event(&host, event, &Encoding::Json.into()).unwrap();
 
         let hec_event = serde_json::from_slice::<HecEvent>(&bytes[..]).unwrap();
 
@@ -474,17 +509,23 @@ mod integration_tests {
 
         // OK
         {
-            let healthcheck =
-                sinks::splunk_hec::healthcheck(get_token(), "http://localhost:8088".to_string())
-                    .unwrap();
+            let healthcheck = sinks::splunk_hec::healthcheck(
+                get_token(),
+                "http://localhost:8088".to_string(),
+                Default::default(),
+            )
+            .unwrap();
             rt.block_on(healthcheck).unwrap();
         }
 
         // Server not listening at address
         {
-            let healthcheck =
-                sinks::splunk_hec::healthcheck(get_token(), "http://localhost:1111".to_string())
-                    .unwrap();
+            let healthcheck = sinks::splunk_hec::healthcheck(
+                get_token(),
+                "http://localhost:1111".to_string(),
+                Default::default(),
+            )
+            .unwrap();
 
             rt.block_on(healthcheck).unwrap_err();
         }
@@ -504,9 +545,12 @@ mod integration_tests {
 
         // Unhealthy server
         {
-            let healthcheck =
-                sinks::splunk_hec::healthcheck(get_token(), "http://503.returnco.de".to_string())
-                    .unwrap();
+            let healthcheck = sinks::splunk_hec::healthcheck(
+                get_token(),
+                "http://503.returnco.de".to_string(),
+                Default::default(),
+            )
+            .unwrap();
             assert_downcast_matches!(
                 rt.block_on(healthcheck).unwrap_err(),
                 HealthcheckError,
@@ -539,7 +583,7 @@ mod integration_tests {
             token: get_token(),
             host_field: "host".into(),
             compression: Some(Compression::None),
-            encoding,
+            encoding: encoding.into(),
             batch_size: Some(1),
             ..Default::default()
         }
@@ -1,7 +1,7 @@
 use crate::{
     buffers::Acker,
     event::{metric::Direction, Event, Metric},
-    sinks::util::{BatchServiceSink, Buffer, SinkExt},
+    sinks::util::{BatchServiceSink, Buffer, Compression, SinkExt},
     topology::config::{DataType, SinkConfig},
 };
 use futures::{future, sink::Sink, Future, Poll};
@@ -87,7 +87,7 @@ impl StatsdSvc {
 
         let sink = BatchServiceSink::new(svc, acker)
             .batched_with_min(
-                Buffer::new(false),
+                Buffer::new(Compression::None),
                 batch_size,
                 Duration::from_secs(batch_timeout),
             )
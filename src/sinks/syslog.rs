@@ -0,0 +1,527 @@
+use crate::{
+    buffers::Acker,
+    event::{self, Event},
+    sinks::tcp::{TcpSink, TlsConfig},
+    sinks::util::{tls::TlsSettings, SinkExt},
+    sources::syslog::rfc3164::{FACILITIES, SEVERITIES},
+    template::Template,
+    topology::config::{DataType, SinkConfig},
+};
+use bytes::Bytes;
+use chrono::{SecondsFormat, Utc};
+use futures::{
+    future, stream::iter_ok, try_ready, Async, AsyncSink, Future, Poll, Sink, StartSend,
+};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::{
+    codec::{BytesCodec, FramedWrite},
+    net::UdpSocket,
+    timer::Delay,
+};
+use tokio_uds::{ConnectFuture, UnixStream};
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("Could not bind UDP socket: {}", source))]
+    UdpBindError { source: std::io::Error },
+}
+
+/// Only the `message` field is sent, with the headers rendered from the
+/// templates below, so unlike the other log sinks there is no `encoding`
+/// option selecting fields or formatting timestamps.
+#[derive(Deserialize, Serialize, Debug)]
+// TODO: add back when serde-rs/serde#1358 is addressed
+// #[serde(deny_unknown_fields)]
+pub struct SyslogSinkConfig {
+    #[serde(flatten)]
+    pub mode: Mode,
+    #[serde(default)]
+    pub format: Format,
+    /// Prefix messages with their length as in RFC 6587 rather than ending
+    /// them with a newline, for the stream modes.
+    #[serde(default)]
+    pub octet_counting: bool,
+    /// A facility name or number, `user` if it can't be rendered.
+    pub facility: Option<Template>,
+    /// A severity name or number, `notice` if it can't be rendered.
+    pub severity: Option<Template>,
+    pub hostname: Option<Template>,
+    pub app_name: Option<Template>,
+    pub procid: Option<Template>,
+    pub msgid: Option<Template>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Mode {
+    Tcp {
+        address: String,
+        tls: Option<TlsConfig>,
+    },
+    Udp {
+        address: String,
+    },
+    Unix {
+        path: PathBuf,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Rfc5424,
+    Rfc3164,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format::Rfc5424
+    }
+}
+
+impl SyslogSinkConfig {
+    pub fn new(mode: Mode) -> Self {
+        Self {
+            mode,
+            format: Format::default(),
+            octet_counting: false,
+            facility: None,
+            severity: None,
+            hostname: None,
+            app_name: None,
+            procid: None,
+            msgid: None,
+        }
+    }
+
+    /// The fields put in the headers, by default taken from the fields the
+    /// `syslog` source sets.
+    fn header(&self) -> Header {
+        let template = |template: &Option<Template>, field: &str| {
+            template
+                .clone()
+                .unwrap_or_else(|| format!("{{{{ {} }}}}", field).into())
+        };
+        Header {
+            format: self.format,
+            facility: template(&self.facility, "facility"),
+            severity: template(&self.severity, "severity"),
+            hostname: template(&self.hostname, &event::HOST),
+            app_name: template(&self.app_name, "appname"),
+            procid: template(&self.procid, "procid"),
+            msgid: template(&self.msgid, "msgid"),
+        }
+    }
+}
+
+#[typetag::serde(name = "syslog")]
+impl SinkConfig for SyslogSinkConfig {
+    fn build(&self, acker: Acker) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
+        let header = self.header();
+        let octet_counting = self.octet_counting;
+        let encode = move |event: Event, stream: bool| {
+            let message = header.encode(&event);
+            if !stream {
+                Bytes::from(message)
+            } else if octet_counting {
+                Bytes::from(format!("{} {}", message.len(), message))
+            } else {
+                Bytes::from(message + "\n")
+            }
+        };
+
+        match &self.mode {
+            Mode::Tcp { address, tls } => {
+                let addr = resolve(address)?;
+                let tls = match tls {
+                    Some(tls) if tls.enabled.unwrap_or(false) => {
+                        Some(TlsSettings::from_options(&Some(tls.options.clone()))?)
+                    }
+                    _ => None,
+                };
+                // The certificate is checked against the host, without the port
+                let hostname = address.rsplitn(2, ':').last().unwrap_or_default();
+
+                let sink = TcpSink::new(hostname.into(), addr, tls)
+                    .stream_ack(acker)
+                    .with_flat_map(move |event| iter_ok(Some(encode(event, true))));
+                Ok((Box::new(sink), super::tcp::tcp_healthcheck(addr)))
+            }
+            Mode::Udp { address } => {
+                let sink = UdpSink::new(resolve(address)?)?
+                    .stream_ack(acker)
+                    .with_flat_map(move |event| iter_ok(Some(encode(event, false))));
+                Ok((Box::new(sink), Box::new(future::ok(()))))
+            }
+            Mode::Unix { path } => {
+                let sink = UnixSink::new(path.clone())
+                    .stream_ack(acker)
+                    .with_flat_map(move |event| iter_ok(Some(encode(event, true))));
+                Ok((Box::new(sink), Box::new(future::ok(()))))
+            }
+        }
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+}
+
+fn resolve(address: &str) -> crate::Result<SocketAddr> {
+    address
+        .to_socket_addrs()
+        .context(super::SocketAddressError)?
+        .next()
+        .ok_or_else(|| {
+            super::BuildError::DNSFailure {
+                address: address.into(),
+            }
+            .into()
+        })
+}
+
+struct Header {
+    format: Format,
+    facility: Template,
+    severity: Template,
+    hostname: Template,
+    app_name: Template,
+    procid: Template,
+    msgid: Template,
+}
+
+impl Header {
+    fn encode(&self, event: &Event) -> String {
+        let render = |template: &Template| {
+            template
+                .render_string(event)
+                .ok()
+                .filter(|value| !value.is_empty())
+        };
+
+        let facility = render(&self.facility)
+            .and_then(|facility| code(&facility, &FACILITIES))
+            .unwrap_or(1);
+        let severity = render(&self.severity)
+            .and_then(|severity| code(&severity, &SEVERITIES))
+            .unwrap_or(5);
+        let pri = facility * 8 + severity;
+
+        let log = event.as_log();
+        let timestamp = match log.get(&event::TIMESTAMP) {
+            Some(event::ValueKind::Timestamp(timestamp)) => *timestamp,
+            _ => Utc::now(),
+        };
+        let message = log
+            .get(&event::MESSAGE)
+            .map(|message| message.to_string_lossy())
+            .unwrap_or_default();
+
+        let hostname = render(&self.hostname);
+        let app_name = render(&self.app_name);
+        let procid = render(&self.procid);
+
+        match self.format {
+            Format::Rfc5424 => format!(
+                "<{}>1 {} {} {} {} {} - {}",
+                pri,
+                timestamp.to_rfc3339_opts(SecondsFormat::Micros, true),
+                header_field(hostname, 255),
+                header_field(app_name, 48),
+                header_field(procid, 128),
+                header_field(render(&self.msgid), 32),
+                message
+            ),
+            Format::Rfc3164 => {
+                let mut line = format!("<{}>{}", pri, timestamp.format("%b %e %H:%M:%S"));
+                if let Some(hostname) = hostname {
+                    line.push(' ');
+                    line.push_str(&header_field(Some(hostname), 255));
+                }
+                if let Some(app_name) = app_name {
+                    line.push(' ');
+                    line.push_str(&header_field(Some(app_name), 32));
+                    if let Some(procid) = procid {
+                        line.push('[');
+                        line.push_str(&header_field(Some(procid), 128));
+                        line.push(']');
+                    }
+                    line.push(':');
+                }
+                line.push(' ');
+                line.push_str(&message);
+                line
+            }
+        }
+    }
+}
+
+/// The code of a facility or severity, given by name or number.
+fn code(value: &str, names: &[&str]) -> Option<usize> {
+    match value.parse::<usize>() {
+        Ok(code) if code < names.len() => Some(code),
+        Ok(_) => None,
+        Err(_) => names
+            .iter()
+            .position(|name| value.eq_ignore_ascii_case(name)),
+    }
+}
+
+/// A header field without spaces, `-` if it is missing.
+fn header_field(value: Option<String>, max_length: usize) -> String {
+    match value {
+        Some(value) => value
+            .chars()
+            .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+            .take(max_length)
+            .collect(),
+        None => "-".into(),
+    }
+}
+
+struct UdpSink {
+    socket: UdpSocket,
+    addr: SocketAddr,
+}
+
+impl UdpSink {
+    fn new(addr: SocketAddr) -> crate::Result<Self> {
+        let from = match addr {
+            SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+            SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+        };
+        let socket = UdpSocket::bind(&from).context(UdpBindError)?;
+        Ok(Self { socket, addr })
+    }
+}
+
+impl Sink for UdpSink {
+    type SinkItem = Bytes;
+    type SinkError = ();
+
+    fn start_send(&mut self, message: Bytes) -> StartSend<Bytes, ()> {
+        match self.socket.poll_send_to(&message, &self.addr) {
+            Ok(Async::Ready(_)) => Ok(AsyncSink::Ready),
+            Ok(Async::NotReady) => Ok(AsyncSink::NotReady(message)),
+            Err(error) => {
+                // Datagrams are best effort, the message is dropped
+                error!(message = "error sending datagram.", %error, rate_limit_secs = 30);
+                Ok(AsyncSink::Ready)
+            }
+        }
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), ()> {
+        Ok(Async::Ready(()))
+    }
+}
+
+/// Writes to a unix stream socket, connecting again after errors.
+struct UnixSink {
+    path: PathBuf,
+    state: UnixSinkState,
+}
+
+enum UnixSinkState {
+    Disconnected,
+    Connecting(ConnectFuture),
+    Connected(FramedWrite<UnixStream, BytesCodec>),
+    Backoff(Delay),
+}
+
+impl UnixSink {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            state: UnixSinkState::Disconnected,
+        }
+    }
+
+    fn backoff(&self) -> UnixSinkState {
+        UnixSinkState::Backoff(Delay::new(Instant::now() + Duration::from_secs(1)))
+    }
+
+    fn poll_connection(&mut self) -> Poll<&mut FramedWrite<UnixStream, BytesCodec>, ()> {
+        loop {
+            self.state = match self.state {
+                UnixSinkState::Disconnected => {
+                    debug!(message = "connecting.", path = ?self.path);
+                    UnixSinkState::Connecting(UnixStream::connect(&self.path))
+                }
+                UnixSinkState::Connecting(ref mut connect) => match connect.poll() {
+                    Ok(Async::Ready(stream)) => {
+                        debug!(message = "connected.", path = ?self.path);
+                        UnixSinkState::Connected(FramedWrite::new(stream, BytesCodec::new()))
+                    }
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(error) => {
+                        error!(message = "unable to connect.", path = ?self.path, %error);
+                        self.backoff()
+                    }
+                },
+                UnixSinkState::Backoff(ref mut delay) => match delay.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    // Err can only occur if the tokio runtime has been shutdown or if more than 2^63 timers have been created
+                    Err(err) => unreachable!(err),
+                    Ok(Async::Ready(())) => UnixSinkState::Disconnected,
+                },
+                UnixSinkState::Connected(ref mut connection) => {
+                    return Ok(Async::Ready(connection));
+                }
+            };
+        }
+    }
+}
+
+impl Sink for UnixSink {
+    type SinkItem = Bytes;
+    type SinkError = ();
+
+    fn start_send(&mut self, message: Bytes) -> StartSend<Bytes, ()> {
+        let connection = match self.poll_connection()? {
+            Async::Ready(connection) => connection,
+            Async::NotReady => return Ok(AsyncSink::NotReady(message)),
+        };
+
+        match connection.start_send(message) {
+            Ok(ok) => Ok(ok),
+            Err(error) => {
+                error!(message = "error in connection.", path = ?self.path, %error);
+                self.state = UnixSinkState::Disconnected;
+                Ok(AsyncSink::Ready)
+            }
+        }
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), ()> {
+        // Don't connect before the first message comes through
+        if let UnixSinkState::Disconnected = self.state {
+            return Ok(Async::Ready(()));
+        }
+
+        let connection = try_ready!(self.poll_connection());
+        match connection.poll_complete() {
+            Ok(ok) => Ok(ok),
+            Err(error) => {
+                error!(message = "error in connection.", path = ?self.path, %error);
+                self.state = UnixSinkState::Disconnected;
+                Ok(Async::Ready(()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        sources::syslog::{self, SyslogConfig},
+        test_util::{next_addr, wait_for_tcp, CollectCurrent},
+        topology::config::{GlobalOptions, SourceConfig},
+    };
+    use chrono::TimeZone;
+    use futures::{stream, sync::mpsc};
+
+    fn event() -> Event {
+        let mut event = Event::from("hello world");
+        let log = event.as_mut_log();
+        log.insert_implicit(
+            event::TIMESTAMP.clone(),
+            Utc.ymd(2019, 2, 13).and_hms_micro(19, 48, 34, 12).into(),
+        );
+        log.insert_implicit("host".into(), "my-host".into());
+        log.insert_implicit("severity".into(), "err".into());
+        log.insert_implicit("facility".into(), "local0".into());
+        log.insert_implicit("appname".into(), "my app".into());
+        log.insert_implicit("procid".into(), 8449.into());
+        event
+    }
+
+    fn config(format: Format) -> SyslogSinkConfig {
+        SyslogSinkConfig {
+            format,
+            ..SyslogSinkConfig::new(Mode::Udp {
+                address: "127.0.0.1:514".into(),
+            })
+        }
+    }
+
+    #[test]
+    fn encodes_rfc5424() {
+        assert_eq!(
+            config(Format::Rfc5424).header().encode(&event()),
+            "<131>1 2019-02-13T19:48:34.000012Z my-host my_app 8449 - - hello world"
+        );
+    }
+
+    #[test]
+    fn encodes_rfc3164() {
+        assert_eq!(
+            config(Format::Rfc3164).header().encode(&event()),
+            "<131>Feb 13 19:48:34 my-host my_app[8449]: hello world"
+        );
+    }
+
+    #[test]
+    fn uses_templates_and_defaults() {
+        let config = SyslogSinkConfig {
+            facility: Some("{{ missing }}".into()),
+            severity: Some("3".into()),
+            app_name: Some("vector".into()),
+            ..config(Format::Rfc3164)
+        };
+        let mut event = event();
+        event.as_mut_log().remove(&"host".into());
+
+        assert_eq!(
+            config.header().encode(&event),
+            "<11>Feb 13 19:48:34 vector[8449]: hello world"
+        );
+    }
+
+    #[test]
+    fn tcp_octet_counting_roundtrip_with_syslog_source() {
+        let (tx, rx) = mpsc::channel(10);
+        let addr = next_addr();
+        let source = SyslogConfig::new(syslog::Mode::Tcp {
+            address: addr.into(),
+            tls: None,
+        })
+        .build("default", &GlobalOptions::default(), tx)
+        .unwrap();
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.spawn(source);
+        wait_for_tcp(addr);
+
+        let config = SyslogSinkConfig {
+            octet_counting: true,
+            ..SyslogSinkConfig::new(Mode::Tcp {
+                address: addr.to_string(),
+                tls: None,
+            })
+        };
+        let (sink, _) = config.build(Acker::Null).unwrap();
+
+        let mut event = event();
+        event
+            .as_mut_log()
+            .insert_explicit(event::MESSAGE.clone(), "a stack\n  trace".into());
+        let _ = rt
+            .block_on(sink.send_all(stream::iter_ok(vec![event])))
+            .unwrap();
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+
+        let (_, output) = CollectCurrent::new(rx).wait().unwrap();
+        assert_eq!(output.len(), 1);
+        let log = output[0].as_log();
+        assert_eq!(log[&event::MESSAGE], "a stack\n  trace".into());
+        assert_eq!(log[&"appname".into()], "my_app".into());
+        assert_eq!(log[&"severity".into()], "err".into());
+        assert_eq!(log[&"facility".into()], "local0".into());
+    }
+}
@@ -2,21 +2,25 @@ use crate::{
     buffers::Acker,
     event::{self, Event},
     sinks::util::{
+        encoding::EncodingConfig,
         tls::{TlsConnectorExt, TlsOptions, TlsSettings},
         SinkExt,
     },
     topology::config::{DataType, SinkConfig},
 };
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use futures::{
     future, stream::iter_ok, try_ready, Async, AsyncSink, Future, Poll, Sink, StartSend,
 };
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
+use std::collections::VecDeque;
+use std::io;
 use std::net::{SocketAddr, ToSocketAddrs};
 use std::time::{Duration, Instant};
 use tokio::{
     codec::{BytesCodec, FramedWrite},
+    io::AsyncRead,
     net::tcp::{ConnectFuture, TcpStream},
     timer::Delay,
 };
@@ -24,6 +28,13 @@ use tokio_retry::strategy::ExponentialBackoff;
 use tokio_tls::{Connect as TlsConnect, TlsConnector, TlsStream};
 use tracing::field;
 
+/// How long to wait for the peer to acknowledge events before giving up on
+/// the connection.
+const ACK_TIMEOUT: Duration = Duration::from_secs(30);
+/// The most events kept waiting for an acknowledgement, new events are only
+/// accepted once the peer catches up.
+const MAX_PENDING: usize = 1000;
+
 #[derive(Debug, Snafu)]
 enum BuildError {
     #[snafu(display("Must specify both TLS key_file and crt_file"))]
@@ -40,7 +51,7 @@ enum BuildError {
 #[serde(deny_unknown_fields)]
 pub struct TcpSinkConfig {
     pub address: String,
-    pub encoding: Encoding,
+    pub encoding: EncodingConfig<Encoding>,
     pub tls: Option<TlsConfig>,
 }
 
@@ -63,7 +74,7 @@ impl TcpSinkConfig {
     pub fn new(address: String) -> Self {
         Self {
             address,
-            encoding: Encoding::Text,
+            encoding: Encoding::Text.into(),
             tls: None,
         }
     }
@@ -115,6 +126,13 @@ pub struct TcpSink {
     tls: Option<TlsSettings>,
     state: TcpSinkState,
     backoff: ExponentialBackoff,
+    handshake: Option<Bytes>,
+    peer_acker: Option<Acker>,
+    pending: VecDeque<Bytes>,
+    unwritten: usize,
+    ack_buffer: BytesMut,
+    ack_timeout: Duration,
+    ack_deadline: Option<Delay>,
 }
 
 enum TcpSinkState {
@@ -138,9 +156,38 @@ impl TcpSink {
             tls,
             state: TcpSinkState::Disconnected,
             backoff: Self::fresh_backoff(),
+            handshake: None,
+            peer_acker: None,
+            pending: VecDeque::new(),
+            unwritten: 0,
+            ack_buffer: BytesMut::new(),
+            ack_timeout: ACK_TIMEOUT,
+            ack_deadline: None,
         }
     }
 
+    /// Sends `handshake` first on every new connection.
+    pub fn handshake(mut self, handshake: Bytes) -> Self {
+        self.handshake = Some(handshake);
+        self
+    }
+
+    /// Acks events once the peer acknowledges them, rather than once they
+    /// are written. The peer writes the number of events it accepted as a
+    /// 32 bit big endian integer. Events not acknowledged when the connection
+    /// is lost, or when the peer doesn't acknowledge anything for the ack
+    /// timeout, are written again on the next one.
+    pub fn acknowledged_by_peer(mut self, acker: Acker) -> Self {
+        self.peer_acker = Some(acker);
+        self
+    }
+
+    /// How long to wait for an acknowledgement before reconnecting.
+    pub fn ack_timeout(mut self, ack_timeout: Duration) -> Self {
+        self.ack_timeout = ack_timeout;
+        self
+    }
+
     fn fresh_backoff() -> ExponentialBackoff {
         // TODO: make configurable
         ExponentialBackoff::from_millis(2)
@@ -152,10 +199,132 @@ impl TcpSink {
         Delay::new(Instant::now() + self.backoff.next().unwrap())
     }
 
+    fn connected(&mut self, mut connection: TcpOrTlsStream) -> TcpSinkState {
+        if let Some(handshake) = &self.handshake {
+            // The write buffer of a new connection always has room for it
+            if let Err(err) = connection.start_send(handshake.clone()) {
+                error!(message = "unable to send handshake.", addr = %self.addr, error = %err);
+                return TcpSinkState::Backoff(self.next_delay());
+            }
+        }
+        TcpSinkState::Connected(connection)
+    }
+
+    fn disconnect(&mut self) {
+        debug!(
+            message = "disconnected.",
+            addr = &field::display(&self.addr)
+        );
+        if !self.pending.is_empty() {
+            // The peer may have received some of them, they are sent twice then
+            warn!(
+                message = "events not acknowledged before disconnecting will be sent again.",
+                count = self.pending.len()
+            );
+        }
+        self.unwritten = self.pending.len();
+        self.ack_buffer.clear();
+        self.ack_deadline = None;
+        self.state = TcpSinkState::Disconnected;
+    }
+
+    /// Keeps an event until the peer acknowledges it.
+    fn keep_unacked(&mut self, line: Bytes) {
+        if self.peer_acker.is_some() {
+            self.pending.push_back(line);
+        }
+    }
+
+    /// Writes the events which weren't acknowledged on a previous connection.
+    fn write_unacked(&mut self) -> Poll<(), io::Error> {
+        let connection = match &mut self.state {
+            TcpSinkState::Connected(connection) => connection,
+            _ => return Ok(Async::Ready(())),
+        };
+
+        while self.unwritten > 0 {
+            let line = self.pending[self.pending.len() - self.unwritten].clone();
+            match connection.start_send(line)? {
+                AsyncSink::Ready => self.unwritten -= 1,
+                AsyncSink::NotReady(_) => return Ok(Async::NotReady),
+            }
+        }
+
+        Ok(Async::Ready(()))
+    }
+
+    /// Reads the acknowledgements of the peer, ready once all the events
+    /// written are acknowledged. Fails if the peer stays silent for the ack
+    /// timeout, as it may not send acknowledgements at all.
+    fn poll_acks(&mut self) -> Poll<(), io::Error> {
+        let acker = match &self.peer_acker {
+            Some(acker) => acker,
+            None => return Ok(Async::Ready(())),
+        };
+        let connection = match &mut self.state {
+            TcpSinkState::Connected(connection) => connection,
+            _ => return Ok(Async::Ready(())),
+        };
+
+        while self.pending.len() > self.unwritten {
+            let mut buf = [0; 64];
+            let n = match connection.poll_read(&mut buf)? {
+                Async::Ready(n) => n,
+                Async::NotReady => {
+                    let ack_timeout = self.ack_timeout;
+                    let deadline = self
+                        .ack_deadline
+                        .get_or_insert_with(|| Delay::new(Instant::now() + ack_timeout));
+                    return match deadline.poll() {
+                        Ok(Async::NotReady) => Ok(Async::NotReady),
+                        Ok(Async::Ready(())) => Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "events not acknowledged in time, is acknowledgement enabled on the peer?",
+                        )),
+                        // Err can only occur if the tokio runtime has been shutdown or if more than 2^63 timers have been created
+                        Err(err) => unreachable!(err),
+                    };
+                }
+            };
+            if n == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "connection closed before acknowledging all events",
+                ));
+            }
+
+            self.ack_buffer.extend_from_slice(&buf[..n]);
+            while self.ack_buffer.len() >= 4 {
+                let count = self.ack_buffer.split_to(4);
+                let count = u32::from_be_bytes([count[0], count[1], count[2], count[3]]) as usize;
+                let count = count.min(self.pending.len() - self.unwritten);
+                acker.ack(count);
+                self.pending.drain(..count);
+                // The peer is making progress, give it a new ack timeout
+                self.ack_deadline = None;
+            }
+        }
+
+        self.ack_deadline = None;
+        Ok(Async::Ready(()))
+    }
+
+    fn poll_flush(&mut self) -> Poll<(), io::Error> {
+        try_ready!(self.write_unacked());
+        if let TcpSinkState::Connected(connection) = &mut self.state {
+            try_ready!(connection.poll_complete());
+        }
+        self.poll_acks()
+    }
+
     fn poll_connection(&mut self) -> Poll<&mut TcpOrTlsStream, ()> {
         loop {
             self.state = match self.state {
                 TcpSinkState::Disconnected => {
+                    // Pick up rotated certificates for the new connection
+                    if let Some(tls) = &mut self.tls {
+                        tls.reload();
+                    }
                     debug!(message = "connecting", addr = &field::display(&self.addr));
                     TcpSinkState::Connecting(TcpStream::connect(&self.addr))
                 }
@@ -190,7 +359,7 @@ impl TcpSink {
                                     TcpSinkState::Backoff(self.next_delay())
                                 }
                             },
-                            None => TcpSinkState::Connected(MaybeTlsStream::Raw(FramedWrite::new(
+                            None => self.connected(MaybeTlsStream::Raw(FramedWrite::new(
                                 socket,
                                 BytesCodec::new(),
                             ))),
@@ -209,7 +378,7 @@ impl TcpSink {
                         Ok(Async::Ready(socket)) => {
                             debug!(message = "negotiated TLS.");
                             self.backoff = Self::fresh_backoff();
-                            TcpSinkState::Connected(MaybeTlsStream::Tls(FramedWrite::new(
+                            self.connected(MaybeTlsStream::Tls(FramedWrite::new(
                                 socket,
                                 BytesCodec::new(),
                             )))
@@ -234,27 +403,46 @@ impl Sink for TcpSink {
     type SinkError = ();
 
     fn start_send(&mut self, line: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        if self.pending.len() >= MAX_PENDING {
+            if let Async::NotReady = self.poll_complete()? {
+                return Ok(AsyncSink::NotReady(line));
+            }
+        }
+
         match self.poll_connection() {
-            Ok(Async::Ready(connection)) => {
-                debug!(
-                    message = "sending event.",
-                    bytes = &field::display(line.len())
-                );
-                match connection.start_send(line) {
-                    Err(err) => {
-                        debug!(
-                            message = "disconnected.",
-                            addr = &field::display(&self.addr)
-                        );
-                        error!("Error in connection {}: {}", self.addr, err);
-                        self.state = TcpSinkState::Disconnected;
-                        Ok(AsyncSink::Ready)
-                    }
-                    Ok(ok) => Ok(ok),
+            Ok(Async::Ready(_)) => (),
+            Ok(Async::NotReady) => return Ok(AsyncSink::NotReady(line)),
+            Err(_) => unreachable!(),
+        }
+
+        // Events lost with a previous connection go first
+        let result = match self.write_unacked() {
+            Ok(Async::Ready(())) => match &mut self.state {
+                TcpSinkState::Connected(connection) => {
+                    debug!(
+                        message = "sending event.",
+                        bytes = &field::display(line.len())
+                    );
+                    connection.start_send(line.clone())
                 }
+                _ => unreachable!(),
+            },
+            Ok(Async::NotReady) => return Ok(AsyncSink::NotReady(line)),
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Err(err) => {
+                error!("Error in connection {}: {}", self.addr, err);
+                self.keep_unacked(line);
+                self.disconnect();
+                Ok(AsyncSink::Ready)
             }
-            Ok(Async::NotReady) => Ok(AsyncSink::NotReady(line)),
-            Err(_) => unreachable!(),
+            Ok(AsyncSink::Ready) => {
+                self.keep_unacked(line);
+                Ok(AsyncSink::Ready)
+            }
+            Ok(AsyncSink::NotReady(_)) => Ok(AsyncSink::NotReady(line)),
         }
     }
 
@@ -262,22 +450,26 @@ impl Sink for TcpSink {
         // Stream::forward will immediately poll_complete the sink it's forwarding to,
         // but we don't want to connect before the first event actually comes through.
         if let TcpSinkState::Disconnected = self.state {
-            return Ok(Async::Ready(()));
+            if self.pending.is_empty() {
+                return Ok(Async::Ready(()));
+            }
         }
 
-        let connection = try_ready!(self.poll_connection());
-
-        match connection.poll_complete() {
-            Err(err) => {
-                debug!(
-                    message = "disconnected.",
-                    addr = &field::display(&self.addr)
-                );
-                error!("Error in connection {}: {}", self.addr, err);
-                self.state = TcpSinkState::Disconnected;
-                Ok(Async::Ready(()))
+        loop {
+            try_ready!(self.poll_connection());
+
+            match self.poll_flush() {
+                Err(err) => {
+                    error!("Error in connection {}: {}", self.addr, err);
+                    self.disconnect();
+                    if self.pending.is_empty() {
+                        return Ok(Async::Ready(()));
+                    }
+                    // Reconnects to send the events not acknowledged yet
+                    self.state = TcpSinkState::Backoff(self.next_delay());
+                }
+                Ok(ok) => return Ok(ok),
             }
-            Ok(ok) => Ok(ok),
         }
     }
 }
@@ -286,7 +478,7 @@ pub fn raw_tcp(
     hostname: String,
     addr: SocketAddr,
     acker: Acker,
-    encoding: Encoding,
+    encoding: EncodingConfig<Encoding>,
     tls: Option<TlsSettings>,
 ) -> super::RouterSink {
     Box::new(
@@ -313,10 +505,11 @@ pub fn tcp_healthcheck(addr: SocketAddr) -> super::Healthcheck {
     Box::new(check)
 }
 
-fn encode_event(event: Event, encoding: &Encoding) -> Option<Bytes> {
+fn encode_event(mut event: Event, encoding: &EncodingConfig<Encoding>) -> Option<Bytes> {
+    encoding.apply_rules(&mut event);
     let log = event.into_log();
 
-    let b = match encoding {
+    let b = match encoding.codec {
         Encoding::Json => serde_json::to_vec(&log.unflatten()),
         Encoding::Text => {
             let bytes = log
@@ -340,6 +533,15 @@ enum MaybeTlsStream<R, T> {
     Tls(T),
 }
 
+impl TcpOrTlsStream {
+    fn poll_read(&mut self, buf: &mut [u8]) -> Poll<usize, io::Error> {
+        match self {
+            MaybeTlsStream::Raw(r) => r.get_mut().poll_read(buf),
+            MaybeTlsStream::Tls(t) => t.get_mut().poll_read(buf),
+        }
+    }
+}
+
 impl<R, T, I, E> Sink for MaybeTlsStream<R, T>
 where
     R: Sink<SinkItem = I, SinkError = E>,
@@ -362,3 +564,91 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::TcpSink;
+    use crate::{
+        buffers::Acker,
+        test_util::{next_addr, runtime},
+    };
+    use bytes::Bytes;
+    use futures::{stream, Sink};
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::atomic::Ordering,
+        thread,
+        time::Duration,
+    };
+
+    fn read_until(stream: &mut TcpStream, end: &[u8]) -> Vec<u8> {
+        let mut received = Vec::new();
+        let mut buf = [0; 64];
+        while !received.ends_with(end) {
+            let n = stream.read(&mut buf).unwrap();
+            assert!(n > 0, "connection closed");
+            received.extend_from_slice(&buf[..n]);
+        }
+        received
+    }
+
+    #[test]
+    fn resends_events_not_acknowledged_before_disconnecting() {
+        let addr = next_addr();
+        let listener = TcpListener::bind(addr).unwrap();
+        let peer = thread::spawn(move || {
+            // Closed without acknowledging anything
+            let (mut first, _) = listener.accept().unwrap();
+            read_until(&mut first, b"first\n");
+            drop(first);
+
+            let (mut second, _) = listener.accept().unwrap();
+            let received = read_until(&mut second, b"second\n");
+            second.write_all(&2u32.to_be_bytes()).unwrap();
+            received
+        });
+
+        let (acker, acked) = Acker::new_for_testing();
+        let sink = TcpSink::new("localhost".into(), addr, None).acknowledged_by_peer(acker);
+        let lines = vec![Bytes::from("first\n"), Bytes::from("second\n")];
+
+        let mut rt = runtime();
+        let _ = rt
+            .block_on(sink.send_all(stream::iter_ok::<_, ()>(lines)))
+            .unwrap();
+
+        assert_eq!(acked.load(Ordering::SeqCst), 2);
+        assert_eq!(peer.join().unwrap(), b"first\nsecond\n".to_vec());
+    }
+
+    #[test]
+    fn reconnects_when_events_are_not_acknowledged_in_time() {
+        let addr = next_addr();
+        let listener = TcpListener::bind(addr).unwrap();
+        let peer = thread::spawn(move || {
+            // Kept open without acknowledging anything
+            let (mut first, _) = listener.accept().unwrap();
+            read_until(&mut first, b"first\n");
+
+            let (mut second, _) = listener.accept().unwrap();
+            read_until(&mut second, b"first\n");
+            second.write_all(&1u32.to_be_bytes()).unwrap();
+            first
+        });
+
+        let (acker, acked) = Acker::new_for_testing();
+        let sink = TcpSink::new("localhost".into(), addr, None)
+            .acknowledged_by_peer(acker)
+            .ack_timeout(Duration::from_millis(100));
+        let lines = vec![Bytes::from("first\n")];
+
+        let mut rt = runtime();
+        let _ = rt
+            .block_on(sink.send_all(stream::iter_ok::<_, ()>(lines)))
+            .unwrap();
+
+        assert_eq!(acked.load(Ordering::SeqCst), 1);
+        peer.join().unwrap();
+    }
+}
@@ -0,0 +1,556 @@
+//! A tower layer limiting the number of in-flight requests, either to a
+//! fixed number or to a number that is adjusted from the observed request
+//! round trip times and back pressure responses.
+//!
+//! The adaptive mode follows an AIMD (additive increase, multiplicative
+//! decrease) scheme. Requests are observed in windows of roughly one round
+//! trip time. At the end of each window the limit is:
+//!
+//! - decreased by `DECREASE_RATIO` if any response in the window signaled
+//!   back pressure (as decided by the sink's `RetryLogic`) or if the average
+//!   round trip time grew noticeably compared to the moving average, or
+//! - increased by one if the limit was reached during the window, meaning
+//!   more concurrency could actually have been used.
+//!
+//! Sinks put the layer inside of their retries, right around the timeout,
+//! so every attempt of a request is measured on its own and a back pressure
+//! response is seen by the limit even when its retry then succeeds. A
+//! request waiting out its retry backoff doesn't hold a slot.
+
+use super::{retries::RetryLogic, Error};
+use futures::{
+    task::{self, Task},
+    Async, Future, Poll,
+};
+use serde::{Deserialize, Serialize};
+use std::cmp;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::clock;
+use tower::{layer::Layer, timeout::error::Elapsed, Service};
+
+const MAX_LIMIT: usize = 200;
+const DECREASE_RATIO: f64 = 0.9;
+const EWMA_ALPHA: f64 = 0.5;
+const RTT_INCREASE_THRESHOLD: f64 = 0.25;
+
+/// The `request_in_flight_limit` option, either a fixed number of requests
+/// or `"adaptive"`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(untagged)]
+pub enum InFlightLimit {
+    Fixed(usize),
+    Adaptive(Adaptive),
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Adaptive {
+    Adaptive,
+}
+
+impl From<usize> for InFlightLimit {
+    fn from(limit: usize) -> Self {
+        InFlightLimit::Fixed(limit)
+    }
+}
+
+/// All the services built by one layer share its limit.
+pub struct AdaptiveConcurrencyLimitLayer<L> {
+    controller: Arc<Controller<L>>,
+}
+
+impl<L: RetryLogic> AdaptiveConcurrencyLimitLayer<L> {
+    pub fn new(limit: InFlightLimit, logic: L) -> Self {
+        Self {
+            controller: Arc::new(Controller::new(limit, logic)),
+        }
+    }
+}
+
+impl<L> Clone for AdaptiveConcurrencyLimitLayer<L> {
+    fn clone(&self) -> Self {
+        Self {
+            controller: Arc::clone(&self.controller),
+        }
+    }
+}
+
+impl<S, L: RetryLogic> Layer<S> for AdaptiveConcurrencyLimitLayer<L> {
+    type Service = AdaptiveConcurrencyLimit<S, L>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AdaptiveConcurrencyLimit {
+            inner,
+            controller: Arc::clone(&self.controller),
+            reserved: false,
+        }
+    }
+}
+
+pub struct AdaptiveConcurrencyLimit<S, L> {
+    inner: S,
+    controller: Arc<Controller<L>>,
+    reserved: bool,
+}
+
+impl<S, L: RetryLogic> AdaptiveConcurrencyLimit<S, L> {
+    pub fn new(inner: S, limit: InFlightLimit, logic: L) -> Self {
+        Self {
+            inner,
+            controller: Arc::new(Controller::new(limit, logic)),
+            reserved: false,
+        }
+    }
+
+    /// The current in-flight limit.
+    pub fn limit(&self) -> usize {
+        self.controller.inner.lock().unwrap().limit
+    }
+}
+
+impl<S, L, Request> Service<Request> for AdaptiveConcurrencyLimit<S, L>
+where
+    S: Service<Request>,
+    S::Error: Into<Error>,
+    L: RetryLogic<Response = S::Response>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = ResponseFuture<S::Future, L>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        if !self.reserved {
+            if let Async::NotReady = self.controller.poll_acquire() {
+                return Ok(Async::NotReady);
+            }
+            self.reserved = true;
+        }
+
+        self.inner.poll_ready().map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        assert!(self.reserved, "poll_ready must be called before call");
+        self.reserved = false;
+
+        ResponseFuture {
+            inner: self.inner.call(request),
+            start: clock::now(),
+            controller: Some(Arc::clone(&self.controller)),
+        }
+    }
+}
+
+impl<S: Clone, L> Clone for AdaptiveConcurrencyLimit<S, L> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            controller: Arc::clone(&self.controller),
+            reserved: false,
+        }
+    }
+}
+
+impl<S, L> Drop for AdaptiveConcurrencyLimit<S, L> {
+    fn drop(&mut self) {
+        if self.reserved {
+            self.controller.release(None);
+        }
+    }
+}
+
+pub struct ResponseFuture<F, L> {
+    inner: F,
+    start: Instant,
+    controller: Option<Arc<Controller<L>>>,
+}
+
+impl<F, L> Future for ResponseFuture<F, L>
+where
+    F: Future,
+    F::Error: Into<Error>,
+    L: RetryLogic<Response = F::Item>,
+{
+    type Item = F::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let result = match self.inner.poll() {
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready(response)) => Ok(response),
+            Err(error) => Err(error.into()),
+        };
+
+        if let Some(controller) = self.controller.take() {
+            let back_pressure = match &result {
+                Ok(response) => controller.logic.should_retry_response(response).is_some(),
+                Err(error) => {
+                    if let Some(error) = error.downcast_ref::<L::Error>() {
+                        controller.logic.is_retriable_error(error)
+                    } else {
+                        error.downcast_ref::<Elapsed>().is_some()
+                    }
+                }
+            };
+
+            controller.release(Some((clock::now() - self.start, back_pressure)));
+        }
+
+        result.map(Async::Ready)
+    }
+}
+
+impl<F, L> Drop for ResponseFuture<F, L> {
+    fn drop(&mut self) {
+        // The request was abandoned before completing, give its slot back
+        // without using it as a measurement.
+        if let Some(controller) = self.controller.take() {
+            controller.release(None);
+        }
+    }
+}
+
+struct Controller<L> {
+    logic: L,
+    adaptive: bool,
+    inner: Mutex<Inner>,
+}
+
+impl<L> Controller<L> {
+    fn new(limit: InFlightLimit, logic: L) -> Self {
+        let (limit, adaptive) = match limit {
+            InFlightLimit::Fixed(limit) => (limit, false),
+            InFlightLimit::Adaptive(_) => (1, true),
+        };
+
+        Self {
+            logic,
+            adaptive,
+            inner: Mutex::new(Inner::new(limit, clock::now())),
+        }
+    }
+
+    fn poll_acquire(&self) -> Async<()> {
+        let mut inner = self.inner.lock().unwrap();
+
+        if inner.in_flight < inner.limit {
+            inner.in_flight += 1;
+            Async::Ready(())
+        } else {
+            inner.reached_limit = true;
+            if !inner.waiting.iter().any(Task::will_notify_current) {
+                inner.waiting.push(task::current());
+            }
+            Async::NotReady
+        }
+    }
+
+    fn release(&self, measurement: Option<(Duration, bool)>) {
+        let mut inner = self.inner.lock().unwrap();
+
+        inner.in_flight -= 1;
+
+        if let (true, Some((rtt, back_pressure))) = (self.adaptive, measurement) {
+            inner.adjust(clock::now(), rtt, back_pressure);
+        }
+
+        for task in inner.waiting.drain(..) {
+            task.notify();
+        }
+    }
+}
+
+struct Inner {
+    limit: usize,
+    in_flight: usize,
+    waiting: Vec<Task>,
+    // Moving average of the round trip time, in seconds
+    past_rtt: Option<f64>,
+    window_end: Instant,
+    window_rtt_sum: f64,
+    window_count: usize,
+    back_pressure: bool,
+    reached_limit: bool,
+}
+
+impl Inner {
+    fn new(limit: usize, now: Instant) -> Self {
+        Self {
+            limit,
+            in_flight: 0,
+            waiting: Vec::new(),
+            past_rtt: None,
+            window_end: now,
+            window_rtt_sum: 0.0,
+            window_count: 0,
+            back_pressure: false,
+            reached_limit: false,
+        }
+    }
+
+    fn adjust(&mut self, now: Instant, rtt: Duration, back_pressure: bool) {
+        self.window_rtt_sum += duration_to_secs(rtt);
+        self.window_count += 1;
+        self.back_pressure |= back_pressure;
+
+        if now < self.window_end {
+            return;
+        }
+
+        let current_rtt = self.window_rtt_sum / self.window_count as f64;
+        let past_rtt = self.past_rtt.unwrap_or(current_rtt);
+        let previous_limit = self.limit;
+
+        if self.back_pressure || current_rtt > past_rtt * (1.0 + RTT_INCREASE_THRESHOLD) {
+            self.limit = cmp::max(1, (self.limit as f64 * DECREASE_RATIO) as usize);
+        } else if self.reached_limit && self.limit < MAX_LIMIT {
+            self.limit += 1;
+        }
+
+        if self.limit != previous_limit {
+            debug!(
+                message = "adjusted in-flight limit.",
+                limit = self.limit as u64,
+                previous_limit = previous_limit as u64,
+                back_pressure = self.back_pressure,
+                rtt_ms = (current_rtt * 1000.0) as u64,
+            );
+        }
+
+        let past_rtt = past_rtt * (1.0 - EWMA_ALPHA) + current_rtt * EWMA_ALPHA;
+        self.past_rtt = Some(past_rtt);
+        self.window_end = now + secs_to_duration(past_rtt);
+        self.window_rtt_sum = 0.0;
+        self.window_count = 0;
+        self.back_pressure = false;
+        self.reached_limit = self.in_flight >= self.limit;
+    }
+}
+
+fn duration_to_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1_000_000_000.0
+}
+
+fn secs_to_duration(secs: f64) -> Duration {
+    Duration::from_nanos((secs * 1_000_000_000.0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::util::retries::RetryPolicy;
+    use crate::test_util::block_on;
+    use futures::future;
+    use std::{borrow::Cow, fmt};
+    use tokio01_test::{assert_not_ready, assert_ready, clock};
+    use tower::retry::Retry;
+    use tower_test::{assert_request_eq, mock};
+
+    #[test]
+    fn in_flight_limit_deserialize() {
+        #[derive(Deserialize, Debug)]
+        struct Config {
+            limit: InFlightLimit,
+        }
+
+        let config: Config = toml::from_str("limit = 5").unwrap();
+        assert_eq!(config.limit, InFlightLimit::Fixed(5));
+
+        let config: Config = toml::from_str(r#"limit = "adaptive""#).unwrap();
+        assert_eq!(config.limit, InFlightLimit::Adaptive(Adaptive::Adaptive));
+
+        assert!(toml::from_str::<Config>(r#"limit = "sometimes""#).is_err());
+    }
+
+    #[test]
+    fn fixed_limit_applies_back_pressure() {
+        block_on::<_, _, ()>(future::lazy(|| {
+            let (service, mut handle) = mock::pair::<&'static str, &'static str>();
+            let mut svc =
+                AdaptiveConcurrencyLimit::new(service, InFlightLimit::Fixed(2), TestLogic);
+
+            assert_ready!(svc.poll_ready());
+            let mut first = svc.call("first");
+            assert_ready!(svc.poll_ready());
+            let _second = svc.call("second");
+            assert_not_ready!(svc.poll_ready());
+
+            assert_request_eq!(handle, "first").send_response("ok");
+            assert_eq!(first.poll().unwrap(), Async::Ready("ok"));
+            assert_ready!(svc.poll_ready());
+            assert_eq!(svc.limit(), 2);
+
+            future::ok(())
+        }))
+        .unwrap();
+    }
+
+    #[test]
+    fn abandoned_request_releases_limit() {
+        block_on::<_, _, ()>(future::lazy(|| {
+            let (service, _handle) = mock::pair::<&'static str, &'static str>();
+            let mut svc =
+                AdaptiveConcurrencyLimit::new(service, InFlightLimit::Fixed(1), TestLogic);
+
+            assert_ready!(svc.poll_ready());
+            let fut = svc.call("first");
+            assert_not_ready!(svc.poll_ready());
+
+            drop(fut);
+            assert_ready!(svc.poll_ready());
+
+            future::ok(())
+        }))
+        .unwrap();
+    }
+
+    #[test]
+    fn waiting_task_is_registered_once() {
+        block_on::<_, _, ()>(future::lazy(|| {
+            let (service, _handle) = mock::pair::<&'static str, &'static str>();
+            let mut svc =
+                AdaptiveConcurrencyLimit::new(service, InFlightLimit::Fixed(1), TestLogic);
+
+            assert_ready!(svc.poll_ready());
+            let _fut = svc.call("first");
+            for _ in 0..3 {
+                assert_not_ready!(svc.poll_ready());
+            }
+            assert_eq!(svc.controller.inner.lock().unwrap().waiting.len(), 1);
+
+            future::ok(())
+        }))
+        .unwrap();
+    }
+
+    #[test]
+    fn retried_back_pressure_decreases_limit() {
+        clock::mock(|clock| {
+            let (service, mut handle) = mock::pair::<&'static str, &'static str>();
+            let limit = AdaptiveConcurrencyLimit::new(
+                service,
+                InFlightLimit::Adaptive(Adaptive::Adaptive),
+                TestLogic,
+            );
+            limit.controller.inner.lock().unwrap().limit = 10;
+            let controller = Arc::clone(&limit.controller);
+
+            let policy = RetryPolicy::new(5, Duration::from_secs(1), TestLogic);
+            let mut svc = Retry::new(policy, limit);
+
+            assert_ready!(svc.poll_ready());
+            let mut fut = svc.call("hello");
+            assert_request_eq!(handle, "hello").send_response("429");
+            assert_not_ready!(fut.poll());
+
+            clock.advance(Duration::from_secs(2));
+            assert_not_ready!(fut.poll());
+
+            assert_request_eq!(handle, "hello").send_response("200");
+            assert_eq!(fut.wait().unwrap(), "200");
+            assert_eq!(controller.inner.lock().unwrap().limit, 9);
+        });
+    }
+
+    #[test]
+    fn adaptive_limit_increases_while_saturated() {
+        let start = Instant::now();
+        let rtt = Duration::from_millis(100);
+        let mut inner = Inner::new(1, start);
+
+        for i in 1..=5 {
+            inner.reached_limit = true;
+            inner.adjust(start + rtt * i, rtt, false);
+        }
+
+        assert_eq!(inner.limit, 6);
+    }
+
+    #[test]
+    fn adaptive_limit_holds_when_not_saturated() {
+        let start = Instant::now();
+        let rtt = Duration::from_millis(100);
+        let mut inner = Inner::new(4, start);
+
+        for i in 1..=5 {
+            inner.adjust(start + rtt * i, rtt, false);
+        }
+
+        assert_eq!(inner.limit, 4);
+    }
+
+    #[test]
+    fn adaptive_limit_decreases_on_back_pressure() {
+        let start = Instant::now();
+        let rtt = Duration::from_millis(100);
+        let mut inner = Inner::new(20, start);
+
+        inner.adjust(start, rtt, true);
+        assert_eq!(inner.limit, 18);
+
+        // Measurements within the same window don't decrease it again
+        inner.adjust(start + rtt / 2, rtt, true);
+        assert_eq!(inner.limit, 18);
+
+        inner.adjust(start + rtt * 2, rtt, false);
+        assert_eq!(inner.limit, 16);
+    }
+
+    #[test]
+    fn adaptive_limit_decreases_on_rtt_increase() {
+        let start = Instant::now();
+        let rtt = Duration::from_millis(100);
+        let mut inner = Inner::new(10, start);
+
+        inner.adjust(start, rtt, false);
+        assert_eq!(inner.limit, 10);
+
+        inner.adjust(start + rtt * 5, rtt * 4, false);
+        assert_eq!(inner.limit, 9);
+    }
+
+    #[test]
+    fn adaptive_limit_never_drops_below_one() {
+        let start = Instant::now();
+        let rtt = Duration::from_millis(100);
+        let mut inner = Inner::new(1, start);
+
+        for i in 0..5 {
+            inner.adjust(start + rtt * i, rtt, true);
+        }
+
+        assert_eq!(inner.limit, 1);
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestLogic;
+
+    impl RetryLogic for TestLogic {
+        type Error = TestError;
+        type Response = &'static str;
+
+        fn is_retriable_error(&self, _error: &Self::Error) -> bool {
+            true
+        }
+
+        fn should_retry_response(&self, response: &Self::Response) -> Option<Cow<str>> {
+            if *response == "429" {
+                Some("too many requests".into())
+            } else {
+                None
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct TestError;
+
+    impl fmt::Display for TestError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "error")
+        }
+    }
+
+    impl std::error::Error for TestError {}
+}
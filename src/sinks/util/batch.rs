@@ -2,6 +2,9 @@ use futures::{try_ready, Async, AsyncSink, Future, Poll, Sink, StartSend};
 use std::time::{Duration, Instant};
 use tokio::timer::Delay;
 
+/// A batch is measured in two dimensions: `len` is its size (in bytes for
+/// byte buffers, in items otherwise) and `num_items` the number of items
+/// pushed into it. Batching sinks flush on whichever limit is hit first.
 pub trait Batch {
     type Input;
     type Output;
@@ -11,6 +14,13 @@ pub trait Batch {
     fn fresh(&self) -> Self;
     fn finish(self) -> Self::Output;
     fn num_items(&self) -> usize;
+
+    /// How much pushing `item` would add to `len`. Batches that know this
+    /// are flushed before an item would take them over the size limit,
+    /// otherwise the last item pushed may go over it.
+    fn size_of(&self, _item: &Self::Input) -> usize {
+        0
+    }
 }
 
 impl<T> Batch for Vec<T> {
@@ -47,6 +57,8 @@ pub struct BatchSink<B, S> {
     inner: S,
     max_size: usize,
     min_size: usize,
+    max_events: Option<usize>,
+    flush_requested: bool,
     closing: bool,
     max_linger: Option<Duration>,
     linger_deadline: Option<Delay>,
@@ -82,18 +94,39 @@ where
             inner,
             max_size,
             min_size,
+            max_events: None,
+            flush_requested: false,
             closing: false,
             max_linger,
             linger_deadline: None,
         }
     }
 
+    /// Also flush batches once they hold `max_events` items.
+    pub fn max_events(mut self, max_events: usize) -> Self {
+        self.max_events = Some(max_events);
+        self
+    }
+
     pub fn into_inner(self) -> S {
         self.inner
     }
 
     fn should_send(&mut self) -> bool {
-        self.closing || self.batch.len() >= self.min_size || self.linger_elapsed()
+        self.closing
+            || self.flush_requested
+            || self.batch.len() >= self.min_size
+            || self.linger_elapsed()
+    }
+
+    /// Whether `item` has to go into the next batch, as adding it to the
+    /// current one would go over one of the limits.
+    fn overflows(&self, item: &B::Input) -> bool {
+        !self.batch.is_empty()
+            && (self.batch.len() + self.batch.size_of(item) > self.max_size
+                || self
+                    .max_events
+                    .map_or(false, |max_events| self.batch.num_items() >= max_events))
     }
 
     fn linger_elapsed(&mut self) -> bool {
@@ -112,6 +145,7 @@ where
             Ok(Async::NotReady)
         } else {
             self.linger_deadline = None;
+            self.flush_requested = false;
             Ok(Async::Ready(()))
         }
     }
@@ -131,10 +165,11 @@ where
     // and need to push it down to the inner sink. The other case, when our batch is not full but
     // we want to push it to the inner sink anyway, can be detected and handled by poll_complete.
     fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
-        if self.batch.len() >= self.max_size {
+        if self.batch.len() >= self.max_size || self.overflows(&item) {
+            self.flush_requested = true;
             self.poll_complete()?;
 
-            if self.batch.len() > self.max_size {
+            if self.overflows(&item) {
                 debug!(message = "Buffer full; applying back pressure.", max_size = %self.max_size, rate_limit_secs = 10);
                 return Ok(AsyncSink::NotReady(item));
             }
@@ -202,7 +237,7 @@ where
 #[cfg(test)]
 mod test {
     use super::{Batch, BatchSink};
-    use crate::sinks::util::Buffer;
+    use crate::sinks::util::{Buffer, Compression};
     use futures::{Future, Sink};
 
     #[test]
@@ -238,7 +273,7 @@ mod test {
 
     #[test]
     fn batch_sink_allows_the_final_item_to_exceed_the_buffer_size() {
-        let buffered = BatchSink::new(vec![], Buffer::new(false), 10);
+        let buffered = BatchSink::new(vec![], Buffer::new(Compression::None), 10);
 
         let input = vec![
             vec![0, 1, 2],
@@ -0,0 +1,279 @@
+use flate2::write::{GzEncoder, ZlibEncoder};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use snafu::Snafu;
+use std::fmt;
+use std::io::{self, Write};
+
+/// The `compression` option of the batching sinks.
+///
+/// It is either the name of the algorithm, `compression = "zstd"`, or a
+/// table that also sets the compression level,
+/// `compression = { algorithm = "zstd", level = 10 }`. Without a level each
+/// algorithm uses its own default.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip(Option<u32>),
+    Deflate(Option<u32>),
+    Zstd(Option<u32>),
+    Snappy,
+    Lz4(Option<u32>),
+}
+
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    None,
+    Gzip,
+    Deflate,
+    Zstd,
+    Snappy,
+    Lz4,
+}
+
+#[derive(Debug, Snafu)]
+pub enum CompressionError {
+    #[snafu(display(
+        "{:?} compression is not supported by this sink, use one of {:?}",
+        algorithm,
+        supported
+    ))]
+    UnsupportedAlgorithm {
+        algorithm: Algorithm,
+        supported: &'static [Algorithm],
+    },
+}
+
+impl Compression {
+    pub fn new(algorithm: Algorithm, level: Option<u32>) -> Result<Self, String> {
+        let max_level = match algorithm {
+            Algorithm::None | Algorithm::Snappy => 0,
+            Algorithm::Gzip | Algorithm::Deflate => 9,
+            Algorithm::Zstd => 21,
+            Algorithm::Lz4 => 16,
+        };
+
+        match level {
+            Some(_) if max_level == 0 => {
+                return Err(format!("{:?} compression does not take a level", algorithm))
+            }
+            Some(level) if level > max_level => {
+                return Err(format!(
+                    "{:?} compression level must be at most {}, got {}",
+                    algorithm, max_level, level
+                ))
+            }
+            _ => (),
+        }
+
+        Ok(match algorithm {
+            Algorithm::None => Compression::None,
+            Algorithm::Gzip => Compression::Gzip(level),
+            Algorithm::Deflate => Compression::Deflate(level),
+            Algorithm::Zstd => Compression::Zstd(level),
+            Algorithm::Snappy => Compression::Snappy,
+            Algorithm::Lz4 => Compression::Lz4(level),
+        })
+    }
+
+    /// Checks that the receiving end of a sink can decompress the configured
+    /// algorithm, most services only understanding a few of them.
+    pub fn supported_by(self, supported: &'static [Algorithm]) -> Result<Self, CompressionError> {
+        if supported.contains(&self.algorithm()) {
+            Ok(self)
+        } else {
+            Err(CompressionError::UnsupportedAlgorithm {
+                algorithm: self.algorithm(),
+                supported,
+            })
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            Compression::None => Algorithm::None,
+            Compression::Gzip(_) => Algorithm::Gzip,
+            Compression::Deflate(_) => Algorithm::Deflate,
+            Compression::Zstd(_) => Algorithm::Zstd,
+            Compression::Snappy => Algorithm::Snappy,
+            Compression::Lz4(_) => Algorithm::Lz4,
+        }
+    }
+
+    pub fn level(&self) -> Option<u32> {
+        match self {
+            Compression::Gzip(level)
+            | Compression::Deflate(level)
+            | Compression::Zstd(level)
+            | Compression::Lz4(level) => *level,
+            Compression::None | Compression::Snappy => None,
+        }
+    }
+
+    /// The value of the `Content-Encoding` header for a compressed body.
+    pub fn content_encoding(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip(_) => Some("gzip"),
+            Compression::Deflate(_) => Some("deflate"),
+            Compression::Zstd(_) => Some("zstd"),
+            Compression::Snappy => Some("snappy"),
+            Compression::Lz4(_) => Some("lz4"),
+        }
+    }
+
+    /// The file extension of a compressed file, without the leading dot.
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip(_) => Some("gz"),
+            Compression::Deflate(_) => Some("zz"),
+            Compression::Zstd(_) => Some("zst"),
+            Compression::Snappy => Some("sz"),
+            Compression::Lz4(_) => Some("lz4"),
+        }
+    }
+
+    pub(super) fn writer(&self) -> Compressor {
+        let buffer = Vec::new();
+        match *self {
+            Compression::None => Compressor::Plain(buffer),
+            Compression::Gzip(level) => Compressor::Gzip(GzEncoder::new(
+                buffer,
+                flate2::Compression::new(level.unwrap_or(6)),
+            )),
+            Compression::Deflate(level) => Compressor::Deflate(ZlibEncoder::new(
+                buffer,
+                flate2::Compression::new(level.unwrap_or(6)),
+            )),
+            Compression::Zstd(level) => Compressor::Zstd(
+                // Level 0 selects zstd's default level
+                zstd::Encoder::new(buffer, level.unwrap_or(0) as i32)
+                    .expect("Level was validated, writing to a Vec can't fail"),
+            ),
+            Compression::Snappy => Compressor::Snappy(snap::write::FrameEncoder::new(buffer)),
+            Compression::Lz4(level) => Compressor::Lz4(
+                lz4::EncoderBuilder::new()
+                    .level(level.unwrap_or(0))
+                    .build(buffer)
+                    .expect("Writing to a Vec can't fail"),
+            ),
+        }
+    }
+}
+
+impl From<Algorithm> for Compression {
+    fn from(algorithm: Algorithm) -> Self {
+        Compression::new(algorithm, None).expect("No level is always valid")
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum CompressionConfig {
+    Algorithm(Algorithm),
+    #[serde(deny_unknown_fields)]
+    Config {
+        algorithm: Algorithm,
+        level: Option<u32>,
+    },
+}
+
+impl<'de> Deserialize<'de> for Compression {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match CompressionConfig::deserialize(deserializer)? {
+            CompressionConfig::Algorithm(algorithm) => Ok(algorithm.into()),
+            CompressionConfig::Config { algorithm, level } => {
+                Compression::new(algorithm, level).map_err(de::Error::custom)
+            }
+        }
+    }
+}
+
+impl Serialize for Compression {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let config = match self.level() {
+            None => CompressionConfig::Algorithm(self.algorithm()),
+            level => CompressionConfig::Config {
+                algorithm: self.algorithm(),
+                level,
+            },
+        };
+        config.serialize(serializer)
+    }
+}
+
+/// A `Vec<u8>` wrapped in the streaming encoder of a compression algorithm.
+pub(super) enum Compressor {
+    Plain(Vec<u8>),
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(ZlibEncoder<Vec<u8>>),
+    Zstd(zstd::Encoder<Vec<u8>>),
+    Snappy(snap::write::FrameEncoder<Vec<u8>>),
+    Lz4(lz4::Encoder<Vec<u8>>),
+}
+
+impl Compressor {
+    pub(super) fn write_all(&mut self, input: &[u8]) -> io::Result<()> {
+        match self {
+            Compressor::Plain(inner) => {
+                inner.extend_from_slice(input);
+                Ok(())
+            }
+            Compressor::Gzip(inner) => inner.write_all(input),
+            Compressor::Deflate(inner) => inner.write_all(input),
+            Compressor::Zstd(inner) => inner.write_all(input),
+            Compressor::Snappy(inner) => inner.write_all(input),
+            Compressor::Lz4(inner) => inner.write_all(input),
+        }
+    }
+
+    /// The size of the output written so far. This is not completely accurate
+    /// as the encoders do some internal buffering.
+    pub(super) fn size(&self) -> usize {
+        match self {
+            Compressor::Plain(inner) => inner.len(),
+            Compressor::Gzip(inner) => inner.get_ref().len(),
+            Compressor::Deflate(inner) => inner.get_ref().len(),
+            Compressor::Zstd(inner) => inner.get_ref().len(),
+            Compressor::Snappy(inner) => inner.get_ref().len(),
+            Compressor::Lz4(inner) => inner.writer().len(),
+        }
+    }
+
+    pub(super) fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            Compressor::Plain(inner) => Ok(inner),
+            Compressor::Gzip(inner) => inner.finish(),
+            Compressor::Deflate(inner) => inner.finish(),
+            Compressor::Zstd(inner) => inner.finish(),
+            Compressor::Snappy(inner) => inner
+                .into_inner()
+                .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string())),
+            Compressor::Lz4(inner) => {
+                let (inner, result) = inner.finish();
+                result.map(|()| inner)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Compressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Compressor::Plain(_) => "Plain",
+            Compressor::Gzip(_) => "Gzip",
+            Compressor::Deflate(_) => "Deflate",
+            Compressor::Zstd(_) => "Zstd",
+            Compressor::Snappy(_) => "Snappy",
+            Compressor::Lz4(_) => "Lz4",
+        };
+        f.debug_struct(name).field("size", &self.size()).finish()
+    }
+}
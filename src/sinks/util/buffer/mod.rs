@@ -1,70 +1,94 @@
 use super::batch::Batch;
-use flate2::write::GzEncoder;
+use compression::Compressor;
 use partition::Partition;
 use serde::{Deserialize, Serialize};
-use std::io::Write;
 
+pub mod compression;
 pub mod metrics;
 pub mod partition;
+pub mod vec;
 
-#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
-#[serde(rename_all = "lowercase")]
-pub enum Compression {
-    None,
-    Gzip,
-}
+pub use compression::Compression;
 
 #[derive(Debug)]
 pub struct Buffer {
-    inner: InnerBuffer,
+    inner: Compressor,
+    compression: Compression,
+    framing: Framing,
     num_items: usize,
 }
 
-#[derive(Debug)]
-pub enum InnerBuffer {
-    Plain(Vec<u8>),
-    Gzip(GzEncoder<Vec<u8>>),
+/// How the items of a batch are put together into a single payload: the
+/// `prefix` is written before the first item, the `delimiter` between items
+/// and the `suffix` after the last one.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Framing {
+    pub prefix: String,
+    pub delimiter: String,
+    pub suffix: String,
+}
+
+impl Framing {
+    /// Frames the items as a JSON array.
+    pub fn json_array() -> Self {
+        Self {
+            prefix: "[".into(),
+            delimiter: ",".into(),
+            suffix: "]".into(),
+        }
+    }
+
+    /// Frames the items as a JSON array under `key` of a wrapper object.
+    pub fn json_object(key: &str) -> Self {
+        let key = serde_json::to_string(key).expect("Serializing a str can't fail");
+        Self {
+            prefix: format!("{{{}:[", key),
+            delimiter: ",".into(),
+            suffix: "]}".into(),
+        }
+    }
 }
 
 impl Buffer {
-    pub fn new(gzip: bool) -> Self {
-        let inner = if gzip {
-            InnerBuffer::Gzip(GzEncoder::new(Vec::new(), flate2::Compression::fast()))
-        } else {
-            InnerBuffer::Plain(Vec::new())
-        };
+    pub fn new(compression: Compression) -> Self {
+        Self::with_framing(compression, Framing::default())
+    }
+
+    pub fn with_framing(compression: Compression, framing: Framing) -> Self {
         Self {
-            inner,
+            inner: compression.writer(),
+            compression,
+            framing,
             num_items: 0,
         }
     }
 
     pub fn push(&mut self, input: &[u8]) {
+        let framing = if self.num_items == 0 {
+            &self.framing.prefix
+        } else {
+            &self.framing.delimiter
+        };
+        self.inner
+            .write_all(framing.as_bytes())
+            .expect("This can't fail because the inner writer is a Vec");
+        self.inner
+            .write_all(input)
+            .expect("This can't fail because the inner writer is a Vec");
         self.num_items += 1;
-        match &mut self.inner {
-            InnerBuffer::Plain(inner) => {
-                inner.extend_from_slice(input);
-            }
-            InnerBuffer::Gzip(inner) => {
-                inner.write_all(input).unwrap();
-            }
-        }
     }
 
-    // This is not guaranteed to be completely accurate as the gzip library does
-    // some internal buffering.
+    // This is not guaranteed to be completely accurate as the compression
+    // libraries do some internal buffering.
     pub fn size(&self) -> usize {
-        match &self.inner {
-            InnerBuffer::Plain(inner) => inner.len(),
-            InnerBuffer::Gzip(inner) => inner.get_ref().len(),
-        }
+        self.inner.size()
     }
 
     pub fn is_empty(&self) -> bool {
-        match &self.inner {
-            InnerBuffer::Plain(inner) => inner.is_empty(),
-            InnerBuffer::Gzip(inner) => inner.get_ref().is_empty(),
-        }
+        // Some encoders write a header up front, so the output size can't be
+        // used here.
+        self.num_items == 0
     }
 }
 
@@ -85,25 +109,18 @@ impl Batch for Buffer {
     }
 
     fn fresh(&self) -> Self {
-        let inner = match &self.inner {
-            InnerBuffer::Plain(_) => InnerBuffer::Plain(Vec::new()),
-            InnerBuffer::Gzip(_) => {
-                InnerBuffer::Gzip(GzEncoder::new(Vec::new(), flate2::Compression::default()))
-            }
-        };
-        Self {
-            inner,
-            num_items: 0,
-        }
+        Self::with_framing(self.compression, self.framing.clone())
     }
 
-    fn finish(self) -> Self::Output {
-        match self.inner {
-            InnerBuffer::Plain(inner) => inner,
-            InnerBuffer::Gzip(inner) => inner
-                .finish()
-                .expect("This can't fail because the inner writer is a Vec"),
+    fn finish(mut self) -> Self::Output {
+        if self.num_items > 0 {
+            self.inner
+                .write_all(self.framing.suffix.as_bytes())
+                .expect("This can't fail because the inner writer is a Vec");
         }
+        self.inner
+            .finish()
+            .expect("This can't fail because the inner writer is a Vec")
     }
 
     fn num_items(&self) -> usize {
@@ -167,6 +184,10 @@ where
     fn num_items(&self) -> usize {
         self.inner.num_items()
     }
+
+    fn size_of(&self, item: &Self::Input) -> usize {
+        self.inner.size_of(&item.inner)
+    }
 }
 
 impl<T, K> PartitionInnerBuffer<T, K> {
@@ -190,7 +211,7 @@ where
 
 #[cfg(test)]
 mod test {
-    use super::Buffer;
+    use super::{compression::Algorithm, Buffer, Compression, Framing};
     use crate::sinks::util::batch::{Batch, BatchSink};
     use futures::{Future, Sink};
     use std::io::Read;
@@ -199,7 +220,7 @@ mod test {
     fn gzip() {
         use flate2::read::GzDecoder;
 
-        let buffered = BatchSink::new(vec![], Buffer::new(true), 1000);
+        let buffered = BatchSink::new(vec![], Buffer::new(Compression::Gzip(None)), 1000);
 
         let input = std::iter::repeat(
             b"It's going down, I'm yelling timber, You better move, you better dance".to_vec(),
@@ -234,4 +255,132 @@ mod test {
         .take(100_000)
         .flatten()));
     }
+
+    fn roundtrip(compression: Compression, decompress: impl Fn(&[u8]) -> Vec<u8>) {
+        let input = b"It's going down, I'm yelling timber, You better move, you better dance\n";
+
+        let mut buffer = Buffer::new(compression);
+        assert!(buffer.is_empty());
+        for _ in 0..1000 {
+            buffer.push(input);
+        }
+        assert!(!buffer.is_empty());
+        assert_eq!(buffer.num_items(), 1000);
+
+        let output = buffer.finish();
+        assert!(output.len() < input.len() * 100);
+        let expected = std::iter::repeat(&input[..])
+            .take(1000)
+            .flatten()
+            .cloned()
+            .collect::<Vec<u8>>();
+        assert_eq!(decompress(&output), expected);
+    }
+
+    #[test]
+    fn deflate() {
+        roundtrip(Compression::Deflate(Some(9)), |data| {
+            let mut decompressed = vec![];
+            flate2::read::ZlibDecoder::new(data)
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            decompressed
+        });
+    }
+
+    #[test]
+    fn zstd() {
+        roundtrip(Compression::Zstd(Some(3)), |data| {
+            zstd::decode_all(data).unwrap()
+        });
+    }
+
+    #[test]
+    fn snappy() {
+        roundtrip(Compression::Snappy, |data| {
+            let mut decompressed = vec![];
+            snap::read::FrameDecoder::new(data)
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            decompressed
+        });
+    }
+
+    #[test]
+    fn lz4() {
+        roundtrip(Compression::Lz4(None), |data| {
+            let mut decompressed = vec![];
+            lz4::Decoder::new(data)
+                .unwrap()
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            decompressed
+        });
+    }
+
+    #[test]
+    fn compression_deserialize() {
+        #[derive(serde::Deserialize, Debug)]
+        struct Config {
+            compression: Compression,
+        }
+
+        let parse = |s: &str| toml::from_str::<Config>(s).map(|config| config.compression);
+
+        assert_eq!(parse(r#"compression = "none""#).unwrap(), Compression::None);
+        assert_eq!(
+            parse(r#"compression = "gzip""#).unwrap(),
+            Compression::Gzip(None)
+        );
+        assert_eq!(
+            parse(r#"compression = { algorithm = "zstd", level = 10 }"#).unwrap(),
+            Compression::Zstd(Some(10))
+        );
+        assert_eq!(
+            parse(r#"compression = { algorithm = "deflate" }"#).unwrap(),
+            Algorithm::Deflate.into()
+        );
+        assert!(parse(r#"compression = { algorithm = "gzip", level = 10 }"#).is_err());
+        assert!(parse(r#"compression = { algorithm = "snappy", level = 1 }"#).is_err());
+        assert!(parse(r#"compression = "brotli""#).is_err());
+    }
+
+    #[test]
+    fn compression_supported_by() {
+        const SUPPORTED: &[Algorithm] = &[Algorithm::None, Algorithm::Gzip];
+
+        assert_eq!(
+            Compression::Gzip(Some(9)).supported_by(SUPPORTED).unwrap(),
+            Compression::Gzip(Some(9))
+        );
+        assert!(Compression::None.supported_by(SUPPORTED).is_ok());
+        assert!(Compression::Zstd(None).supported_by(SUPPORTED).is_err());
+        assert!(Compression::Snappy.supported_by(SUPPORTED).is_err());
+    }
+
+    fn framed(framing: Framing, items: &[&str]) -> String {
+        let mut buffer = Buffer::with_framing(Compression::None, framing);
+        for item in items {
+            buffer.push(item.as_bytes());
+        }
+        String::from_utf8(buffer.finish()).unwrap()
+    }
+
+    #[test]
+    fn framing() {
+        assert_eq!(framed(Framing::default(), &["a", "b"]), "ab");
+        assert_eq!(framed(Framing::json_array(), &["1", "2", "3"]), "[1,2,3]");
+        assert_eq!(
+            framed(Framing::json_object("events"), &["1", "2"]),
+            r#"{"events":[1,2]}"#
+        );
+
+        let framing = Framing {
+            prefix: "<".into(),
+            delimiter: "|".into(),
+            suffix: ">".into(),
+        };
+        assert_eq!(framed(framing.clone(), &["a"]), "<a>");
+        assert_eq!(framed(framing, &[]), "");
+    }
 }
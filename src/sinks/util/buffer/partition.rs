@@ -34,6 +34,7 @@ struct Config {
     max_linger: Option<Duration>,
     max_size: usize,
     min_size: usize,
+    max_events: Option<usize>,
 }
 
 enum LingerState<K> {
@@ -50,6 +51,7 @@ where
             max_linger: None,
             max_size,
             min_size: 0,
+            max_events: None,
         };
 
         Self {
@@ -76,6 +78,7 @@ where
             max_linger: Some(linger),
             max_size,
             min_size,
+            max_events: None,
         };
 
         Self {
@@ -90,6 +93,12 @@ where
         }
     }
 
+    /// Also flush batches once they hold `max_events` items.
+    pub fn max_events(mut self, max_events: usize) -> Self {
+        self.config.max_events = Some(max_events);
+        self
+    }
+
     pub fn into_inner_sink(self) -> S {
         self.sink
     }
@@ -148,7 +157,22 @@ where
 
         let partition = item.partition();
 
-        if let Some(batch) = self.partitions.get_mut(&partition) {
+        let overflows = self
+            .partitions
+            .get(&partition)
+            .map_or(false, |batch| self.config.overflows(batch, &item));
+        if overflows {
+            // Send the batch on its own and start a new one with this item.
+            let batch = self.partitions.remove(&partition).unwrap();
+            if let Some(linger_cancel) = self.linger_handles.remove(&partition) {
+                linger_cancel
+                    .send(partition.clone())
+                    .map_err(|_| ())
+                    .expect("Linger deadline should be removed on elapsed.");
+            }
+            self.sending.push_back(batch);
+            self.poll_complete()?;
+        } else if let Some(batch) = self.partitions.get_mut(&partition) {
             if batch.len() >= self.config.max_size {
                 self.poll_complete()?;
 
@@ -196,8 +220,7 @@ where
         }
 
         let closing = self.closing;
-        let max_size = self.config.max_size;
-        let min_size = self.config.min_size;
+        let config = self.config;
 
         let mut partitions = Vec::new();
         while let Ok(Async::Ready(Some(linger))) = self.lingers.poll() {
@@ -214,7 +237,7 @@ where
         let ready = self
             .partitions
             .iter()
-            .filter(|(_, b)| closing || (b.len() >= max_size || b.len() >= min_size))
+            .filter(|(_, b)| closing || config.is_full(*b) || b.len() >= config.min_size)
             .map(|(p, _)| p.clone())
             .collect::<Vec<_>>();
 
@@ -250,12 +273,32 @@ where
     }
 }
 
+impl Config {
+    fn is_full<B: Batch>(&self, batch: &B) -> bool {
+        batch.len() >= self.max_size
+            || self
+                .max_events
+                .map_or(false, |max_events| batch.num_items() >= max_events)
+    }
+
+    /// Whether `item` has to go into a new batch, as adding it to `batch`
+    /// would go over one of the limits.
+    fn overflows<B: Batch>(&self, batch: &B, item: &B::Input) -> bool {
+        !batch.is_empty()
+            && (batch.len() + batch.size_of(item) > self.max_size
+                || self
+                    .max_events
+                    .map_or(false, |max_events| batch.num_items() >= max_events))
+    }
+}
+
 impl<B, S, K> fmt::Debug for PartitionedBatchSink<B, S, K> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("PartitionedBatchSink")
             .field("max_linger", &self.config.max_linger)
             .field("max_size", &self.config.max_size)
             .field("min_size", &self.config.min_size)
+            .field("max_events", &self.config.max_events)
             .finish()
     }
 }
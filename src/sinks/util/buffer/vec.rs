@@ -0,0 +1,146 @@
+use super::super::batch::Batch;
+
+/// The encoded size of an item, as counted against the request size limit
+/// of a service.
+pub trait ByteSize {
+    fn byte_size(&self) -> usize;
+}
+
+/// A batch of items whose size is the sum of their `ByteSize`, for services
+/// that limit both the number of records and the size of a request.
+#[derive(Debug, Clone)]
+pub struct VecBuffer<T> {
+    items: Vec<T>,
+    bytes: usize,
+}
+
+impl<T> VecBuffer<T> {
+    pub fn new() -> Self {
+        Self {
+            items: Vec::new(),
+            bytes: 0,
+        }
+    }
+}
+
+impl<T> Default for VecBuffer<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ByteSize> Batch for VecBuffer<T> {
+    type Input = T;
+    type Output = Vec<T>;
+
+    fn len(&self) -> usize {
+        self.bytes
+    }
+
+    fn push(&mut self, item: Self::Input) {
+        self.bytes += item.byte_size();
+        self.items.push(item);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    fn fresh(&self) -> Self {
+        Self::new()
+    }
+
+    fn finish(self) -> Self::Output {
+        self.items
+    }
+
+    fn num_items(&self) -> usize {
+        self.items.len()
+    }
+
+    fn size_of(&self, item: &Self::Input) -> usize {
+        item.byte_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::util::{
+        BatchSink, PartitionBuffer, PartitionInnerBuffer, PartitionedBatchSink,
+    };
+    use futures::{Future, Sink};
+
+    impl ByteSize for &'static str {
+        fn byte_size(&self) -> usize {
+            self.len()
+        }
+    }
+
+    #[test]
+    fn flushes_before_going_over_the_size() {
+        let buffered = BatchSink::new(vec![], VecBuffer::new(), 10);
+
+        let input = vec!["aaaa", "bbbb", "cccc", "dddddddddddd", "e"];
+        let (buffered, _) = buffered
+            .send_all(futures::stream::iter_ok(input))
+            .wait()
+            .unwrap();
+
+        assert_eq!(
+            buffered.into_inner(),
+            vec![
+                vec!["aaaa", "bbbb"],
+                vec!["cccc"],
+                vec!["dddddddddddd"],
+                vec!["e"]
+            ]
+        );
+    }
+
+    #[test]
+    fn flushes_on_max_events() {
+        let buffered = BatchSink::new(vec![], VecBuffer::new(), 100).max_events(2);
+
+        let input = vec!["a", "b", "c", "d", "e"];
+        let (buffered, _) = buffered
+            .send_all(futures::stream::iter_ok(input))
+            .wait()
+            .unwrap();
+
+        assert_eq!(
+            buffered.into_inner(),
+            vec![vec!["a", "b"], vec!["c", "d"], vec!["e"]]
+        );
+    }
+
+    #[test]
+    fn partitioned_flushes_on_either_limit() {
+        let buffered =
+            PartitionedBatchSink::new(vec![], PartitionBuffer::new(VecBuffer::new()), 10)
+                .max_events(3);
+
+        let input = vec!["a", "b", "c", "d", "eeeeeeeeee", "f"]
+            .into_iter()
+            .map(|item| PartitionInnerBuffer::new(item, 1));
+        let (buffered, _) = buffered
+            .send_all(futures::stream::iter_ok(input))
+            .wait()
+            .unwrap();
+
+        let output = buffered
+            .into_inner_sink()
+            .into_iter()
+            .map(|batch| batch.into_parts().0)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            output,
+            vec![
+                vec!["a", "b", "c"],
+                vec!["d"],
+                vec!["eeeeeeeeee"],
+                vec!["f"]
+            ]
+        );
+    }
+}
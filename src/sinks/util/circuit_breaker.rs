@@ -0,0 +1,425 @@
+//! A tower layer that stops sending requests to a downstream service that
+//! keeps failing.
+//!
+//! The breaker counts consecutive failed requests, as classified by the
+//! sink's `RetryLogic`. Once the failure threshold is reached the breaker
+//! opens and `poll_ready` returns `NotReady`, so the batch sink stops pulling
+//! events and they accumulate in the sink's buffer instead. While open, the
+//! sink's healthcheck is run every probe interval and the first successful
+//! probe closes the breaker again.
+//!
+//! The breaker's internal metrics carry no scope of their own, they are
+//! reported under the scope of the sink span they are recorded in.
+
+use super::{retries::RetryLogic, Error};
+use crate::sinks::Healthcheck;
+use futures::{
+    task::{self, Task},
+    Async, Future, Poll,
+};
+use std::mem;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::{clock, timer::Delay};
+use tower::{layer::Layer, timeout::error::Elapsed, Service};
+
+/// Builds a fresh healthcheck future for every probe.
+pub type Probe = Arc<dyn Fn() -> Healthcheck + Send + Sync>;
+
+#[derive(Clone)]
+pub struct CircuitBreakerLayer<L> {
+    failure_threshold: Option<usize>,
+    probe_interval: Duration,
+    logic: L,
+    probe: Probe,
+}
+
+impl<L: RetryLogic> CircuitBreakerLayer<L> {
+    /// A `failure_threshold` of `None` disables the breaker, requests are
+    /// then passed through untouched.
+    pub fn new<F>(failure_threshold: Option<usize>, logic: L, probe: F) -> Self
+    where
+        F: Fn() -> Healthcheck + Send + Sync + 'static,
+    {
+        Self {
+            failure_threshold,
+            probe_interval: Duration::from_secs(30),
+            logic,
+            probe: Arc::new(probe),
+        }
+    }
+
+    pub fn probe_interval(mut self, probe_interval: Duration) -> Self {
+        self.probe_interval = probe_interval;
+        self
+    }
+}
+
+impl<S, L: RetryLogic> Layer<S> for CircuitBreakerLayer<L> {
+    type Service = CircuitBreaker<S, L>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        let breaker = Breaker {
+            failure_threshold: self.failure_threshold,
+            probe_interval: self.probe_interval,
+            logic: self.logic.clone(),
+            probe: Arc::clone(&self.probe),
+            state: Mutex::new(State::default()),
+        };
+
+        if breaker.failure_threshold.is_some() {
+            trace!(circuit_breaker_open_gauge = 0u64);
+        }
+
+        CircuitBreaker {
+            inner,
+            breaker: Arc::new(breaker),
+        }
+    }
+}
+
+pub struct CircuitBreaker<S, L> {
+    inner: S,
+    breaker: Arc<Breaker<L>>,
+}
+
+impl<S, L> CircuitBreaker<S, L> {
+    /// Whether the breaker is currently open.
+    pub fn is_open(&self) -> bool {
+        self.breaker.state.lock().unwrap().open.is_some()
+    }
+}
+
+impl<S, L, Request> Service<Request> for CircuitBreaker<S, L>
+where
+    S: Service<Request>,
+    S::Error: Into<Error>,
+    L: RetryLogic<Response = S::Response>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = ResponseFuture<S::Future, L>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        if let Async::NotReady = self.breaker.poll_closed() {
+            return Ok(Async::NotReady);
+        }
+
+        self.inner.poll_ready().map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        ResponseFuture {
+            inner: self.inner.call(request),
+            breaker: Arc::clone(&self.breaker),
+        }
+    }
+}
+
+impl<S: Clone, L> Clone for CircuitBreaker<S, L> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            breaker: Arc::clone(&self.breaker),
+        }
+    }
+}
+
+pub struct ResponseFuture<F, L> {
+    inner: F,
+    breaker: Arc<Breaker<L>>,
+}
+
+impl<F, L> Future for ResponseFuture<F, L>
+where
+    F: Future,
+    F::Error: Into<Error>,
+    L: RetryLogic<Response = F::Item>,
+{
+    type Item = F::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let result = match self.inner.poll() {
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready(response)) => Ok(response),
+            Err(error) => Err(error.into()),
+        };
+
+        let logic = &self.breaker.logic;
+        let failed = match &result {
+            Ok(response) => logic.should_retry_response(response).is_some(),
+            Err(error) => {
+                if let Some(error) = error.downcast_ref::<L::Error>() {
+                    logic.is_retriable_error(error)
+                } else {
+                    error.downcast_ref::<Elapsed>().is_some()
+                }
+            }
+        };
+        self.breaker.record(failed);
+
+        result.map(Async::Ready)
+    }
+}
+
+struct Breaker<L> {
+    failure_threshold: Option<usize>,
+    probe_interval: Duration,
+    logic: L,
+    probe: Probe,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    consecutive_failures: usize,
+    open: Option<Open>,
+    waiting: Vec<Task>,
+}
+
+struct Open {
+    next_probe: Delay,
+    probing: Probing,
+}
+
+/// The healthcheck probe of an open breaker.
+enum Probing {
+    Idle,
+    Running(Healthcheck),
+    /// Taken out of the state by the task polling it, so the lock isn't
+    /// held while the healthcheck runs.
+    Polling,
+}
+
+impl<L> Breaker<L> {
+    fn record(&self, failed: bool) {
+        let threshold = match self.failure_threshold {
+            Some(threshold) => threshold,
+            None => return,
+        };
+
+        let mut state = self.state.lock().unwrap();
+
+        if !failed {
+            state.consecutive_failures = 0;
+            return;
+        }
+
+        state.consecutive_failures += 1;
+
+        if state.open.is_none() && state.consecutive_failures >= threshold {
+            warn!(
+                message = "downstream service is failing; opening circuit breaker.",
+                consecutive_failures = state.consecutive_failures as u64,
+                probe_interval_secs = self.probe_interval.as_secs(),
+            );
+            trace!(circuit_breaker_open_gauge = 1u64);
+            trace!(circuit_breaker_opened_counter = 1u64);
+
+            state.open = Some(Open {
+                next_probe: Delay::new(clock::now() + self.probe_interval),
+                probing: Probing::Idle,
+            });
+        }
+    }
+
+    /// Drives the healthcheck probes while the breaker is open, resolving
+    /// once it is closed.
+    fn poll_closed(&self) -> Async<()> {
+        loop {
+            let mut probing = {
+                let mut state = self.state.lock().unwrap();
+
+                let open = match &mut state.open {
+                    Some(open) => open,
+                    None => return Async::Ready(()),
+                };
+
+                match mem::replace(&mut open.probing, Probing::Polling) {
+                    Probing::Running(probing) => probing,
+                    Probing::Idle => match open.next_probe.poll() {
+                        Ok(Async::NotReady) => {
+                            open.probing = Probing::Idle;
+                            return self.wait(state);
+                        }
+                        Ok(Async::Ready(())) => {
+                            debug!(message = "probing downstream service.");
+                            (self.probe)()
+                        }
+                        Err(error) => {
+                            error!(message = "timer error; probing now.", %error);
+                            (self.probe)()
+                        }
+                    },
+                    // Another task is polling the probe and closes the
+                    // breaker once it passes.
+                    Probing::Polling => return self.wait(state),
+                }
+            };
+
+            let result = probing.poll();
+            let mut state = self.state.lock().unwrap();
+
+            match result {
+                Ok(Async::NotReady) => {
+                    if let Some(open) = &mut state.open {
+                        open.probing = Probing::Running(probing);
+                    }
+                    return self.wait(state);
+                }
+                Ok(Async::Ready(())) => {
+                    info!(message = "downstream service is healthy; closing circuit breaker.");
+                    trace!(circuit_breaker_open_gauge = 0u64);
+
+                    state.open = None;
+                    state.consecutive_failures = 0;
+                    for task in state.waiting.drain(..) {
+                        task.notify();
+                    }
+                    return Async::Ready(());
+                }
+                Err(error) => {
+                    debug!(message = "downstream service is still unhealthy.", %error);
+                    if let Some(open) = &mut state.open {
+                        open.probing = Probing::Idle;
+                        open.next_probe = Delay::new(clock::now() + self.probe_interval);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Only the task that last polled the probe is woken when it completes,
+    /// so keep track of every task waiting on the breaker.
+    fn wait(&self, mut state: MutexGuard<'_, State>) -> Async<()> {
+        state.waiting.push(task::current());
+        Async::NotReady
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use std::fmt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio01_test::{assert_not_ready, assert_ready, clock};
+    use tower_test::{assert_request_eq, mock};
+
+    fn probe(
+        healthy_after: usize,
+    ) -> (
+        Arc<AtomicUsize>,
+        impl Fn() -> Healthcheck + Send + Sync + 'static,
+    ) {
+        let probes = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&probes);
+        let probe = move || -> Healthcheck {
+            if counter.fetch_add(1, Ordering::SeqCst) + 1 >= healthy_after {
+                Box::new(future::ok::<(), crate::Error>(()))
+            } else {
+                Box::new(future::err::<(), crate::Error>("unhealthy".into()))
+            }
+        };
+        (probes, probe)
+    }
+
+    fn fail(
+        svc: &mut CircuitBreaker<mock::Mock<&'static str, &'static str>, TestLogic>,
+        handle: &mut mock::Handle<&'static str, &'static str>,
+    ) {
+        assert_ready!(svc.poll_ready());
+        let mut fut = svc.call("hello");
+        assert_request_eq!(handle, "hello").send_error(TestError);
+        assert!(fut.poll().is_err());
+    }
+
+    fn succeed(
+        svc: &mut CircuitBreaker<mock::Mock<&'static str, &'static str>, TestLogic>,
+        handle: &mut mock::Handle<&'static str, &'static str>,
+    ) {
+        assert_ready!(svc.poll_ready());
+        let mut fut = svc.call("hello");
+        assert_request_eq!(handle, "hello").send_response("world");
+        assert_eq!(fut.poll().unwrap(), Async::Ready("world"));
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures_and_probes() {
+        clock::mock(|clock| {
+            let (probes, probe) = probe(2);
+            let layer = CircuitBreakerLayer::new(Some(2), TestLogic, probe)
+                .probe_interval(Duration::from_secs(10));
+            let (service, mut handle) = mock::pair();
+            let mut svc = layer.layer(service);
+
+            fail(&mut svc, &mut handle);
+            assert!(!svc.is_open());
+            fail(&mut svc, &mut handle);
+            assert!(svc.is_open());
+            assert_not_ready!(svc.poll_ready());
+            assert_eq!(probes.load(Ordering::SeqCst), 0);
+
+            clock.advance(Duration::from_secs(10));
+            assert_not_ready!(svc.poll_ready());
+            assert_eq!(probes.load(Ordering::SeqCst), 1);
+
+            clock.advance(Duration::from_secs(10));
+            assert_ready!(svc.poll_ready());
+            assert_eq!(probes.load(Ordering::SeqCst), 2);
+            assert!(!svc.is_open());
+
+            succeed(&mut svc, &mut handle);
+        });
+    }
+
+    #[test]
+    fn success_resets_failures() {
+        let (_, probe) = probe(1);
+        let layer = CircuitBreakerLayer::new(Some(2), TestLogic, probe);
+        let (service, mut handle) = mock::pair();
+        let mut svc = layer.layer(service);
+
+        fail(&mut svc, &mut handle);
+        succeed(&mut svc, &mut handle);
+        fail(&mut svc, &mut handle);
+        assert!(!svc.is_open());
+    }
+
+    #[test]
+    fn disabled_never_opens() {
+        let (_, probe) = probe(1);
+        let layer = CircuitBreakerLayer::new(None, TestLogic, probe);
+        let (service, mut handle) = mock::pair();
+        let mut svc = layer.layer(service);
+
+        for _ in 0..10 {
+            fail(&mut svc, &mut handle);
+        }
+        assert!(!svc.is_open());
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestLogic;
+
+    impl RetryLogic for TestLogic {
+        type Error = TestError;
+        type Response = &'static str;
+
+        fn is_retriable_error(&self, _error: &Self::Error) -> bool {
+            true
+        }
+    }
+
+    #[derive(Debug)]
+    struct TestError;
+
+    impl fmt::Display for TestError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "error")
+        }
+    }
+
+    impl std::error::Error for TestError {}
+}
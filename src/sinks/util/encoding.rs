@@ -0,0 +1,245 @@
+//! The `encoding` option shared by the log sinks.
+//!
+//! It can be given either as a bare codec, `encoding = "json"`, or as a
+//! table that also selects the fields to send and how timestamps are
+//! formatted:
+//!
+//! ```toml
+//! [sinks.out.encoding]
+//!   codec = "json"
+//!   except_fields = ["host"]
+//!   timestamp_format = "unix"
+//! ```
+//!
+//! Each sink keeps its own codec enum, as not every sink supports the same
+//! codecs.
+
+use crate::event::{Event, ValueKind};
+use serde::{Deserialize, Deserializer, Serialize};
+use string_cache::DefaultAtom as Atom;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct EncodingConfig<E> {
+    pub codec: E,
+    pub only_fields: Option<Vec<Atom>>,
+    pub except_fields: Option<Vec<Atom>>,
+    pub timestamp_format: Option<TimestampFormat>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampFormat {
+    Rfc3339,
+    Unix,
+}
+
+impl<E> EncodingConfig<E> {
+    /// Removes the fields excluded by `only_fields` and `except_fields` and
+    /// formats the timestamps, before the event is handed to the codec.
+    pub fn apply_rules(&self, event: &mut Event) {
+        let log = match event {
+            Event::Log(log) => log,
+            Event::Metric(_) => return,
+        };
+
+        if let Some(only_fields) = &self.only_fields {
+            let removed = log
+                .keys()
+                .filter(|key| !only_fields.contains(key))
+                .cloned()
+                .collect::<Vec<_>>();
+            for key in removed {
+                log.remove(&key);
+            }
+        }
+
+        if let Some(except_fields) = &self.except_fields {
+            for key in except_fields {
+                log.remove(key);
+            }
+        }
+
+        if let Some(TimestampFormat::Unix) = self.timestamp_format {
+            let keys = log.keys().cloned().collect::<Vec<_>>();
+            for key in keys {
+                if let Some(value) = log.get_mut(&key) {
+                    if let ValueKind::Timestamp(timestamp) = value {
+                        *value = ValueKind::Integer(timestamp.timestamp());
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<E> From<E> for EncodingConfig<E> {
+    fn from(codec: E) -> Self {
+        Self {
+            codec,
+            only_fields: None,
+            except_fields: None,
+            timestamp_format: None,
+        }
+    }
+}
+
+impl<E: Default> Default for EncodingConfig<E> {
+    fn default() -> Self {
+        E::default().into()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EncodingConfigInner<E> {
+    Codec(E),
+    #[serde(deny_unknown_fields)]
+    Config {
+        codec: E,
+        only_fields: Option<Vec<Atom>>,
+        except_fields: Option<Vec<Atom>>,
+        timestamp_format: Option<TimestampFormat>,
+    },
+}
+
+impl<'de, E: Deserialize<'de>> Deserialize<'de> for EncodingConfig<E> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(match EncodingConfigInner::deserialize(deserializer)? {
+            EncodingConfigInner::Codec(codec) => codec.into(),
+            EncodingConfigInner::Config {
+                codec,
+                only_fields,
+                except_fields,
+                timestamp_format,
+            } => Self {
+                codec,
+                only_fields,
+                except_fields,
+                timestamp_format,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+    #[serde(rename_all = "snake_case")]
+    enum Codec {
+        Text,
+        Json,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Config {
+        encoding: EncodingConfig<Codec>,
+    }
+
+    #[test]
+    fn deserialize_bare_codec() {
+        let config: Config = toml::from_str(r#"encoding = "json""#).unwrap();
+        assert_eq!(config.encoding, Codec::Json.into());
+    }
+
+    #[test]
+    fn deserialize_table() {
+        let config: Config = toml::from_str(
+            r#"
+            [encoding]
+              codec = "text"
+              only_fields = ["message"]
+              timestamp_format = "unix"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.encoding.codec, Codec::Text);
+        assert_eq!(config.encoding.only_fields, Some(vec!["message".into()]));
+        assert_eq!(config.encoding.except_fields, None);
+        assert_eq!(
+            config.encoding.timestamp_format,
+            Some(TimestampFormat::Unix)
+        );
+    }
+
+    #[test]
+    fn deserialize_rejects_unknown_options() {
+        assert!(toml::from_str::<Config>(
+            r#"
+            [encoding]
+              codec = "text"
+              only_field = ["message"]
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn apply_only_fields() {
+        let mut event = Event::from("hello");
+        event.as_mut_log().insert_explicit("a".into(), "a".into());
+        event.as_mut_log().insert_explicit("b".into(), "b".into());
+
+        let encoding = EncodingConfig {
+            only_fields: Some(vec!["message".into(), "a".into()]),
+            ..Codec::Json.into()
+        };
+        encoding.apply_rules(&mut event);
+
+        let mut keys = event
+            .as_log()
+            .keys()
+            .map(|key| &key[..])
+            .collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, vec!["a", "message"]);
+    }
+
+    #[test]
+    fn apply_except_fields() {
+        let mut event = Event::from("hello");
+        event.as_mut_log().insert_explicit("a".into(), "a".into());
+
+        let encoding = EncodingConfig {
+            except_fields: Some(vec!["a".into(), "timestamp".into()]),
+            ..Codec::Json.into()
+        };
+        encoding.apply_rules(&mut event);
+
+        assert_eq!(
+            event
+                .as_log()
+                .keys()
+                .map(|key| &key[..])
+                .collect::<Vec<_>>(),
+            vec!["message"]
+        );
+    }
+
+    #[test]
+    fn apply_unix_timestamp_format() {
+        let timestamp = Utc.ymd(2019, 11, 4).and_hms(10, 0, 0);
+        let mut event = Event::from("hello");
+        event
+            .as_mut_log()
+            .insert_explicit("timestamp".into(), timestamp.into());
+
+        let encoding = EncodingConfig {
+            timestamp_format: Some(TimestampFormat::Unix),
+            ..Codec::Json.into()
+        };
+        encoding.apply_rules(&mut event);
+
+        assert_eq!(
+            event.as_log()[&"timestamp".into()],
+            ValueKind::Integer(timestamp.timestamp())
+        );
+        assert_eq!(event.as_log()[&"message".into()], "hello".into());
+    }
+}
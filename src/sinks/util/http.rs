@@ -1,29 +1,46 @@
 use super::{
+    proxy::{ProxyConnector, ProxySettings},
     retries::RetryLogic,
-    tls::{TlsConnectorExt, TlsSettings},
+    tls::{self, TlsConnectorExt, TlsSettings},
 };
 use bytes::Bytes;
-use futures::{Future, Poll, Stream};
-use http::{Request, StatusCode};
+use chrono::{DateTime, Utc};
+use futures::{
+    future::{self, Shared},
+    Future, Poll, Stream,
+};
+use headers::HeaderMapExt;
+use http::{
+    header::{self, HeaderValue, AUTHORIZATION, CONTENT_TYPE, PROXY_AUTHORIZATION, RETRY_AFTER},
+    uri::InvalidUri,
+    Request, StatusCode, Uri,
+};
 use hyper::client::HttpConnector;
 use hyper_tls::HttpsConnector;
 use native_tls::TlsConnector;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
 use std::borrow::Cow;
-use std::sync::Arc;
-use tokio::executor::DefaultExecutor;
+use std::fmt;
+use std::sync::{Arc, Mutex, Once, RwLock};
+use std::time::{Duration, Instant};
+use tokio::{clock, executor::DefaultExecutor};
 use tower::Service;
 use tower_hyper::client::Client;
 use tracing::field;
 use tracing_tower::{InstrumentableService, InstrumentedService};
 
-pub type RequestBuilder = Box<dyn Fn(Vec<u8>) -> hyper::Request<Vec<u8>> + Sync + Send>;
+pub type RequestBuilder<T> = Box<dyn Fn(T) -> hyper::Request<Vec<u8>> + Sync + Send>;
 pub type Response = hyper::Response<Bytes>;
-pub type Error = hyper::Error;
-
-#[derive(Clone)]
-pub struct HttpService {
-    inner: InstrumentedService<Client<HttpsConnector<HttpConnector>, Vec<u8>>, Request<Vec<u8>>>,
-    request_builder: Arc<RequestBuilder>,
+pub type Error = crate::Error;
+pub type HttpsClient = hyper::Client<HttpsConnector<ProxyConnector<HttpConnector>>>;
+
+/// An HTTP client service, `T` is the batch the requests are built from.
+pub struct HttpService<T = Vec<u8>> {
+    client: Arc<ServiceClient>,
+    request_builder: Arc<RequestBuilder<T>>,
+    authorizer: Option<Authorizer>,
+    proxy_settings: Arc<ProxySettings>,
 }
 
 impl HttpService {
@@ -39,68 +56,171 @@ impl HttpService {
     }
 }
 
+type InnerClient = InstrumentedService<
+    Client<HttpsConnector<ProxyConnector<HttpConnector>>, Vec<u8>>,
+    Request<Vec<u8>>,
+>;
+
+/// The client of an `HttpService` and its clones. It is built again once
+/// the files of its TLS settings change, checked on a timer started by the
+/// first request.
+struct ServiceClient {
+    current: RwLock<InnerClient>,
+    settings: Mutex<ClientSettings>,
+    watching: Once,
+}
+
+#[derive(Clone, Default)]
+struct ClientSettings {
+    threads: usize,
+    tls_settings: Option<TlsSettings>,
+    proxy_settings: ProxySettings,
+    http2_only: bool,
+}
+
+impl ClientSettings {
+    fn build(&self) -> InnerClient {
+        let mut http = HttpConnector::new(self.threads);
+        http.enforce_http(false);
+        let mut tls = native_tls::TlsConnector::builder();
+        if let Some(settings) = &self.tls_settings {
+            tls.use_tls_settings(settings.clone());
+        }
+        let tls = tls.build().expect("TLS initialization failed");
+        let http = ProxyConnector::new(http, self.proxy_settings.clone());
+        let https = HttpsConnector::from((http, tls));
+        let client = hyper::Client::builder()
+            .executor(DefaultExecutor::current())
+            .http2_only(self.http2_only)
+            .build(https);
+        Client::with_client(client).instrument(info_span!("http"))
+    }
+}
+
+impl ServiceClient {
+    fn current(self: &Arc<Self>) -> InnerClient {
+        self.watching.call_once(|| {
+            let settings = self.settings.lock().expect("poisoned lock");
+            if settings
+                .tls_settings
+                .as_ref()
+                .map_or(false, TlsSettings::has_files)
+            {
+                tls::watch(self, ServiceClient::reload);
+            }
+        });
+        self.current.read().expect("poisoned lock").clone()
+    }
+
+    fn reload(&self) {
+        let mut settings = self.settings.lock().expect("poisoned lock");
+        if settings
+            .tls_settings
+            .as_mut()
+            .map_or(false, TlsSettings::reload)
+        {
+            *self.current.write().expect("poisoned lock") = settings.build();
+        }
+    }
+}
+
 /// A builder for `HttpService`s
 #[derive(Default)]
 pub struct HttpServiceBuilder {
-    threads: usize,
-    tls_settings: Option<TlsSettings>,
+    client: ClientSettings,
+    authorizer: Option<Authorizer>,
 }
 
 impl HttpServiceBuilder {
     fn new() -> Self {
         Self {
-            threads: 4,
+            client: ClientSettings {
+                threads: 4,
+                ..Default::default()
+            },
             ..Default::default()
         }
     }
 
     /// Build the configured `HttpService`
-    pub fn build<F>(self, request_builder: F) -> HttpService
+    pub fn build<T, F>(self, request_builder: F) -> HttpService<T>
     where
-        F: Fn(Vec<u8>) -> hyper::Request<Vec<u8>> + Sync + Send + 'static,
+        F: Fn(T) -> hyper::Request<Vec<u8>> + Sync + Send + 'static,
     {
-        let mut http = HttpConnector::new(self.threads);
-        http.enforce_http(false);
-        let mut tls = native_tls::TlsConnector::builder();
-        if let Some(settings) = self.tls_settings {
-            tls.use_tls_settings(settings);
-        }
-        let tls = tls.build().expect("TLS initialization failed");
-        let https = HttpsConnector::from((http, tls));
-        let client = hyper::Client::builder()
-            .executor(DefaultExecutor::current())
-            .build(https);
-        let inner = Client::with_client(client).instrument(info_span!("http"));
+        let proxy_settings = Arc::new(self.client.proxy_settings.clone());
+        let client = ServiceClient {
+            current: RwLock::new(self.client.build()),
+            settings: Mutex::new(self.client),
+            watching: Once::new(),
+        };
         HttpService {
-            inner,
+            client: Arc::new(client),
             request_builder: Arc::new(Box::new(request_builder)),
+            authorizer: self.authorizer,
+            proxy_settings,
         }
     }
 
     /// Set the number of threads used by the `HttpService`
     pub fn threads(mut self, threads: usize) -> Self {
-        self.threads = threads;
+        self.client.threads = threads;
         self
     }
 
     /// Set the standard TLS settings
     pub fn tls_settings(mut self, settings: TlsSettings) -> Self {
-        self.tls_settings = Some(settings);
+        self.client.tls_settings = Some(settings);
+        self
+    }
+
+    /// Set the proxies requests are sent through
+    pub fn proxy_settings(mut self, settings: ProxySettings) -> Self {
+        self.client.proxy_settings = settings;
         self
     }
+
+    /// Set the `Authorization` header of every request
+    pub fn authorizer(mut self, authorizer: Option<Authorizer>) -> Self {
+        self.authorizer = authorizer;
+        self
+    }
+
+    /// Only speak HTTP/2, without negotiating it first
+    pub fn http2_only(mut self, http2_only: bool) -> Self {
+        self.client.http2_only = http2_only;
+        self
+    }
+}
+
+pub fn https_client(tls: TlsSettings, proxy: ProxySettings) -> crate::Result<HttpsClient> {
+    Ok(hyper::Client::builder().build(https_connector(tls, proxy)?))
 }
 
-pub fn https_client(
+/// An HTTPS connector going through the proxies, for clients built elsewhere
+/// such as the AWS ones.
+pub fn https_connector(
     tls: TlsSettings,
-) -> crate::Result<hyper::Client<HttpsConnector<HttpConnector>>> {
+    proxy: ProxySettings,
+) -> crate::Result<HttpsConnector<ProxyConnector<HttpConnector>>> {
     let mut http = HttpConnector::new(1);
     http.enforce_http(false);
     let tls = TlsConnector::builder().use_tls_settings(tls).build()?;
-    let https = HttpsConnector::from((http, tls));
-    Ok(hyper::Client::builder().build(https))
+    let http = ProxyConnector::new(http, proxy);
+    Ok(HttpsConnector::from((http, tls)))
 }
 
-impl Service<Vec<u8>> for HttpService {
+impl<T> Clone for HttpService<T> {
+    fn clone(&self) -> Self {
+        Self {
+            client: Arc::clone(&self.client),
+            request_builder: Arc::clone(&self.request_builder),
+            authorizer: self.authorizer.clone(),
+            proxy_settings: Arc::clone(&self.proxy_settings),
+        }
+    }
+}
+
+impl<T> Service<T> for HttpService<T> {
     type Response = Response;
     type Error = Error;
     type Future = Box<dyn Future<Item = Self::Response, Error = Self::Error> + Send + 'static>;
@@ -109,14 +229,27 @@ impl Service<Vec<u8>> for HttpService {
         Ok(().into())
     }
 
-    fn call(&mut self, body: Vec<u8>) -> Self::Future {
-        let request = (self.request_builder)(body);
+    fn call(&mut self, body: T) -> Self::Future {
+        let mut request = (self.request_builder)(body);
+        if let Some(value) = self.proxy_settings.authorization(request.uri()) {
+            request.headers_mut().insert(PROXY_AUTHORIZATION, value);
+        }
 
-        debug!(message = "sending request.");
+        let mut inner = self.client.current();
+        let response = match &self.authorizer {
+            None => {
+                debug!(message = "sending request.");
+                future::Either::A(inner.call(request).map_err(Into::into))
+            }
+            Some(authorizer) => {
+                future::Either::B(authorizer.authorize(request).and_then(move |request| {
+                    debug!(message = "sending request.");
+                    inner.call(request).map_err(Into::into)
+                }))
+            }
+        };
 
-        let fut = self
-            .inner
-            .call(request)
+        let fut = response
             .inspect(|res| {
                 debug!(
                     message = "response.",
@@ -128,12 +261,319 @@ impl Service<Vec<u8>> for HttpService {
                 let (parts, body) = r.into_parts();
                 body.concat2()
                     .map(|b| hyper::Response::from_parts(parts, b.into_bytes()))
+                    .map_err(Into::into)
             });
 
         Box::new(fut)
     }
 }
 
+/// The `auth` option of the HTTP based sinks.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", tag = "strategy")]
+pub enum Auth {
+    Basic {
+        user: String,
+        password: String,
+    },
+    Bearer {
+        token: String,
+    },
+    /// The OAuth2 client credentials grant: an access token is requested
+    /// from `token_endpoint` and requested again shortly before it expires.
+    Oauth2 {
+        token_endpoint: String,
+        client_id: String,
+        client_secret: String,
+        scopes: Option<Vec<String>>,
+    },
+}
+
+/// The `basic_auth` option the sinks had before `auth`, still accepted.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BasicAuth {
+    pub user: String,
+    pub password: String,
+}
+
+#[derive(Debug, Snafu)]
+pub enum AuthError {
+    #[snafu(display("Only one of `auth` and `basic_auth` can be set"))]
+    AuthAndBasicAuth,
+    #[snafu(display("Invalid OAuth2 token endpoint {:?}: {}", endpoint, source))]
+    InvalidTokenEndpoint {
+        endpoint: String,
+        source: InvalidUri,
+    },
+    #[snafu(display("Credentials are not a valid header value: {}", source))]
+    InvalidCredentials { source: header::InvalidHeaderValue },
+}
+
+/// Why an OAuth2 access token couldn't be requested. Cloned to every request
+/// waiting on the same token.
+#[derive(Clone, Debug, Snafu)]
+pub enum TokenError {
+    #[snafu(display("OAuth2 token request failed: {}", message))]
+    TokenRequest { message: String },
+    #[snafu(display("OAuth2 token request failed with status {}: {}", status, body))]
+    TokenRequestFailed { status: StatusCode, body: String },
+    #[snafu(display("Invalid OAuth2 token response: {}", message))]
+    InvalidTokenResponse { message: String },
+}
+
+impl TokenError {
+    /// Whether requesting the token again could succeed, used by the retry
+    /// policy of the request that needed it.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            TokenError::TokenRequest { .. } => true,
+            TokenError::TokenRequestFailed { status, .. } => {
+                *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            }
+            TokenError::InvalidTokenResponse { .. } => false,
+        }
+    }
+}
+
+impl Auth {
+    /// The `auth` option of a sink, or its `basic_auth` one.
+    pub fn choose(
+        auth: &Option<Auth>,
+        basic_auth: &Option<BasicAuth>,
+    ) -> crate::Result<Option<Auth>> {
+        match (auth, basic_auth) {
+            (Some(_), Some(_)) => Err(AuthError::AuthAndBasicAuth.into()),
+            (Some(auth), None) => Ok(Some(auth.clone())),
+            (None, Some(BasicAuth { user, password })) => Ok(Some(Auth::Basic {
+                user: user.clone(),
+                password: password.clone(),
+            })),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// The OAuth2 token endpoint is requested with the sink's TLS and proxy
+    /// settings.
+    pub fn build(
+        &self,
+        tls_settings: TlsSettings,
+        proxy_settings: ProxySettings,
+    ) -> crate::Result<Authorizer> {
+        let authorizer = match self {
+            Auth::Basic { user, password } => {
+                let credentials = base64::encode(format!("{}:{}", user, password).as_bytes());
+                Authorizer::Static(
+                    HeaderValue::from_str(&format!("Basic {}", credentials))
+                        .context(InvalidCredentials)?,
+                )
+            }
+            Auth::Bearer { token } => Authorizer::Static(
+                HeaderValue::from_str(&format!("Bearer {}", token)).context(InvalidCredentials)?,
+            ),
+            Auth::Oauth2 {
+                token_endpoint,
+                client_id,
+                client_secret,
+                scopes,
+            } => {
+                let token_endpoint =
+                    token_endpoint
+                        .parse::<Uri>()
+                        .with_context(|| InvalidTokenEndpoint {
+                            endpoint: token_endpoint.clone(),
+                        })?;
+
+                Authorizer::OAuth2(Arc::new(OAuth2 {
+                    client: https_client(tls_settings, proxy_settings)?,
+                    token_endpoint,
+                    client_id: client_id.clone(),
+                    client_secret: client_secret.clone(),
+                    scopes: scopes.clone(),
+                    token: Mutex::new(Token::Missing),
+                }))
+            }
+        };
+
+        Ok(authorizer)
+    }
+}
+
+/// Sets the `Authorization` header of requests, built from an `Auth`.
+#[derive(Clone, Debug)]
+pub enum Authorizer {
+    Static(HeaderValue),
+    OAuth2(Arc<OAuth2>),
+}
+
+impl Authorizer {
+    pub fn authorize<B>(
+        &self,
+        mut request: Request<B>,
+    ) -> impl Future<Item = Request<B>, Error = crate::Error> {
+        let authorization = match self {
+            Authorizer::Static(value) => future::Either::A(future::ok(value.clone())),
+            Authorizer::OAuth2(oauth2) => future::Either::B(OAuth2::access_token(oauth2)),
+        };
+
+        authorization.map(move |value| {
+            request.headers_mut().insert(AUTHORIZATION, value);
+            request
+        })
+    }
+}
+
+/// Sets the `Authorization` header of `request` when there's an authorizer,
+/// for the requests that aren't made through an `HttpService`.
+pub fn authorize<B>(
+    authorizer: Option<&Authorizer>,
+    request: Request<B>,
+) -> impl Future<Item = Request<B>, Error = crate::Error> {
+    match authorizer {
+        Some(authorizer) => future::Either::A(authorizer.authorize(request)),
+        None => future::Either::B(future::ok(request)),
+    }
+}
+
+/// Access tokens are requested again this long before they expire.
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+/// How long a token is used when the token endpoint doesn't say.
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(60);
+
+type TokenFuture = Box<dyn Future<Item = HeaderValue, Error = TokenError> + Send>;
+
+#[derive(Debug)]
+pub struct OAuth2 {
+    client: HttpsClient,
+    token_endpoint: Uri,
+    client_id: String,
+    client_secret: String,
+    scopes: Option<Vec<String>>,
+    token: Mutex<Token>,
+}
+
+/// The access token of an `OAuth2` authorizer. While one is requested, every
+/// request needing it waits on the same token request.
+enum Token {
+    Missing,
+    Requesting(Shared<TokenFuture>),
+    Valid(HeaderValue, Instant),
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Missing => write!(f, "Missing"),
+            Token::Requesting(_) => write!(f, "Requesting"),
+            Token::Valid(_, expires_at) => f.debug_tuple("Valid").field(expires_at).finish(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+impl OAuth2 {
+    fn access_token(
+        this: &Arc<Self>,
+    ) -> Box<dyn Future<Item = HeaderValue, Error = crate::Error> + Send> {
+        let mut token = this.token.lock().unwrap();
+        let request = match &*token {
+            Token::Valid(value, expires_at) if clock::now() + TOKEN_EXPIRY_MARGIN < *expires_at => {
+                return Box::new(future::ok(value.clone()));
+            }
+            Token::Requesting(request) => request.clone(),
+            _ => {
+                let request = Self::request_token(this).shared();
+                *token = Token::Requesting(request.clone());
+                request
+            }
+        };
+
+        Box::new(
+            request
+                .map(|value| (*value).clone())
+                .map_err(|error| crate::Error::from((*error).clone())),
+        )
+    }
+
+    fn request_token(this: &Arc<Self>) -> TokenFuture {
+        debug!(message = "requesting OAuth2 access token.", token_endpoint = %this.token_endpoint);
+
+        let mut form = url::form_urlencoded::Serializer::new(String::new());
+        form.append_pair("grant_type", "client_credentials");
+        if let Some(scopes) = &this.scopes {
+            form.append_pair("scope", &scopes.join(" "));
+        }
+
+        let mut request = Request::post(&this.token_endpoint)
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(hyper::Body::from(form.finish()))
+            .expect("The token endpoint was already parsed");
+        request
+            .headers_mut()
+            .typed_insert(headers::Authorization::basic(
+                &this.client_id,
+                &this.client_secret,
+            ));
+
+        let this = Arc::clone(this);
+        let fut = this
+            .client
+            .request(request)
+            .and_then(|response| {
+                let (parts, body) = response.into_parts();
+                body.concat2().map(move |body| (parts.status, body))
+            })
+            .map_err(|error| TokenError::TokenRequest {
+                message: error.to_string(),
+            })
+            .and_then(|(status, body)| parse_token(status, &body))
+            .then(move |result| {
+                *this.token.lock().unwrap() = match &result {
+                    Ok((value, expires_at)) => Token::Valid(value.clone(), *expires_at),
+                    Err(error) => {
+                        warn!(message = "unable to request OAuth2 access token.", %error);
+                        Token::Missing
+                    }
+                };
+                result.map(|(value, _)| value)
+            });
+
+        Box::new(fut)
+    }
+}
+
+/// The `Authorization` header value and expiry of a token response.
+fn parse_token(status: StatusCode, body: &[u8]) -> Result<(HeaderValue, Instant), TokenError> {
+    if !status.is_success() {
+        return Err(TokenError::TokenRequestFailed {
+            status,
+            body: String::from_utf8_lossy(body).into_owned(),
+        });
+    }
+
+    let response: TokenResponse =
+        serde_json::from_slice(body).map_err(|error| TokenError::InvalidTokenResponse {
+            message: error.to_string(),
+        })?;
+    let value =
+        HeaderValue::from_str(&format!("Bearer {}", response.access_token)).map_err(|error| {
+            TokenError::InvalidTokenResponse {
+                message: error.to_string(),
+            }
+        })?;
+    let lifetime = response
+        .expires_in
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TOKEN_LIFETIME);
+
+    Ok((value, clock::now() + lifetime))
+}
+
 #[derive(Clone)]
 pub struct HttpRetryLogic;
 
@@ -157,15 +597,41 @@ impl RetryLogic for HttpRetryLogic {
             _ => None,
         }
     }
+
+    fn retry_after(&self, response: &Self::Response) -> Option<Duration> {
+        response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after)
+    }
+}
+
+/// Parse a `Retry-After` header value, which is either a number of seconds
+/// or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = (date.with_timezone(&Utc) - Utc::now())
+        .to_std()
+        .unwrap_or_else(|_| Duration::from_secs(0));
+    Some(delay)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::sinks::util::proxy::ProxyConfig;
     use futures::{Future, Sink, Stream};
     use http::Method;
     use hyper::service::service_fn;
     use hyper::{Body, Response, Server, Uri};
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use tower::Service;
 
     #[test]
@@ -183,6 +649,35 @@ mod test {
         assert!(logic.should_retry_response(&response_501).is_none());
     }
 
+    #[test]
+    fn util_http_retry_after() {
+        let logic = HttpRetryLogic;
+
+        let response = Response::builder()
+            .status(429)
+            .header("Retry-After", "120")
+            .body(Bytes::new())
+            .unwrap();
+        assert_eq!(logic.retry_after(&response), Some(Duration::from_secs(120)));
+
+        let response = Response::builder()
+            .status(503)
+            .header("Retry-After", "Wed, 21 Oct 2015 07:28:00 GMT")
+            .body(Bytes::new())
+            .unwrap();
+        assert_eq!(logic.retry_after(&response), Some(Duration::from_secs(0)));
+
+        let response = Response::builder()
+            .status(503)
+            .header("Retry-After", "soon")
+            .body(Bytes::new())
+            .unwrap();
+        assert_eq!(logic.retry_after(&response), None);
+
+        let response = Response::builder().status(503).body(Bytes::new()).unwrap();
+        assert_eq!(logic.retry_after(&response), None);
+    }
+
     #[test]
     fn util_http_it_makes_http_requests() {
         let addr = crate::test_util::next_addr();
@@ -235,4 +730,271 @@ mod test {
         let (body, _rest) = rx.into_future().wait().unwrap();
         assert_eq!(body.unwrap().unwrap(), "hello");
     }
+
+    #[test]
+    fn util_http_sends_proxy_authorization() {
+        let addr = crate::test_util::next_addr();
+        let proxy = ProxySettings::from_config(&ProxyConfig {
+            http: Some(format!("http://user:pass@{}", addr)),
+            no_proxy: Some(vec![]),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let mut service = HttpService::builder()
+            .proxy_settings(proxy)
+            .build(|body: Vec<u8>| {
+                hyper::Request::post("http://example.com/path")
+                    .body(body)
+                    .unwrap()
+            });
+        let req = service.call(b"hello".to_vec());
+
+        let (tx, rx) = futures::sync::mpsc::unbounded();
+        let new_service = move || {
+            let tx = tx.clone();
+            service_fn(move |req: hyper::Request<Body>| {
+                tx.unbounded_send(req.into_parts().0).unwrap();
+                future::ok::<_, hyper::Error>(Response::new(Body::empty()))
+            })
+        };
+
+        let server = Server::bind(&addr)
+            .serve(new_service)
+            .map_err(|e| eprintln!("server error: {}", e));
+
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.spawn(server);
+        rt.block_on(req).unwrap();
+        rt.shutdown_now().wait().unwrap();
+
+        let (parts, _rest) = rx.into_future().wait().unwrap();
+        let parts = parts.unwrap();
+        assert_eq!(parts.uri, "http://example.com/path");
+        assert_eq!(parts.headers[PROXY_AUTHORIZATION], "Basic dXNlcjpwYXNz");
+    }
+
+    #[test]
+    fn util_http_auth_deserialize() {
+        #[derive(Deserialize, Debug)]
+        struct Config {
+            auth: Auth,
+        }
+
+        let parse = |s: &str| toml::from_str::<Config>(s).map(|config| config.auth);
+
+        assert_eq!(
+            parse(r#"auth = { strategy = "bearer", token = "abc" }"#).unwrap(),
+            Auth::Bearer {
+                token: "abc".into()
+            }
+        );
+        assert_eq!(
+            parse(
+                r#"
+                [auth]
+                  strategy = "oauth2"
+                  token_endpoint = "https://example.com/token"
+                  client_id = "id"
+                  client_secret = "secret"
+                "#
+            )
+            .unwrap(),
+            Auth::Oauth2 {
+                token_endpoint: "https://example.com/token".into(),
+                client_id: "id".into(),
+                client_secret: "secret".into(),
+                scopes: None,
+            }
+        );
+        assert!(parse(r#"auth = { strategy = "basic", user = "waldo" }"#).is_err());
+    }
+
+    #[test]
+    fn util_http_auth_from_basic_auth() {
+        let basic_auth = Some(BasicAuth {
+            user: "waldo".into(),
+            password: "hunter2".into(),
+        });
+        let bearer = Some(Auth::Bearer {
+            token: "abc".into(),
+        });
+
+        assert_eq!(
+            Auth::choose(&None, &basic_auth).unwrap(),
+            Some(Auth::Basic {
+                user: "waldo".into(),
+                password: "hunter2".into(),
+            })
+        );
+        assert_eq!(Auth::choose(&bearer, &None).unwrap(), bearer);
+        assert!(Auth::choose(&bearer, &basic_auth).is_err());
+    }
+
+    #[test]
+    fn util_http_static_authorization() {
+        let authorize = |auth: Auth| {
+            let authorizer = auth
+                .build(TlsSettings::default(), ProxySettings::default())
+                .unwrap();
+            let request = Request::get("http://localhost/").body(()).unwrap();
+            let request = authorizer.authorize(request).wait().unwrap();
+            request.headers()[AUTHORIZATION].clone()
+        };
+
+        assert_eq!(
+            authorize(Auth::Basic {
+                user: "waldo".into(),
+                password: "hunter2".into(),
+            }),
+            "Basic d2FsZG86aHVudGVyMg=="
+        );
+        assert_eq!(
+            authorize(Auth::Bearer {
+                token: "abc".into()
+            }),
+            "Bearer abc"
+        );
+    }
+
+    #[test]
+    fn util_http_oauth2_reuses_access_token() {
+        let addr = crate::test_util::next_addr();
+        let (tx, rx) = futures::sync::mpsc::unbounded();
+
+        let new_service = move || {
+            let tx = tx.clone();
+            service_fn(move |req: hyper::Request<Body>| {
+                let (parts, body) = req.into_parts();
+                let tx = tx.clone();
+                body.concat2().map(move |body| {
+                    tx.unbounded_send((parts, body.to_vec())).unwrap();
+                    Response::new(Body::from(
+                        r#"{"access_token":"abc","token_type":"bearer","expires_in":3600}"#,
+                    ))
+                })
+            })
+        };
+
+        let server = Server::bind(&addr)
+            .serve(new_service)
+            .map_err(|e| eprintln!("server error: {}", e));
+
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.spawn(server);
+
+        let authorizer = Auth::Oauth2 {
+            token_endpoint: format!("http://{}/token", addr),
+            client_id: "id".into(),
+            client_secret: "secret".into(),
+            scopes: Some(vec!["read".into(), "write".into()]),
+        }
+        .build(TlsSettings::default(), ProxySettings::default())
+        .unwrap();
+
+        for _ in 0..2 {
+            let request = Request::get("http://localhost/").body(()).unwrap();
+            let request = rt.block_on(authorizer.authorize(request)).unwrap();
+            assert_eq!(request.headers()[AUTHORIZATION], "Bearer abc");
+        }
+
+        rt.shutdown_now().wait().unwrap();
+
+        let token_requests = rx.collect().wait().unwrap();
+        assert_eq!(token_requests.len(), 1);
+        let (parts, body) = &token_requests[0];
+        assert_eq!(parts.method, Method::POST);
+        assert_eq!(parts.uri.path(), "/token");
+        assert_eq!(parts.headers[AUTHORIZATION], "Basic aWQ6c2VjcmV0");
+        assert_eq!(
+            String::from_utf8_lossy(body),
+            "grant_type=client_credentials&scope=read+write"
+        );
+    }
+
+    #[test]
+    fn util_http_oauth2_requests_one_token_at_a_time() {
+        let addr = crate::test_util::next_addr();
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let served = Arc::clone(&requests);
+        let new_service = move || {
+            let served = Arc::clone(&served);
+            service_fn(move |_req: hyper::Request<Body>| {
+                // The first token request fails, the ones after it succeed.
+                let response = if served.fetch_add(1, Ordering::SeqCst) == 0 {
+                    Response::builder()
+                        .status(StatusCode::SERVICE_UNAVAILABLE)
+                        .body(Body::empty())
+                } else {
+                    Response::builder()
+                        .body(Body::from(r#"{"access_token":"abc","expires_in":3600}"#))
+                };
+                future::ok::<_, hyper::Error>(response.unwrap())
+            })
+        };
+
+        let server = Server::bind(&addr)
+            .serve(new_service)
+            .map_err(|e| eprintln!("server error: {}", e));
+
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.spawn(server);
+
+        let authorizer = Auth::Oauth2 {
+            token_endpoint: format!("http://{}/token", addr),
+            client_id: "id".into(),
+            client_secret: "secret".into(),
+            scopes: None,
+        }
+        .build(TlsSettings::default(), ProxySettings::default())
+        .unwrap();
+
+        let authorize_all = |authorizer: &Authorizer| {
+            let requests = (0..3)
+                .map(|_| authorizer.authorize(Request::get("http://localhost/").body(()).unwrap()))
+                .collect::<Vec<_>>();
+            future::join_all(
+                requests
+                    .into_iter()
+                    .map(|request| request.then(Ok::<_, ()>)),
+            )
+        };
+
+        let failed = rt.block_on(authorize_all(&authorizer)).unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        for result in failed {
+            let error = result.unwrap_err();
+            let error = error.downcast_ref::<TokenError>().unwrap();
+            assert!(error.is_retriable());
+        }
+
+        let authorized = rt.block_on(authorize_all(&authorizer)).unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        for result in authorized {
+            assert_eq!(result.unwrap().headers()[AUTHORIZATION], "Bearer abc");
+        }
+
+        rt.shutdown_now().wait().unwrap();
+    }
+
+    #[test]
+    fn util_http_oauth2_token_errors_retriable() {
+        let failed = |status| TokenError::TokenRequestFailed {
+            status,
+            body: String::new(),
+        };
+
+        assert!(failed(StatusCode::INTERNAL_SERVER_ERROR).is_retriable());
+        assert!(failed(StatusCode::TOO_MANY_REQUESTS).is_retriable());
+        assert!(!failed(StatusCode::UNAUTHORIZED).is_retriable());
+        assert!(TokenError::TokenRequest {
+            message: "connection refused".into()
+        }
+        .is_retriable());
+        assert!(!TokenError::InvalidTokenResponse {
+            message: "missing field `access_token`".into()
+        }
+        .is_retriable());
+    }
 }
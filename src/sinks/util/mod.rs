@@ -1,7 +1,12 @@
+pub mod adaptive_concurrency;
 pub mod batch;
 pub mod buffer;
+pub mod circuit_breaker;
+pub mod encoding;
 pub mod http;
+pub mod proxy;
 pub mod retries;
+pub mod rusoto;
 pub mod tls;
 
 use crate::buffers::Acker;
@@ -16,7 +21,8 @@ use tower::Service;
 pub use batch::{Batch, BatchSink};
 pub use buffer::metrics::MetricBuffer;
 pub use buffer::partition::{Partition, PartitionedBatchSink};
-pub use buffer::{Buffer, Compression, PartitionBuffer, PartitionInnerBuffer};
+pub use buffer::vec::{ByteSize, VecBuffer};
+pub use buffer::{Buffer, Compression, Framing, PartitionBuffer, PartitionInnerBuffer};
 
 pub trait SinkExt<T>
 where
@@ -1,8 +1,10 @@
-use super::Error;
+use super::{http::TokenError, Error};
 use futures::{try_ready, Async, Future, Poll};
+use rand::{thread_rng, Rng};
 use std::borrow::Cow;
+use std::cmp;
 use std::time::{Duration, Instant};
-use tokio::timer::Delay;
+use tokio::{clock, timer::Delay};
 use tower::{retry::Policy, timeout::error::Elapsed};
 
 pub trait RetryLogic: Clone {
@@ -14,44 +16,117 @@ pub trait RetryLogic: Clone {
     fn should_retry_response(&self, _response: &Self::Response) -> Option<Cow<str>> {
         None
     }
+
+    /// How long the service asked us to wait before retrying, for example
+    /// through a `Retry-After` header. Overrides the computed backoff.
+    fn retry_after(&self, _response: &Self::Response) -> Option<Duration> {
+        None
+    }
 }
 
+/// The longest delay a service may ask for before a retry, much longer than
+/// `max_backoff` as the service knows best when it will be ready again.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30 * 60);
+
+/// Retries with exponential backoff and full jitter: the n-th retry waits
+/// a random duration between zero and `initial_backoff * 2^n`, capped at
+/// `max_backoff`. Delays asked for by the service are honoured up to
+/// `MAX_RETRY_AFTER`. Retries stop once `remaining_attempts` is exhausted or,
+/// if set, once the next retry would start after `max_duration` has passed
+/// since the first attempt failed.
 #[derive(Debug, Clone)]
-pub struct FixedRetryPolicy<L: RetryLogic> {
+pub struct RetryPolicy<L: RetryLogic> {
     remaining_attempts: usize,
-    backoff: Duration,
+    previous_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_duration: Option<Duration>,
+    first_failure: Option<Instant>,
     logic: L,
 }
 
 pub struct RetryPolicyFuture<L: RetryLogic> {
     delay: Delay,
-    policy: FixedRetryPolicy<L>,
+    policy: RetryPolicy<L>,
 }
 
-impl<L: RetryLogic> FixedRetryPolicy<L> {
-    pub fn new(remaining_attempts: usize, backoff: Duration, logic: L) -> Self {
-        FixedRetryPolicy {
+impl<L: RetryLogic> RetryPolicy<L> {
+    pub fn new(remaining_attempts: usize, initial_backoff: Duration, logic: L) -> Self {
+        RetryPolicy {
             remaining_attempts,
-            backoff,
+            previous_attempts: 0,
+            initial_backoff,
+            max_backoff: cmp::max(initial_backoff, Duration::from_secs(10)),
+            max_duration: None,
+            first_failure: None,
             logic,
         }
     }
 
-    fn build_retry(&self) -> RetryPolicyFuture<L> {
-        let policy = FixedRetryPolicy::new(
-            self.remaining_attempts - 1,
-            self.backoff,
-            self.logic.clone(),
-        );
-        let next = Instant::now() + self.backoff;
-        let delay = Delay::new(next);
+    /// Set the upper bound of the backoff between two attempts
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Set the maximum total time spent retrying a single request
+    pub fn max_duration(mut self, max_duration: Option<Duration>) -> Self {
+        self.max_duration = max_duration;
+        self
+    }
+
+    fn backoff(&self) -> Duration {
+        let exponent = cmp::min(self.previous_attempts, 31);
+        let ceiling = self
+            .initial_backoff
+            .checked_mul(1 << exponent)
+            .map_or(self.max_backoff, |backoff| {
+                cmp::min(backoff, self.max_backoff)
+            });
+
+        let ceiling_ms = ceiling.as_millis() as u64;
+        if ceiling_ms == 0 {
+            ceiling
+        } else {
+            Duration::from_millis(thread_rng().gen_range(0, ceiling_ms + 1))
+        }
+    }
+
+    fn build_retry(&self, delay: Option<Duration>) -> Option<RetryPolicyFuture<L>> {
+        let delay = match delay {
+            Some(delay) => cmp::min(delay, MAX_RETRY_AFTER),
+            None => self.backoff(),
+        };
+        let now = clock::now();
+        let first_failure = self.first_failure.unwrap_or(now);
+
+        if let Some(max_duration) = self.max_duration {
+            if now + delay > first_failure + max_duration {
+                error!(
+                    message = "retries exhausted; maximum retry duration exceeded.",
+                    max_duration_secs = max_duration.as_secs()
+                );
+                return None;
+            }
+        }
 
-        debug!(message = "retrying request.", delay_ms = %self.backoff.as_millis());
-        RetryPolicyFuture { delay, policy }
+        let policy = RetryPolicy {
+            remaining_attempts: self.remaining_attempts - 1,
+            previous_attempts: self.previous_attempts + 1,
+            first_failure: Some(first_failure),
+            ..self.clone()
+        };
+        let delay_ms = delay.as_millis();
+
+        debug!(message = "retrying request.", %delay_ms);
+        Some(RetryPolicyFuture {
+            delay: Delay::new(now + delay),
+            policy,
+        })
     }
 }
 
-impl<Req, Res, L> Policy<Req, Res, Error> for FixedRetryPolicy<L>
+impl<Req, Res, L> Policy<Req, Res, Error> for RetryPolicy<L>
 where
     Req: Clone,
     L: RetryLogic<Response = Res>,
@@ -68,7 +143,7 @@ where
 
                 if let Some(ref reason) = self.logic.should_retry_response(response) {
                     warn!(message = "retrying after response.", %reason);
-                    Some(self.build_retry())
+                    self.build_retry(self.logic.retry_after(response))
                 } else {
                     None
                 }
@@ -82,14 +157,22 @@ where
                 if let Some(expected) = error.downcast_ref::<L::Error>() {
                     if self.logic.is_retriable_error(expected) {
                         warn!("retrying after error: {}", expected);
-                        Some(self.build_retry())
+                        self.build_retry(None)
                     } else {
                         error!(message = "encountered non-retriable error.", %error);
                         None
                     }
                 } else if error.downcast_ref::<Elapsed>().is_some() {
                     warn!("request timedout.");
-                    Some(self.build_retry())
+                    self.build_retry(None)
+                } else if let Some(error) = error.downcast_ref::<TokenError>() {
+                    if error.is_retriable() {
+                        warn!("retrying after error: {}", error);
+                        self.build_retry(None)
+                    } else {
+                        error!(message = "encountered non-retriable error.", %error);
+                        None
+                    }
                 } else {
                     warn!(message = "unexpected error type.", %error);
                     None
@@ -104,7 +187,7 @@ where
 }
 
 impl<L: RetryLogic> Future for RetryPolicyFuture<L> {
-    type Item = FixedRetryPolicy<L>;
+    type Item = RetryPolicy<L>;
     type Error = ();
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
@@ -131,7 +214,7 @@ mod tests {
         clock::mock(|clock| {
             trace_init();
 
-            let policy = FixedRetryPolicy::new(5, Duration::from_secs(1), SvcRetryLogic);
+            let policy = RetryPolicy::new(5, Duration::from_secs(1), SvcRetryLogic);
 
             let (service, mut handle) = mock::pair();
             let mut svc = Retry::new(policy, service);
@@ -154,7 +237,7 @@ mod tests {
     fn service_error_no_retry() {
         trace_init();
 
-        let policy = FixedRetryPolicy::new(5, Duration::from_secs(1), SvcRetryLogic);
+        let policy = RetryPolicy::new(5, Duration::from_secs(1), SvcRetryLogic);
 
         let (service, mut handle) = mock::pair();
         let mut svc = Retry::new(policy, service);
@@ -171,7 +254,7 @@ mod tests {
         clock::mock(|clock| {
             trace_init();
 
-            let policy = FixedRetryPolicy::new(5, Duration::from_secs(1), SvcRetryLogic);
+            let policy = RetryPolicy::new(5, Duration::from_secs(1), SvcRetryLogic);
 
             let (service, mut handle) = mock::pair();
             let mut svc = Retry::new(policy, service);
@@ -190,6 +273,94 @@ mod tests {
         });
     }
 
+    #[test]
+    fn retry_after_response() {
+        let policy = RetryPolicy::new(5, Duration::from_secs(1), SvcRetryLogic);
+
+        let retry =
+            Policy::<&str, &str, super::Error>::retry(&policy, &"hello", Ok(&"retry")).unwrap();
+        assert!(retry.delay.deadline() >= Instant::now() + Duration::from_secs(4));
+
+        let retry = Policy::<&str, &str, super::Error>::retry(&policy, &"hello", Ok(&"world"));
+        assert!(retry.is_none());
+    }
+
+    #[test]
+    fn max_duration_exceeded() {
+        let policy = RetryPolicy::new(5, Duration::from_secs(1), SvcRetryLogic)
+            .max_duration(Some(Duration::from_secs(2)));
+
+        let retry = Policy::<&str, &str, super::Error>::retry(&policy, &"hello", Ok(&"retry"));
+        assert!(retry.is_none());
+    }
+
+    #[test]
+    fn retry_after_exceeds_max_backoff() {
+        let policy = RetryPolicy::new(5, Duration::from_secs(1), SvcRetryLogic)
+            .max_backoff(Duration::from_secs(2));
+
+        let before = Instant::now();
+        let retry =
+            Policy::<&str, &str, super::Error>::retry(&policy, &"hello", Ok(&"retry")).unwrap();
+        assert!(retry.delay.deadline() <= Instant::now() + Duration::from_secs(5));
+        assert!(retry.delay.deadline() >= before + Duration::from_secs(5));
+    }
+
+    #[test]
+    fn retry_after_is_capped() {
+        let policy = RetryPolicy::new(5, Duration::from_secs(1), SvcRetryLogic);
+
+        let retry =
+            Policy::<&str, &str, super::Error>::retry(&policy, &"hello", Ok(&"retry tomorrow"))
+                .unwrap();
+        assert!(retry.delay.deadline() <= Instant::now() + MAX_RETRY_AFTER);
+    }
+
+    #[test]
+    fn max_duration_counts_from_first_failure() {
+        clock::mock(|clock| {
+            trace_init();
+
+            let policy = RetryPolicy::new(5, Duration::from_secs(1), SvcRetryLogic)
+                .max_backoff(Duration::from_secs(1))
+                .max_duration(Some(Duration::from_secs(10)));
+
+            let (service, mut handle) = mock::pair();
+            let mut svc = Retry::new(policy, service);
+
+            assert_ready!(svc.poll_ready());
+            let mut fut = svc.call("hello");
+            assert_request_eq!(handle, "hello").send_error(Error(true));
+            assert_not_ready!(fut.poll());
+
+            clock.advance(Duration::from_secs(2));
+            assert_not_ready!(fut.poll());
+
+            // The second attempt fails after the retries may.
+            clock.advance(Duration::from_secs(9));
+            assert_request_eq!(handle, "hello").send_error(Error(true));
+            assert_err!(fut.poll());
+        });
+    }
+
+    #[test]
+    fn exponential_backoff_is_capped() {
+        let mut policy = RetryPolicy::new(10, Duration::from_secs(1), SvcRetryLogic)
+            .max_backoff(Duration::from_secs(8));
+
+        for attempt in 0..10 {
+            policy.previous_attempts = attempt;
+            let ceiling = cmp::min(
+                Duration::from_secs(1) * 2u32.pow(attempt),
+                Duration::from_secs(8),
+            );
+
+            for _ in 0..20 {
+                assert!(policy.backoff() <= ceiling);
+            }
+        }
+    }
+
     #[derive(Debug, Clone)]
     struct SvcRetryLogic;
 
@@ -200,6 +371,22 @@ mod tests {
         fn is_retriable_error(&self, error: &Self::Error) -> bool {
             error.0
         }
+
+        fn should_retry_response(&self, response: &Self::Response) -> Option<Cow<str>> {
+            if response.starts_with("retry") {
+                Some("retry requested".into())
+            } else {
+                None
+            }
+        }
+
+        fn retry_after(&self, response: &Self::Response) -> Option<Duration> {
+            match *response {
+                "retry" => Some(Duration::from_secs(5)),
+                "retry tomorrow" => Some(Duration::from_secs(24 * 60 * 60)),
+                _ => None,
+            }
+        }
     }
 
     #[derive(Debug)]
@@ -0,0 +1,44 @@
+use super::{
+    http::https_connector,
+    proxy::{ProxyConnector, ProxySettings},
+    tls::TlsSettings,
+};
+use http::Uri;
+use hyper::client::HttpConnector;
+use hyper_tls::HttpsConnector;
+use rusoto_core::{request::DispatchSignedRequest, signature::SignedRequest, HttpClient};
+use std::time::Duration;
+
+type Dispatcher = HttpClient<HttpsConnector<ProxyConnector<HttpConnector>>>;
+
+/// The dispatcher of the AWS clients, going through the sink's proxy.
+pub struct Client {
+    inner: Dispatcher,
+    proxy: ProxySettings,
+}
+
+pub fn client(proxy: ProxySettings) -> crate::Result<Client> {
+    let connector = https_connector(TlsSettings::default(), proxy.clone())?;
+    Ok(Client {
+        inner: HttpClient::from_connector(connector),
+        proxy,
+    })
+}
+
+impl DispatchSignedRequest for Client {
+    type Future = <Dispatcher as DispatchSignedRequest>::Future;
+
+    fn dispatch(&self, mut request: SignedRequest, timeout: Option<Duration>) -> Self::Future {
+        // Requests to plain http endpoints are sent to the proxy in absolute
+        // form, so its credentials go in the headers. The signature doesn't
+        // cover headers added after signing.
+        let uri = format!("{}://{}/", request.scheme(), request.hostname()).parse::<Uri>();
+        if let Some(authorization) = uri.ok().and_then(|uri| self.proxy.authorization(&uri)) {
+            if let Ok(authorization) = authorization.to_str() {
+                request.add_header("Proxy-Authorization", authorization);
+            }
+        }
+
+        self.inner.dispatch(request, timeout)
+    }
+}
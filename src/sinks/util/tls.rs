@@ -1,3 +1,4 @@
+use futures::{Future, Stream};
 use native_tls::{Certificate, Identity, TlsConnectorBuilder};
 use openssl::{
     pkcs12::Pkcs12,
@@ -10,6 +11,12 @@ use std::fmt::Debug;
 use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::{clock, timer::Interval};
+
+/// How often the files TLS settings are loaded from are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
 
 #[derive(Debug, Snafu)]
 enum TlsError {
@@ -73,6 +80,8 @@ pub struct TlsSettings {
     accept_invalid_hostnames: bool,
     authority: Option<Certificate>,
     identity: Option<IdentityStore>, // native_tls::Identity doesn't implement Clone yet
+    options: TlsOptions,
+    stamps: FileStamps,
 }
 
 #[derive(Clone)]
@@ -94,6 +103,14 @@ impl TlsSettings {
             return Err(TlsError::MissingCrtKeyFile.into());
         }
 
+        // Taken before reading the files, so changes made while they are
+        // read are noticed by the next reload.
+        let stamps = FileStamps::new(
+            [&options.ca_path, &options.crt_path, &options.key_path]
+                .iter()
+                .filter_map(|path| path.as_ref()),
+        );
+
         let authority = match options.ca_path {
             None => None,
             Some(ref path) => Some(load_certificate(path)?),
@@ -137,8 +154,87 @@ impl TlsSettings {
             accept_invalid_hostnames: !options.verify_hostname.unwrap_or(true),
             authority,
             identity,
+            options: options.clone(),
+            stamps,
         })
     }
+
+    /// Loads the settings again if one of their files has changed since
+    /// they were loaded, so rotated certificates are used for new
+    /// connections. The current settings are kept if the new files are
+    /// invalid. Returns whether the settings were loaded again.
+    pub fn reload(&mut self) -> bool {
+        if let Some(stamps) = self.stamps.changed() {
+            match Self::from_options(&Some(self.options.clone())) {
+                Ok(settings) => {
+                    info!("Reloaded changed TLS certificates.");
+                    *self = settings;
+                    return true;
+                }
+                Err(error) => {
+                    error!(message = "Could not reload changed TLS certificates.", %error);
+                    self.stamps = stamps;
+                }
+            }
+        }
+        false
+    }
+
+    /// Whether the settings are loaded from any files, which could change.
+    pub fn has_files(&self) -> bool {
+        !self.stamps.0.is_empty()
+    }
+}
+
+/// Spawns a task calling `reload` every `RELOAD_INTERVAL` for as long as
+/// `target` is alive, so the files of TLS settings aren't checked on every
+/// connection.
+pub(crate) fn watch<T, F>(target: &Arc<T>, reload: F)
+where
+    T: Send + Sync + 'static,
+    F: Fn(&T) + Send + 'static,
+{
+    let target = Arc::downgrade(target);
+    let watch = Interval::new(clock::now() + RELOAD_INTERVAL, RELOAD_INTERVAL)
+        .map_err(|error| error!(message = "TLS reload timer failed.", %error))
+        .map(move |_| target.upgrade())
+        .take_while(|target| Ok(target.is_some()))
+        .for_each(move |target| {
+            reload(&target.expect("Checked by take_while"));
+            Ok(())
+        });
+    tokio::spawn(watch);
+}
+
+/// The modification times of the files TLS settings are loaded from.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FileStamps(Vec<(PathBuf, Option<SystemTime>)>);
+
+impl FileStamps {
+    pub fn new<'a>(paths: impl IntoIterator<Item = &'a PathBuf>) -> Self {
+        Self(
+            paths
+                .into_iter()
+                .map(|path| (path.clone(), modified(path)))
+                .collect(),
+        )
+    }
+
+    /// The current stamps, if any of the files has changed.
+    pub fn changed(&self) -> Option<Self> {
+        let current = Self::new(self.0.iter().map(|(path, _)| path));
+        if current.0 == self.0 {
+            None
+        } else {
+            Some(current)
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
 }
 
 pub trait TlsConnectorExt {
@@ -174,7 +270,10 @@ fn load_certificate(filename: &Path) -> crate::Result<Certificate> {
 }
 
 /// Load a private key from a named file
-fn load_key(filename: &Path, pass_phrase: &Option<String>) -> crate::Result<PKey<Private>> {
+pub(crate) fn load_key(
+    filename: &Path,
+    pass_phrase: &Option<String>,
+) -> crate::Result<PKey<Private>> {
     let data = open_read(filename, "key")?;
     match pass_phrase {
         None => Ok(PKey::private_key_from_der(&data)
@@ -189,14 +288,14 @@ fn load_key(filename: &Path, pass_phrase: &Option<String>) -> crate::Result<PKey
 }
 
 /// Load an X.509 certificate from a named file
-fn load_x509(filename: &Path) -> crate::Result<X509> {
+pub(crate) fn load_x509(filename: &Path) -> crate::Result<X509> {
     let data = open_read(filename, "certificate")?;
     Ok(X509::from_der(&data)
         .or_else(|_| X509::from_pem(&data))
         .with_context(|| X509ParseError { filename })?)
 }
 
-fn open_read(filename: &Path, note: &'static str) -> crate::Result<Vec<u8>> {
+pub(crate) fn open_read(filename: &Path, note: &'static str) -> crate::Result<Vec<u8>> {
     let mut text = Vec::<u8>::new();
 
     File::open(filename)
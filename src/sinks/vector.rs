@@ -1,34 +1,143 @@
 use crate::{
     buffers::Acker,
     event::proto,
-    sinks::tcp::TcpSink,
-    sinks::util::SinkExt,
+    sinks::tcp::{TcpSink, TlsConfig},
+    sinks::util::{
+        adaptive_concurrency::{AdaptiveConcurrencyLimitLayer, InFlightLimit},
+        buffer::compression::Algorithm,
+        http::{https_connector, HttpRetryLogic, HttpService, HttpsClient, Response},
+        proxy::ProxySettings,
+        retries::RetryPolicy,
+        tls::TlsSettings,
+        BatchServiceSink, Buffer, Compression, SinkExt,
+    },
     topology::config::{DataType, SinkConfig},
     Event,
 };
 use bytes::{BufMut, Bytes, BytesMut};
-use futures::{future, Future, Sink};
+use futures::{future, Future, Poll, Sink};
+use http::{
+    header::{AUTHORIZATION, CONTENT_ENCODING},
+    Method, StatusCode, Uri,
+};
+use hyper::{Body, Request};
 use prost::Message;
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
 use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+use std::time::Duration;
 use tokio::net::TcpStream;
+use tower::{Service, ServiceBuilder};
+
+/// The path version 2 batches are posted to.
+pub(crate) const EVENTS_PATH: &str = "/v2/events";
+/// The path listing the supported versions in `VERSIONS_HEADER`.
+pub(crate) const HEALTH_PATH: &str = "/health";
+/// The version of the protocol a request uses.
+pub(crate) const VERSION_HEADER: &str = "vector-protocol-version";
+/// The comma separated versions a source supports.
+pub(crate) const VERSIONS_HEADER: &str = "vector-protocol-versions";
+/// The versions of the HTTP protocol this sink speaks, newest first.
+const SUPPORTED_VERSIONS: &[&str] = &["2"];
+/// Version 2 of the protocol only supports gzip compression.
+const SUPPORTED_COMPRESSION: &[Algorithm] = &[Algorithm::None, Algorithm::Gzip];
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("`addresses` requires version 2 of the protocol"))]
+    AddressesWithoutV2,
+}
+
+/// Version 1 sends length delimited events over a TCP connection, version 2
+/// posts compressed batches of them over HTTP/2.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolVersion {
+    #[serde(rename = "1")]
+    V1,
+    #[serde(rename = "2")]
+    V2,
+}
+
+impl Default for ProtocolVersion {
+    fn default() -> Self {
+        ProtocolVersion::V1
+    }
+}
 
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct VectorSinkConfig {
     pub address: String,
+    #[serde(default)]
+    pub version: ProtocolVersion,
+    /// More aggregators, requests are sent to each of them in turn.
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    /// The maximum number of events in a batch.
+    pub batch_size: Option<usize>,
+    pub batch_max_bytes: Option<usize>,
+    pub batch_timeout: Option<u64>,
+    pub compression: Option<Compression>,
+    pub tls: Option<TlsConfig>,
+    /// A secret sent before any events, the source rejects the connection
+    /// if it doesn't match its own.
+    pub auth_token: Option<String>,
+    /// Ack events only once the source acknowledges them.
+    #[serde(default)]
+    pub acknowledgements: bool,
 }
 
 impl VectorSinkConfig {
     pub fn new(address: String) -> Self {
-        Self { address }
+        Self {
+            address,
+            version: ProtocolVersion::V1,
+            addresses: Vec::new(),
+            batch_size: None,
+            batch_max_bytes: None,
+            batch_timeout: None,
+            compression: None,
+            tls: None,
+            auth_token: None,
+            acknowledgements: false,
+        }
     }
 }
 
 #[typetag::serde(name = "vector")]
 impl SinkConfig for VectorSinkConfig {
     fn build(&self, acker: Acker) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
+        let tls = match &self.tls {
+            Some(tls) if tls.enabled.unwrap_or(false) => {
+                Some(TlsSettings::from_options(&Some(tls.options.clone()))?)
+            }
+            _ => None,
+        };
+
+        match self.version {
+            ProtocolVersion::V1 if !self.addresses.is_empty() => {
+                Err(Box::new(BuildError::AddressesWithoutV2))
+            }
+            ProtocolVersion::V1 => self.build_v1(tls, acker),
+            ProtocolVersion::V2 => self.build_v2(tls, acker),
+        }
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+}
+
+impl VectorSinkConfig {
+    fn build_v1(
+        &self,
+        tls: Option<TlsSettings>,
+        acker: Acker,
+    ) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
         let addr = self
             .address
             .to_socket_addrs()
@@ -38,29 +147,273 @@ impl SinkConfig for VectorSinkConfig {
                 address: self.address.clone(),
             }))?;
 
-        let sink = vector(self.address.clone(), addr, acker);
+        // The certificate is checked against the host, without the port
+        let hostname = match self.address.rfind(':') {
+            Some(colon) => self.address[..colon].to_string(),
+            None => self.address.clone(),
+        };
+
+        let sink = vector(
+            hostname,
+            addr,
+            tls,
+            self.auth_token.as_ref().map(|token| token.as_str()),
+            self.acknowledgements,
+            acker,
+        );
         let healthcheck = super::tcp::tcp_healthcheck(addr);
 
         Ok((sink, healthcheck))
     }
 
-    fn input_type(&self) -> DataType {
-        DataType::Log
+    fn build_v2(
+        &self,
+        tls: Option<TlsSettings>,
+        acker: Acker,
+    ) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
+        let compression = self
+            .compression
+            .unwrap_or(Compression::Gzip(None))
+            .supported_by(SUPPORTED_COMPRESSION)?;
+
+        let scheme = if tls.is_some() { "https" } else { "http" };
+        let aggregators = std::iter::once(&self.address)
+            .chain(&self.addresses)
+            .map(|address| Aggregator::new(format!("{}://{}", scheme, address)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let aggregators = Arc::new(aggregators);
+        let tls = tls.unwrap_or_default();
+        let client = hyper::Client::builder()
+            .http2_only(true)
+            .build(https_connector(tls.clone(), ProxySettings::default())?);
+
+        let batch_size = self.batch_size.unwrap_or(1000);
+        let batch_max_bytes = self.batch_max_bytes.unwrap_or(bytesize::mib(1u64) as usize);
+        let batch_timeout = self.batch_timeout.unwrap_or(1);
+
+        let policy = RetryPolicy::new(usize::max_value(), Duration::from_secs(1), HttpRetryLogic)
+            .max_backoff(Duration::from_secs(10));
+
+        let auth_token = self.auth_token.clone();
+        let uris = Arc::clone(&aggregators);
+        let http_service = HttpService::builder()
+            .tls_settings(tls)
+            .http2_only(true)
+            .build(
+                move |(index, version, body): (usize, &'static str, Vec<u8>)| {
+                    let mut builder = hyper::Request::builder();
+                    builder.method(Method::POST);
+                    builder.uri(uris[index].uri.clone());
+                    builder.header(VERSION_HEADER, version);
+
+                    if let Some(content_encoding) = compression.content_encoding() {
+                        builder.header(CONTENT_ENCODING, content_encoding);
+                    }
+                    if let Some(token) = &auth_token {
+                        builder.header(AUTHORIZATION, format!("Bearer {}", token));
+                    }
+
+                    builder.body(body).unwrap()
+                },
+            );
+        let negotiated = Negotiated {
+            inner: http_service,
+            client: client.clone(),
+            aggregators: Arc::clone(&aggregators),
+            next: Arc::new(AtomicUsize::new(0)),
+        };
+
+        let service = ServiceBuilder::new()
+            .retry(policy)
+            .layer(AdaptiveConcurrencyLimitLayer::new(
+                InFlightLimit::Fixed(5),
+                HttpRetryLogic,
+            ))
+            .timeout(Duration::from_secs(30))
+            .service(negotiated);
+
+        let sink = BatchServiceSink::new(service, acker)
+            .batched_with_min(
+                Buffer::new(compression),
+                batch_max_bytes,
+                Duration::from_secs(batch_timeout),
+            )
+            .max_events(batch_size)
+            .with(|event| encode_event(event).map(|frame| frame.to_vec()));
+
+        let healthcheck = healthcheck_v2(&aggregators, client);
+
+        Ok((Box::new(sink), healthcheck))
     }
 }
 
-pub fn vector(hostname: String, addr: SocketAddr, acker: Acker) -> super::RouterSink {
-    Box::new(
-        TcpSink::new(hostname, addr, None)
-            .stream_ack(acker)
-            .with(encode_event),
-    )
+pub fn vector(
+    hostname: String,
+    addr: SocketAddr,
+    tls: Option<TlsSettings>,
+    auth_token: Option<&str>,
+    acknowledgements: bool,
+    acker: Acker,
+) -> super::RouterSink {
+    let mut sink = TcpSink::new(hostname, addr, tls);
+    if let Some(token) = auth_token {
+        sink = sink.handshake(encode_frame(token.as_bytes()));
+    }
+
+    if acknowledgements {
+        Box::new(sink.acknowledged_by_peer(acker).with(encode_event))
+    } else {
+        Box::new(sink.stream_ack(acker).with(encode_event))
+    }
 }
 
 #[derive(Debug, Snafu)]
 enum HealthcheckError {
     #[snafu(display("Connect error: {}", source))]
     ConnectError { source: std::io::Error },
+    #[snafu(display(
+        "{} supports none of the protocol versions {:?}",
+        base,
+        SUPPORTED_VERSIONS
+    ))]
+    UnsupportedVersion { base: String },
+}
+
+/// An aggregator batches are sent to, with the protocol version negotiated
+/// with it.
+struct Aggregator {
+    base: String,
+    uri: Uri,
+    version: Mutex<Option<&'static str>>,
+}
+
+impl Aggregator {
+    fn new(base: String) -> crate::Result<Arc<Self>> {
+        let uri = format!("{}{}", base, EVENTS_PATH)
+            .parse::<Uri>()
+            .context(super::UriParseError)?;
+        Ok(Arc::new(Self {
+            base,
+            uri,
+            version: Mutex::new(None),
+        }))
+    }
+
+    fn negotiate(
+        self: Arc<Self>,
+        client: &HttpsClient,
+    ) -> impl Future<Item = &'static str, Error = crate::Error> {
+        negotiate_version(client, &self.base).map(move |version| {
+            let mut current = self.version.lock().unwrap();
+            if *current != Some(version) {
+                info!(message = "negotiated protocol version.", base = %self.base, %version);
+                *current = Some(version);
+            }
+            version
+        })
+    }
+}
+
+/// The newest version both this sink and the aggregator at `base` support,
+/// out of the ones its health endpoint lists.
+fn negotiate_version(
+    client: &HttpsClient,
+    base: &str,
+) -> impl Future<Item = &'static str, Error = crate::Error> {
+    let request = Request::get(format!("{}{}", base, HEALTH_PATH))
+        .body(Body::empty())
+        .unwrap();
+    let base = base.to_string();
+
+    client
+        .request(request)
+        .map_err(crate::Error::from)
+        .and_then(move |response| {
+            let status = response.status();
+            if status != StatusCode::OK {
+                return Err(super::HealthcheckError::UnexpectedStatus { status }.into());
+            }
+
+            let versions = response
+                .headers()
+                .get(VERSIONS_HEADER)
+                .and_then(|versions| versions.to_str().ok())
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .collect::<Vec<_>>();
+            SUPPORTED_VERSIONS
+                .iter()
+                .find(|version| versions.contains(*version))
+                .cloned()
+                .ok_or_else(|| HealthcheckError::UnsupportedVersion { base }.into())
+        })
+}
+
+/// Sends each batch to the next aggregator in turn. The protocol version is
+/// negotiated with an aggregator before the first batch sent to it, and
+/// again when it rejects a batch as a bad request, in case it was replaced
+/// by one speaking other versions; the batch is then sent again if the
+/// version changed.
+#[derive(Clone)]
+struct Negotiated {
+    inner: HttpService<(usize, &'static str, Vec<u8>)>,
+    client: HttpsClient,
+    aggregators: Arc<Vec<Arc<Aggregator>>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl Service<Vec<u8>> for Negotiated {
+    type Response = Response;
+    type Error = crate::Error;
+    type Future = Box<dyn Future<Item = Self::Response, Error = Self::Error> + Send + 'static>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, body: Vec<u8>) -> Self::Future {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.aggregators.len();
+        let aggregator = Arc::clone(&self.aggregators[index]);
+        let client = self.client.clone();
+        let mut inner = self.inner.clone();
+
+        let cached = *aggregator.version.lock().unwrap();
+        let version = match cached {
+            Some(version) => future::Either::A(future::ok(version)),
+            None => future::Either::B(Arc::clone(&aggregator).negotiate(&client)),
+        };
+
+        let fut = version.and_then(move |version| {
+            inner
+                .call((index, version, body.clone()))
+                .and_then(move |response| {
+                    if response.status() != StatusCode::BAD_REQUEST {
+                        return future::Either::A(future::ok(response));
+                    }
+
+                    future::Either::B(aggregator.negotiate(&client).and_then(move |renegotiated| {
+                        if renegotiated == version {
+                            future::Either::A(future::ok(response))
+                        } else {
+                            future::Either::B(inner.call((index, renegotiated, body)))
+                        }
+                    }))
+                })
+        });
+
+        Box::new(fut)
+    }
+}
+
+/// Checks every aggregator is up and supports a version of the protocol.
+fn healthcheck_v2(aggregators: &[Arc<Aggregator>], client: HttpsClient) -> super::Healthcheck {
+    let checks = aggregators
+        .iter()
+        .map(|aggregator| Arc::clone(aggregator).negotiate(&client))
+        .collect::<Vec<_>>();
+
+    Box::new(future::join_all(checks).map(|_| ()))
 }
 
 pub fn vector_healthcheck(addr: SocketAddr) -> super::Healthcheck {
@@ -74,7 +427,15 @@ pub fn vector_healthcheck(addr: SocketAddr) -> super::Healthcheck {
     Box::new(check)
 }
 
-fn encode_event(event: Event) -> Result<Bytes, ()> {
+/// Frames `data` the way events are, prefixed by its length.
+fn encode_frame(data: &[u8]) -> Bytes {
+    let mut out = BytesMut::with_capacity(data.len() + 4);
+    out.put_u32_be(data.len() as u32);
+    out.put_slice(data);
+    out.freeze()
+}
+
+pub(crate) fn encode_event(event: Event) -> Result<Bytes, ()> {
     let event = proto::EventWrapper::from(event);
     let event_len = event.encoded_len() as u32;
     let full_len = event_len + 4;
@@ -84,3 +445,48 @@ fn encode_event(event: Event) -> Result<Bytes, ()> {
     event.encode(&mut out).unwrap();
     Ok(out.freeze())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{next_addr, runtime};
+    use hyper::{service::service_fn, Response, Server};
+
+    fn negotiate_with(versions: &'static str) -> crate::Result<&'static str> {
+        let addr = next_addr();
+        let server = Server::bind(&addr)
+            .http2_only(true)
+            .serve(move || {
+                service_fn(move |_| {
+                    future::ok::<_, hyper::Error>(
+                        Response::builder()
+                            .header(VERSIONS_HEADER, versions)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                })
+            })
+            .map_err(|error| panic!("server error: {}", error));
+
+        let mut rt = runtime();
+        rt.spawn(server);
+
+        let client = hyper::Client::builder()
+            .http2_only(true)
+            .build(https_connector(TlsSettings::default(), ProxySettings::default()).unwrap());
+        let aggregator = Aggregator::new(format!("http://{}", addr)).unwrap();
+        let version = rt.block_on(Arc::clone(&aggregator).negotiate(&client));
+
+        assert_eq!(
+            *aggregator.version.lock().unwrap(),
+            version.as_ref().ok().cloned()
+        );
+        version
+    }
+
+    #[test]
+    fn vector_v2_negotiates_newest_common_version() {
+        assert_eq!(negotiate_with("3, 2").unwrap(), "2");
+        assert!(negotiate_with("1").is_err());
+    }
+}
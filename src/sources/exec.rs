@@ -0,0 +1,305 @@
+use crate::{
+    event::{self, Event},
+    topology::config::{DataType, GlobalOptions, SourceConfig},
+};
+use bytes::Bytes;
+use codec::BytesDelimitedCodec;
+use futures::{
+    future::{self, Either, Loop},
+    sync::mpsc,
+    Future, Sink, Stream,
+};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::{
+    cmp, io,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use string_cache::DefaultAtom as Atom;
+use tokio::{
+    codec::FramedRead,
+    timer::{Delay, Interval},
+};
+use tokio_process::CommandExt;
+
+lazy_static! {
+    pub static ref COMMAND: Atom = Atom::from("command");
+    pub static ref PID: Atom = Atom::from("pid");
+}
+
+const STDOUT: &str = "stdout";
+const STDERR: &str = "stderr";
+
+/// The first delay before respawning a command in streaming mode, doubled
+/// every time it exits again quickly.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("The command must not be empty"))]
+    EmptyCommand,
+}
+
+/// Runs a command and turns the lines of its output into events. Commands
+/// still running are killed when the source is shut down.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ExecConfig {
+    /// The program and its arguments, not run through a shell.
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub mode: Mode,
+    /// How often the command is run in scheduled mode.
+    #[serde(default = "default_exec_interval_secs")]
+    pub exec_interval_secs: u64,
+    /// Whether the command is run again when it exits in streaming mode.
+    #[serde(default = "default_respawn_on_exit")]
+    pub respawn_on_exit: bool,
+    #[serde(default = "default_respawn_max_backoff_secs")]
+    pub respawn_max_backoff_secs: u64,
+    pub working_directory: Option<PathBuf>,
+    /// Whether stderr lines are events too, or go to Vector's stderr.
+    #[serde(default = "default_include_stderr")]
+    pub include_stderr: bool,
+    #[serde(default = "default_max_length")]
+    pub max_length: usize,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// The command is run every `exec_interval_secs`.
+    Scheduled,
+    /// The command is run once and keeps running.
+    Streaming,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::Scheduled
+    }
+}
+
+fn default_exec_interval_secs() -> u64 {
+    60
+}
+
+fn default_respawn_on_exit() -> bool {
+    true
+}
+
+fn default_respawn_max_backoff_secs() -> u64 {
+    60
+}
+
+fn default_include_stderr() -> bool {
+    true
+}
+
+fn default_max_length() -> usize {
+    bytesize::kib(100u64) as usize
+}
+
+impl ExecConfig {
+    pub fn new(command: Vec<String>) -> Self {
+        Self {
+            command,
+            mode: Mode::default(),
+            exec_interval_secs: default_exec_interval_secs(),
+            respawn_on_exit: default_respawn_on_exit(),
+            respawn_max_backoff_secs: default_respawn_max_backoff_secs(),
+            working_directory: None,
+            include_stderr: default_include_stderr(),
+            max_length: default_max_length(),
+        }
+    }
+}
+
+#[typetag::serde(name = "exec")]
+impl SourceConfig for ExecConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        if self.command.is_empty() {
+            return Err(BuildError::EmptyCommand.into());
+        }
+        let config = Arc::new(self.clone());
+
+        match self.mode {
+            Mode::Scheduled => {
+                let interval = Duration::from_secs(self.exec_interval_secs);
+                Ok(Box::new(
+                    Interval::new(Instant::now(), interval)
+                        .map_err(|error| error!(message = "timer error.", %error))
+                        // A failed run is logged, the next one still happens
+                        .for_each(move |_| run(Arc::clone(&config), out.clone()).then(|_| Ok(()))),
+                ))
+            }
+            Mode::Streaming => {
+                let max_backoff = Duration::from_secs(self.respawn_max_backoff_secs);
+                Ok(Box::new(future::loop_fn(INITIAL_BACKOFF, move |backoff| {
+                    let config = Arc::clone(&config);
+                    let started = Instant::now();
+                    run(Arc::clone(&config), out.clone()).then(move |_| {
+                        if !config.respawn_on_exit {
+                            return Either::A(future::ok(Loop::Break(())));
+                        }
+                        // A command which ran for a while starts over.
+                        let backoff = if started.elapsed() >= max_backoff {
+                            INITIAL_BACKOFF
+                        } else {
+                            cmp::min(backoff, max_backoff)
+                        };
+                        info!(
+                            message = "respawning command.",
+                            backoff_secs = backoff.as_secs()
+                        );
+                        Either::B(
+                            Delay::new(Instant::now() + backoff)
+                                .map_err(|error| error!(message = "timer error.", %error))
+                                .map(move |_| Loop::Continue(backoff * 2)),
+                        )
+                    })
+                })))
+            }
+        }
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+}
+
+type Lines = Box<dyn Stream<Item = (Bytes, &'static str), Error = io::Error> + Send>;
+
+/// Runs the command once, until it exits and its output is sent.
+fn run(config: Arc<ExecConfig>, out: mpsc::Sender<Event>) -> impl Future<Item = (), Error = ()> {
+    future::lazy(move || {
+        let mut command = Command::new(&config.command[0]);
+        command
+            .args(&config.command[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(if config.include_stderr {
+                Stdio::piped()
+            } else {
+                Stdio::inherit()
+            });
+        if let Some(directory) = &config.working_directory {
+            command.current_dir(directory);
+        }
+
+        let mut child = match command.spawn_async() {
+            Ok(child) => child,
+            Err(error) => {
+                error!(message = "failed spawning command.", command = ?config.command, %error);
+                return Either::A(future::ok(()));
+            }
+        };
+        let pid = child.id();
+        debug!(message = "command spawned.", command = ?config.command, %pid);
+
+        let codec = || BytesDelimitedCodec::new_with_max_length(b'\n', config.max_length);
+        let mut lines: Lines = match child.stdout().take() {
+            Some(stdout) => Box::new(FramedRead::new(stdout, codec()).map(|line| (line, STDOUT))),
+            None => Box::new(futures::stream::empty()),
+        };
+        if let Some(stderr) = child.stderr().take() {
+            let stderr = FramedRead::new(stderr, codec()).map(|line| (line, STDERR));
+            lines = Box::new(lines.select(stderr));
+        }
+
+        let command_line = config.command.join(" ");
+        let events = lines
+            .map(move |(line, stream)| create_event(line, stream, pid, &command_line))
+            .map_err(|error| error!(message = "failed reading command output.", %error));
+
+        // The child is killed if dropped, so it is kept until it exits.
+        Either::B(
+            events
+                .forward(out.sink_map_err(|e| error!("error sending event: {:?}", e)))
+                .and_then(move |_| {
+                    child.map_err(|error| error!(message = "failed waiting for command.", %error))
+                })
+                .map(move |status| info!(message = "command exited.", %pid, %status)),
+        )
+    })
+}
+
+fn create_event(line: Bytes, stream: &'static str, pid: u32, command: &str) -> Event {
+    let mut event = Event::from(line);
+    let log = event.as_mut_log();
+    log.insert_implicit(event::STREAM.clone(), stream.into());
+    log.insert_implicit(PID.clone(), i64::from(pid).into());
+    log.insert_implicit(COMMAND.clone(), command.into());
+    event
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExecConfig, Mode, PID};
+    use crate::{
+        event::{self, Event, ValueKind},
+        test_util::{collect_n, runtime},
+        topology::config::{GlobalOptions, SourceConfig},
+    };
+    use futures::sync::mpsc;
+
+    fn collect(config: ExecConfig, count: usize) -> Vec<Event> {
+        let (tx, rx) = mpsc::channel(10);
+        let source = config
+            .build("default", &GlobalOptions::default(), tx)
+            .unwrap();
+
+        let mut rt = runtime();
+        rt.spawn(source);
+        rt.block_on(collect_n(rx, count)).unwrap()
+    }
+
+    #[test]
+    fn scheduled_command_stdout_and_stderr() {
+        let config = ExecConfig::new(vec![
+            "sh".into(),
+            "-c".into(),
+            "echo hello; echo oops >&2".into(),
+        ]);
+        let mut events = collect(config, 2);
+        events.sort_by_key(|event| event.as_log()[&event::STREAM].to_string_lossy());
+
+        assert_eq!(events[0].as_log()[&event::MESSAGE], "oops".into());
+        assert_eq!(events[0].as_log()[&event::STREAM], "stderr".into());
+        assert_eq!(events[1].as_log()[&event::MESSAGE], "hello".into());
+        assert_eq!(events[1].as_log()[&event::STREAM], "stdout".into());
+        assert_eq!(
+            events[1].as_log()[&"command".into()],
+            "sh -c echo hello; echo oops >&2".into()
+        );
+    }
+
+    #[test]
+    fn streaming_command_is_respawned() {
+        let config = ExecConfig {
+            mode: Mode::Streaming,
+            respawn_max_backoff_secs: 1,
+            ..ExecConfig::new(vec!["echo".into(), "again".into()])
+        };
+        let events = collect(config, 2);
+
+        let pids = events
+            .iter()
+            .map(|event| match event.as_log()[&PID] {
+                ValueKind::Integer(pid) => pid,
+                _ => panic!("pid isn't an integer"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(events[1].as_log()[&event::MESSAGE], "again".into());
+        assert_ne!(pids[0], pids[1]);
+    }
+}
@@ -0,0 +1,822 @@
+use super::util::CumulativeCounters;
+use crate::{
+    event::{Event, Metric},
+    topology::config::{DataType, GlobalOptions, SourceConfig},
+};
+use chrono::{DateTime, Utc};
+use futures::{future, stream, sync::mpsc, Future, Sink, Stream};
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::CString,
+    fs, io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::timer::{Interval, Timeout};
+
+/// The unit of the sector counts of `/proc/diskstats`, whatever the device.
+const SECTOR_SIZE: f64 = 512.0;
+
+/// How long reading the usage of a filesystem can take. It hangs for
+/// unreachable network filesystems.
+const STATVFS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The types of network filesystems, whose usage isn't read by default.
+const NETWORK_FILESYSTEMS: [&str; 12] = [
+    "nfs*",
+    "cifs",
+    "smb*",
+    "9p",
+    "afs",
+    "ceph",
+    "glusterfs",
+    "lustre",
+    "davfs",
+    "fuse.sshfs",
+    "fuse.glusterfs",
+    "fuse.s3fs",
+];
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("Invalid pattern {:?}: {}", pattern, source))]
+    InvalidPattern {
+        pattern: String,
+        source: glob::PatternError,
+    },
+}
+
+/// Host metrics read from procfs, like the ones of the node exporter.
+/// Only Linux is supported.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HostMetricsConfig {
+    #[serde(default = "default_scrape_interval_secs")]
+    pub scrape_interval_secs: u64,
+    #[serde(default = "default_collectors")]
+    pub collectors: Vec<Collector>,
+    #[serde(default)]
+    pub disk: DeviceFilter,
+    #[serde(default)]
+    pub filesystem: FilesystemFilter,
+    #[serde(default)]
+    pub network: DeviceFilter,
+    /// Where procfs is mounted, usually elsewhere for the host's in a container.
+    #[serde(default = "default_procfs_root")]
+    pub procfs_root: PathBuf,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Collector {
+    Cpu,
+    Memory,
+    Load,
+    Disk,
+    Filesystem,
+    Network,
+}
+
+/// Glob patterns of the names kept, all of them without `includes`, and of
+/// the ones dropped.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FilterList {
+    pub includes: Option<Vec<String>>,
+    #[serde(default)]
+    pub excludes: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceFilter {
+    #[serde(default)]
+    pub devices: FilterList,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FilesystemFilter {
+    #[serde(default)]
+    pub devices: FilterList,
+    #[serde(default)]
+    pub mountpoints: FilterList,
+    /// The filesystem types, network ones are excluded by default.
+    #[serde(default = "default_filesystem_types")]
+    pub types: FilterList,
+}
+
+impl Default for FilesystemFilter {
+    fn default() -> Self {
+        Self {
+            devices: FilterList::default(),
+            mountpoints: FilterList::default(),
+            types: default_filesystem_types(),
+        }
+    }
+}
+
+fn default_scrape_interval_secs() -> u64 {
+    15
+}
+
+fn default_collectors() -> Vec<Collector> {
+    vec![
+        Collector::Cpu,
+        Collector::Memory,
+        Collector::Load,
+        Collector::Disk,
+        Collector::Filesystem,
+        Collector::Network,
+    ]
+}
+
+fn default_filesystem_types() -> FilterList {
+    FilterList {
+        includes: None,
+        excludes: NETWORK_FILESYSTEMS.iter().map(|t| t.to_string()).collect(),
+    }
+}
+
+fn default_procfs_root() -> PathBuf {
+    "/proc".into()
+}
+
+impl HostMetricsConfig {
+    pub fn new() -> Self {
+        Self {
+            scrape_interval_secs: default_scrape_interval_secs(),
+            collectors: default_collectors(),
+            disk: DeviceFilter::default(),
+            filesystem: FilesystemFilter::default(),
+            network: DeviceFilter::default(),
+            procfs_root: default_procfs_root(),
+        }
+    }
+}
+
+#[typetag::serde(name = "host_metrics")]
+impl SourceConfig for HostMetricsConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        let mut host = HostMetrics::new(self)?;
+        let filesystems = Arc::new(Filesystems::new(self)?);
+        let interval = Duration::from_secs(self.scrape_interval_secs);
+
+        let metrics = Interval::new(Instant::now(), interval)
+            .map_err(|error| error!(message = "timer error.", %error))
+            .and_then(move |_| filesystems.usage())
+            .map(move |usage| stream::iter_ok(host.collect(&usage)))
+            .flatten()
+            .map(Event::Metric);
+
+        Ok(Box::new(
+            metrics
+                .forward(out.sink_map_err(|e| error!("error sending metric: {:?}", e)))
+                .map(|_| info!("finished sending")),
+        ))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Metric
+    }
+}
+
+struct Filter {
+    includes: Option<Vec<Pattern>>,
+    excludes: Vec<Pattern>,
+}
+
+impl Filter {
+    fn new(list: &FilterList) -> crate::Result<Self> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| Pattern::new(pattern).context(InvalidPattern { pattern }))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Self {
+            includes: list.includes.as_ref().map(|p| compile(p)).transpose()?,
+            excludes: compile(&list.excludes)?,
+        })
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        let included = match &self.includes {
+            Some(includes) => includes.iter().any(|pattern| pattern.matches(name)),
+            None => true,
+        };
+        included && !self.excludes.iter().any(|pattern| pattern.matches(name))
+    }
+}
+
+struct HostMetrics {
+    collectors: Vec<Collector>,
+    procfs_root: PathBuf,
+    disk_devices: Filter,
+    network_devices: Filter,
+    ticks_per_second: f64,
+    counters: CumulativeCounters,
+}
+
+impl HostMetrics {
+    fn new(config: &HostMetricsConfig) -> crate::Result<Self> {
+        let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        Ok(Self {
+            collectors: config.collectors.clone(),
+            procfs_root: config.procfs_root.clone(),
+            disk_devices: Filter::new(&config.disk.devices)?,
+            network_devices: Filter::new(&config.network.devices)?,
+            ticks_per_second: if ticks > 0 { ticks as f64 } else { 100.0 },
+            counters: CumulativeCounters::default(),
+        })
+    }
+
+    /// The metrics of every collector, with the counters as increments since
+    /// the previous collection. The usage of the filesystems is read
+    /// beforehand by `Filesystems`.
+    fn collect(&mut self, filesystems: &[(Mount, libc::statvfs)]) -> Vec<Metric> {
+        let now = Utc::now();
+        let mut metrics = Vec::new();
+        for collector in self.collectors.clone() {
+            match self.collect_one(collector, now, filesystems) {
+                Ok(collected) => metrics.extend(collected),
+                Err(error) => error!(
+                    message = "failed collecting host metrics.",
+                    ?collector,
+                    %error,
+                    rate_limit_secs = 60
+                ),
+            }
+        }
+
+        let counters = &mut self.counters;
+        metrics
+            .into_iter()
+            .filter_map(|metric| counters.increment(metric))
+            .collect()
+    }
+
+    fn collect_one(
+        &self,
+        collector: Collector,
+        now: DateTime<Utc>,
+        filesystems: &[(Mount, libc::statvfs)],
+    ) -> io::Result<Vec<Metric>> {
+        let mut metrics = Vec::new();
+        let mut out = Metrics {
+            metrics: &mut metrics,
+            timestamp: now,
+        };
+        match collector {
+            Collector::Cpu => parse_cpu(&self.read("stat")?, self.ticks_per_second, &mut out),
+            Collector::Memory => parse_memory(&self.read("meminfo")?, &mut out),
+            Collector::Load => parse_load(&self.read("loadavg")?, &mut out),
+            Collector::Disk => {
+                parse_diskstats(&self.read("diskstats")?, &self.disk_devices, &mut out)
+            }
+            Collector::Filesystem => {
+                for (mount, stat) in filesystems {
+                    filesystem_usage(mount, stat, &mut out);
+                }
+            }
+            Collector::Network => {
+                parse_net_dev(&self.read("net/dev")?, &self.network_devices, &mut out)
+            }
+        }
+        Ok(metrics)
+    }
+
+    fn read(&self, file: &str) -> io::Result<String> {
+        fs::read_to_string(self.procfs_root.join(file))
+    }
+}
+
+/// Metrics of a collection, which all have its timestamp.
+struct Metrics<'a> {
+    metrics: &'a mut Vec<Metric>,
+    timestamp: DateTime<Utc>,
+}
+
+impl<'a> Metrics<'a> {
+    fn counter(&mut self, name: &str, val: f64, tags: &[(&str, &str)]) {
+        self.metrics.push(Metric::Counter {
+            name: name.into(),
+            val,
+            timestamp: Some(self.timestamp),
+            tags: to_tags(tags),
+        });
+    }
+
+    fn gauge(&mut self, name: &str, val: f64, tags: &[(&str, &str)]) {
+        self.metrics.push(Metric::Gauge {
+            name: name.into(),
+            val,
+            direction: None,
+            timestamp: Some(self.timestamp),
+            tags: to_tags(tags),
+        });
+    }
+}
+
+fn to_tags(tags: &[(&str, &str)]) -> Option<HashMap<String, String>> {
+    if tags.is_empty() {
+        None
+    } else {
+        Some(
+            tags.iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+}
+
+const CPU_MODES: [&str; 8] = [
+    "user", "nice", "system", "idle", "iowait", "irq", "softirq", "steal",
+];
+
+/// The `cpuN` lines of `/proc/stat`, in ticks spent in each mode.
+fn parse_cpu(text: &str, ticks_per_second: f64, out: &mut Metrics) {
+    for line in text.lines() {
+        let mut fields = line.split_whitespace();
+        let cpu = match fields.next() {
+            Some(name) if name.starts_with("cpu") && name.len() > 3 => &name[3..],
+            _ => continue,
+        };
+        for (mode, ticks) in CPU_MODES.iter().zip(fields) {
+            if let Ok(ticks) = ticks.parse::<f64>() {
+                out.counter(
+                    "host_cpu_seconds_total",
+                    ticks / ticks_per_second,
+                    &[("cpu", cpu), ("mode", *mode)],
+                );
+            }
+        }
+    }
+}
+
+const MEMORY_FIELDS: [(&str, &str); 7] = [
+    ("MemTotal", "host_memory_total_bytes"),
+    ("MemFree", "host_memory_free_bytes"),
+    ("MemAvailable", "host_memory_available_bytes"),
+    ("Buffers", "host_memory_buffers_bytes"),
+    ("Cached", "host_memory_cached_bytes"),
+    ("SwapTotal", "host_memory_swap_total_bytes"),
+    ("SwapFree", "host_memory_swap_free_bytes"),
+];
+
+/// `/proc/meminfo`, where sizes are in kibibytes.
+fn parse_memory(text: &str, out: &mut Metrics) {
+    for line in text.lines() {
+        let mut fields = line.split_whitespace();
+        let (field, value) = match (fields.next(), fields.next()) {
+            (Some(field), Some(value)) => (field.trim_end_matches(':'), value),
+            _ => continue,
+        };
+        let name = MEMORY_FIELDS
+            .iter()
+            .find(|(known, _)| *known == field)
+            .map(|(_, name)| name);
+        if let (Some(name), Ok(value)) = (name, value.parse::<f64>()) {
+            let multiplier = if fields.next() == Some("kB") {
+                1024.0
+            } else {
+                1.0
+            };
+            out.gauge(name, value * multiplier, &[]);
+        }
+    }
+}
+
+fn parse_load(text: &str, out: &mut Metrics) {
+    let names = ["host_load1", "host_load5", "host_load15"];
+    for (name, value) in names.iter().zip(text.split_whitespace()) {
+        if let Ok(value) = value.parse() {
+            out.gauge(name, value, &[]);
+        }
+    }
+}
+
+/// `/proc/diskstats`, the fields after the device name are in
+/// Documentation/admin-guide/iostats.rst of the kernel.
+fn parse_diskstats(text: &str, devices: &Filter, out: &mut Metrics) {
+    for line in text.lines() {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        if fields.len() < 14 || !devices.matches(fields[2]) {
+            continue;
+        }
+        let device = fields[2];
+        let field = |index: usize| fields[index].parse::<f64>().unwrap_or_default();
+        let tags = [("device", device)];
+
+        out.counter("host_disk_reads_completed_total", field(3), &tags);
+        out.counter("host_disk_read_bytes_total", field(5) * SECTOR_SIZE, &tags);
+        out.counter("host_disk_writes_completed_total", field(7), &tags);
+        out.counter(
+            "host_disk_written_bytes_total",
+            field(9) * SECTOR_SIZE,
+            &tags,
+        );
+        out.counter("host_disk_io_time_seconds_total", field(12) / 1000.0, &tags);
+    }
+}
+
+/// Reads the usage of the mounted filesystems on the blocking threads of
+/// the runtime, giving up on the ones taking longer than `STATVFS_TIMEOUT`.
+struct Filesystems {
+    enabled: bool,
+    procfs_root: PathBuf,
+    devices: Filter,
+    mountpoints: Filter,
+    types: Filter,
+    /// The mountpoints whose usage is being read, a read still hanging
+    /// after its timeout isn't started again.
+    pending: Mutex<HashSet<String>>,
+}
+
+impl Filesystems {
+    fn new(config: &HostMetricsConfig) -> crate::Result<Self> {
+        Ok(Self {
+            enabled: config.collectors.contains(&Collector::Filesystem),
+            procfs_root: config.procfs_root.clone(),
+            devices: Filter::new(&config.filesystem.devices)?,
+            mountpoints: Filter::new(&config.filesystem.mountpoints)?,
+            types: Filter::new(&config.filesystem.types)?,
+            pending: Mutex::new(HashSet::new()),
+        })
+    }
+
+    fn usage(
+        self: &Arc<Self>,
+    ) -> Box<dyn Future<Item = Vec<(Mount, libc::statvfs)>, Error = ()> + Send> {
+        if !self.enabled {
+            return Box::new(future::ok(Vec::new()));
+        }
+        let mounts = match fs::read_to_string(self.procfs_root.join("mounts")) {
+            Ok(mounts) => parse_mounts(&mounts),
+            Err(error) => {
+                error!(
+                    message = "failed collecting host metrics.",
+                    collector = ?Collector::Filesystem,
+                    %error,
+                    rate_limit_secs = 60
+                );
+                return Box::new(future::ok(Vec::new()));
+            }
+        };
+
+        let usage = mounts
+            .into_iter()
+            .filter(|mount| {
+                self.devices.matches(&mount.device)
+                    && self.mountpoints.matches(&mount.mountpoint)
+                    && self.types.matches(&mount.filesystem)
+            })
+            .map(|mount| {
+                let this = Arc::clone(self);
+                let mountpoint = mount.mountpoint.clone();
+                let stat = future::poll_fn(move || {
+                    tokio_threadpool::blocking(|| this.statvfs(&mountpoint))
+                });
+                Timeout::new(stat, STATVFS_TIMEOUT).then(move |result| {
+                    match result {
+                        Ok(Ok(stat)) => return Ok(Some((mount, stat))),
+                        Ok(Err(error)) => debug!(
+                            message = "failed reading filesystem usage.",
+                            mountpoint = %mount.mountpoint,
+                            %error
+                        ),
+                        Err(ref error) if error.is_elapsed() => warn!(
+                            message = "reading filesystem usage timed out.",
+                            mountpoint = %mount.mountpoint,
+                            rate_limit_secs = 60
+                        ),
+                        Err(error) => error!(
+                            message = "failed reading filesystem usage.",
+                            mountpoint = %mount.mountpoint,
+                            %error,
+                            rate_limit_secs = 60
+                        ),
+                    }
+                    Ok::<_, ()>(None)
+                })
+            });
+        Box::new(future::join_all(usage).map(|usage| usage.into_iter().flatten().collect()))
+    }
+
+    fn statvfs(&self, mountpoint: &str) -> io::Result<libc::statvfs> {
+        if !self
+            .pending
+            .lock()
+            .expect("poisoned lock")
+            .insert(mountpoint.to_owned())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "previous read still hanging",
+            ));
+        }
+        let stat = statvfs(Path::new(mountpoint));
+        self.pending
+            .lock()
+            .expect("poisoned lock")
+            .remove(mountpoint);
+        stat
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Mount {
+    device: String,
+    mountpoint: String,
+    filesystem: String,
+}
+
+/// `/proc/mounts`, where spaces and such in paths are octal escapes.
+fn parse_mounts(text: &str) -> Vec<Mount> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some(Mount {
+                device: unescape_octal(fields.next()?),
+                mountpoint: unescape_octal(fields.next()?),
+                filesystem: fields.next()?.into(),
+            })
+        })
+        .collect()
+}
+
+fn unescape_octal(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4).and_then(|digits| {
+            std::str::from_utf8(digits)
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 8).ok())
+        });
+        match escape {
+            Some(byte) if bytes[i] == b'\\' => {
+                unescaped.push(byte);
+                i += 4;
+            }
+            _ => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+/// The usage of a mounted filesystem, pseudo filesystems having no size.
+fn filesystem_usage(mount: &Mount, stat: &libc::statvfs, out: &mut Metrics) {
+    let block_size = stat.f_frsize as f64;
+    let total = stat.f_blocks as f64 * block_size;
+    if total == 0.0 {
+        return;
+    }
+    let free = stat.f_bfree as f64 * block_size;
+    let tags = [
+        ("device", mount.device.as_str()),
+        ("mountpoint", mount.mountpoint.as_str()),
+        ("filesystem", mount.filesystem.as_str()),
+    ];
+
+    out.gauge("host_filesystem_total_bytes", total, &tags);
+    out.gauge("host_filesystem_free_bytes", free, &tags);
+    out.gauge(
+        "host_filesystem_available_bytes",
+        stat.f_bavail as f64 * block_size,
+        &tags,
+    );
+    out.gauge("host_filesystem_used_bytes", total - free, &tags);
+}
+
+fn statvfs(path: &Path) -> io::Result<libc::statvfs> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } == 0 {
+        Ok(stat)
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+const NETWORK_FIELDS: [(usize, &str); 8] = [
+    (0, "host_network_receive_bytes_total"),
+    (1, "host_network_receive_packets_total"),
+    (2, "host_network_receive_errs_total"),
+    (3, "host_network_receive_drop_total"),
+    (8, "host_network_transmit_bytes_total"),
+    (9, "host_network_transmit_packets_total"),
+    (10, "host_network_transmit_errs_total"),
+    (11, "host_network_transmit_drop_total"),
+];
+
+/// `/proc/net/dev`, with two header lines then `interface: counters`.
+fn parse_net_dev(text: &str, devices: &Filter, out: &mut Metrics) {
+    for line in text.lines().skip(2) {
+        let mut parts = line.splitn(2, ':');
+        let (device, counters) = match (parts.next(), parts.next()) {
+            (Some(device), Some(counters)) => (device.trim(), counters),
+            _ => continue,
+        };
+        if !devices.matches(device) {
+            continue;
+        }
+        let counters = counters.split_whitespace().collect::<Vec<_>>();
+        for (index, name) in NETWORK_FIELDS.iter() {
+            if let Some(Ok(value)) = counters.get(*index).map(|value| value.parse()) {
+                out.counter(name, value, &[("device", device)]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::runtime;
+
+    fn collect(f: impl FnOnce(&mut Metrics)) -> Vec<Metric> {
+        let mut metrics = Vec::new();
+        f(&mut Metrics {
+            metrics: &mut metrics,
+            timestamp: Utc::now(),
+        });
+        metrics
+    }
+
+    fn value(metrics: &[Metric], name: &str, tags: &[(&str, &str)]) -> Option<f64> {
+        metrics.iter().find_map(|metric| match metric {
+            Metric::Counter {
+                name: n,
+                val,
+                tags: t,
+                ..
+            }
+            | Metric::Gauge {
+                name: n,
+                val,
+                tags: t,
+                ..
+            } if n == name && *t == to_tags(tags) => Some(*val),
+            _ => None,
+        })
+    }
+
+    fn filter(includes: Option<&[&str]>, excludes: &[&str]) -> Filter {
+        let list = FilterList {
+            includes: includes.map(|p| p.iter().map(|p| p.to_string()).collect()),
+            excludes: excludes.iter().map(|p| p.to_string()).collect(),
+        };
+        Filter::new(&list).unwrap()
+    }
+
+    #[test]
+    fn parses_cpu_memory_and_load() {
+        let stat = "cpu  200 0 100 1000 0 0 0 0 0 0\n\
+                    cpu0 100 0 50 500 10 0 0 0 0 0\n\
+                    intr 12345\n";
+        let metrics = collect(|out| parse_cpu(stat, 100.0, out));
+        assert_eq!(metrics.len(), 8);
+        assert_eq!(
+            value(
+                &metrics,
+                "host_cpu_seconds_total",
+                &[("cpu", "0"), ("mode", "idle")]
+            ),
+            Some(5.0)
+        );
+
+        let meminfo = "MemTotal:       16318488 kB\n\
+                       MemFree:         1000 kB\n\
+                       HugePages_Total:       0\n";
+        let metrics = collect(|out| parse_memory(meminfo, out));
+        assert_eq!(metrics.len(), 2);
+        assert_eq!(
+            value(&metrics, "host_memory_free_bytes", &[]),
+            Some(1_024_000.0)
+        );
+
+        let metrics = collect(|out| parse_load("0.52 0.58 0.59 1/467 12345\n", out));
+        assert_eq!(value(&metrics, "host_load15", &[]), Some(0.59));
+    }
+
+    #[test]
+    fn parses_diskstats_of_filtered_devices() {
+        let diskstats = "   8       0 sda 100 0 2048 50 200 0 4096 70 0 1500 120 0 0 0 0\n\
+                         7       0 loop0 1 0 2 0 0 0 0 0 0 0 0 0 0 0 0\n";
+        let metrics = collect(|out| parse_diskstats(diskstats, &filter(None, &["loop*"]), out));
+        assert_eq!(metrics.len(), 5);
+        let sda = [("device", "sda")];
+        assert_eq!(
+            value(&metrics, "host_disk_read_bytes_total", &sda),
+            Some(2048.0 * 512.0)
+        );
+        assert_eq!(
+            value(&metrics, "host_disk_io_time_seconds_total", &sda),
+            Some(1.5)
+        );
+    }
+
+    #[test]
+    fn parses_net_dev_of_filtered_devices() {
+        let net_dev = "Inter-|   Receive   |  Transmit\n \
+                       face |bytes    packets errs drop|bytes    packets errs drop\n    \
+                       lo: 1000 10 0 0 0 0 0 0 1000 10 0 0 0 0 0 0\n  \
+                       eth0: 5000 50 1 2 0 0 0 0 7000 70 3 4 0 0 0 0\n";
+        let metrics = collect(|out| parse_net_dev(net_dev, &filter(Some(&["eth*"]), &[]), out));
+        assert_eq!(metrics.len(), 8);
+        let eth0 = [("device", "eth0")];
+        assert_eq!(
+            value(&metrics, "host_network_receive_drop_total", &eth0),
+            Some(2.0)
+        );
+        assert_eq!(
+            value(&metrics, "host_network_transmit_bytes_total", &eth0),
+            Some(7000.0)
+        );
+    }
+
+    #[test]
+    fn parses_mounts_with_escapes() {
+        let mounts = "/dev/sda1 / ext4 rw,relatime 0 0\n\
+                      /dev/sdb1 /mnt/my\\040disk vfat rw 0 0\n";
+        assert_eq!(
+            parse_mounts(mounts),
+            vec![
+                Mount {
+                    device: "/dev/sda1".into(),
+                    mountpoint: "/".into(),
+                    filesystem: "ext4".into(),
+                },
+                Mount {
+                    device: "/dev/sdb1".into(),
+                    mountpoint: "/mnt/my disk".into(),
+                    filesystem: "vfat".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_network_filesystems() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("mounts"),
+            "/dev/sda1 / ext4 rw,relatime 0 0\n\
+             server:/export /mnt/unreachable nfs4 rw 0 0\n",
+        )
+        .unwrap();
+
+        let config = HostMetricsConfig {
+            procfs_root: dir.path().into(),
+            ..HostMetricsConfig::new()
+        };
+        let filesystems = Arc::new(Filesystems::new(&config).unwrap());
+        let usage = runtime().block_on(filesystems.usage()).unwrap();
+
+        let mountpoints = usage
+            .iter()
+            .map(|(mount, _)| mount.mountpoint.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(mountpoints, vec!["/"]);
+    }
+
+    #[test]
+    fn counters_are_increments_between_collections() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("loadavg"), "1.00 0.50 0.25 1/100 1\n").unwrap();
+        fs::write(dir.path().join("stat"), "cpu0 100 0 0 0 0 0 0 0\n").unwrap();
+
+        let config = HostMetricsConfig {
+            collectors: vec![Collector::Cpu, Collector::Load],
+            procfs_root: dir.path().into(),
+            ..HostMetricsConfig::new()
+        };
+        let mut host = HostMetrics::new(&config).unwrap();
+        host.ticks_per_second = 100.0;
+
+        let metrics = host.collect(&[]);
+        assert_eq!(metrics.len(), 3);
+        assert!(metrics.iter().all(Metric::is_gauge));
+
+        fs::write(dir.path().join("stat"), "cpu0 250 0 0 0 0 0 0 0\n").unwrap();
+        let metrics = host.collect(&[]);
+        let user = [("cpu", "0"), ("mode", "user")];
+        assert_eq!(value(&metrics, "host_cpu_seconds_total", &user), Some(1.5));
+        assert_eq!(value(&metrics, "host_load1", &[]), Some(1.0));
+    }
+}
@@ -0,0 +1,420 @@
+use super::util::{
+    http::{
+        authorized, default_max_body_size, gzipped, incoming, read_body, respond, ResponseFuture,
+    },
+    tls::TlsSettings,
+    TlsConfig,
+};
+use crate::{
+    event::{self, Event},
+    topology::config::{DataType, GlobalOptions, SourceConfig},
+    transforms::json_parser,
+};
+use chrono::Utc;
+use futures::{future, stream, sync::mpsc, Future, Sink};
+use http::header::{HeaderMap, WWW_AUTHENTICATE};
+use hyper::{service::service_fn, Body, Method, Request, Response, Server, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{net::SocketAddr, sync::Arc};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HttpConfig {
+    pub address: SocketAddr,
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// The path requests are accepted on.
+    #[serde(default = "default_path")]
+    pub path: String,
+    /// Request headers put in fields of the same name.
+    #[serde(default)]
+    pub headers: Vec<String>,
+    /// Query parameters put in fields of the same name.
+    #[serde(default)]
+    pub query_parameters: Vec<String>,
+    pub auth: Option<HttpSourceAuth>,
+    /// Larger request bodies, before or after decompression, are rejected.
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: usize,
+    pub tls: Option<TlsConfig>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// Every line is the message of an event.
+    Text,
+    /// Every line is an event as a JSON object.
+    Ndjson,
+    /// A JSON array of events as objects, or a single one.
+    Json,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Text
+    }
+}
+
+/// The `auth` option of the `http` source, requests without the
+/// credentials are rejected.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", tag = "strategy")]
+pub enum HttpSourceAuth {
+    Basic { user: String, password: String },
+    Bearer { token: String },
+}
+
+impl HttpSourceAuth {
+    /// The expected value of the `Authorization` header
+    fn header_value(&self) -> String {
+        match self {
+            HttpSourceAuth::Basic { user, password } => format!(
+                "Basic {}",
+                base64::encode(format!("{}:{}", user, password).as_bytes())
+            ),
+            HttpSourceAuth::Bearer { token } => format!("Bearer {}", token),
+        }
+    }
+}
+
+fn default_path() -> String {
+    "/".into()
+}
+
+impl HttpConfig {
+    pub fn new(address: SocketAddr) -> Self {
+        Self {
+            address,
+            encoding: Encoding::default(),
+            path: default_path(),
+            headers: Vec::new(),
+            query_parameters: Vec::new(),
+            auth: None,
+            max_body_size: default_max_body_size(),
+            tls: None,
+        }
+    }
+}
+
+#[typetag::serde(name = "http")]
+impl SourceConfig for HttpConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        let tls = TlsSettings::from_config(&self.tls)?;
+        let incoming = incoming(self.address, tls)?;
+
+        let config = Arc::new(self.clone());
+        let authorization = self.auth.as_ref().map(HttpSourceAuth::header_value);
+        let new_service = move || {
+            let config = Arc::clone(&config);
+            let authorization = authorization.clone();
+            let out = out.clone();
+            service_fn(move |request| handle(request, &config, &authorization, out.clone()))
+        };
+
+        let server = Server::builder(incoming)
+            .serve(new_service)
+            .map_err(|error| error!(message = "server error.", %error));
+
+        Ok(Box::new(server))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+}
+
+fn handle(
+    request: Request<Body>,
+    config: &HttpConfig,
+    authorization: &Option<String>,
+    out: mpsc::Sender<Event>,
+) -> ResponseFuture {
+    if request.uri().path() != config.path {
+        return respond(StatusCode::NOT_FOUND, "Not found");
+    }
+    if request.method() != Method::POST {
+        return respond(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed");
+    }
+
+    let headers = request.headers();
+    if let Some(expected) = authorization {
+        if !authorized(headers, expected) {
+            let response = Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header(WWW_AUTHENTICATE, "Basic, Bearer")
+                .body(Body::from("Invalid credentials"))
+                .unwrap();
+            return Box::new(future::ok(response));
+        }
+    }
+
+    let gzip = match gzipped(headers) {
+        Some(gzip) => gzip,
+        None => return respond(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Unsupported encoding"),
+    };
+
+    let fields = request_fields(headers, request.uri().query(), config);
+    let encoding = config.encoding;
+
+    let body = read_body(request.into_body(), gzip, config.max_body_size);
+    Box::new(body.then(move |body| {
+        let events = body
+            .map_err(|error| (error.status(), error.to_string()))
+            .and_then(|body| {
+                decode_body(&body, encoding).map_err(|error| (StatusCode::BAD_REQUEST, error))
+            })
+            .map(|mut events| {
+                for event in &mut events {
+                    for (name, value) in &fields {
+                        event
+                            .as_mut_log()
+                            .insert_explicit(name.as_str().into(), value.clone().into());
+                    }
+                }
+                events
+            });
+
+        match events {
+            Ok(events) => future::Either::A(
+                out.send_all(stream::iter_ok::<_, mpsc::SendError<Event>>(events))
+                    .then(|result| match result {
+                        Ok(_) => respond(StatusCode::OK, ""),
+                        Err(_) => respond(StatusCode::SERVICE_UNAVAILABLE, "Shutting down"),
+                    }),
+            ),
+            Err((status, error)) => {
+                debug!(message = "invalid request body.", %error, rate_limit_secs = 30);
+                future::Either::B(respond(status, error))
+            }
+        }
+    }))
+}
+
+/// The configured headers and query parameters present in the request.
+fn request_fields(
+    headers: &HeaderMap,
+    query: Option<&str>,
+    config: &HttpConfig,
+) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    for name in &config.headers {
+        if let Some(value) = headers.get(name.as_str()) {
+            fields.push((
+                name.clone(),
+                String::from_utf8_lossy(value.as_bytes()).into(),
+            ));
+        }
+    }
+
+    let query = url::form_urlencoded::parse(query.unwrap_or_default().as_bytes());
+    for (name, value) in query {
+        if config.query_parameters.iter().any(|param| *param == name) {
+            fields.push((name.into_owned(), value.into_owned()));
+        }
+    }
+
+    fields
+}
+
+fn decode_body(body: &[u8], encoding: Encoding) -> Result<Vec<Event>, String> {
+    let body = String::from_utf8_lossy(body);
+    let lines = body.lines().filter(|line| !line.trim().is_empty());
+
+    match encoding {
+        Encoding::Text => Ok(lines.map(Event::from).collect()),
+        Encoding::Ndjson => lines
+            .map(|line| {
+                let value = serde_json::from_str(line)
+                    .map_err(|error| format!("Invalid JSON line: {}", error))?;
+                json_event(value)
+            })
+            .collect(),
+        Encoding::Json => match serde_json::from_str(&body) {
+            Ok(Value::Array(values)) => values.into_iter().map(json_event).collect(),
+            Ok(value) => json_event(value).map(|event| vec![event]),
+            Err(error) => Err(format!("Invalid JSON: {}", error)),
+        },
+    }
+}
+
+fn json_event(value: Value) -> Result<Event, String> {
+    let object = match value {
+        Value::Object(object) => object,
+        _ => return Err("Events must be JSON objects".into()),
+    };
+
+    let mut event = Event::new_empty_log();
+    for (name, value) in object {
+        json_parser::insert(&mut event, name, value);
+    }
+    if event.as_log().get(&event::TIMESTAMP).is_none() {
+        event
+            .as_mut_log()
+            .insert_implicit(event::TIMESTAMP.clone(), Utc::now().into());
+    }
+    Ok(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Encoding, HttpConfig, HttpSourceAuth};
+    use crate::{
+        event::{self, Event},
+        test_util::{next_addr, wait_for_tcp, CollectCurrent},
+        topology::config::{GlobalOptions, SourceConfig},
+    };
+    use flate2::{write::GzEncoder, Compression};
+    use futures::{sync::mpsc, Future};
+    use hyper::{Body, Request, StatusCode};
+    use std::io::Write;
+
+    fn source(config: HttpConfig) -> (mpsc::Receiver<Event>, tokio::runtime::Runtime) {
+        let (tx, rx) = mpsc::channel(100);
+        let addr = config.address;
+        let server = config
+            .build("default", &GlobalOptions::default(), tx)
+            .unwrap();
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.spawn(server);
+        wait_for_tcp(addr);
+        (rx, rt)
+    }
+
+    fn post(rt: &mut tokio::runtime::Runtime, request: Request<Body>) -> hyper::Response<Body> {
+        rt.block_on(hyper::Client::new().request(request)).unwrap()
+    }
+
+    #[test]
+    fn text_lines_with_headers_and_query_parameters() {
+        let addr = next_addr();
+        let config = HttpConfig {
+            headers: vec!["User-Agent".into()],
+            query_parameters: vec!["source".into()],
+            ..HttpConfig::new(addr)
+        };
+        let (rx, mut rt) = source(config);
+
+        let request = Request::post(format!("http://{}/?source=staging&other=1", addr))
+            .header("User-Agent", "test")
+            .body(Body::from("first\n\nsecond\n"))
+            .unwrap();
+        assert_eq!(post(&mut rt, request).status(), StatusCode::OK);
+
+        let (_, events) = CollectCurrent::new(rx).wait().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].as_log()[&event::MESSAGE], "first".into());
+        assert_eq!(events[1].as_log()[&event::MESSAGE], "second".into());
+        assert_eq!(events[0].as_log()[&"User-Agent".into()], "test".into());
+        assert_eq!(events[0].as_log()[&"source".into()], "staging".into());
+        assert!(events[0].as_log().get(&"other".into()).is_none());
+    }
+
+    #[test]
+    fn gzipped_json_array() {
+        let addr = next_addr();
+        let config = HttpConfig {
+            encoding: Encoding::Json,
+            path: "/events".into(),
+            ..HttpConfig::new(addr)
+        };
+        let (rx, mut rt) = source(config);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(br#"[{"message": "one", "nested": {"x": 1}}, {"message": "two"}]"#)
+            .unwrap();
+        let request = Request::post(format!("http://{}/events", addr))
+            .header("Content-Encoding", "gzip")
+            .body(Body::from(encoder.finish().unwrap()))
+            .unwrap();
+        assert_eq!(post(&mut rt, request).status(), StatusCode::OK);
+
+        let request = Request::post(format!("http://{}/events", addr))
+            .body(Body::from(r#"["not an object"]"#))
+            .unwrap();
+        assert_eq!(post(&mut rt, request).status(), StatusCode::BAD_REQUEST);
+
+        let (_, events) = CollectCurrent::new(rx).wait().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].as_log()[&event::MESSAGE], "one".into());
+        assert_eq!(events[0].as_log()[&"nested.x".into()], 1.into());
+        assert!(events[0].as_log().get(&event::TIMESTAMP).is_some());
+        assert_eq!(events[1].as_log()[&event::MESSAGE], "two".into());
+    }
+
+    #[test]
+    fn rejects_large_bodies() {
+        let addr = next_addr();
+        let config = HttpConfig {
+            max_body_size: 100,
+            ..HttpConfig::new(addr)
+        };
+        let (rx, mut rt) = source(config);
+
+        let request = Request::post(format!("http://{}/", addr))
+            .body(Body::from(vec![b'a'; 101]))
+            .unwrap();
+        assert_eq!(
+            post(&mut rt, request).status(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+
+        // Small once compressed, but not once decompressed
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[b'a'; 10_000]).unwrap();
+        let request = Request::post(format!("http://{}/", addr))
+            .header("Content-Encoding", "gzip")
+            .body(Body::from(encoder.finish().unwrap()))
+            .unwrap();
+        assert_eq!(
+            post(&mut rt, request).status(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+
+        let request = Request::post(format!("http://{}/", addr))
+            .body(Body::from("accepted"))
+            .unwrap();
+        assert_eq!(post(&mut rt, request).status(), StatusCode::OK);
+
+        let (_, events) = CollectCurrent::new(rx).wait().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].as_log()[&event::MESSAGE], "accepted".into());
+    }
+
+    #[test]
+    fn rejects_invalid_credentials() {
+        let addr = next_addr();
+        let config = HttpConfig {
+            encoding: Encoding::Ndjson,
+            auth: Some(HttpSourceAuth::Basic {
+                user: "user".into(),
+                password: "secret".into(),
+            }),
+            ..HttpConfig::new(addr)
+        };
+        let (rx, mut rt) = source(config);
+
+        let request = Request::post(format!("http://{}/", addr))
+            .header("Authorization", "Basic d3Jvbmc6d3Jvbmc=")
+            .body(Body::from(r#"{"message": "rejected"}"#))
+            .unwrap();
+        assert_eq!(post(&mut rt, request).status(), StatusCode::UNAUTHORIZED);
+
+        let request = Request::post(format!("http://{}/", addr))
+            .header("Authorization", "Basic dXNlcjpzZWNyZXQ=")
+            .body(Body::from(r#"{"message": "accepted"}"#))
+            .unwrap();
+        assert_eq!(post(&mut rt, request).status(), StatusCode::OK);
+
+        let (_, events) = CollectCurrent::new(rx).wait().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].as_log()[&event::MESSAGE], "accepted".into());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{
+    event::Event,
+    topology::config::{DataType, GlobalOptions, SourceConfig},
+    trace,
+};
+use futures::{sync::mpsc, Future, Sink, Stream};
+use serde::{Deserialize, Serialize};
+
+/// Vector's own logs, with their level, target, fields and the fields of
+/// their spans. The logs of the source itself aren't received, nor the fields
+/// of spans created before the first `internal_logs` source was built.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct InternalLogsConfig {}
+
+#[typetag::serde(name = "internal_logs")]
+impl SourceConfig for InternalLogsConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        let logs = trace::subscribe();
+
+        Ok(Box::new(trace::without_broadcast(
+            logs.forward(out.sink_map_err(|e| error!("error sending log: {:?}", e)))
+                .map(|_| info!("finished sending")),
+        )))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InternalLogsConfig;
+    use crate::{
+        event,
+        test_util::runtime,
+        topology::config::{GlobalOptions, SourceConfig},
+        trace::{self, BroadcastLayer},
+    };
+    use futures::{sync::mpsc, Stream};
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+    use tokio::{runtime::current_thread, timer::Delay};
+    use tracing_subscriber::{layer::SubscriberExt, FmtSubscriber};
+
+    #[test]
+    fn receives_vector_logs_with_span_fields() {
+        let (tx, rx) = mpsc::channel(10);
+        let source = InternalLogsConfig::default()
+            .build("default", &GlobalOptions::default(), tx)
+            .unwrap();
+
+        let subscriber = FmtSubscriber::builder()
+            .with_env_filter("vector=info")
+            .finish()
+            .with(BroadcastLayer::default());
+        tracing::subscriber::with_default(subscriber, || {
+            let span = info_span!("sink", name = "out");
+            let _enter = span.enter();
+            debug!(message = "filtered out.");
+            info!(message = "flushed.", count = 3, rate_limit_secs = 10);
+        });
+
+        // The logs of the other tests are broadcast to this source too.
+        let logs = rx
+            .filter(|log| log.as_log().get(&event::MESSAGE) == Some(&"flushed.".into()))
+            .take(1)
+            .collect();
+
+        let mut rt = runtime();
+        rt.spawn(source);
+        let events = rt.block_on(logs).unwrap();
+
+        let log = events[0].as_log();
+        assert_eq!(log[&event::MESSAGE], "flushed.".into());
+        assert_eq!(log[&"count".into()], 3.into());
+        assert_eq!(log[&"span.sink.name".into()], "out".into());
+        assert_eq!(log[&"metadata.level".into()], "INFO".into());
+        assert!(log.get(&"rate_limit_secs".into()).is_none());
+        assert!(log.get(&event::TIMESTAMP).is_some());
+    }
+
+    #[test]
+    fn sink_logging_each_event_does_not_feed_back() {
+        let subscriber = FmtSubscriber::builder()
+            .with_env_filter("vector=info")
+            .finish()
+            .with(BroadcastLayer::default());
+        let received = Arc::new(AtomicUsize::new(0));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let (tx, rx) = mpsc::channel(100);
+            let source = InternalLogsConfig::default()
+                .build("default", &GlobalOptions::default(), tx)
+                .unwrap();
+
+            // Logs every event it receives, each of them being received too.
+            let counted = Arc::clone(&received);
+            let sink = rx.for_each(move |_| {
+                counted.fetch_add(1, Ordering::SeqCst);
+                info!(message = "sent event.");
+                Ok(())
+            });
+
+            let mut rt = current_thread::Runtime::new().unwrap();
+            rt.spawn(source);
+            rt.spawn(sink);
+            info!(message = "started.");
+            rt.block_on(Delay::new(Instant::now() + Duration::from_millis(500)))
+                .unwrap();
+        });
+
+        // The first log, then the sink's log up to the rate limit, and maybe
+        // the logs of the other tests running at the same time.
+        let received = received.load(Ordering::SeqCst);
+        assert!(received > 1);
+        assert!(received < 2 * trace::BROADCAST_RATE_LIMIT as usize);
+    }
+}
@@ -0,0 +1,157 @@
+use super::util::CumulativeCounters;
+use crate::{
+    event::{Event, Metric},
+    metrics,
+    topology::config::{DataType, GlobalOptions, SourceConfig},
+};
+use chrono::{DateTime, Utc};
+use futures::{stream, sync::mpsc, Future, Sink, Stream};
+use hotmic::snapshot::{Snapshot, TypedMeasurement};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tokio::timer::Interval;
+
+/// Vector's own metrics, the ones served on `--metrics-addr`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct InternalMetricsConfig {
+    #[serde(default = "default_scrape_interval_secs")]
+    pub scrape_interval_secs: u64,
+}
+
+fn default_scrape_interval_secs() -> u64 {
+    15
+}
+
+#[typetag::serde(name = "internal_metrics")]
+impl SourceConfig for InternalMetricsConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        let controller = metrics::controller();
+        let interval = Duration::from_secs(self.scrape_interval_secs);
+
+        // Hotmic counters are totals since Vector started.
+        let mut counters = CumulativeCounters::default();
+        let metrics = Interval::new(Instant::now(), interval)
+            .map_err(|error| error!(message = "timer error.", %error))
+            .map(move |_| {
+                let metrics = match controller.get_snapshot() {
+                    Ok(snapshot) => snapshot_metrics(snapshot, Utc::now()),
+                    Err(error) => {
+                        error!(message = "failed snapshotting metrics.", ?error);
+                        Vec::new()
+                    }
+                };
+                let events = metrics
+                    .into_iter()
+                    .filter_map(|metric| counters.increment(metric))
+                    .map(Event::Metric)
+                    .collect::<Vec<_>>();
+                stream::iter_ok(events)
+            })
+            .flatten();
+
+        Ok(Box::new(
+            metrics
+                .forward(out.sink_map_err(|e| error!("error sending metric: {:?}", e)))
+                .map(|_| info!("finished sending")),
+        ))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Metric
+    }
+}
+
+/// The measurements of a snapshot, named like they are served. The quantiles
+/// of histograms are gauges and their sums and counts cumulative counters.
+fn snapshot_metrics(snapshot: Snapshot, now: DateTime<Utc>) -> Vec<Metric> {
+    let counter = |name: String, val: f64| Metric::Counter {
+        name,
+        val,
+        timestamp: Some(now),
+        tags: None,
+    };
+    let gauge = |name: String, val: f64, tags: Option<HashMap<String, String>>| Metric::Gauge {
+        name,
+        val,
+        direction: None,
+        timestamp: Some(now),
+        tags,
+    };
+
+    let mut metrics = Vec::new();
+    for measurement in snapshot.into_vec() {
+        let (name, summary) = match measurement {
+            TypedMeasurement::Counter(label, value) => {
+                metrics.push(counter(label.replace('.', "_"), value as f64));
+                continue;
+            }
+            TypedMeasurement::Gauge(label, value) => {
+                metrics.push(gauge(label.replace('.', "_"), value as f64, None));
+                continue;
+            }
+            TypedMeasurement::TimingHistogram(label, summary) => {
+                (label.replace('.', "_") + "_nanoseconds", summary)
+            }
+            TypedMeasurement::ValueHistogram(label, summary) => (label.replace('.', "_"), summary),
+        };
+
+        for (percentile, value) in summary.measurements() {
+            let mut tags = HashMap::new();
+            tags.insert("quantile".to_owned(), percentile.as_quantile().to_string());
+            metrics.push(gauge(name.clone(), *value as f64, Some(tags)));
+        }
+        metrics.push(counter(name.clone() + "_sum", summary.sum() as f64));
+        metrics.push(counter(name + "_count", summary.count() as f64));
+    }
+    metrics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::snapshot_metrics;
+    use crate::{event::Metric, metrics, test_util::wait_for};
+    use chrono::Utc;
+
+    #[test]
+    fn snapshot_counters_and_gauges() {
+        let (controller, sink) = metrics::build();
+        sink.update_count("file.events_counter".to_owned(), 3);
+        sink.update_gauge("file.buffer_gauge".to_owned(), 7);
+
+        let now = Utc::now();
+        wait_for(|| snapshot_metrics(controller.get_snapshot().unwrap(), now).len() == 2);
+
+        let mut metrics = snapshot_metrics(controller.get_snapshot().unwrap(), now);
+        metrics.sort_by_key(|metric| match metric {
+            Metric::Counter { .. } => 0,
+            _ => 1,
+        });
+        assert_eq!(
+            metrics,
+            vec![
+                Metric::Counter {
+                    name: "file_events_counter".into(),
+                    val: 3.0,
+                    timestamp: Some(now),
+                    tags: None,
+                },
+                Metric::Gauge {
+                    name: "file_buffer_gauge".into(),
+                    val: 7.0,
+                    direction: None,
+                    timestamp: Some(now),
+                    tags: None,
+                },
+            ]
+        );
+    }
+}
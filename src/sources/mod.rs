@@ -1,10 +1,17 @@
 use futures::Future;
 
 pub mod docker;
+pub mod exec;
 pub mod file;
+pub mod host_metrics;
+pub mod http;
+pub mod internal_logs;
+pub mod internal_metrics;
 pub mod journald;
 #[cfg(feature = "rdkafka")]
 pub mod kafka;
+pub mod prometheus;
+pub mod splunk_hec;
 pub mod statsd;
 pub mod stdin;
 pub mod syslog;
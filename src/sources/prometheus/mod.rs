@@ -0,0 +1,207 @@
+use super::util::CumulativeCounters;
+use crate::{
+    event::Event,
+    sinks::util::{http::https_client, proxy::ProxySettings, tls::TlsSettings},
+    topology::config::{DataType, GlobalOptions, SourceConfig},
+};
+use futures::{stream, sync::mpsc, Future, Sink, Stream};
+use http::{StatusCode, Uri};
+use hyper::{Body, Request};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tokio::{timer::Interval, util::FutureExt};
+
+mod parser;
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("Invalid host {:?}: {}", host, source))]
+    InvalidHost {
+        host: String,
+        source: http::uri::InvalidUri,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PrometheusConfig {
+    /// The exporters scraped, their metrics are at `<host>/metrics`.
+    pub hosts: Vec<String>,
+    #[serde(default = "default_scrape_interval_secs")]
+    pub scrape_interval_secs: u64,
+}
+
+fn default_scrape_interval_secs() -> u64 {
+    15
+}
+
+#[typetag::serde(name = "prometheus")]
+impl SourceConfig for PrometheusConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        let urls = self
+            .hosts
+            .iter()
+            .map(|host| {
+                format!("{}/metrics", host.trim_end_matches('/'))
+                    .parse::<Uri>()
+                    .context(InvalidHost { host: host.clone() })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let client = https_client(TlsSettings::default(), ProxySettings::default())?;
+        let interval = Duration::from_secs(self.scrape_interval_secs);
+
+        let mut counters = HashMap::new();
+        let scrapes = Interval::new(Instant::now(), interval)
+            .map_err(|error| error!(message = "timer error.", %error))
+            .map(move |_| stream::iter_ok(urls.clone()))
+            .flatten()
+            .map(move |url| {
+                let request = Request::get(url.clone()).body(Body::empty()).unwrap();
+                client
+                    .request(request)
+                    .and_then(|response| {
+                        let status = response.status();
+                        response
+                            .into_body()
+                            .concat2()
+                            .map(move |body| (status, body))
+                    })
+                    // A scrape that's still running when the next one starts
+                    // is given up.
+                    .timeout(interval)
+                    .then(move |result| match result {
+                        Ok((StatusCode::OK, body)) => Ok(Some((url, body))),
+                        Ok((status, _)) => {
+                            error!(message = "failed scraping.", %url, %status);
+                            Ok(None)
+                        }
+                        Err(error) => {
+                            error!(message = "failed scraping.", %url, %error);
+                            Ok(None)
+                        }
+                    })
+            })
+            .buffer_unordered(16)
+            .filter_map(|scrape| scrape)
+            .map(move |(url, body)| {
+                let (metrics, errors) = parser::parse(&String::from_utf8_lossy(&body));
+                for error in errors {
+                    warn!(
+                        message = "skipped malformed line.",
+                        %url,
+                        %error,
+                        rate_limit_secs = 10
+                    );
+                }
+                // Counters are cumulative in Prometheus.
+                let counters = counters
+                    .entry(url.to_string())
+                    .or_insert_with(CumulativeCounters::default);
+                let events = metrics
+                    .into_iter()
+                    .filter_map(|metric| counters.increment(metric))
+                    .map(Event::Metric)
+                    .collect::<Vec<_>>();
+                stream::iter_ok(events)
+            })
+            .flatten();
+
+        Ok(Box::new(
+            scrapes
+                .forward(out.sink_map_err(|e| error!("error sending metric: {:?}", e)))
+                .map(|_| info!("finished sending")),
+        ))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Metric
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrometheusConfig;
+    use crate::{
+        event::{Event, Metric},
+        test_util::{collect_n, next_addr, wait_for_tcp},
+        topology::config::{GlobalOptions, SourceConfig},
+    };
+    use futures::{sync::mpsc, Future};
+    use hyper::{service::service_fn_ok, Body, Response, Server};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn scrapes_counter_increments_and_gauges() {
+        let addr = next_addr();
+        let scrapes = Arc::new(AtomicUsize::new(0));
+        let exporter = Server::bind(&addr)
+            .serve(move || {
+                let scrapes = Arc::clone(&scrapes);
+                service_fn_ok(move |_| {
+                    let scrape = scrapes.fetch_add(1, Ordering::SeqCst) as f64;
+                    Response::new(Body::from(format!(
+                        "# TYPE requests_total counter\n\
+                         requests_total{{code=\"200\"}} {}\n\
+                         # TYPE temperature gauge\n\
+                         temperature 21.5\n",
+                        100.0 + 10.0 * scrape
+                    )))
+                })
+            })
+            .map_err(|_| ());
+
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.spawn(exporter);
+        wait_for_tcp(addr);
+
+        let (tx, rx) = mpsc::channel(100);
+        let config = PrometheusConfig {
+            hosts: vec![format!("http://{}", addr)],
+            scrape_interval_secs: 1,
+        };
+        rt.spawn(
+            config
+                .build("default", &GlobalOptions::default(), tx)
+                .unwrap(),
+        );
+
+        // The gauge of the first scrape, then the counter's increment and the
+        // gauge of the second one.
+        let events = rt.block_on(collect_n(rx, 3)).unwrap();
+        let metrics = events
+            .into_iter()
+            .map(Event::into_metric)
+            .collect::<Vec<_>>();
+        let gauges = metrics.iter().filter(|metric| metric.is_gauge()).count();
+        let counters = metrics
+            .iter()
+            .filter(|metric| metric.is_counter())
+            .collect::<Vec<_>>();
+
+        assert_eq!(gauges, 2);
+        match counters.as_slice() {
+            [Metric::Counter {
+                name, val, tags, ..
+            }] => {
+                assert_eq!(name, "requests_total");
+                assert_eq!(*val, 10.0);
+                assert_eq!(tags.as_ref().unwrap()["code"], "200");
+            }
+            _ => panic!("unexpected counters {:?}", counters),
+        }
+
+        rt.shutdown_now().wait().unwrap();
+    }
+}
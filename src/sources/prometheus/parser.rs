@@ -0,0 +1,422 @@
+use crate::event::Metric;
+use chrono::{DateTime, TimeZone, Utc};
+use snafu::Snafu;
+use std::collections::HashMap;
+
+#[derive(Debug, Snafu, PartialEq)]
+pub enum ParserError {
+    #[snafu(display("Invalid metric name on line {}", line))]
+    InvalidName { line: usize },
+    #[snafu(display("Invalid labels on line {}", line))]
+    InvalidLabels { line: usize },
+    #[snafu(display("Invalid value {:?} on line {}", value, line))]
+    InvalidValue { line: usize, value: String },
+    #[snafu(display("Invalid timestamp {:?} on line {}", timestamp, line))]
+    InvalidTimestamp { line: usize, timestamp: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MetricType {
+    Counter,
+    Gauge,
+    Histogram,
+    Summary,
+    Untyped,
+}
+
+impl MetricType {
+    fn from_str(name: &str) -> Self {
+        match name {
+            "counter" => MetricType::Counter,
+            "gauge" => MetricType::Gauge,
+            "histogram" => MetricType::Histogram,
+            "summary" => MetricType::Summary,
+            _ => MetricType::Untyped,
+        }
+    }
+}
+
+/// Parses the Prometheus text exposition format, labels becoming tags.
+///
+/// Counters keep the cumulative value of the exporter. The buckets, sums and
+/// counts of histograms and summaries are cumulative too, so they are counters
+/// named like their samples, and the quantiles of summaries are gauges.
+/// Untyped samples are gauges, and `NaN` samples are skipped.
+///
+/// Malformed lines are skipped too, with the errors returned alongside the
+/// metrics of the other lines.
+pub fn parse(text: &str) -> (Vec<Metric>, Vec<ParserError>) {
+    let mut types = HashMap::new();
+    let mut metrics = Vec::new();
+    let mut errors = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('#') {
+            let mut words = line[1..].split_whitespace();
+            if let (Some("TYPE"), Some(name), Some(kind)) =
+                (words.next(), words.next(), words.next())
+            {
+                types.insert(name.to_owned(), MetricType::from_str(kind));
+            }
+            continue;
+        }
+
+        let sample = match parse_sample(line, line_number) {
+            Ok(sample) => sample,
+            Err(error) => {
+                errors.push(error);
+                continue;
+            }
+        };
+        if sample.value.is_nan() {
+            continue;
+        }
+
+        let is_counter = match sample_type(&types, &sample.name) {
+            MetricType::Counter => true,
+            MetricType::Histogram => true,
+            MetricType::Summary => !sample.labels.contains_key("quantile"),
+            MetricType::Gauge | MetricType::Untyped => false,
+        };
+        let tags = if sample.labels.is_empty() {
+            None
+        } else {
+            Some(sample.labels)
+        };
+
+        metrics.push(if is_counter {
+            Metric::Counter {
+                name: sample.name,
+                val: sample.value,
+                timestamp: sample.timestamp,
+                tags,
+            }
+        } else {
+            Metric::Gauge {
+                name: sample.name,
+                val: sample.value,
+                direction: None,
+                timestamp: sample.timestamp,
+                tags,
+            }
+        });
+    }
+
+    (metrics, errors)
+}
+
+/// The type of a sample, the one of its family for the `_bucket`, `_sum`
+/// and `_count` samples of histograms and summaries.
+fn sample_type(types: &HashMap<String, MetricType>, name: &str) -> MetricType {
+    if let Some(kind) = types.get(name) {
+        return *kind;
+    }
+    for suffix in &["_bucket", "_sum", "_count"] {
+        if name.ends_with(suffix) {
+            let family = &name[..name.len() - suffix.len()];
+            match types.get(family) {
+                Some(MetricType::Histogram) => return MetricType::Histogram,
+                Some(MetricType::Summary) if *suffix != "_bucket" => return MetricType::Summary,
+                _ => (),
+            }
+        }
+    }
+    MetricType::Untyped
+}
+
+#[derive(Debug)]
+struct Sample {
+    name: String,
+    labels: HashMap<String, String>,
+    value: f64,
+    timestamp: Option<DateTime<Utc>>,
+}
+
+fn parse_sample(line: &str, line_number: usize) -> Result<Sample, ParserError> {
+    let name_end = line
+        .find(|c: char| c == '{' || c.is_whitespace())
+        .unwrap_or_else(|| line.len());
+    let name = &line[..name_end];
+    if !is_valid_name(name) {
+        return Err(ParserError::InvalidName { line: line_number });
+    }
+
+    let mut rest = &line[name_end..];
+    let mut labels = HashMap::new();
+    if rest.starts_with('{') {
+        let (parsed, remainder) =
+            parse_labels(&rest[1..]).ok_or(ParserError::InvalidLabels { line: line_number })?;
+        labels = parsed;
+        rest = remainder;
+    }
+
+    let mut words = rest.split_whitespace();
+    let value = words.next().unwrap_or_default();
+    let value = parse_value(value).ok_or_else(|| ParserError::InvalidValue {
+        line: line_number,
+        value: value.to_owned(),
+    })?;
+
+    let timestamp = match words.next() {
+        Some(timestamp) => Some(
+            timestamp
+                .parse::<i64>()
+                .ok()
+                .and_then(|millis| Utc.timestamp_millis_opt(millis).single())
+                .ok_or_else(|| ParserError::InvalidTimestamp {
+                    line: line_number,
+                    timestamp: timestamp.to_owned(),
+                })?,
+        ),
+        None => None,
+    };
+
+    Ok(Sample {
+        name: name.to_owned(),
+        labels,
+        value,
+        timestamp,
+    })
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == ':' => (),
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// Parses `name="value",...}` and returns the labels with what follows them.
+fn parse_labels(mut input: &str) -> Option<(HashMap<String, String>, &str)> {
+    let mut labels = HashMap::new();
+    loop {
+        input = input.trim_start();
+        if input.starts_with('}') {
+            return Some((labels, &input[1..]));
+        }
+
+        let equals = input.find('=')?;
+        let name = input[..equals].trim();
+        if !is_valid_name(name) || name.contains(':') {
+            return None;
+        }
+        input = input[equals + 1..].trim_start();
+        if !input.starts_with('"') {
+            return None;
+        }
+
+        let mut value = String::new();
+        let mut chars = input[1..].char_indices();
+        let end = loop {
+            match chars.next()? {
+                (i, '"') => break i + 2,
+                (_, '\\') => match chars.next()?.1 {
+                    'n' => value.push('\n'),
+                    c => value.push(c),
+                },
+                (_, c) => value.push(c),
+            }
+        };
+        labels.insert(name.to_owned(), value);
+
+        input = input[end..].trim_start();
+        if input.starts_with(',') {
+            input = &input[1..];
+        } else if !input.starts_with('}') {
+            return None;
+        }
+    }
+}
+
+fn parse_value(value: &str) -> Option<f64> {
+    match value {
+        "+Inf" | "Inf" => Some(std::f64::INFINITY),
+        "-Inf" => Some(std::f64::NEG_INFINITY),
+        "NaN" => Some(std::f64::NAN),
+        _ => value.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, ParserError};
+    use crate::event::Metric;
+    use chrono::{TimeZone, Utc};
+
+    fn tags(tags: &[(&str, &str)]) -> Option<std::collections::HashMap<String, String>> {
+        Some(
+            tags.iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn parse_counters_and_gauges() {
+        let text = r#"
+            # HELP http_requests_total The total number of HTTP requests.
+            # TYPE http_requests_total counter
+            http_requests_total{method="post",code="200"} 1027 1395066363000
+            http_requests_total{method="post",code="400"}    3 1395066363000
+
+            # A comment
+            # TYPE temperature gauge
+            temperature -4.5e1
+            untyped_metric{path="C:\\DIR\\",quote="\"",newline="\n"} 12
+            "#;
+
+        assert_eq!(
+            parse_valid(text),
+            vec![
+                Metric::Counter {
+                    name: "http_requests_total".into(),
+                    val: 1027.0,
+                    timestamp: Some(Utc.timestamp(1_395_066_363, 0)),
+                    tags: tags(&[("method", "post"), ("code", "200")]),
+                },
+                Metric::Counter {
+                    name: "http_requests_total".into(),
+                    val: 3.0,
+                    timestamp: Some(Utc.timestamp(1_395_066_363, 0)),
+                    tags: tags(&[("method", "post"), ("code", "400")]),
+                },
+                Metric::Gauge {
+                    name: "temperature".into(),
+                    val: -45.0,
+                    direction: None,
+                    timestamp: None,
+                    tags: None,
+                },
+                Metric::Gauge {
+                    name: "untyped_metric".into(),
+                    val: 12.0,
+                    direction: None,
+                    timestamp: None,
+                    tags: tags(&[("path", "C:\\DIR\\"), ("quote", "\""), ("newline", "\n")]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_histogram() {
+        let text = r#"
+            # TYPE http_request_duration_seconds histogram
+            http_request_duration_seconds_bucket{le="0.1"} 33444
+            http_request_duration_seconds_bucket{le="+Inf"} 144320
+            http_request_duration_seconds_sum 53423
+            http_request_duration_seconds_count 144320
+            "#;
+
+        let metrics = parse_valid(text);
+        assert_eq!(metrics.len(), 4);
+        assert!(metrics.iter().all(Metric::is_counter));
+        assert_eq!(
+            metrics[1],
+            Metric::Counter {
+                name: "http_request_duration_seconds_bucket".into(),
+                val: 144_320.0,
+                timestamp: None,
+                tags: tags(&[("le", "+Inf")]),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_summary() {
+        let text = r#"
+            # TYPE rpc_duration_seconds summary
+            rpc_duration_seconds{quantile="0.5"} 4773
+            rpc_duration_seconds{quantile="0.99"} NaN
+            rpc_duration_seconds_sum 1.7560473e+07
+            rpc_duration_seconds_count 2693
+            "#;
+
+        let metrics = parse_valid(text);
+        assert_eq!(metrics.len(), 3);
+        assert_eq!(
+            metrics[0],
+            Metric::Gauge {
+                name: "rpc_duration_seconds".into(),
+                val: 4773.0,
+                direction: None,
+                timestamp: None,
+                tags: tags(&[("quantile", "0.5")]),
+            }
+        );
+        assert!(metrics[1].is_counter());
+        assert!(metrics[2].is_counter());
+    }
+
+    /// The metrics of text without malformed lines.
+    fn parse_valid(text: &str) -> Vec<Metric> {
+        let (metrics, errors) = parse(text);
+        assert_eq!(errors, vec![]);
+        metrics
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            parse("9metric 1").1,
+            vec![ParserError::InvalidName { line: 1 }]
+        );
+        assert_eq!(
+            parse("\nmetric{label=unquoted} 1").1,
+            vec![ParserError::InvalidLabels { line: 2 }]
+        );
+        assert_eq!(
+            parse("metric{label=\"value\"} one").1,
+            vec![ParserError::InvalidValue {
+                line: 1,
+                value: "one".into()
+            }]
+        );
+        assert_eq!(
+            parse("metric 1 yesterday").1,
+            vec![ParserError::InvalidTimestamp {
+                line: 1,
+                timestamp: "yesterday".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_skips_malformed_lines() {
+        let text = r#"
+            # TYPE up gauge
+            up 1
+            up{job="broken} 1
+            requests_total 1027
+            "#;
+        let (metrics, errors) = parse(text);
+        assert_eq!(errors, vec![ParserError::InvalidLabels { line: 4 }]);
+        assert_eq!(
+            metrics,
+            vec![
+                Metric::Gauge {
+                    name: "up".into(),
+                    val: 1.0,
+                    direction: None,
+                    timestamp: None,
+                    tags: None,
+                },
+                Metric::Gauge {
+                    name: "requests_total".into(),
+                    val: 1027.0,
+                    direction: None,
+                    timestamp: None,
+                    tags: None,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,678 @@
+use super::util::{
+    http::{
+        authorized, default_max_body_size, gzipped, incoming, read_body, BodyError, ResponseFuture,
+    },
+    tls::TlsSettings,
+    TlsConfig,
+};
+use crate::{
+    event::{self, Event},
+    topology::config::{DataType, GlobalOptions, SourceConfig},
+    transforms::json_parser,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use futures::{future, stream, sync::mpsc, Future, Sink};
+use http::header::{HeaderMap, AUTHORIZATION, CONTENT_TYPE};
+use hyper::{service::service_fn, Body, Method, Request, Response, Server, StatusCode};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::{net::SocketAddr, sync::Arc};
+use string_cache::DefaultAtom as Atom;
+use uuid::Uuid;
+
+lazy_static! {
+    pub static ref CHANNEL: Atom = Atom::from("splunk_channel");
+    pub static ref INDEX: Atom = Atom::from("splunk_index");
+    pub static ref SOURCE: Atom = Atom::from("splunk_source");
+    pub static ref SOURCETYPE: Atom = Atom::from("splunk_sourcetype");
+}
+
+const CHANNEL_HEADER: &str = "X-Splunk-Request-Channel";
+
+/// Accepts events sent with the Splunk HTTP Event Collector API, such as
+/// the ones of the `splunk_hec` sink and the Splunk logging libraries.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SplunkConfig {
+    #[serde(default = "default_address")]
+    pub address: SocketAddr,
+    /// Requests must be authorized with `Splunk <token>` when set.
+    pub token: Option<String>,
+    /// Larger request bodies, before or after decompression, are rejected.
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: usize,
+    pub tls: Option<TlsConfig>,
+}
+
+fn default_address() -> SocketAddr {
+    "0.0.0.0:8088".parse().unwrap()
+}
+
+impl SplunkConfig {
+    pub fn new(address: SocketAddr) -> Self {
+        Self {
+            address,
+            token: None,
+            max_body_size: default_max_body_size(),
+            tls: None,
+        }
+    }
+}
+
+#[typetag::serde(name = "splunk_hec")]
+impl SourceConfig for SplunkConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        let tls = TlsSettings::from_config(&self.tls)?;
+        let incoming = incoming(self.address, tls)?;
+
+        let token = Arc::new(self.token.as_ref().map(|token| format!("Splunk {}", token)));
+        let max_body_size = self.max_body_size;
+        let new_service = move || {
+            let token = Arc::clone(&token);
+            let out = out.clone();
+            service_fn(move |request| handle(request, &token, max_body_size, out.clone()))
+        };
+
+        let server = Server::builder(incoming)
+            .serve(new_service)
+            .map_err(|error| error!(message = "server error.", %error));
+
+        Ok(Box::new(server))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Endpoint {
+    /// JSON objects with the event and its metadata.
+    Event,
+    /// Every line is the message of an event.
+    Raw,
+    Health,
+}
+
+impl Endpoint {
+    fn from_path(path: &str) -> Option<Self> {
+        match path.trim_end_matches('/') {
+            "/services/collector"
+            | "/services/collector/event"
+            | "/services/collector/event/1.0" => Some(Endpoint::Event),
+            "/services/collector/raw" | "/services/collector/raw/1.0" => Some(Endpoint::Raw),
+            "/services/collector/health" | "/services/collector/health/1.0" => {
+                Some(Endpoint::Health)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The errors of the HEC API, answered with its status and code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ApiError {
+    NotFound,
+    MethodNotAllowed,
+    MissingAuthorization,
+    InvalidAuthorization,
+    InvalidToken,
+    NoData,
+    InvalidDataFormat { event: usize },
+    ContentTooLarge,
+    ServerBusy,
+    MissingChannel,
+    InvalidChannel,
+    MissingEventField { event: usize },
+    BlankEventField { event: usize },
+}
+
+impl ApiError {
+    fn response(self) -> Response<Body> {
+        let (status, code, text, event) = match self {
+            ApiError::NotFound => (StatusCode::NOT_FOUND, 404, "Not found", None),
+            ApiError::MethodNotAllowed => (
+                StatusCode::METHOD_NOT_ALLOWED,
+                405,
+                "Method not allowed",
+                None,
+            ),
+            ApiError::MissingAuthorization => {
+                (StatusCode::UNAUTHORIZED, 2, "Token is required", None)
+            }
+            ApiError::InvalidAuthorization => {
+                (StatusCode::UNAUTHORIZED, 3, "Invalid authorization", None)
+            }
+            ApiError::InvalidToken => (StatusCode::FORBIDDEN, 4, "Invalid token", None),
+            ApiError::NoData => (StatusCode::BAD_REQUEST, 5, "No data", None),
+            ApiError::InvalidDataFormat { event } => (
+                StatusCode::BAD_REQUEST,
+                6,
+                "Invalid data format",
+                Some(event),
+            ),
+            ApiError::ContentTooLarge => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                413,
+                "Content too large",
+                None,
+            ),
+            ApiError::ServerBusy => (StatusCode::SERVICE_UNAVAILABLE, 9, "Server is busy", None),
+            ApiError::MissingChannel => {
+                (StatusCode::BAD_REQUEST, 10, "Data channel is missing", None)
+            }
+            ApiError::InvalidChannel => (StatusCode::BAD_REQUEST, 11, "Invalid data channel", None),
+            ApiError::MissingEventField { event } => (
+                StatusCode::BAD_REQUEST,
+                12,
+                "Event field is required",
+                Some(event),
+            ),
+            ApiError::BlankEventField { event } => (
+                StatusCode::BAD_REQUEST,
+                13,
+                "Event field cannot be blank",
+                Some(event),
+            ),
+        };
+
+        let mut body = json!({ "text": text, "code": code });
+        if let Some(event) = event {
+            body["invalid-event-number"] = json!(event);
+        }
+        reply(status, body)
+    }
+}
+
+fn handle(
+    request: Request<Body>,
+    token: &Option<String>,
+    max_body_size: usize,
+    out: mpsc::Sender<Event>,
+) -> ResponseFuture {
+    let prepared = prepare(&request, token);
+    let (endpoint, channel, gzip) = match prepared {
+        Ok((Endpoint::Health, _, _)) => {
+            let body = json!({ "text": "HEC is healthy", "code": 17 });
+            return Box::new(future::ok(reply(StatusCode::OK, body)));
+        }
+        Ok(prepared) => prepared,
+        Err(error) => return Box::new(future::ok(error.response())),
+    };
+    let defaults = query_defaults(request.uri().query());
+
+    let body = read_body(request.into_body(), gzip, max_body_size);
+    Box::new(body.then(move |body| {
+        let events = body
+            .map_err(|error| match error {
+                BodyError::TooLarge { .. } => ApiError::ContentTooLarge,
+                _ => ApiError::InvalidDataFormat { event: 0 },
+            })
+            .and_then(|body| match endpoint {
+                Endpoint::Raw => Ok(decode_raw(&body, &defaults)),
+                _ => decode_events(&body),
+            })
+            .and_then(|mut events| {
+                if events.is_empty() {
+                    return Err(ApiError::NoData);
+                }
+                if let Some(channel) = &channel {
+                    for event in &mut events {
+                        event
+                            .as_mut_log()
+                            .insert_implicit(CHANNEL.clone(), channel.as_str().into());
+                    }
+                }
+                Ok(events)
+            });
+
+        match events {
+            Ok(events) => future::Either::A(
+                out.send_all(stream::iter_ok::<_, mpsc::SendError<Event>>(events))
+                    .then(|result| {
+                        Ok::<_, hyper::Error>(match result {
+                            Ok(_) => reply(StatusCode::OK, json!({ "text": "Success", "code": 0 })),
+                            Err(_) => ApiError::ServerBusy.response(),
+                        })
+                    }),
+            ),
+            Err(error) => {
+                debug!(message = "invalid request.", ?error, rate_limit_secs = 30);
+                future::Either::B(future::ok(error.response()))
+            }
+        }
+    }))
+}
+
+/// Checks the endpoint, the token and the channel of a request before its
+/// body is read.
+fn prepare(
+    request: &Request<Body>,
+    token: &Option<String>,
+) -> Result<(Endpoint, Option<String>, bool), ApiError> {
+    let endpoint = Endpoint::from_path(request.uri().path()).ok_or(ApiError::NotFound)?;
+    let method = match endpoint {
+        Endpoint::Health => Method::GET,
+        _ => Method::POST,
+    };
+    if request.method() != method {
+        return Err(ApiError::MethodNotAllowed);
+    }
+    if endpoint == Endpoint::Health {
+        return Ok((endpoint, None, false));
+    }
+
+    let headers = request.headers();
+    authorize(headers, token)?;
+
+    let channel = channel(headers, request.uri().query())?;
+    if endpoint == Endpoint::Raw && channel.is_none() {
+        return Err(ApiError::MissingChannel);
+    }
+
+    let gzip = gzipped(headers).ok_or(ApiError::InvalidDataFormat { event: 0 })?;
+
+    Ok((endpoint, channel, gzip))
+}
+
+fn authorize(headers: &HeaderMap, token: &Option<String>) -> Result<(), ApiError> {
+    let expected = match token {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+    let value = headers
+        .get(AUTHORIZATION)
+        .ok_or(ApiError::MissingAuthorization)?
+        .as_bytes();
+    if !value.starts_with(b"Splunk ") {
+        return Err(ApiError::InvalidAuthorization);
+    }
+    if authorized(headers, expected) {
+        Ok(())
+    } else {
+        Err(ApiError::InvalidToken)
+    }
+}
+
+/// The channel of a request, given in a header or the `channel` query
+/// parameter. It must be a GUID.
+fn channel(headers: &HeaderMap, query: Option<&str>) -> Result<Option<String>, ApiError> {
+    let channel = match headers.get(CHANNEL_HEADER) {
+        Some(value) => Some(
+            value
+                .to_str()
+                .map_err(|_| ApiError::InvalidChannel)?
+                .to_owned(),
+        ),
+        None => url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+            .find(|(name, _)| name == "channel")
+            .map(|(_, value)| value.into_owned()),
+    };
+
+    match channel {
+        Some(channel) => Uuid::parse_str(&channel)
+            .map(|_| Some(channel))
+            .map_err(|_| ApiError::InvalidChannel),
+        None => Ok(None),
+    }
+}
+
+/// The `host`, `source`, `sourcetype` and `index` query parameters, which
+/// apply to every event of the raw endpoint.
+fn query_defaults(query: Option<&str>) -> Vec<(Atom, String)> {
+    url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+        .filter_map(|(name, value)| {
+            let field = match name.as_ref() {
+                "host" => event::HOST.clone(),
+                "source" => SOURCE.clone(),
+                "sourcetype" => SOURCETYPE.clone(),
+                "index" => INDEX.clone(),
+                _ => return None,
+            };
+            Some((field, value.into_owned()))
+        })
+        .collect()
+}
+
+fn decode_raw(body: &[u8], defaults: &[(Atom, String)]) -> Vec<Event> {
+    String::from_utf8_lossy(body)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut event = Event::from(line);
+            for (field, value) in defaults {
+                event
+                    .as_mut_log()
+                    .insert_implicit(field.clone(), value.as_str().into());
+            }
+            event
+        })
+        .collect()
+}
+
+/// The body of the event endpoint is a sequence of JSON objects, not an array.
+fn decode_events(body: &[u8]) -> Result<Vec<Event>, ApiError> {
+    serde_json::Deserializer::from_slice(body)
+        .into_iter::<Value>()
+        .enumerate()
+        .map(|(index, value)| match value {
+            Ok(Value::Object(object)) => decode_event(object, index),
+            _ => Err(ApiError::InvalidDataFormat { event: index }),
+        })
+        .collect()
+}
+
+fn decode_event(mut object: Map<String, Value>, index: usize) -> Result<Event, ApiError> {
+    let mut event = Event::new_empty_log();
+
+    match object.remove("event") {
+        None => return Err(ApiError::MissingEventField { event: index }),
+        Some(Value::Null) => return Err(ApiError::BlankEventField { event: index }),
+        Some(Value::String(message)) => {
+            if message.is_empty() {
+                return Err(ApiError::BlankEventField { event: index });
+            }
+            event
+                .as_mut_log()
+                .insert_explicit(event::MESSAGE.clone(), message.into());
+        }
+        Some(Value::Object(fields)) => {
+            if fields.is_empty() {
+                return Err(ApiError::BlankEventField { event: index });
+            }
+            for (name, value) in fields {
+                json_parser::insert(&mut event, name, value);
+            }
+        }
+        Some(value) => {
+            event
+                .as_mut_log()
+                .insert_explicit(event::MESSAGE.clone(), value.to_string().into());
+        }
+    }
+
+    // Indexed fields, kept alongside the event's own.
+    if let Some(Value::Object(fields)) = object.remove("fields") {
+        for (name, value) in fields {
+            json_parser::insert(&mut event, name, value);
+        }
+    }
+
+    let log = event.as_mut_log();
+    let timestamp = match object.get("time") {
+        Some(Value::Number(time)) => time.as_f64(),
+        Some(Value::String(time)) => time.parse().ok(),
+        _ => None,
+    }
+    .and_then(parse_time)
+    .unwrap_or_else(Utc::now);
+    log.insert_implicit(event::TIMESTAMP.clone(), timestamp.into());
+
+    for (name, field) in &[
+        ("host", &*event::HOST),
+        ("source", &*SOURCE),
+        ("sourcetype", &*SOURCETYPE),
+        ("index", &*INDEX),
+    ] {
+        if let Some(Value::String(value)) = object.remove(*name) {
+            log.insert_implicit((*field).clone(), value.into());
+        }
+    }
+
+    Ok(event)
+}
+
+/// Seconds since the epoch, with a fraction. Clients send anything, so
+/// values which aren't a valid timestamp are ignored.
+fn parse_time(time: f64) -> Option<DateTime<Utc>> {
+    if !time.is_finite() || time < i64::min_value() as f64 || time >= i64::max_value() as f64 {
+        return None;
+    }
+    let seconds = time.floor();
+    let nanos = ((time - seconds) * 1e9).min(999_999_999.0) as u32;
+    Utc.timestamp_opt(seconds as i64, nanos).single()
+}
+
+fn reply(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_time, SplunkConfig, CHANNEL, SOURCETYPE};
+    use crate::{
+        buffers::Acker,
+        event::{self, Event, ValueKind},
+        sinks::{
+            splunk_hec::{self, Encoding, HecSinkConfig},
+            util::Compression,
+        },
+        test_util::{next_addr, random_lines, wait_for_tcp, CollectCurrent},
+        topology::config::{GlobalOptions, SourceConfig},
+    };
+    use futures::{stream, sync::mpsc, Future, Sink, Stream};
+    use hyper::{Body, Request, StatusCode};
+    use serde_json::Value;
+    use std::net::SocketAddr;
+
+    const TOKEN: &str = "token";
+
+    fn source() -> (SocketAddr, mpsc::Receiver<Event>, tokio::runtime::Runtime) {
+        source_with_config(SplunkConfig {
+            token: Some(TOKEN.into()),
+            ..SplunkConfig::new(next_addr())
+        })
+    }
+
+    fn source_with_config(
+        config: SplunkConfig,
+    ) -> (SocketAddr, mpsc::Receiver<Event>, tokio::runtime::Runtime) {
+        let (tx, rx) = mpsc::channel(100);
+        let addr = config.address;
+        let server = config
+            .build("default", &GlobalOptions::default(), tx)
+            .unwrap();
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.spawn(server);
+        wait_for_tcp(addr);
+        (addr, rx, rt)
+    }
+
+    fn sink_config(addr: SocketAddr, encoding: Encoding) -> HecSinkConfig {
+        HecSinkConfig {
+            host: format!("http://{}", addr),
+            token: TOKEN.into(),
+            host_field: "host".into(),
+            encoding: encoding.into(),
+            compression: Some(Compression::Gzip(None)),
+            ..Default::default()
+        }
+    }
+
+    fn send(rt: &mut tokio::runtime::Runtime, request: Request<Body>) -> (StatusCode, Value) {
+        let response = rt.block_on(hyper::Client::new().request(request)).unwrap();
+        let status = response.status();
+        let body = rt.block_on(response.into_body().concat2()).unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[test]
+    fn receives_text_events_from_splunk_hec_sink() {
+        let (addr, rx, mut rt) = source();
+        let config = sink_config(addr, Encoding::Text);
+
+        let healthcheck = splunk_hec::healthcheck(
+            config.token.clone(),
+            config.host.clone(),
+            Default::default(),
+        )
+        .unwrap();
+        rt.block_on(healthcheck).unwrap();
+
+        let sink = splunk_hec::hec(config, Acker::Null).unwrap();
+        let lines = random_lines(100).take(10).collect::<Vec<_>>();
+        let events = lines.iter().map(|line| {
+            let mut event = Event::from(line.as_str());
+            event
+                .as_mut_log()
+                .insert_implicit("host".into(), "example.com".into());
+            event
+        });
+        rt.block_on(sink.send_all(stream::iter_ok::<_, ()>(events.collect::<Vec<_>>())))
+            .unwrap();
+
+        let (_, events) = CollectCurrent::new(rx).wait().unwrap();
+        assert_eq!(events.len(), lines.len());
+        for (event, line) in events.iter().zip(&lines) {
+            assert_eq!(event.as_log()[&event::MESSAGE], line.as_str().into());
+            assert_eq!(event.as_log()[&event::HOST], "example.com".into());
+        }
+    }
+
+    #[test]
+    fn receives_json_events_from_splunk_hec_sink() {
+        let (addr, rx, mut rt) = source();
+        let sink = splunk_hec::hec(sink_config(addr, Encoding::Json), Acker::Null).unwrap();
+
+        let mut event = Event::from("hello");
+        event
+            .as_mut_log()
+            .insert_explicit("nested.key".into(), "value".into());
+        let timestamp = match event.as_log()[&event::TIMESTAMP] {
+            ValueKind::Timestamp(ts) => ts.timestamp(),
+            _ => unreachable!(),
+        };
+        rt.block_on(sink.send(event)).unwrap();
+
+        let (_, events) = CollectCurrent::new(rx).wait().unwrap();
+        assert_eq!(events.len(), 1);
+        let log = events[0].as_log();
+        assert_eq!(log[&event::MESSAGE], "hello".into());
+        assert_eq!(log[&"nested.key".into()], "value".into());
+        match log[&event::TIMESTAMP] {
+            ValueKind::Timestamp(ts) => assert_eq!(ts.timestamp(), timestamp),
+            _ => panic!("timestamp isn't one"),
+        }
+    }
+
+    #[test]
+    fn parses_times_within_range() {
+        let time = parse_time(1_500_000_000.25).unwrap();
+        assert_eq!(time.timestamp(), 1_500_000_000);
+        assert_eq!(time.timestamp_subsec_millis(), 250);
+        assert_eq!(parse_time(-1.5).unwrap().timestamp_millis(), -1500);
+
+        assert_eq!(parse_time(std::f64::INFINITY), None);
+        assert_eq!(parse_time(std::f64::NAN), None);
+        assert_eq!(parse_time(1e300), None);
+        assert_eq!(parse_time(-1e300), None);
+    }
+
+    #[test]
+    fn raw_endpoint_requires_a_channel() {
+        let (addr, rx, mut rt) = source();
+        let channel = "a2ef1f7a-f9b5-4c56-b8c6-2b3bd3e1f3a4";
+
+        let request = Request::post(format!("http://{}/services/collector/raw", addr))
+            .header("Authorization", "Splunk token")
+            .body(Body::from("first\nsecond\n"))
+            .unwrap();
+        let (status, body) = send(&mut rt, request);
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], 10);
+
+        let request = Request::post(format!(
+            "http://{}/services/collector/raw?channel={}&sourcetype=app",
+            addr, channel
+        ))
+        .header("Authorization", "Splunk token")
+        .body(Body::from("first\nsecond\n"))
+        .unwrap();
+        let (status, body) = send(&mut rt, request);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["code"], 0);
+
+        let (_, events) = CollectCurrent::new(rx).wait().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].as_log()[&event::MESSAGE], "first".into());
+        assert_eq!(events[1].as_log()[&event::MESSAGE], "second".into());
+        assert_eq!(events[0].as_log()[&CHANNEL], channel.into());
+        assert_eq!(events[0].as_log()[&SOURCETYPE], "app".into());
+    }
+
+    #[test]
+    fn rejects_invalid_requests() {
+        let (addr, rx, mut rt) = source();
+        let uri = format!("http://{}/services/collector/event", addr);
+
+        let request = Request::post(uri.as_str())
+            .body(Body::from(r#"{"event": "hello"}"#))
+            .unwrap();
+        let (status, body) = send(&mut rt, request);
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], 2);
+
+        let request = Request::post(uri.as_str())
+            .header("Authorization", "Splunk wrong")
+            .body(Body::from(r#"{"event": "hello"}"#))
+            .unwrap();
+        let (status, body) = send(&mut rt, request);
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["code"], 4);
+
+        let request = Request::post(uri.as_str())
+            .header("Authorization", "Splunk token")
+            .header("X-Splunk-Request-Channel", "not-a-guid")
+            .body(Body::from(r#"{"event": "hello"}"#))
+            .unwrap();
+        let (_, body) = send(&mut rt, request);
+        assert_eq!(body["code"], 11);
+
+        let request = Request::post(uri.as_str())
+            .header("Authorization", "Splunk token")
+            .body(Body::from(r#"{"event": "hello"} {"time": 1} "#))
+            .unwrap();
+        let (status, body) = send(&mut rt, request);
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], 12);
+        assert_eq!(body["invalid-event-number"], 1);
+
+        let (_, events) = CollectCurrent::new(rx).wait().unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn rejects_large_bodies() {
+        let (addr, rx, mut rt) = source_with_config(SplunkConfig {
+            max_body_size: 100,
+            ..SplunkConfig::new(next_addr())
+        });
+        let uri = format!("http://{}/services/collector/event", addr);
+
+        let event = format!(r#"{{"event": "{}"}}"#, "a".repeat(100));
+        let request = Request::post(uri.as_str()).body(Body::from(event)).unwrap();
+        let (status, body) = send(&mut rt, request);
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["code"], 413);
+
+        let request = Request::post(uri.as_str())
+            .body(Body::from(r#"{"event": "accepted"}"#))
+            .unwrap();
+        let (status, _) = send(&mut rt, request);
+        assert_eq!(status, StatusCode::OK);
+
+        let (_, events) = CollectCurrent::new(rx).wait().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].as_log()[&event::MESSAGE], "accepted".into());
+    }
+}
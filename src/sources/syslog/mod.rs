@@ -1,10 +1,11 @@
-use super::util::{SocketListenAddr, TcpSource};
+use super::util::{tls::TlsSettings, SocketListenAddr, TcpSource, TlsConfig};
 use crate::{
     event::{self, Event},
     topology::config::{DataType, GlobalOptions, SourceConfig},
 };
 use bytes::Bytes;
-use chrono::{TimeZone, Utc};
+use chrono::{DateTime, TimeZone, Utc};
+use codec::OctetCountingDecoder;
 use derive_is_enum_variant::is_enum_variant;
 use futures::{future, sync::mpsc, Future, Sink, Stream};
 use serde::{Deserialize, Serialize};
@@ -19,6 +20,8 @@ use tokio_uds::UnixListener;
 use tracing::field;
 use tracing_futures::Instrument;
 
+pub(crate) mod rfc3164;
+
 #[derive(Deserialize, Serialize, Debug)]
 // TODO: add back when serde-rs/serde#1358 is addressed
 // #[serde(deny_unknown_fields)]
@@ -33,9 +36,16 @@ pub struct SyslogConfig {
 #[derive(Deserialize, Serialize, Debug, Clone, is_enum_variant)]
 #[serde(tag = "mode", rename_all = "snake_case")]
 pub enum Mode {
-    Tcp { address: SocketListenAddr },
-    Udp { address: SocketAddr },
-    Unix { path: PathBuf },
+    Tcp {
+        address: SocketListenAddr,
+        tls: Option<TlsConfig>,
+    },
+    Udp {
+        address: SocketAddr,
+    },
+    Unix {
+        path: PathBuf,
+    },
 }
 
 fn default_max_length() -> usize {
@@ -63,13 +73,14 @@ impl SourceConfig for SyslogConfig {
         let host_key = self.host_key.clone().unwrap_or(event::HOST.to_string());
 
         match self.mode.clone() {
-            Mode::Tcp { address } => {
+            Mode::Tcp { address, tls } => {
                 let source = SyslogTcpSource {
                     max_length: self.max_length,
                     host_key,
                 };
                 let shutdown_secs = 30;
-                source.run(address, shutdown_secs, out)
+                let tls = TlsSettings::from_config(&tls)?;
+                source.run(address, shutdown_secs, tls, out)
             }
             Mode::Udp { address } => Ok(udp(address, self.max_length, host_key, out)),
             Mode::Unix { path } => Ok(unix(path, self.max_length, host_key, out)),
@@ -88,14 +99,14 @@ struct SyslogTcpSource {
 }
 
 impl TcpSource for SyslogTcpSource {
-    type Decoder = LinesCodec;
+    type Decoder = OctetCountingDecoder;
 
     fn decoder(&self) -> Self::Decoder {
-        LinesCodec::new_with_max_length(self.max_length)
+        OctetCountingDecoder::new_with_max_length(self.max_length)
     }
 
-    fn build_event(&self, frame: String, host: Option<Bytes>) -> Option<Event> {
-        event_from_str(&self.host_key, host, frame).map(|event| {
+    fn build_event(&self, frame: Bytes, host: Option<Bytes>) -> Option<Event> {
+        event_from_str(&self.host_key, host, String::from_utf8_lossy(&frame)).map(|event| {
             trace!(
                 message = "Received one event.",
                 event = field::debug(&event)
@@ -196,7 +207,6 @@ pub fn unix(
 // TODO: many more cases to handle:
 // handle parse errors instead of discarding
 // non-strict rfc5424 parsing (see ignored tests)
-// octet framing (i.e. num bytes as ascii string prefix) with and without delimiters
 // null byte delimiter in place of newline
 
 fn event_from_str(
@@ -211,39 +221,113 @@ fn event_from_str(
     );
 
     let line = line.trim();
-    syslog_rfc5424::parse_message(line)
-        .map(|parsed| {
-            let mut event = Event::from(&parsed.msg[..]);
-
-            if let Some(host) = &parsed.hostname {
-                event
-                    .as_mut_log()
-                    .insert_implicit(host_key.into(), host.clone().into());
-            } else if let Some(default_host) = default_host {
-                event
-                    .as_mut_log()
-                    .insert_implicit(host_key.into(), default_host.into());
-            }
+    let parsed = match syslog_rfc5424::parse_message(line) {
+        Ok(parsed) => parsed,
+        // Only messages that aren't RFC 5424 at all are tried as RFC 3164
+        Err(error) if is_rfc5424(line) => {
+            warn!(message = "Problem parsing incoming message, check syslog format", %error, rate_limit_secs = 10);
+            return None;
+        }
+        Err(_) => return event_from_rfc3164(host_key, default_host, line, Utc::now()),
+    };
 
-            let timestamp = parsed
-                .timestamp
-                .map(|ts| Utc.timestamp(ts, parsed.timestamp_nanos.unwrap_or(0) as u32))
-                .unwrap_or_else(Utc::now);
-            event
-                .as_mut_log()
-                .insert_implicit(event::TIMESTAMP.clone(), timestamp.into());
+    let mut event = Event::from(&parsed.msg[..]);
+
+    if let Some(host) = &parsed.hostname {
+        event
+            .as_mut_log()
+            .insert_implicit(host_key.into(), host.clone().into());
+    } else if let Some(default_host) = default_host {
+        event
+            .as_mut_log()
+            .insert_implicit(host_key.into(), default_host.into());
+    }
 
-            insert_fields_from_rfc5424(&mut event, parsed);
+    let timestamp = parsed
+        .timestamp
+        .map(|ts| Utc.timestamp(ts, parsed.timestamp_nanos.unwrap_or(0) as u32))
+        .unwrap_or_else(Utc::now);
+    event
+        .as_mut_log()
+        .insert_implicit(event::TIMESTAMP.clone(), timestamp.into());
 
-            trace!(
-                message = "processing one event.",
-                event = &field::debug(&event)
+    insert_fields_from_rfc5424(&mut event, parsed);
+
+    trace!(
+        message = "processing one event.",
+        event = &field::debug(&event)
+    );
+
+    Some(event)
+}
+
+/// RFC 5424 messages start with a version after the priority.
+fn is_rfc5424(line: &str) -> bool {
+    let version = match line.find('>') {
+        Some(end) if line.starts_with('<') => &line[end + 1..],
+        _ => return false,
+    };
+    let digits = version.bytes().take_while(u8::is_ascii_digit).count();
+    digits > 0 && version[digits..].starts_with(' ')
+}
+
+/// Parses an RFC 3164 message, a timestamp without a year is placed relative
+/// to `now`.
+fn event_from_rfc3164(
+    host_key: &str,
+    default_host: Option<Bytes>,
+    line: &str,
+    now: DateTime<Utc>,
+) -> Option<Event> {
+    let parsed = match rfc3164::parse(line, now) {
+        Some(parsed) => parsed,
+        None => {
+            warn!(
+                message = "Problem parsing incoming message, check syslog format",
+                rate_limit_secs = 10
             );
+            return None;
+        }
+    };
 
-            event
-        })
-        .map_err(|error| warn!(message = "Problem parsing incoming message, check syslog format", %error, rate_limit_secs = 10))
-        .ok()
+    let mut event = Event::from(parsed.msg);
+    let log = event.as_mut_log();
+
+    if let Some(host) = parsed.hostname {
+        log.insert_implicit(host_key.into(), host.into());
+    } else if let Some(default_host) = default_host {
+        log.insert_implicit(host_key.into(), default_host.into());
+    }
+
+    let timestamp = parsed.timestamp.unwrap_or_else(Utc::now);
+    log.insert_implicit(event::TIMESTAMP.clone(), timestamp.into());
+
+    log.insert_implicit("severity".into(), parsed.severity.into());
+    log.insert_implicit("facility".into(), parsed.facility.into());
+
+    if let Some(app_name) = parsed.appname {
+        log.insert_implicit("appname".into(), app_name.into());
+    }
+    if let Some(proc_id) = parsed.procid {
+        let value = match proc_id.parse::<i32>() {
+            Ok(pid) => pid.into(),
+            Err(_) => proc_id.into(),
+        };
+        log.insert_implicit("procid".into(), value);
+    }
+    if let Some(sequence) = parsed
+        .sequence
+        .and_then(|sequence| sequence.parse::<i64>().ok())
+    {
+        log.insert_implicit("sequence".into(), sequence.into());
+    }
+
+    trace!(
+        message = "processing one event.",
+        event = &field::debug(&event)
+    );
+
+    Some(event)
 }
 
 fn insert_fields_from_rfc5424(event: &mut Event, parsed: SyslogMessage) {
@@ -277,9 +361,9 @@ fn insert_fields_from_rfc5424(event: &mut Event, parsed: SyslogMessage) {
 
 #[cfg(test)]
 mod test {
-    use super::{event_from_str, SyslogConfig};
+    use super::{event_from_rfc3164, event_from_str, SyslogConfig};
     use crate::event::{self, Event};
-    use chrono::TimeZone;
+    use chrono::{DateTime, TimeZone, Utc};
 
     #[test]
     fn config() {
@@ -428,63 +512,100 @@ mod test {
         );
     }
 
+    /// BSD timestamps don't have a year, it depends on when they are parsed.
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2019, 6, 1).and_hms(12, 0, 0)
+    }
+
     #[test]
-    #[ignore]
     fn syslog_ng_default_network() {
         let raw = r#"<13>Feb 13 20:07:26 74794bfb6795 root[8539]: i am foobar"#;
 
-        let mut expected = Event::from(raw);
-        expected.as_mut_log().insert_implicit(
-            event::TIMESTAMP.clone(),
-            chrono::Utc.ymd(2019, 2, 13).and_hms(20, 7, 26).into(),
-        );
-        expected
-            .as_mut_log()
-            .insert_implicit("host".into(), "74794bfb6795".into());
+        let mut expected = Event::from("i am foobar");
+        {
+            let expected = expected.as_mut_log();
+            expected.insert_implicit(
+                event::TIMESTAMP.clone(),
+                Utc.ymd(2019, 2, 13).and_hms(20, 7, 26).into(),
+            );
+            expected.insert_implicit("host".into(), "74794bfb6795".into());
+            expected.insert_implicit("severity".into(), "notice".into());
+            expected.insert_implicit("facility".into(), "user".into());
+            expected.insert_implicit("appname".into(), "root".into());
+            expected.insert_implicit("procid".into(), 8539.into());
+        }
 
         assert_eq!(
-            event_from_str(&"host".to_string(), None, raw).unwrap(),
+            event_from_rfc3164(&"host".to_string(), None, raw, now()).unwrap(),
             expected
         );
     }
 
     #[test]
-    #[ignore]
     fn rsyslog_omfwd_tcp_default() {
         let raw = r#"<190>Feb 13 21:31:56 74794bfb6795 liblogging-stdlog:  [origin software="rsyslogd" swVersion="8.24.0" x-pid="8979" x-info="http://www.rsyslog.com"] start"#;
 
-        let mut expected = Event::from(raw);
-        expected.as_mut_log().insert_implicit(
-            event::TIMESTAMP.clone(),
-            chrono::Utc.ymd(2019, 2, 13).and_hms(21, 31, 56).into(),
+        let mut expected = Event::from(
+            r#"[origin software="rsyslogd" swVersion="8.24.0" x-pid="8979" x-info="http://www.rsyslog.com"] start"#,
         );
-        expected
-            .as_mut_log()
-            .insert_implicit("host".into(), "74794bfb6795".into());
+        {
+            let expected = expected.as_mut_log();
+            expected.insert_implicit(
+                event::TIMESTAMP.clone(),
+                Utc.ymd(2019, 2, 13).and_hms(21, 31, 56).into(),
+            );
+            expected.insert_implicit("host".into(), "74794bfb6795".into());
+            expected.insert_implicit("severity".into(), "info".into());
+            expected.insert_implicit("facility".into(), "local7".into());
+            expected.insert_implicit("appname".into(), "liblogging-stdlog".into());
+        }
 
         assert_eq!(
-            event_from_str(&"host".to_string(), None, raw).unwrap(),
+            event_from_rfc3164(&"host".to_string(), None, raw, now()).unwrap(),
             expected
         );
     }
 
     #[test]
-    #[ignore]
     fn rsyslog_omfwd_tcp_forward_format() {
         let raw = r#"<190>2019-02-13T21:53:30.605850+00:00 74794bfb6795 liblogging-stdlog:  [origin software="rsyslogd" swVersion="8.24.0" x-pid="9043" x-info="http://www.rsyslog.com"] start"#;
 
-        let mut expected = Event::from(raw);
-        expected.as_mut_log().insert_implicit(
-            event::TIMESTAMP.clone(),
-            chrono::Utc.ymd(2019, 2, 13).and_hms(21, 53, 30).into(),
+        let mut expected = Event::from(
+            r#"[origin software="rsyslogd" swVersion="8.24.0" x-pid="9043" x-info="http://www.rsyslog.com"] start"#,
         );
-        expected
-            .as_mut_log()
-            .insert_implicit("host".into(), "74794bfb6795".into());
+        {
+            let expected = expected.as_mut_log();
+            expected.insert_implicit(
+                event::TIMESTAMP.clone(),
+                chrono::Utc
+                    .ymd(2019, 2, 13)
+                    .and_hms_micro(21, 53, 30, 605_850)
+                    .into(),
+            );
+            expected.insert_implicit("host".into(), "74794bfb6795".into());
+            expected.insert_implicit("severity".into(), "info".into());
+            expected.insert_implicit("facility".into(), "local7".into());
+            expected.insert_implicit("appname".into(), "liblogging-stdlog".into());
+        }
 
         assert_eq!(
             event_from_str(&"host".to_string(), None, raw).unwrap(),
             expected
         );
     }
+
+    #[test]
+    fn bsd_without_hostname_uses_default_host() {
+        let raw = "<189>123: *Mar  1 18:48:50.483 UTC: %SYS-5-CONFIG_I: Configured from console";
+
+        let event = event_from_str(&"host".to_string(), Some("10.0.0.1".into()), raw).unwrap();
+        let log = event.as_log();
+        assert_eq!(log[&"host".into()], "10.0.0.1".into());
+        assert_eq!(log[&"sequence".into()], 123.into());
+        assert_eq!(log[&"facility".into()], "local7".into());
+        assert_eq!(
+            log[&event::MESSAGE],
+            "%SYS-5-CONFIG_I: Configured from console".into()
+        );
+    }
 }
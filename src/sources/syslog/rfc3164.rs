@@ -0,0 +1,339 @@
+//! Parsing of RFC 3164 (BSD) syslog messages and the variants of it sent by
+//! network gear: missing years, missing hostnames, Cisco sequence numbers
+//! and RFC 3339 timestamps.
+//!
+//! BSD timestamps carry no timezone, they are taken to be UTC.
+
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+
+pub(crate) const FACILITIES: [&str; 24] = [
+    "kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news", "uucp", "cron", "authpriv",
+    "ftp", "ntp", "audit", "alert", "clockd", "local0", "local1", "local2", "local3", "local4",
+    "local5", "local6", "local7",
+];
+
+pub(crate) const SEVERITIES: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// The longest tag accepted, the 32 characters of the RFC are commonly
+/// exceeded.
+const MAX_TAG_LEN: usize = 64;
+
+#[derive(Debug, PartialEq)]
+pub struct Message<'a> {
+    pub facility: &'static str,
+    pub severity: &'static str,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub hostname: Option<&'a str>,
+    pub appname: Option<&'a str>,
+    pub procid: Option<&'a str>,
+    /// The sequence number Cisco devices put before the timestamp.
+    pub sequence: Option<&'a str>,
+    pub msg: &'a str,
+}
+
+/// Parses `line`, a timestamp without a year is placed in the year before
+/// `now` if it would otherwise be in the future.
+pub fn parse(line: &str, now: DateTime<Utc>) -> Option<Message<'_>> {
+    // Messages without a priority get the default one of the RFC,
+    // user.notice
+    let (pri, rest) = match parse_pri(line) {
+        Some((pri, rest)) => (pri?, rest),
+        None => (13, line),
+    };
+
+    let (sequence, rest) = parse_sequence(rest);
+    let (timestamp, rest) = match parse_timestamp(rest, now) {
+        Some((timestamp, rest)) => (Some(timestamp), rest),
+        None => (None, rest),
+    };
+
+    // The hostname only ever follows a timestamp, and is missing if the
+    // next word is already the tag.
+    let (hostname, rest) = match timestamp {
+        Some(_) => match next_word(rest) {
+            Some((word, after)) if is_hostname(word) && !after.is_empty() => (Some(word), after),
+            _ => (None, rest),
+        },
+        None => (None, rest),
+    };
+
+    let (appname, procid, msg) = match parse_tag(rest) {
+        Some((appname, procid, msg)) => (Some(appname), procid, msg),
+        None => (None, None, rest),
+    };
+
+    Some(Message {
+        facility: FACILITIES[pri / 8],
+        severity: SEVERITIES[pri % 8],
+        timestamp,
+        hostname,
+        appname,
+        procid,
+        sequence,
+        msg: msg.trim_start(),
+    })
+}
+
+/// `None` if there is no priority, `Some(None)` if it is invalid.
+fn parse_pri(line: &str) -> Option<(Option<usize>, &str)> {
+    if !line.starts_with('<') {
+        return None;
+    }
+    let end = line.find('>')?;
+    let digits = &line[1..end];
+    let pri = if !digits.is_empty() && digits.len() <= 3 && digits.bytes().all(is_digit) {
+        digits
+            .parse()
+            .ok()
+            .filter(|pri| *pri < FACILITIES.len() * 8)
+    } else {
+        None
+    };
+    Some((pri, &line[end + 1..]))
+}
+
+/// Cisco's `123: ` sequence numbers.
+fn parse_sequence(rest: &str) -> (Option<&str>, &str) {
+    let digits = rest.bytes().take_while(|b| is_digit(*b)).count();
+    if digits > 0 && rest[digits..].starts_with(": ") {
+        (Some(&rest[..digits]), &rest[digits + 2..])
+    } else {
+        (None, rest)
+    }
+}
+
+fn parse_timestamp(rest: &str, now: DateTime<Utc>) -> Option<(DateTime<Utc>, &str)> {
+    // Cisco marks timestamps that aren't synchronized with `*` or `.`
+    let rest = rest.trim_start_matches(|c| c == '*' || c == '.');
+
+    if let Some((word, after)) = next_word(rest) {
+        if let Ok(timestamp) = DateTime::parse_from_rfc3339(word.trim_end_matches(':')) {
+            return Some((timestamp.with_timezone(&Utc), after));
+        }
+    }
+
+    // Mmm dd [yyyy] hh:mm:ss[.fff] [UTC][:]
+    let month = MONTHS.iter().position(|month| rest.starts_with(month))? as u32 + 1;
+    if !rest[3..].starts_with(' ') {
+        return None;
+    }
+    let (day, rest) = next_word(&rest[3..])?;
+    let day = day.parse::<u32>().ok()?;
+
+    let (mut time, mut rest) = next_word(rest)?;
+    let mut year = None;
+    if time.len() == 4 && time.bytes().all(is_digit) {
+        year = time.parse::<i32>().ok();
+        let (next, after) = next_word(rest)?;
+        time = next;
+        rest = after;
+    }
+
+    let time = time.trim_end_matches(':');
+    let (time, nanos) = match time.find('.') {
+        Some(dot) => (&time[..dot], parse_fraction(&time[dot + 1..])?),
+        None => (time, 0),
+    };
+    let mut hms = time.splitn(3, ':').map(|part| part.parse::<u32>().ok());
+    let (hour, minute, second) = (hms.next()??, hms.next()??, hms.next()??);
+
+    if let Some((zone, after)) = next_word(rest) {
+        if zone.trim_end_matches(':') == "UTC" || zone.trim_end_matches(':') == "GMT" {
+            rest = after;
+        }
+    }
+
+    let at = |year| {
+        Utc.ymd_opt(year, month, day)
+            .single()?
+            .and_hms_nano_opt(hour, minute, second, nanos)
+    };
+    let timestamp = match year {
+        Some(year) => at(year)?,
+        None => match at(now.year()) {
+            // Sent at the end of the previous year
+            Some(timestamp) if timestamp > now + Duration::days(1) => at(now.year() - 1)?,
+            Some(timestamp) => timestamp,
+            None => at(now.year() - 1)?,
+        },
+    };
+
+    Some((timestamp, rest))
+}
+
+fn parse_fraction(digits: &str) -> Option<u32> {
+    if digits.is_empty() || digits.len() > 9 || !digits.bytes().all(is_digit) {
+        return None;
+    }
+    let nanos = digits.parse::<u32>().ok()?;
+    Some(nanos * 10u32.pow(9 - digits.len() as u32))
+}
+
+/// `appname[procid]: ` or `appname: `
+fn parse_tag(rest: &str) -> Option<(&str, Option<&str>, &str)> {
+    let (word, msg) = match rest.find(' ') {
+        Some(space) => (&rest[..space], &rest[space..]),
+        None => (rest, ""),
+    };
+    if !word.ends_with(':') {
+        return None;
+    }
+    let word = &word[..word.len() - 1];
+
+    let (appname, procid) = match word.find('[') {
+        Some(open) if word.ends_with(']') => (&word[..open], Some(&word[open + 1..word.len() - 1])),
+        Some(_) => return None,
+        None => (word, None),
+    };
+
+    let valid = !appname.is_empty()
+        && appname.len() <= MAX_TAG_LEN
+        && appname
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_./".contains(c));
+    if valid {
+        Some((appname, procid.filter(|procid| !procid.is_empty()), msg))
+    } else {
+        None
+    }
+}
+
+fn is_hostname(word: &str) -> bool {
+    !word.ends_with(':')
+        && !word.starts_with('%')
+        && word
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_.:".contains(c))
+}
+
+/// The next space separated word and what follows it.
+fn next_word(rest: &str) -> Option<(&str, &str)> {
+    let rest = rest.trim_start_matches(' ');
+    if rest.is_empty() {
+        return None;
+    }
+    match rest.find(' ') {
+        Some(space) => Some((&rest[..space], &rest[space + 1..])),
+        None => Some((rest, "")),
+    }
+}
+
+fn is_digit(b: u8) -> bool {
+    b.is_ascii_digit()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2019, 6, 1).and_hms(12, 0, 0)
+    }
+
+    #[test]
+    fn bsd_message() {
+        let message = parse(
+            "<13>Feb 13 20:07:26 74794bfb6795 root[8539]: i am foobar",
+            now(),
+        )
+        .unwrap();
+        assert_eq!(
+            message,
+            Message {
+                facility: "user",
+                severity: "notice",
+                timestamp: Some(Utc.ymd(2019, 2, 13).and_hms(20, 7, 26)),
+                hostname: Some("74794bfb6795"),
+                appname: Some("root"),
+                procid: Some("8539"),
+                sequence: None,
+                msg: "i am foobar",
+            }
+        );
+    }
+
+    #[test]
+    fn missing_hostname() {
+        let message = parse("<38>Feb  5 10:01:02 sshd: Accepted publickey", now()).unwrap();
+        assert_eq!(message.facility, "auth");
+        assert_eq!(message.severity, "info");
+        assert_eq!(message.hostname, None);
+        assert_eq!(message.appname, Some("sshd"));
+        assert_eq!(message.procid, None);
+        assert_eq!(message.msg, "Accepted publickey");
+    }
+
+    #[test]
+    fn year_and_fraction() {
+        let message = parse("<13>Feb 13 2018 20:07:26.123 host app: msg", now()).unwrap();
+        assert_eq!(
+            message.timestamp,
+            Some(Utc.ymd(2018, 2, 13).and_hms_milli(20, 7, 26, 123))
+        );
+        assert_eq!(message.hostname, Some("host"));
+    }
+
+    #[test]
+    fn missing_year_in_the_future_is_last_year() {
+        let message = parse("<13>Dec 31 23:59:59 host app: msg", now()).unwrap();
+        assert_eq!(
+            message.timestamp,
+            Some(Utc.ymd(2018, 12, 31).and_hms(23, 59, 59))
+        );
+    }
+
+    #[test]
+    fn rfc3339_timestamp() {
+        let message = parse(
+            "<190>2019-02-13T21:53:30.605850+00:00 74794bfb6795 liblogging-stdlog:  start",
+            now(),
+        )
+        .unwrap();
+        assert_eq!(message.facility, "local7");
+        assert_eq!(message.severity, "info");
+        assert_eq!(
+            message.timestamp,
+            Some(Utc.ymd(2019, 2, 13).and_hms_micro(21, 53, 30, 605_850))
+        );
+        assert_eq!(message.appname, Some("liblogging-stdlog"));
+        assert_eq!(message.msg, "start");
+    }
+
+    #[test]
+    fn cisco_sequence_number() {
+        let message = parse(
+            "<189>123: *Mar  1 18:48:50.483 UTC: %SYS-5-CONFIG_I: Configured from console",
+            now(),
+        )
+        .unwrap();
+        assert_eq!(message.sequence, Some("123"));
+        assert_eq!(
+            message.timestamp,
+            Some(Utc.ymd(2019, 3, 1).and_hms_milli(18, 48, 50, 483))
+        );
+        assert_eq!(message.hostname, None);
+        assert_eq!(message.appname, None);
+        assert_eq!(message.msg, "%SYS-5-CONFIG_I: Configured from console");
+    }
+
+    #[test]
+    fn without_priority_or_timestamp() {
+        let message = parse("just some text", now()).unwrap();
+        assert_eq!(message.facility, "user");
+        assert_eq!(message.severity, "notice");
+        assert_eq!(message.timestamp, None);
+        assert_eq!(message.msg, "just some text");
+    }
+
+    #[test]
+    fn invalid_priority() {
+        assert_eq!(parse("<192>Feb 13 20:07:26 host app: msg", now()), None);
+        assert_eq!(parse("<abc>Feb 13 20:07:26 host app: msg", now()), None);
+    }
+}
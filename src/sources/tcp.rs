@@ -1,4 +1,4 @@
-use super::util::{SocketListenAddr, TcpSource};
+use super::util::{tls::TlsSettings, SocketListenAddr, TcpSource, TlsConfig};
 use crate::{
     event::{self, Event},
     topology::config::{DataType, GlobalOptions, SourceConfig},
@@ -19,6 +19,7 @@ pub struct TcpConfig {
     #[serde(default = "default_shutdown_timeout_secs")]
     pub shutdown_timeout_secs: u64,
     pub host_key: Option<Atom>,
+    pub tls: Option<TlsConfig>,
 }
 
 fn default_max_length() -> usize {
@@ -36,6 +37,7 @@ impl TcpConfig {
             max_length: default_max_length(),
             host_key: None,
             shutdown_timeout_secs: default_shutdown_timeout_secs(),
+            tls: None,
         }
     }
 }
@@ -51,7 +53,8 @@ impl SourceConfig for TcpConfig {
         let tcp = RawTcpSource {
             config: self.clone(),
         };
-        tcp.run(self.address, self.shutdown_timeout_secs, out)
+        let tls = TlsSettings::from_config(&self.tls)?;
+        tcp.run(self.address, self.shutdown_timeout_secs, tls, out)
     }
 
     fn output_type(&self) -> DataType {
@@ -96,12 +99,14 @@ impl TcpSource for RawTcpSource {
 
 #[cfg(test)]
 mod test {
-    use super::TcpConfig;
+    use super::{TcpConfig, TlsConfig};
     use crate::event;
-    use crate::test_util::{block_on, next_addr, send_lines, wait_for_tcp};
+    use crate::test_util::{block_on, next_addr, runtime, send_lines, wait_for_tcp};
     use crate::topology::config::{GlobalOptions, SourceConfig};
     use futures::sync::mpsc;
-    use futures::Stream;
+    use futures::{Future, Stream};
+    use native_tls::{Certificate, Identity};
+    use tokio::net::TcpStream;
 
     #[test]
     fn tcp_it_includes_host() {
@@ -177,4 +182,57 @@ mod test {
             "more short".into()
         );
     }
+
+    #[test]
+    fn tcp_with_tls_includes_peer_subject() {
+        let (tx, rx) = mpsc::channel(1);
+
+        let addr = next_addr();
+
+        let mut config = TcpConfig::new(addr.into());
+        config.tls = Some(TlsConfig {
+            enabled: Some(true),
+            crt_path: Some("tests/data/localhost.crt".into()),
+            key_path: Some("tests/data/localhost.key".into()),
+            ca_path: Some("tests/data/Vector_CA.crt".into()),
+            verify_certificate: Some(true),
+            peer_key: Some("peer".into()),
+            ..Default::default()
+        });
+
+        let server = config
+            .build("default", &GlobalOptions::default(), tx)
+            .unwrap();
+        let mut rt = runtime();
+        rt.spawn(server);
+        wait_for_tcp(addr);
+
+        let ca = std::fs::read("tests/data/Vector_CA.crt").unwrap();
+        let identity = std::fs::read("tests/data/localhost.p12").unwrap();
+        let connector = native_tls::TlsConnector::builder()
+            .add_root_certificate(Certificate::from_pem(&ca).unwrap())
+            .identity(Identity::from_pkcs12(&identity, "NOPASS").unwrap())
+            .build()
+            .unwrap();
+        let connector = tokio_tls::TlsConnector::from(connector);
+
+        let _stream = rt
+            .block_on(
+                TcpStream::connect(&addr)
+                    .map_err(|error| error.to_string())
+                    .and_then(move |stream| {
+                        connector
+                            .connect("localhost", stream)
+                            .map_err(|error| error.to_string())
+                    })
+                    .and_then(|stream| {
+                        tokio::io::write_all(stream, b"test\n").map_err(|error| error.to_string())
+                    }),
+            )
+            .unwrap();
+
+        let event = rx.wait().next().unwrap().unwrap();
+        assert_eq!(event.as_log()[&event::MESSAGE], "test".into());
+        assert_eq!(event.as_log()[&"peer".into()], "CN=localhost".into());
+    }
 }
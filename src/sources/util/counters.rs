@@ -0,0 +1,49 @@
+use crate::event::Metric;
+use std::collections::{BTreeMap, HashMap};
+
+type Series = (String, BTreeMap<String, String>);
+
+/// Turns cumulative counters, like the ones of Prometheus exporters or of
+/// the kernel, into the increments of Vector's counters.
+///
+/// A counter becomes the difference with the previous value of the same
+/// series. The first value of a series is only recorded, and a value lower
+/// than the previous one means the counter was reset so all of it is the
+/// increment. Other metrics are left as is.
+#[derive(Debug, Default)]
+pub struct CumulativeCounters {
+    previous: HashMap<Series, f64>,
+}
+
+impl CumulativeCounters {
+    pub fn increment(&mut self, metric: Metric) -> Option<Metric> {
+        match metric {
+            Metric::Counter {
+                name,
+                val,
+                timestamp,
+                tags,
+            } => {
+                let series = (
+                    name.clone(),
+                    tags.iter()
+                        .flatten()
+                        .map(|(k, v)| (k.clone(), v.clone()))
+                        .collect(),
+                );
+                let increment = match self.previous.insert(series, val) {
+                    Some(last) if val >= last => val - last,
+                    Some(_) => val,
+                    None => return None,
+                };
+                Some(Metric::Counter {
+                    name,
+                    val: increment,
+                    timestamp,
+                    tags,
+                })
+            }
+            metric => Some(metric),
+        }
+    }
+}
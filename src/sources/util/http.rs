@@ -0,0 +1,157 @@
+use super::tls::{MaybeTlsStream, TlsSettings};
+use bytes::{Bytes, BytesMut};
+use flate2::read::GzDecoder;
+use futures::{future, sync::mpsc, Future, Stream};
+use http::header::{HeaderMap, AUTHORIZATION, CONTENT_ENCODING};
+use hyper::{Body, Response, StatusCode};
+use snafu::{ResultExt, Snafu};
+use std::{
+    io::{self, Read},
+    net::SocketAddr,
+    time::Duration,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    timer::Timeout,
+};
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("Could not bind to {}: {}", addr, source))]
+    BindError { addr: SocketAddr, source: io::Error },
+}
+
+#[derive(Debug, Snafu)]
+pub enum BodyError {
+    #[snafu(display("Request body larger than {} bytes", max_size))]
+    TooLarge { max_size: usize },
+    #[snafu(display("Could not read request body: {}", source))]
+    BodyRead { source: hyper::Error },
+    #[snafu(display("Invalid gzip body: {}", source))]
+    InvalidGzip { source: io::Error },
+}
+
+impl BodyError {
+    /// The status of the response to the request.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            BodyError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            BodyError::BodyRead { .. } | BodyError::InvalidGzip { .. } => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// The response of an HTTP source to a request.
+pub type ResponseFuture = Box<dyn Future<Item = Response<Body>, Error = hyper::Error> + Send>;
+
+/// The default `max_body_size` of the HTTP sources.
+pub fn default_max_body_size() -> usize {
+    bytesize::mib(10u64) as usize
+}
+
+/// How long a client has to complete its TLS handshake.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The connections accepted on `addr` for an HTTP server, with TLS when it
+/// is configured. TLS handshakes run in their own tasks so slow clients
+/// don't hold up the others, failed or timed out ones only drop their
+/// connection.
+pub fn incoming(
+    addr: SocketAddr,
+    tls: Option<TlsSettings>,
+) -> crate::Result<impl Stream<Item = MaybeTlsStream<TcpStream>, Error = io::Error> + Send> {
+    let listener = TcpListener::bind(&addr).context(BindError { addr })?;
+    info!(message = "listening.", %addr);
+
+    let (handshaked_tx, handshaked_rx) = mpsc::unbounded();
+    let accepted = listener.incoming().filter_map(move |socket| match &tls {
+        Some(tls) => {
+            let handshaked_tx = handshaked_tx.clone();
+            let handshake =
+                Timeout::new(tls.accept(socket), TLS_HANDSHAKE_TIMEOUT).then(move |result| {
+                    match result {
+                        Ok(socket) => {
+                            let _ = handshaked_tx.unbounded_send(socket);
+                        }
+                        Err(error) => warn!(
+                            message = "TLS handshake failed.",
+                            error = %error.into_inner().unwrap_or_else(|| "timed out".into())
+                        ),
+                    }
+                    Ok(())
+                });
+            tokio::spawn(handshake);
+            None
+        }
+        None => Some(MaybeTlsStream::Raw(socket)),
+    });
+    let handshaked = handshaked_rx.map_err(|()| unreachable!("receivers don't fail"));
+
+    Ok(accepted.select(handshaked))
+}
+
+/// Responds with `status` and a plain `body`.
+pub fn respond(status: StatusCode, body: impl Into<Body>) -> ResponseFuture {
+    let response = Response::builder()
+        .status(status)
+        .body(body.into())
+        .unwrap();
+    Box::new(future::ok(response))
+}
+
+/// Whether the `Authorization` header of a request is `expected`, compared
+/// in constant time.
+pub fn authorized(headers: &HeaderMap, expected: &str) -> bool {
+    headers.get(AUTHORIZATION).map_or(false, |value| {
+        let value = value.as_bytes();
+        value.len() == expected.len() && openssl::memcmp::eq(value, expected.as_bytes())
+    })
+}
+
+/// Whether the body of a request is gzipped, `None` when its
+/// `Content-Encoding` is one `read_body` can't decode.
+pub fn gzipped(headers: &HeaderMap) -> Option<bool> {
+    match headers.get(CONTENT_ENCODING).map(|v| v.as_bytes()) {
+        None | Some(b"identity") => Some(false),
+        Some(b"gzip") => Some(true),
+        Some(_) => None,
+    }
+}
+
+/// Reads the body of a request, gunzipped when its `Content-Encoding` is
+/// `gzip`. Bodies larger than `max_size`, before or after decompression, are
+/// rejected once that much has been read, so neither large bodies nor gzip
+/// bombs are buffered.
+pub fn read_body(
+    body: Body,
+    gzip: bool,
+    max_size: usize,
+) -> impl Future<Item = Bytes, Error = BodyError> {
+    body.map_err(|source| BodyError::BodyRead { source })
+        .fold(BytesMut::new(), move |mut read, chunk| {
+            if read.len() + chunk.len() > max_size {
+                return Err(BodyError::TooLarge { max_size });
+            }
+            read.extend_from_slice(&chunk);
+            Ok(read)
+        })
+        .and_then(move |body| {
+            if gzip {
+                gunzip(&body, max_size)
+            } else {
+                Ok(body.freeze())
+            }
+        })
+}
+
+fn gunzip(body: &[u8], max_size: usize) -> Result<Bytes, BodyError> {
+    let mut decompressed = Vec::new();
+    GzDecoder::new(body)
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .context(InvalidGzip)?;
+    if decompressed.len() > max_size {
+        return Err(BodyError::TooLarge { max_size });
+    }
+    Ok(decompressed.into())
+}
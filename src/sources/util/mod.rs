@@ -1,3 +1,8 @@
+mod counters;
+pub mod http;
 mod tcp;
+pub mod tls;
 
+pub use counters::CumulativeCounters;
 pub use tcp::{SocketListenAddr, TcpSource};
+pub use tls::TlsConfig;
@@ -1,5 +1,6 @@
+use super::tls::{MaybeTlsStream, TlsSettings};
 use crate::Event;
-use bytes::Bytes;
+use bytes::{BufMut, Bytes, BytesMut};
 use futures::{future, sync::mpsc, Future, Sink, Stream};
 use listenfd::ListenFd;
 use serde::{de, Deserialize, Deserializer, Serialize};
@@ -10,7 +11,8 @@ use std::{
 };
 use stream_cancel::{StreamExt, Tripwire};
 use tokio::{
-    codec::{Decoder, FramedRead},
+    codec::{BytesCodec, Decoder, FramedRead, FramedWrite},
+    io::AsyncRead,
     net::TcpListener,
     reactor::Handle,
     timer,
@@ -29,12 +31,34 @@ pub trait TcpSource: Clone + Send + 'static {
         host: Option<Bytes>,
     ) -> Option<Event>;
 
+    /// Whether the first frame of every connection is a handshake to be
+    /// checked with `check_handshake` before accepting any events.
+    fn requires_handshake(&self) -> bool {
+        false
+    }
+
+    fn check_handshake(&self, _frame: &<Self::Decoder as tokio::codec::Decoder>::Item) -> bool {
+        true
+    }
+
+    /// Whether to tell the peer how many events were accepted, by writing
+    /// their count as a 32 bit big endian integer once they are sent on.
+    /// Frames `build_event` drops are acknowledged too, the peer would only
+    /// send them again.
+    fn acknowledge(&self) -> bool {
+        false
+    }
+
     fn run(
         self,
         addr: SocketListenAddr,
         shutdown_timeout_secs: u64,
+        tls: Option<TlsSettings>,
         out: mpsc::Sender<Event>,
-    ) -> crate::Result<crate::sources::Source> {
+    ) -> crate::Result<crate::sources::Source>
+    where
+        <Self::Decoder as tokio::codec::Decoder>::Item: Send,
+    {
         let out = out.sink_map_err(|e| error!("error sending event: {:?}", e));
 
         let mut listenfd = ListenFd::from_env();
@@ -110,20 +134,97 @@ pub trait TcpSource: Clone + Send + 'static {
                         .map_err(|_| ());
 
                     let source = self.clone();
+                    let peer_key = tls.as_ref().and_then(|tls| tls.peer_key().cloned());
+                    let socket = match &tls {
+                        Some(tls) => future::Either::A(tls.accept(socket)),
+                        None => future::Either::B(future::ok(MaybeTlsStream::Raw(socket))),
+                    };
+
                     span.in_scope(|| {
                         debug!("accepted a new socket.");
 
                         let out = out.clone();
 
-                        let events_in = FramedRead::new(socket, source.decoder())
-                            .take_until(tripwire)
-                            .filter_map(move |frame| {
-                                let host = host.clone();
-                                source.build_event(frame, host)
-                            })
-                            .map_err(|error| warn!(message = "connection error.", %error));
+                        let handler = socket
+                            .map_err(|error| warn!(message = "TLS handshake failed.", %error))
+                            .and_then(move |socket| {
+                                let peer_subject = socket.peer_subject().map(Bytes::from);
+                                let decoder = source.decoder();
+                                let (reader, writer) = socket.split();
+
+                                let frames = FramedRead::new(reader, decoder).take_until(tripwire);
+                                let frames: Box<dyn Stream<Item = _, Error = _> + Send> = if source
+                                    .requires_handshake()
+                                {
+                                    let handshake_source = source.clone();
+                                    Box::new(
+                                        frames
+                                            .into_future()
+                                            .map_err(|(error, _)| error)
+                                            .and_then(move |(frame, frames)| match frame {
+                                                Some(ref frame)
+                                                    if handshake_source.check_handshake(frame) =>
+                                                {
+                                                    Ok(frames)
+                                                }
+                                                _ => Err(io::Error::new(
+                                                    io::ErrorKind::PermissionDenied,
+                                                    "invalid handshake",
+                                                )),
+                                            })
+                                            .flatten_stream(),
+                                    )
+                                } else {
+                                    Box::new(frames)
+                                };
+
+                                let acknowledge = source.acknowledge();
+                                let events = frames
+                                    .map(move |frame| {
+                                        let host = host.clone();
+                                        let mut event = source.build_event(frame, host)?;
+                                        if let (Some(key), Some(subject), Event::Log(log)) =
+                                            (&peer_key, &peer_subject, &mut event)
+                                        {
+                                            log.insert_implicit(
+                                                key.clone(),
+                                                subject.clone().into(),
+                                            );
+                                        }
+                                        Some(event)
+                                    })
+                                    .map_err(|error| warn!(message = "connection error.", %error));
 
-                        let handler = events_in.forward(out).map(|_| debug!("connection closed."));
+                                if acknowledge {
+                                    let acks = FramedWrite::new(writer, BytesCodec::new())
+                                        .sink_map_err(
+                                            |error| warn!(message = "connection error.", %error),
+                                        );
+                                    future::Either::A(
+                                        events
+                                            .and_then(move |event| match event {
+                                                Some(event) => future::Either::A(
+                                                    out.clone().send(event).map(|_| ()),
+                                                ),
+                                                None => future::Either::B(future::ok(())),
+                                            })
+                                            .map(|_| {
+                                                let mut ack = BytesMut::with_capacity(4);
+                                                ack.put_u32_be(1);
+                                                ack.freeze()
+                                            })
+                                            .forward(acks)
+                                            .map(|_| debug!("connection closed.")),
+                                    )
+                                } else {
+                                    future::Either::B(
+                                        events
+                                            .filter_map(|event| event)
+                                            .forward(out)
+                                            .map(|_| debug!("connection closed.")),
+                                    )
+                                }
+                            });
 
                         tokio::spawn(handler.instrument(span.clone()));
                     });
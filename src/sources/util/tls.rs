@@ -0,0 +1,338 @@
+use crate::sinks::util::tls::{self, load_key, load_x509, open_read, FileStamps};
+use futures::{Future, Poll};
+use openssl::{
+    pkcs12::Pkcs12,
+    ssl::{SslAcceptor, SslMethod, SslVerifyMode},
+    x509::X509Ref,
+};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, Once, RwLock};
+use string_cache::DefaultAtom as Atom;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_openssl::{SslAcceptorExt, SslStream};
+
+#[derive(Debug, Snafu)]
+enum TlsError {
+    #[snafu(display("TLS sources require a crt_path"))]
+    MissingCrtFile,
+    #[snafu(display("Could not parse PKCS#12 identity in {:?}: {}", filename, source))]
+    Pkcs12ParseError {
+        filename: PathBuf,
+        source: openssl::error::ErrorStack,
+    },
+    #[snafu(display("Could not build TLS acceptor: {}", source))]
+    AcceptorBuildError { source: openssl::error::ErrorStack },
+}
+
+/// The `tls` option of the TCP based sources
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub enabled: Option<bool>,
+    /// The server certificate, either in PEM or DER form along with
+    /// `key_path`, or as a PKCS#12 archive.
+    pub crt_path: Option<PathBuf>,
+    pub key_path: Option<PathBuf>,
+    pub key_pass: Option<String>,
+    /// The CA client certificates are verified against. Clients presenting
+    /// a certificate not signed by it are rejected.
+    pub ca_path: Option<PathBuf>,
+    /// Reject clients not presenting a certificate.
+    pub verify_certificate: Option<bool>,
+    /// The key the subject of the client certificate is put in.
+    pub peer_key: Option<Atom>,
+}
+
+/// Directly usable settings for TLS acceptors. The acceptor is built again
+/// once one of the files it was built from changes, so rotated certificates
+/// are used for new connections.
+#[derive(Clone)]
+pub struct TlsSettings {
+    config: TlsConfig,
+    acceptor: Arc<Acceptor>,
+}
+
+/// The files are checked for changes on a timer started by the first
+/// connection, and not while accepting connections.
+struct Acceptor {
+    current: RwLock<SslAcceptor>,
+    stamps: Mutex<FileStamps>,
+    watching: Once,
+}
+
+impl Acceptor {
+    /// Builds the acceptor again if its files have changed. The previous one
+    /// is kept if the new files are invalid.
+    fn reload(&self, config: &TlsConfig) {
+        let mut stamps = self.stamps.lock().expect("poisoned lock");
+        if let Some(changed) = stamps.changed() {
+            match build_acceptor(config) {
+                Ok(acceptor) => {
+                    info!("Reloaded changed TLS certificates.");
+                    *self.current.write().expect("poisoned lock") = acceptor;
+                }
+                Err(error) => error!(message = "Could not reload changed TLS certificates.", %error),
+            }
+            *stamps = changed;
+        }
+    }
+}
+
+impl TlsSettings {
+    pub fn from_config(config: &Option<TlsConfig>) -> crate::Result<Option<Self>> {
+        let config = match config {
+            Some(config) if config.enabled.unwrap_or(false) => config,
+            _ => return Ok(None),
+        };
+
+        // Taken before reading the files, so changes made while they are
+        // read are noticed by the next check.
+        let stamps = file_stamps(config);
+        let acceptor = build_acceptor(config)?;
+
+        Ok(Some(Self {
+            config: config.clone(),
+            acceptor: Arc::new(Acceptor {
+                current: RwLock::new(acceptor),
+                stamps: Mutex::new(stamps),
+                watching: Once::new(),
+            }),
+        }))
+    }
+
+    fn acceptor(&self) -> SslAcceptor {
+        self.acceptor.watching.call_once(|| {
+            let config = self.config.clone();
+            tls::watch(&self.acceptor, move |acceptor| acceptor.reload(&config));
+        });
+        self.acceptor.current.read().expect("poisoned lock").clone()
+    }
+
+    /// The key the subject of the client certificate is put in, if any
+    pub fn peer_key(&self) -> Option<&Atom> {
+        self.config.peer_key.as_ref()
+    }
+
+    pub fn accept<S>(&self, stream: S) -> impl Future<Item = MaybeTlsStream<S>, Error = String>
+    where
+        S: AsyncRead + AsyncWrite + std::fmt::Debug,
+    {
+        self.acceptor()
+            .accept_async(stream)
+            .map(MaybeTlsStream::Tls)
+            .map_err(|error| error.to_string())
+    }
+}
+
+fn file_stamps(config: &TlsConfig) -> FileStamps {
+    FileStamps::new(
+        [&config.crt_path, &config.key_path, &config.ca_path]
+            .iter()
+            .filter_map(|path| path.as_ref()),
+    )
+}
+
+fn build_acceptor(config: &TlsConfig) -> crate::Result<SslAcceptor> {
+    let crt_path = config.crt_path.as_ref().ok_or(TlsError::MissingCrtFile)?;
+    let (crt, key) = match config.key_path {
+        Some(ref key_path) => (load_x509(crt_path)?, load_key(key_path, &config.key_pass)?),
+        None => {
+            let data = open_read(crt_path, "certificate")?;
+            let key_pass = config.key_pass.as_ref().map(|s| s.as_str()).unwrap_or("");
+            let parsed = Pkcs12::from_der(&data)
+                .and_then(|pkcs12| pkcs12.parse(key_pass))
+                .with_context(|| Pkcs12ParseError { filename: crt_path })?;
+            (parsed.cert, parsed.pkey)
+        }
+    };
+
+    let mut builder =
+        SslAcceptor::mozilla_intermediate(SslMethod::tls()).context(AcceptorBuildError)?;
+    builder.set_certificate(&crt).context(AcceptorBuildError)?;
+    builder.set_private_key(&key).context(AcceptorBuildError)?;
+    builder.check_private_key().context(AcceptorBuildError)?;
+
+    let mut verify_mode = SslVerifyMode::NONE;
+    if let Some(ref ca_path) = config.ca_path {
+        let ca = load_x509(ca_path)?;
+        builder.add_client_ca(&ca).context(AcceptorBuildError)?;
+        builder
+            .cert_store_mut()
+            .add_cert(ca)
+            .context(AcceptorBuildError)?;
+        verify_mode = SslVerifyMode::PEER;
+    }
+    if config.verify_certificate.unwrap_or(false) {
+        verify_mode = SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT;
+    }
+    builder.set_verify(verify_mode);
+
+    Ok(builder.build())
+}
+
+/// A connection accepted by a TCP source, with or without TLS.
+#[derive(Debug)]
+pub enum MaybeTlsStream<S> {
+    Raw(S),
+    Tls(SslStream<S>),
+}
+
+impl<S> MaybeTlsStream<S> {
+    /// The subject of the certificate the peer presented, as
+    /// comma separated `NAME=value` pairs.
+    pub fn peer_subject(&self) -> Option<String> {
+        match self {
+            MaybeTlsStream::Raw(_) => None,
+            MaybeTlsStream::Tls(stream) => stream
+                .get_ref()
+                .ssl()
+                .peer_certificate()
+                .map(|certificate| subject(&certificate)),
+        }
+    }
+}
+
+fn subject(certificate: &X509Ref) -> String {
+    certificate
+        .subject_name()
+        .entries()
+        .filter_map(|entry| {
+            let name = entry.object().nid().short_name().ok()?;
+            let value = entry.data().as_utf8().ok()?;
+            Some(format!("{}={}", name, value))
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl<S: Read + Write> Read for MaybeTlsStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            MaybeTlsStream::Raw(stream) => stream.read(buf),
+            MaybeTlsStream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite> AsyncRead for MaybeTlsStream<S> {}
+
+impl<S: Read + Write> Write for MaybeTlsStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            MaybeTlsStream::Raw(stream) => stream.write(buf),
+            MaybeTlsStream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            MaybeTlsStream::Raw(stream) => stream.flush(),
+            MaybeTlsStream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite> AsyncWrite for MaybeTlsStream<S> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        match self {
+            MaybeTlsStream::Raw(stream) => stream.shutdown(),
+            MaybeTlsStream::Tls(stream) => stream.shutdown(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CA: &str = "tests/data/Vector_CA.crt";
+    const CRT: &str = "tests/data/localhost.crt";
+    const KEY: &str = "tests/data/localhost.key";
+
+    fn config() -> TlsConfig {
+        TlsConfig {
+            enabled: Some(true),
+            crt_path: Some(CRT.into()),
+            key_path: Some(KEY.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn from_config_disabled() {
+        assert!(TlsSettings::from_config(&None).unwrap().is_none());
+        let config = TlsConfig {
+            enabled: Some(false),
+            ..config()
+        };
+        assert!(TlsSettings::from_config(&Some(config)).unwrap().is_none());
+    }
+
+    #[test]
+    fn from_config_requires_crt() {
+        let config = TlsConfig {
+            crt_path: None,
+            ..config()
+        };
+        assert!(TlsSettings::from_config(&Some(config)).is_err());
+    }
+
+    #[test]
+    fn from_config_pem_and_pkcs12() {
+        assert!(TlsSettings::from_config(&Some(config())).unwrap().is_some());
+
+        let config = TlsConfig {
+            crt_path: Some("tests/data/localhost.p12".into()),
+            key_path: None,
+            key_pass: Some("NOPASS".into()),
+            ca_path: Some(CA.into()),
+            verify_certificate: Some(true),
+            ..config()
+        };
+        assert!(TlsSettings::from_config(&Some(config)).unwrap().is_some());
+    }
+
+    #[test]
+    fn certificate_subject() {
+        let crt = load_x509(CRT.as_ref()).unwrap();
+        let subject = subject(&crt);
+        assert!(subject.contains("CN=localhost"), "{}", subject);
+    }
+
+    #[test]
+    fn reloads_changed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let crt_path = dir.path().join("server.crt");
+        let key_path = dir.path().join("server.key");
+        std::fs::copy(CRT, &crt_path).unwrap();
+        std::fs::copy(KEY, &key_path).unwrap();
+
+        let config = TlsConfig {
+            crt_path: Some(crt_path.clone()),
+            key_path: Some(key_path.clone()),
+            ..config()
+        };
+        let settings = TlsSettings::from_config(&Some(config)).unwrap().unwrap();
+        let changed = || settings.acceptor.stamps.lock().unwrap().changed().is_some();
+        assert!(!changed());
+
+        // Invalid files are noticed, but the previous acceptor stays in use
+        std::fs::write(&crt_path, "not a certificate").unwrap();
+        std::fs::remove_file(&key_path).unwrap();
+        assert!(changed());
+        settings.acceptor.reload(&settings.config);
+        assert!(!changed());
+
+        // The new certificate is used once the files are valid again
+        std::fs::copy(CRT, &crt_path).unwrap();
+        std::fs::copy(KEY, &key_path).unwrap();
+        assert!(changed());
+        settings.acceptor.reload(&settings.config);
+        let acceptor = settings.acceptor.current.read().unwrap().clone();
+        let certificate = acceptor.context().certificate().unwrap();
+        assert!(subject(certificate).contains("CN=localhost"));
+    }
+}
@@ -1,22 +1,49 @@
-use super::util::{SocketListenAddr, TcpSource};
+use super::util::{
+    http::{
+        authorized, default_max_body_size, gzipped, incoming, read_body, respond, ResponseFuture,
+    },
+    tls::TlsSettings,
+    SocketListenAddr, TcpSource, TlsConfig,
+};
 use crate::{
     event::proto,
+    sinks::vector::{ProtocolVersion, EVENTS_PATH, HEALTH_PATH, VERSIONS_HEADER, VERSION_HEADER},
     topology::config::{DataType, GlobalOptions, SourceConfig},
     Event,
 };
 use bytes::{Bytes, BytesMut};
-use futures::sync::mpsc;
+use futures::{future, stream, sync::mpsc, Future, Sink};
+use hyper::{service::service_fn, Body, Method, Request, Response, Server, StatusCode};
 use prost::Message;
 use serde::{Deserialize, Serialize};
-use tokio::codec::LengthDelimitedCodec;
+use snafu::Snafu;
+use tokio::codec::{Decoder, LengthDelimitedCodec};
 use tracing::field;
 
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("Version 2 of the protocol can't listen on a systemd socket"))]
+    SystemdFdWithV2,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct VectorConfig {
     pub address: SocketListenAddr,
+    #[serde(default)]
+    pub version: ProtocolVersion,
     #[serde(default = "default_shutdown_timeout_secs")]
     pub shutdown_timeout_secs: u64,
+    pub tls: Option<TlsConfig>,
+    /// A secret the sinks have to send before any events.
+    pub auth_token: Option<String>,
+    /// Acknowledge events to the sinks once they are accepted.
+    #[serde(default)]
+    pub acknowledgements: bool,
+    /// The largest request body accepted by version 2 of the protocol, in
+    /// bytes. Larger bodies, before or after decompression, are rejected.
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: usize,
 }
 
 fn default_shutdown_timeout_secs() -> u64 {
@@ -27,7 +54,12 @@ impl VectorConfig {
     pub fn new(address: SocketListenAddr) -> Self {
         Self {
             address,
+            version: ProtocolVersion::V1,
             shutdown_timeout_secs: default_shutdown_timeout_secs(),
+            tls: None,
+            auth_token: None,
+            acknowledgements: false,
+            max_body_size: default_max_body_size(),
         }
     }
 }
@@ -40,8 +72,17 @@ impl SourceConfig for VectorConfig {
         _globals: &GlobalOptions,
         out: mpsc::Sender<Event>,
     ) -> crate::Result<super::Source> {
-        let vector = VectorSource;
-        vector.run(self.address, self.shutdown_timeout_secs, out)
+        let tls = TlsSettings::from_config(&self.tls)?;
+        match self.version {
+            ProtocolVersion::V1 => {
+                let vector = VectorSource {
+                    auth_token: self.auth_token.clone().map(Bytes::from),
+                    acknowledgements: self.acknowledgements,
+                };
+                vector.run(self.address, self.shutdown_timeout_secs, tls, out)
+            }
+            ProtocolVersion::V2 => self.serve_v2(tls, out),
+        }
     }
 
     fn output_type(&self) -> DataType {
@@ -49,8 +90,120 @@ impl SourceConfig for VectorConfig {
     }
 }
 
+impl VectorConfig {
+    /// Serves version 2 of the protocol, where every response acknowledges
+    /// the events of its request.
+    fn serve_v2(
+        &self,
+        tls: Option<TlsSettings>,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        let addr = match self.address {
+            SocketListenAddr::SocketAddr(addr) => addr,
+            SocketListenAddr::SystemdFd(_) => return Err(Box::new(BuildError::SystemdFdWithV2)),
+        };
+        let incoming = incoming(addr, tls)?;
+
+        let auth_token = self.auth_token.clone().map(Bytes::from);
+        let max_body_size = self.max_body_size;
+        let new_service = move || {
+            let auth_token = auth_token.clone();
+            let out = out.clone();
+            service_fn(move |request| handle_v2(request, &auth_token, max_body_size, out.clone()))
+        };
+
+        let server = Server::builder(incoming)
+            .serve(new_service)
+            .map_err(|error| error!(message = "server error.", %error));
+
+        Ok(Box::new(server))
+    }
+}
+
+fn handle_v2(
+    request: Request<Body>,
+    auth_token: &Option<Bytes>,
+    max_body_size: usize,
+    out: mpsc::Sender<Event>,
+) -> ResponseFuture {
+    match (request.method(), request.uri().path()) {
+        (&Method::GET, HEALTH_PATH) => {
+            let response = Response::builder()
+                .header(VERSIONS_HEADER, "2")
+                .body(Body::empty())
+                .unwrap();
+            return Box::new(future::ok(response));
+        }
+        (&Method::POST, EVENTS_PATH) => (),
+        _ => return respond(StatusCode::NOT_FOUND, "Not found"),
+    }
+
+    let headers = request.headers();
+    if headers.get(VERSION_HEADER).map(|v| v.as_bytes()) != Some(b"2") {
+        return respond(StatusCode::BAD_REQUEST, "Unsupported protocol version");
+    }
+
+    if let Some(token) = auth_token {
+        let expected = format!("Bearer {}", String::from_utf8_lossy(token));
+        if !authorized(headers, &expected) {
+            warn!("Rejecting request with an invalid auth token.");
+            return respond(StatusCode::UNAUTHORIZED, "Invalid auth token");
+        }
+    }
+
+    let gzip = match gzipped(headers) {
+        Some(gzip) => gzip,
+        None => return respond(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Unsupported encoding"),
+    };
+
+    let body = read_body(request.into_body(), gzip, max_body_size)
+        .map_err(|error| (error.status(), error.to_string()));
+    Box::new(body.then(move |body| {
+        match body
+            .and_then(|body| decode_batch(body).map_err(|error| (StatusCode::BAD_REQUEST, error)))
+        {
+            Ok(events) => future::Either::A(
+                out.send_all(stream::iter_ok::<_, mpsc::SendError<Event>>(events))
+                    .then(|result| match result {
+                        Ok(_) => respond(StatusCode::OK, ""),
+                        Err(_) => respond(StatusCode::SERVICE_UNAVAILABLE, "Shutting down"),
+                    }),
+            ),
+            Err((status, error)) => {
+                warn!(message = "invalid batch.", %error);
+                future::Either::B(respond(status, error))
+            }
+        }
+    }))
+}
+
+/// Decodes the length delimited events of a batch.
+fn decode_batch(body: Bytes) -> Result<Vec<Event>, String> {
+    let mut data = BytesMut::from(body);
+
+    let mut codec = LengthDelimitedCodec::new();
+    let mut events = Vec::new();
+    while let Some(frame) = codec
+        .decode(&mut data)
+        .map_err(|error| format!("Invalid frame: {}", error))?
+    {
+        let event = proto::EventWrapper::decode(frame)
+            .map_err(|error| format!("Invalid event: {}", error))?;
+        events.push(Event::from(event));
+    }
+
+    if data.is_empty() {
+        Ok(events)
+    } else {
+        Err("Truncated frame".into())
+    }
+}
+
 #[derive(Debug, Clone)]
-struct VectorSource;
+struct VectorSource {
+    auth_token: Option<Bytes>,
+    acknowledgements: bool,
+}
 
 impl TcpSource for VectorSource {
     type Decoder = LengthDelimitedCodec;
@@ -74,19 +227,45 @@ impl TcpSource for VectorSource {
             }
         }
     }
+
+    fn requires_handshake(&self) -> bool {
+        self.auth_token.is_some()
+    }
+
+    fn check_handshake(&self, frame: &BytesMut) -> bool {
+        match &self.auth_token {
+            Some(token) => {
+                let valid = token.len() == frame.len() && openssl::memcmp::eq(token, frame);
+                if !valid {
+                    warn!("Rejecting connection with an invalid auth token.");
+                }
+                valid
+            }
+            None => true,
+        }
+    }
+
+    fn acknowledge(&self) -> bool {
+        self.acknowledgements
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::VectorConfig;
+    use super::{decode_batch, VectorConfig};
     use crate::{
         buffers::Acker,
-        sinks::vector::vector,
+        sinks::{
+            util::tls::{TlsOptions, TlsSettings},
+            vector::{vector, ProtocolVersion, VectorSinkConfig},
+        },
+        sources::util::TlsConfig,
         test_util::{next_addr, wait_for_tcp, CollectCurrent},
-        topology::config::{GlobalOptions, SourceConfig},
+        topology::config::{GlobalOptions, SinkConfig, SourceConfig},
         Event,
     };
     use futures::{stream, sync::mpsc, Future, Sink};
+    use std::sync::atomic::Ordering;
 
     #[test]
     fn tcp_it_works_with_vector_sink() {
@@ -100,7 +279,7 @@ mod test {
         rt.spawn(server);
         wait_for_tcp(addr);
 
-        let sink = vector("NONE".into(), addr, Acker::Null);
+        let sink = vector("NONE".into(), addr, None, None, false, Acker::Null);
         let events = vec![
             Event::from("test"),
             Event::from("events"),
@@ -121,4 +300,234 @@ mod test {
         let (_, output) = CollectCurrent::new(rx).wait().unwrap();
         assert_eq!(events, output);
     }
+
+    fn secure_config(addr: std::net::SocketAddr) -> VectorConfig {
+        VectorConfig {
+            tls: Some(TlsConfig {
+                enabled: Some(true),
+                crt_path: Some("tests/data/localhost.crt".into()),
+                key_path: Some("tests/data/localhost.key".into()),
+                ..Default::default()
+            }),
+            auth_token: Some("secret".into()),
+            acknowledgements: true,
+            ..VectorConfig::new(addr.into())
+        }
+    }
+
+    fn client_tls() -> Option<TlsSettings> {
+        let options = TlsOptions {
+            ca_path: Some("tests/data/Vector_CA.crt".into()),
+            ..Default::default()
+        };
+        Some(TlsSettings::from_options(&Some(options)).unwrap())
+    }
+
+    #[test]
+    fn tls_auth_and_acknowledgements_with_vector_sink() {
+        let (tx, rx) = mpsc::channel(100);
+
+        let addr = next_addr();
+        let server = secure_config(addr)
+            .build("default", &GlobalOptions::default(), tx)
+            .unwrap();
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.spawn(server);
+        wait_for_tcp(addr);
+
+        let (acker, acked) = Acker::new_for_testing();
+        let sink = vector(
+            "localhost".into(),
+            addr,
+            client_tls(),
+            Some("secret"),
+            true,
+            acker,
+        );
+        let events = vec![Event::from("acknowledged"), Event::from("events")];
+
+        // Completes only once the source has acknowledged every event
+        let _ = rt
+            .block_on(sink.send_all(stream::iter_ok(events.clone().into_iter())))
+            .unwrap();
+        assert_eq!(acked.load(Ordering::SeqCst), events.len());
+
+        let (_, output) = CollectCurrent::new(rx).wait().unwrap();
+        assert_eq!(events, output);
+    }
+
+    #[test]
+    fn rejects_invalid_auth_token() {
+        let (tx, rx) = mpsc::channel(100);
+
+        let addr = next_addr();
+        let server = secure_config(addr)
+            .build("default", &GlobalOptions::default(), tx)
+            .unwrap();
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.spawn(server);
+        wait_for_tcp(addr);
+
+        let sink = vector(
+            "localhost".into(),
+            addr,
+            client_tls(),
+            Some("wrong"),
+            false,
+            Acker::Null,
+        );
+        let _ = rt
+            .block_on(sink.send_all(stream::iter_ok(vec![Event::from("rejected")])))
+            .unwrap();
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+
+        let (_, output) = CollectCurrent::new(rx).wait().unwrap();
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn acknowledges_frames_that_fail_to_decode() {
+        use std::io::{Read, Write};
+
+        let (tx, rx) = mpsc::channel(100);
+
+        let addr = next_addr();
+        let config = VectorConfig {
+            acknowledgements: true,
+            ..VectorConfig::new(addr.into())
+        };
+        let server = config
+            .build("default", &GlobalOptions::default(), tx)
+            .unwrap();
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.spawn(server);
+        wait_for_tcp(addr);
+
+        // A length delimited frame holding a truncated protobuf varint
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        stream.write_all(&3u32.to_be_bytes()).unwrap();
+        stream.write_all(&[0xff, 0xff, 0xff]).unwrap();
+
+        let mut ack = [0; 4];
+        stream.read_exact(&mut ack).unwrap();
+        assert_eq!(u32::from_be_bytes(ack), 1);
+
+        let (_, output) = CollectCurrent::new(rx).wait().unwrap();
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn v2_balances_batches_across_sources() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let (first_addr, second_addr) = (next_addr(), next_addr());
+        let mut receivers = Vec::new();
+        for addr in &[first_addr, second_addr] {
+            let (tx, rx) = mpsc::channel(100);
+            let config = VectorConfig {
+                version: ProtocolVersion::V2,
+                auth_token: Some("secret".into()),
+                ..VectorConfig::new((*addr).into())
+            };
+            rt.spawn(
+                config
+                    .build("default", &GlobalOptions::default(), tx)
+                    .unwrap(),
+            );
+            wait_for_tcp(*addr);
+            receivers.push(rx);
+        }
+
+        let config = VectorSinkConfig {
+            version: ProtocolVersion::V2,
+            addresses: vec![second_addr.to_string()],
+            // Every event is a batch of its own
+            batch_size: Some(1),
+            auth_token: Some("secret".into()),
+            ..VectorSinkConfig::new(first_addr.to_string())
+        };
+        let (acker, acked) = Acker::new_for_testing();
+        let (sink, healthcheck) = config.build(acker).unwrap();
+        rt.block_on(healthcheck).unwrap();
+
+        let events = (0..4)
+            .map(|i| Event::from(format!("event {}", i)))
+            .collect::<Vec<_>>();
+        let _ = rt
+            .block_on(sink.send_all(stream::iter_ok(events.clone().into_iter())))
+            .unwrap();
+        assert_eq!(acked.load(Ordering::SeqCst), events.len());
+
+        let mut output = Vec::new();
+        for rx in receivers {
+            let (_, received) = CollectCurrent::new(rx).wait().unwrap();
+            assert_eq!(received.len(), 2);
+            output.extend(received);
+        }
+        output.sort_by_key(|event| event.as_log()[&crate::event::MESSAGE].to_string_lossy());
+        assert_eq!(events, output);
+    }
+
+    #[test]
+    fn v2_rejects_invalid_auth_token() {
+        let (tx, _rx) = mpsc::channel(100);
+        let addr = next_addr();
+        let config = VectorConfig {
+            version: ProtocolVersion::V2,
+            auth_token: Some("secret".into()),
+            ..VectorConfig::new(addr.into())
+        };
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.spawn(
+            config
+                .build("default", &GlobalOptions::default(), tx)
+                .unwrap(),
+        );
+        wait_for_tcp(addr);
+
+        let client = hyper::Client::builder().http2_only(true).build_http();
+        let request = hyper::Request::post(format!("http://{}/v2/events", addr))
+            .header("vector-protocol-version", "2")
+            .header("authorization", "Bearer wrong")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let response = rt.block_on(client.request(request)).unwrap();
+        assert_eq!(response.status(), hyper::StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn v2_rejects_large_bodies() {
+        let (tx, _rx) = mpsc::channel(100);
+        let addr = next_addr();
+        let config = VectorConfig {
+            version: ProtocolVersion::V2,
+            max_body_size: 100,
+            ..VectorConfig::new(addr.into())
+        };
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.spawn(
+            config
+                .build("default", &GlobalOptions::default(), tx)
+                .unwrap(),
+        );
+        wait_for_tcp(addr);
+
+        let client = hyper::Client::builder().http2_only(true).build_http();
+        let request = hyper::Request::post(format!("http://{}/v2/events", addr))
+            .header("vector-protocol-version", "2")
+            .body(hyper::Body::from(vec![0; 101]))
+            .unwrap();
+        let response = rt.block_on(client.request(request)).unwrap();
+        assert_eq!(response.status(), hyper::StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn decode_batch_rejects_truncated_frames() {
+        let frame = crate::sinks::vector::encode_event(Event::from("event")).unwrap();
+        let mut body = frame.to_vec();
+        body.extend_from_slice(&frame[..frame.len() - 1]);
+
+        assert!(decode_batch(frame.into()).is_ok());
+        assert!(decode_batch(body.into()).is_err());
+    }
 }
@@ -73,6 +73,13 @@ impl Template {
             .map(|bytes| String::from_utf8(Vec::from(bytes.as_ref())).expect("this is a bug"))
     }
 
+    /// Only renders `{{field}}` references, for values like uris where a `%`
+    /// isn't a strftime specifier.
+    pub fn fields_only(mut self) -> Self {
+        self.has_ts = false;
+        self
+    }
+
     pub fn is_dynamic(&self) -> bool {
         self.has_fields || self.has_ts
     }
@@ -165,6 +172,10 @@ mod tests {
         assert_eq!(false, Template::from("/kube-demo/echo").is_dynamic());
         assert_eq!(true, Template::from("/kube-demo/{{ foo }}").is_dynamic());
         assert_eq!(true, Template::from("/kube-demo/{{ foo }}/%F").is_dynamic());
+        assert_eq!(
+            false,
+            Template::from("/?q=a%20b").fields_only().is_dynamic()
+        );
     }
 
     #[test]
@@ -146,7 +146,7 @@ pub fn build_pieces(config: &super::Config) -> Result<(Pieces, Vec<String>), Vec
         };
 
         let (input_tx, input_rx) = futures::sync::mpsc::channel(100);
-        let input_tx = buffers::BufferInputCloner::Memory(input_tx, buffers::WhenFull::Block);
+        let input_tx = buffers::BufferInputCloner::Memory(input_tx, buffers::WhenFull::Block, None);
 
         let (output, control) = Fanout::new();
 
@@ -180,7 +180,10 @@ pub fn build_pieces(config: &super::Config) -> Result<(Pieces, Vec<String>), Vec
             Ok(buffer) => buffer,
         };
 
-        let (sink, healthcheck) = match sink.inner.build(acker) {
+        // Build in the same span the sink's task runs in, so metrics recorded
+        // while building are scoped by its name too.
+        let span = info_span!("sink", %name, scope = %name);
+        let (sink, healthcheck) = match span.in_scope(|| sink.inner.build(acker)) {
             Err(error) => {
                 errors.push(format!("Sink \"{}\": {}", name, error));
                 continue;
@@ -322,7 +322,9 @@ impl RunningTopology {
     ) {
         let task = new_pieces.tasks.remove(name).unwrap();
         let task = handle_errors(task, self.abort_tx.clone());
-        let task = task.instrument(info_span!("sink", %name));
+        // Metrics recorded by the sink without a scope of their own are
+        // reported under its name.
+        let task = task.instrument(info_span!("sink", %name, scope = %name));
         let spawned = oneshot::spawn(task, &rt.executor());
         if let Some(previous) = self.tasks.insert(name.to_string(), spawned) {
             previous.forget();
@@ -545,7 +547,7 @@ mod tests {
             &[&"in"],
             ConsoleSinkConfig {
                 target: Target::Stdout,
-                encoding: Encoding::Text,
+                encoding: Encoding::Text.into(),
             },
         );
         old_config.global.data_dir = Some(Path::new("/asdf").to_path_buf());
@@ -1,28 +1,47 @@
-use crate::metrics;
+use crate::{
+    event::{self, Event, ValueKind},
+    metrics,
+};
+use chrono::Utc;
+use futures::{sync::mpsc, Future, Poll};
+use lazy_static::lazy_static;
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
 use tracing::{
+    callsite::Identifier,
     dispatcher::{set_global_default, Dispatch},
-    span::Span,
+    field::{Field, Visit},
+    span::{self, Span},
+    Subscriber,
 };
 use tracing_limit::Limit;
 use tracing_log::LogTracer;
 use tracing_metrics::MetricsSubscriber;
-use tracing_subscriber::{layer::SubscriberExt, FmtSubscriber};
+use tracing_subscriber::{
+    layer::{Context, Layer, SubscriberExt},
+    FmtSubscriber,
+};
 
 pub use tracing_futures::Instrument;
 pub use tracing_tower::{InstrumentableService, InstrumentedService};
 
-pub fn init(color: bool, levels: &str, metrics: Option<metrics::Sink>) {
+pub fn init(color: bool, levels: &str) {
     let subscriber = FmtSubscriber::builder()
         .with_ansi(color)
         .with_env_filter(levels)
         .finish()
-        .with(Limit::default());
+        .with(Limit::default())
+        .with(BroadcastLayer::default());
 
-    let dispatch = if let Some(sink) = metrics {
-        Dispatch::new(MetricsSubscriber::new(subscriber, sink))
-    } else {
-        Dispatch::new(subscriber)
-    };
+    let dispatch = Dispatch::new(MetricsSubscriber::lazy(subscriber, metrics::sink));
 
     let _ = LogTracer::init();
     let _ = set_global_default(dispatch);
@@ -31,3 +50,230 @@ pub fn init(color: bool, levels: &str, metrics: Option<metrics::Sink>) {
 pub fn current_span() -> Span {
     Span::current()
 }
+
+/// How many of Vector's own events an `internal_logs` source can be behind
+/// before the next ones are dropped.
+const BROADCAST_CAPACITY: usize = 1000;
+
+/// How many events of a single callsite are broadcast each second. Logs
+/// about handling Vector's own logs, like a sink logging every event it
+/// sends, would otherwise feed back into the `internal_logs` sources.
+pub(crate) const BROADCAST_RATE_LIMIT: u32 = 10;
+
+lazy_static! {
+    static ref SENDERS: Mutex<Vec<mpsc::Sender<Event>>> = Mutex::new(Vec::new());
+}
+
+/// The length of `SENDERS`, checked before doing any work for an event or
+/// span so there's none without an `internal_logs` source.
+static RECEIVERS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Set while events mustn't be broadcast, to avoid logs about
+    /// broadcasting logs.
+    static SUPPRESSED: Cell<bool> = Cell::new(false);
+}
+
+/// Receives Vector's own events as logs, once they passed the log level
+/// filter. They are dropped when the receiver falls too far behind.
+pub fn subscribe() -> mpsc::Receiver<Event> {
+    let (tx, rx) = mpsc::channel(BROADCAST_CAPACITY);
+    SENDERS.lock().unwrap().push(tx);
+    RECEIVERS.fetch_add(1, Ordering::SeqCst);
+    rx
+}
+
+/// Polls `inner` without broadcasting its events, for the `internal_logs`
+/// sources which would otherwise receive their own logs.
+pub fn without_broadcast<F: Future>(inner: F) -> WithoutBroadcast<F> {
+    WithoutBroadcast { inner }
+}
+
+pub struct WithoutBroadcast<F> {
+    inner: F,
+}
+
+impl<F: Future> Future for WithoutBroadcast<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let suppressed = SUPPRESSED.with(|suppressed| suppressed.replace(true));
+        let result = self.inner.poll();
+        SUPPRESSED.with(|s| s.set(suppressed));
+        result
+    }
+}
+
+/// Sends events to the receivers of `subscribe` as logs, with the fields of
+/// their spans.
+#[derive(Debug, Default)]
+pub struct BroadcastLayer {
+    spans: RwLock<HashMap<span::Id, SpanData>>,
+    /// When the current second started for each callsite, and how many of
+    /// its events were broadcast since.
+    rates: Mutex<HashMap<Identifier, (Instant, u32)>>,
+}
+
+#[derive(Debug)]
+struct SpanData {
+    name: &'static str,
+    parent: Option<span::Id>,
+    fields: Fields,
+}
+
+impl BroadcastLayer {
+    fn within_rate_limit(&self, callsite: Identifier) -> bool {
+        let now = Instant::now();
+        let mut rates = self.rates.lock().unwrap();
+        let (start, count) = rates.entry(callsite).or_insert((now, 0));
+        if now.duration_since(*start) >= Duration::from_secs(1) {
+            *start = now;
+            *count = 0;
+        }
+        *count += 1;
+        *count <= BROADCAST_RATE_LIMIT
+    }
+
+    fn log_event<S: Subscriber>(&self, event: &tracing::Event, ctx: &Context<S>) -> Event {
+        let mut log = Event::new_empty_log();
+        let metadata = event.metadata();
+
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        for (name, value) in fields.0 {
+            log.as_mut_log().insert_explicit(name.into(), value);
+        }
+
+        // The innermost spans are inserted first, and win.
+        let spans = self.spans.read().unwrap();
+        let mut id = ctx.current_span().id().cloned();
+        while let Some(span) = id.and_then(|id| spans.get(&id)) {
+            for (name, value) in &span.fields.0 {
+                let name = format!("span.{}.{}", span.name, name);
+                if log.as_log().get(&name.as_str().into()).is_none() {
+                    log.as_mut_log().insert_explicit(name.into(), value.clone());
+                }
+            }
+            id = span.parent.clone();
+        }
+
+        let fields = log.as_mut_log();
+        fields.insert_explicit("metadata.level".into(), metadata.level().to_string().into());
+        fields.insert_explicit("metadata.target".into(), metadata.target().into());
+        if let Some(module_path) = metadata.module_path() {
+            fields.insert_explicit("metadata.module_path".into(), module_path.into());
+        }
+        fields.insert_implicit(event::TIMESTAMP.clone(), Utc::now().into());
+        log
+    }
+}
+
+impl<S> Layer<S> for BroadcastLayer
+where
+    S: Subscriber,
+{
+    fn new_span(&self, attrs: &span::Attributes, id: &span::Id, ctx: Context<S>) {
+        // Spans created without any receiver are never tracked, their fields
+        // are missing from the logs emitted in them.
+        if RECEIVERS.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        let parent = if attrs.is_contextual() {
+            ctx.current_span().id().cloned()
+        } else {
+            attrs.parent().cloned()
+        };
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+
+        let span = SpanData {
+            name: attrs.metadata().name(),
+            parent,
+            fields,
+        };
+        self.spans.write().unwrap().insert(id.clone(), span);
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record, _ctx: Context<S>) {
+        if RECEIVERS.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        if let Some(span) = self.spans.write().unwrap().get_mut(id) {
+            values.record(&mut span.fields);
+        }
+    }
+
+    fn on_event(&self, event: &tracing::Event, ctx: Context<S>) {
+        if RECEIVERS.load(Ordering::Relaxed) == 0 || SUPPRESSED.with(Cell::get) {
+            return;
+        }
+        if !self.within_rate_limit(event.metadata().callsite()) {
+            return;
+        }
+        let mut senders = SENDERS.lock().unwrap();
+
+        SUPPRESSED.with(|suppressed| suppressed.set(true));
+        let log = self.log_event(event, &ctx);
+        let mut i = 0;
+        while i < senders.len() {
+            match senders[i].try_send(log.clone()) {
+                Err(ref error) if error.is_disconnected() => {
+                    senders.swap_remove(i);
+                    RECEIVERS.fetch_sub(1, Ordering::SeqCst);
+                }
+                _ => i += 1,
+            }
+        }
+        if senders.is_empty() {
+            // The spans aren't tracked anymore, and wouldn't be closed.
+            self.spans.write().unwrap().clear();
+        }
+        SUPPRESSED.with(|suppressed| suppressed.set(false));
+    }
+
+    fn on_close(&self, id: span::Id, _ctx: Context<S>) {
+        if RECEIVERS.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        self.spans.write().unwrap().remove(&id);
+    }
+}
+
+/// The fields of events and spans, but the rate limiting one which is an
+/// instruction to `Limit`.
+#[derive(Debug, Default)]
+struct Fields(Vec<(String, ValueKind)>);
+
+impl Fields {
+    fn insert(&mut self, field: &Field, value: ValueKind) {
+        if field.name() != "rate_limit_secs" {
+            self.0.push((field.name().to_owned(), value));
+        }
+    }
+}
+
+impl Visit for Fields {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, (value as i64).into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{:?}", value).into());
+    }
+}
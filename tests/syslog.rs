@@ -28,6 +28,7 @@ fn test_tcp_syslog() {
         "in",
         SyslogConfig::new(Mode::Tcp {
             address: in_addr.into(),
+            tls: None,
         }),
     );
     config.add_sink("out", &["in"], tcp_json_sink(out_addr.to_string()));
@@ -322,7 +323,7 @@ fn encode_priority(severity: Severity, facility: Facility) -> u8 {
 fn tcp_json_sink(address: String) -> TcpSinkConfig {
     TcpSinkConfig {
         address,
-        encoding: tcp::Encoding::Json,
+        encoding: tcp::Encoding::Json.into(),
         tls: None,
     }
 }